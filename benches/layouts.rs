@@ -0,0 +1,52 @@
+// Compares the plain, B-heap and van Emde Boas layouts on heaps of increasing size. The plain layout
+// should win while the heap fits in cache, and the blocked layouts should catch up and pull ahead
+// somewhere past the size of the last level cache. Where exactly depends on the machine, which is the
+// point of running it.
+//
+//     cargo bench --bench layouts
+//
+// FELICITY_BENCH_MAX_SHIFT sets the largest heap size as a power of two, 24 by default.
+use felicity::b_heap::BHeap;
+use felicity::heap::{MinHeap, MinOrder};
+use felicity::veb_heap::VebHeap;
+use rand::prelude::*;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+fn time<H>(values: &[u64], heap: &mut H, insert: impl Fn(&mut H, u64), pop: impl Fn(&mut H) -> Option<u64>) -> Duration {
+    let start = Instant::now();
+    for &value in values {
+        insert(heap, value);
+    }
+
+    while let Some(value) = pop(heap) {
+        black_box(value);
+    }
+
+    start.elapsed()
+}
+
+fn main() {
+    let max_shift: u32 = std::env::var("FELICITY_BENCH_MAX_SHIFT").ok().and_then(|shift| shift.parse().ok()).unwrap_or(24);
+    let mut rng = thread_rng();
+
+    println!("{:>10} {:>12} {:>12} {:>12}", "elements", "plain ns/op", "b-heap ns/op", "veb ns/op");
+    for shift in (10..=max_shift).step_by(2) {
+        let len = 1usize << shift;
+        let values: Vec<u64> = (0..len).map(|_| rng.gen()).collect();
+
+        let mut plain = MinHeap::with_capacity(len);
+        let plain_time = time(&values, &mut plain, MinHeap::insert, MinHeap::pop);
+
+        let mut b_heap = BHeap::<_, MinOrder<_>>::new();
+        b_heap.reserve(len);
+        let b_heap_time = time(&values, &mut b_heap, BHeap::insert, BHeap::pop);
+
+        let mut veb = VebHeap::<_, MinOrder<_>>::new();
+        let veb_time = time(&values, &mut veb, VebHeap::insert, VebHeap::pop);
+
+        // Each element is inserted once and popped once
+        let per_op = |duration: Duration| duration.as_nanos() as f64 / (2 * len) as f64;
+        println!("{:>10} {:>12.1} {:>12.1} {:>12.1}", len, per_op(plain_time), per_op(b_heap_time), per_op(veb_time));
+    }
+}
@@ -0,0 +1,45 @@
+// Compares SimdMinHeap with the plain binary MinHeap on u32 keys. Build with and without the simd
+// feature to see what the vector child selection adds on top of the 4-ary layout. Where this was first
+// run, the 4-ary heap was around twice as fast as the binary one on every size that fits in cache, and the
+// vector selection was within noise of the scalar one, so most of the gain is the layout and the lack of
+// branches rather than the SIMD itself.
+//
+//     cargo bench --bench simd --features simd
+use felicity::heap::MinHeap;
+use felicity::simd_heap::SimdMinHeap;
+use rand::prelude::*;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+fn time<H>(values: &[u32], heap: &mut H, insert: impl Fn(&mut H, u32), pop: impl Fn(&mut H) -> Option<u32>) -> Duration {
+    let start = Instant::now();
+    for &value in values {
+        insert(heap, value);
+    }
+
+    while let Some(value) = pop(heap) {
+        black_box(value);
+    }
+
+    start.elapsed()
+}
+
+fn main() {
+    let mut rng = thread_rng();
+
+    println!("{:>10} {:>12} {:>12}", "elements", "binary ns/op", "simd ns/op");
+    for shift in (10..=22).step_by(2) {
+        let len = 1usize << shift;
+        let values: Vec<u32> = (0..len).map(|_| rng.gen()).collect();
+
+        let mut binary = MinHeap::with_capacity(len);
+        let binary_time = time(&values, &mut binary, MinHeap::insert, MinHeap::pop);
+
+        let mut simd = SimdMinHeap::with_capacity(len);
+        let simd_time = time(&values, &mut simd, SimdMinHeap::insert, SimdMinHeap::pop);
+
+        // Each element is inserted once and popped once
+        let per_op = |duration: Duration| duration.as_nanos() as f64 / (2 * len) as f64;
+        println!("{:>10} {:>12.1} {:>12.1}", len, per_op(binary_time), per_op(simd_time));
+    }
+}
@@ -0,0 +1,383 @@
+use crate::heap::{Heap, HeapOrder, MaxOrder};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+
+// A priority queue for async code, where pop is a future that waits for there to be something to pop
+// rather than returning None, so tasks routing work between each other can just await the next job. Every
+// clone is a handle to the same queue, and it works under any executor since all it needs is the Waker.
+//
+// A push wakes just the one task that has been waiting longest, and it is given the top of the heap when
+// it runs, so busy queues don't have every waiting task waking up to fight over each element. Waiters are
+// woken first come first served rather than by priority, because pop has no priority of its own to rank
+// them by. The priorities belong to the elements, and those still come out highest first whichever task
+// takes them. Once the queue is closed nothing more can be pushed, but what is already in it can still be
+// popped, and pop only gives up with None once it is both closed and empty.
+//
+// With the futures feature each handle is also a Stream of the elements in priority order, ending once the
+// queue is closed and empty, so it can go straight into the StreamExt combinators and select loops.
+struct State<T, Order: HeapOrder<T>> {
+    heap: Heap<T, Order>,
+    // Tasks waiting in pop, in the order they started waiting
+    waiters: VecDeque<(u64, Waker)>,
+    next_waiter: u64,
+    closed: bool,
+}
+
+pub struct AsyncPriorityQueue<T, Order: HeapOrder<T> = MaxOrder<T>> {
+    state: Arc<Mutex<State<T, Order>>>,
+    // The pop this handle is waiting on as a Stream, so that it keeps its place between polls
+    #[cfg(feature = "futures")]
+    next: Option<Pop<T, Order>>,
+}
+
+impl<T, Order: HeapOrder<T>> AsyncPriorityQueue<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State {
+                heap: Heap::with_capacity_and_cmp(0, order),
+                waiters: VecDeque::new(),
+                next_waiter: 0,
+                closed: false,
+            })),
+            #[cfg(feature = "futures")]
+            next: None,
+        }
+    }
+
+    // Only a snapshot, since other tasks can be pushing and popping at the same time
+    pub fn len(&self) -> usize {
+        self.lock().heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    // Hands the value back if the queue has been closed
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut state = self.lock();
+        if state.closed {
+            return Err(value);
+        }
+
+        state.heap.insert(value);
+        let waiter = state.waiters.pop_front();
+        drop(state);
+
+        if let Some((_, waker)) = waiter {
+            waker.wake();
+        }
+
+        Ok(())
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        self.lock().heap.pop()
+    }
+
+    // Waits for the top of the queue. Resolves to None once the queue is closed and empty.
+    pub fn pop(&self) -> Pop<T, Order> {
+        Pop { state: self.state.clone(), waiter: None }
+    }
+
+    // Stops any more pushes and wakes every waiting task, so that they can all see whether there is anything
+    // left for them
+    pub fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        let waiters = std::mem::take(&mut state.waiters);
+        drop(state);
+
+        for (_, waker) in waiters {
+            waker.wake();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T, Order>> {
+        lock(&self.state)
+    }
+}
+
+fn lock<T, Order: HeapOrder<T>>(state: &Mutex<State<T, Order>>) -> MutexGuard<'_, State<T, Order>> {
+    // Nothing runs user code while holding the lock apart from the order and waking, and neither can lose
+    // elements if they panic, so there is no reason to give up on a poisoned queue
+    state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl<T, Order: HeapOrder<T>> Clone for AsyncPriorityQueue<T, Order> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+            #[cfg(feature = "futures")]
+            next: None,
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for AsyncPriorityQueue<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> std::fmt::Debug for AsyncPriorityQueue<T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.lock();
+        f.debug_struct("AsyncPriorityQueue").field("len", &state.heap.len()).field("closed", &state.closed).finish()
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T, Order: HeapOrder<T>> futures_core::Stream for AsyncPriorityQueue<T, Order> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let this = self.get_mut();
+        let next = this.next.get_or_insert_with(|| Pop { state: this.state.clone(), waiter: None });
+        let ret = Pin::new(next).poll(cx);
+        if ret.is_ready() {
+            this.next = None;
+        }
+
+        ret
+    }
+}
+
+pub struct Pop<T, Order: HeapOrder<T>> {
+    state: Arc<Mutex<State<T, Order>>>,
+    // Set once this has had to wait, and cleared again once it is done
+    waiter: Option<u64>,
+}
+
+impl<T, Order: HeapOrder<T>> Future for Pop<T, Order> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let this = self.get_mut();
+        let mut state = lock(&this.state);
+
+        let value = state.heap.pop();
+        if value.is_some() || state.closed {
+            // Anything that wakes this after now is for somebody else
+            if let Some(waiter) = this.waiter.take() {
+                state.waiters.retain(|(other, _)| *other != waiter);
+            }
+
+            return Poll::Ready(value);
+        }
+
+        // Either this hasn't waited yet, or it was woken and somebody else got the element first. Either way
+        // it waits at the back of the line, or stays where it is if it has spuriously been polled again.
+        match this.waiter.and_then(|waiter| state.waiters.iter_mut().find(|(other, _)| *other == waiter)) {
+            Some((_, waker)) => waker.clone_from(cx.waker()),
+            None => {
+                let waiter = state.next_waiter;
+                state.next_waiter += 1;
+                state.waiters.push_back((waiter, cx.waker().clone()));
+                this.waiter = Some(waiter);
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Drop for Pop<T, Order> {
+    fn drop(&mut self) {
+        let Some(waiter) = self.waiter else {
+            return;
+        };
+
+        let mut state = lock(&self.state);
+        match state.waiters.iter().position(|(other, _)| *other == waiter) {
+            Some(position) => {
+                state.waiters.remove(position);
+            }
+            None if !state.heap.is_empty() => {
+                // This was woken for an element and then given up on before it could take it, so pass the
+                // wake on to whoever is next, or they could wait forever with the element sat there
+                if let Some((_, waker)) = state.waiters.pop_front() {
+                    drop(state);
+                    waker.wake();
+                }
+            }
+            None => (),
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T>> std::fmt::Debug for Pop<T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pop").field("waiting", &self.waiter.is_some()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::Wake;
+    use std::thread::Thread;
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(ret) = future.as_mut().poll(&mut cx) {
+                return ret;
+            }
+
+            std::thread::park();
+        }
+    }
+
+    #[derive(Default)]
+    struct Flag(AtomicBool);
+
+    impl Wake for Flag {
+        fn wake(self: Arc<Self>) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_priority_order_and_close() {
+        let queue = AsyncPriorityQueue::<u32>::new();
+        for value in [3, 9, 1] {
+            queue.push(value).unwrap();
+        }
+
+        assert_eq!(queue.try_pop(), Some(9));
+        assert_eq!(block_on(queue.pop()), Some(3));
+
+        queue.close();
+        assert_eq!(queue.push(5), Err(5));
+        assert_eq!(block_on(queue.pop()), Some(1));
+        assert_eq!(block_on(queue.pop()), None);
+        assert_eq!(queue.try_pop(), None);
+    }
+
+    #[test]
+    fn test_waiters() {
+        let queue = AsyncPriorityQueue::<u32>::new();
+        let popped = Mutex::new(Vec::new());
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let (queue, popped) = (queue.clone(), &popped);
+                scope.spawn(move || {
+                    while let Some(value) = block_on(queue.pop()) {
+                        popped.lock().unwrap().push(value);
+                    }
+                });
+            }
+
+            for value in 0..1000 {
+                queue.push(value).unwrap();
+            }
+
+            queue.close();
+        });
+
+        let mut popped = popped.into_inner().unwrap();
+        popped.sort();
+        assert_eq!(popped, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_dropped_waiter_passes_wake_on() {
+        let queue = AsyncPriorityQueue::<u32>::new();
+        let (first_flag, second_flag) = (Arc::new(Flag::default()), Arc::new(Flag::default()));
+        let (first_waker, second_waker) = (Waker::from(first_flag.clone()), Waker::from(second_flag.clone()));
+
+        let mut first = queue.pop();
+        let mut second = queue.pop();
+        assert_eq!(Pin::new(&mut first).poll(&mut Context::from_waker(&first_waker)), Poll::Pending);
+        assert_eq!(Pin::new(&mut second).poll(&mut Context::from_waker(&second_waker)), Poll::Pending);
+
+        // Only the first waiter is woken, and when it goes away without popping the second gets the wake
+        queue.push(7).unwrap();
+        assert!(first_flag.0.load(Ordering::SeqCst));
+        assert!(!second_flag.0.load(Ordering::SeqCst));
+
+        drop(first);
+        assert!(second_flag.0.load(Ordering::SeqCst));
+        assert_eq!(Pin::new(&mut second).poll(&mut Context::from_waker(&second_waker)), Poll::Ready(Some(7)));
+    }
+
+    #[test]
+    fn test_wake_order() {
+        let queue = AsyncPriorityQueue::<u32>::new();
+        let flags: Vec<Arc<Flag>> = (0..3).map(|_| Arc::new(Flag::default())).collect();
+        let wakers: Vec<Waker> = flags.iter().map(|flag| Waker::from(flag.clone())).collect();
+        let woken = || flags.iter().map(|flag| flag.0.load(Ordering::SeqCst)).collect::<Vec<_>>();
+
+        let mut pops: Vec<_> = (0..3).map(|_| queue.pop()).collect();
+        for (pop, waker) in pops.iter_mut().zip(&wakers) {
+            assert_eq!(Pin::new(pop).poll(&mut Context::from_waker(waker)), Poll::Pending);
+        }
+
+        // Each push wakes the next task in the order they started waiting, however much the value is worth
+        queue.push(1).unwrap();
+        assert_eq!(woken(), [true, false, false]);
+        queue.push(5).unwrap();
+        assert_eq!(woken(), [true, true, false]);
+
+        // And whichever of them gets there first takes the top
+        assert_eq!(Pin::new(&mut pops[1]).poll(&mut Context::from_waker(&wakers[1])), Poll::Ready(Some(5)));
+        assert_eq!(Pin::new(&mut pops[0]).poll(&mut Context::from_waker(&wakers[0])), Poll::Ready(Some(1)));
+
+        queue.close();
+        assert_eq!(woken(), [true, true, true]);
+        assert_eq!(Pin::new(&mut pops[2]).poll(&mut Context::from_waker(&wakers[2])), Poll::Ready(None));
+    }
+
+    #[cfg(feature = "futures")]
+    #[test]
+    fn test_stream() {
+        use futures_core::Stream;
+
+        let queue = AsyncPriorityQueue::<u32>::new();
+        let mut stream = queue.clone();
+        let next = |stream: &mut AsyncPriorityQueue<u32>| block_on(std::future::poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)));
+
+        for value in [2, 8, 5] {
+            queue.push(value).unwrap();
+        }
+
+        assert_eq!(next(&mut stream), Some(8));
+
+        // A stream that is waiting keeps its place when it is polled again, and ends once the queue closes
+        let waker = Waker::from(Arc::new(Flag::default()));
+        queue.try_pop();
+        queue.try_pop();
+        assert_eq!(Pin::new(&mut stream).poll_next(&mut Context::from_waker(&waker)), Poll::Pending);
+        assert_eq!(Pin::new(&mut stream).poll_next(&mut Context::from_waker(&waker)), Poll::Pending);
+        assert_eq!(queue.lock().waiters.len(), 1);
+
+        queue.push(1).unwrap();
+        queue.close();
+        assert_eq!(next(&mut stream), Some(1));
+        assert_eq!(next(&mut stream), None);
+    }
+}
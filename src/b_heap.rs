@@ -0,0 +1,243 @@
+use crate::heap::HeapOrder;
+
+// A B-heap (Kamp's layout, as used by Varnish) is a binary heap whose elements are laid out so that any
+// sift only touches a handful of pages. The array is cut into pages of a power of two elements, and each
+// page holds a small subtree of the heap. The leaves of one page have their children in another page,
+// so sifting from root to leaf crosses O(log n / log page_size) pages instead of touching a new page at
+// nearly every level like the plain layout does once the heap is much bigger than the cache.
+//
+// The price is slightly more complicated index arithmetic, and a tree that is one level deeper per page:
+// the first two slots of every page after the first are the roots of the two subtrees in the page, and
+// each has only one child of its own. Indices are one based, with index 1 at the start of the storage.
+#[derive(Debug, Clone)]
+pub struct BHeap<T, Order: HeapOrder<T>> {
+    data: Vec<T>,
+    page_shift: u32,
+    order: Order,
+}
+
+impl<T, Order: HeapOrder<T>> BHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    // Uses pages of 4KiB, or as near as the element size allows
+    pub fn with_cmp(order: Order) -> Self {
+        let page_elements = (4096 / std::mem::size_of::<T>().max(1)).max(4);
+        Self::with_page_size_and_cmp(page_elements, order)
+    }
+
+    // The page size is in elements, and is rounded down to a power of two no smaller than four
+    pub fn with_page_size_and_cmp(page_elements: usize, order: Order) -> Self {
+        Self {
+            data: Vec::new(),
+            page_shift: page_elements.max(4).ilog2(),
+            order,
+        }
+    }
+
+    pub fn from_vec_and_cmp(data: Vec<T>, order: Order) -> Self {
+        let mut ret = Self::with_cmp(order);
+        ret.data = data;
+
+        // The tree isn't complete, but every index still comes after its parent, so sifting down from the
+        // back still builds a heap bottom up
+        for index in (1..=ret.data.len()).rev() {
+            ret.sift_down(index);
+        }
+
+        ret
+    }
+
+    pub fn page_size(&self) -> usize {
+        1 << self.page_shift
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional);
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    // Iterates over the elements in storage order
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    pub fn insert(&mut self, value: T) {
+        self.data.push(value);
+        self.sift_up(self.data.len());
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let ret = self.data.swap_remove(0);
+        self.sift_down(1);
+        Some(ret)
+    }
+
+    // Pops everything into a new Vec and reverses it, so the top of the heap is last like Heap's. Unlike Heap
+    // it doesn't sort in place, as sift_down only knows how to stay inside the whole of the storage.
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let mut ret = Vec::with_capacity(self.data.len());
+        ret.extend(std::iter::from_fn(|| self.pop()));
+        ret.reverse();
+        ret
+    }
+
+    fn page_mask(&self) -> usize {
+        self.page_size() - 1
+    }
+
+    fn parent(&self, index: usize) -> usize {
+        let page_size = self.page_size();
+        let page_mask = self.page_mask();
+        let offset = index & page_mask;
+
+        if index < page_size || offset > 3 {
+            // Inside a page the usual arithmetic applies, relative to the start of the page
+            (index & !page_mask) | (offset >> 1)
+        } else if offset < 2 {
+            // A page root, whose parent is on the bottom row of an earlier page
+            let mut parent = (index - page_size) >> self.page_shift;
+            parent += parent & !(page_mask >> 1);
+            parent | (page_size / 2)
+        } else {
+            // The only child of a page root
+            index - 2
+        }
+    }
+
+    fn children(&self, index: usize) -> (usize, usize) {
+        let page_mask = self.page_mask();
+
+        if index > page_mask && index & (page_mask - 1) == 0 {
+            (index + 2, index + 2)
+        } else if index & (self.page_size() >> 1) != 0 {
+            // The bottom row of a page has its children at the roots of a later page
+            let page = ((index & !page_mask) >> 1) | (index & (page_mask >> 1));
+            let child = (page + 1) << self.page_shift;
+            (child, child + 1)
+        } else {
+            let child = index + (index & page_mask);
+            (child, child + 1)
+        }
+    }
+
+    fn goes_above(&self, left: usize, right: usize) -> bool {
+        self.order.left_can_go_above(&self.data[left - 1], &self.data[right - 1])
+    }
+
+    fn sift_up(&mut self, mut index: usize) {
+        while index > 1 {
+            let parent = self.parent(index);
+            if !self.goes_above(index, parent) {
+                break;
+            }
+
+            self.data.swap(index - 1, parent - 1);
+            index = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut index: usize) {
+        let len = self.data.len();
+        loop {
+            let (left, right) = self.children(index);
+            if left > len {
+                break;
+            }
+
+            let best = if right != left && right <= len && self.goes_above(right, left) { right } else { left };
+            if !self.goes_above(best, index) {
+                break;
+            }
+
+            self.data.swap(index - 1, best - 1);
+            index = best;
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for BHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for BHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> FromIterator<T> for BHeap<T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        Self::from_vec_and_cmp(iter.into_iter().collect(), Order::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MinOrder;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_layout() {
+        // Every index has exactly one parent that comes before it, and is one of that parent's children
+        for page_size in [4, 8, 64, 512] {
+            let heap = BHeap::<u32, MinOrder<_>>::with_page_size_and_cmp(page_size, MinOrder::default());
+            for index in 2..100000 {
+                let parent = heap.parent(index);
+                assert!(parent < index);
+
+                let (left, right) = heap.children(parent);
+                assert!(left == index || right == index, "{} is not a child of {} with page size {}", index, parent, page_size);
+            }
+        }
+    }
+
+    #[test]
+    fn test_insert_pop() {
+        let mut values: Vec<u32> = (0..10000).collect();
+        values.shuffle(&mut thread_rng());
+
+        for page_size in [4, 16, 1024] {
+            let mut heap = BHeap::with_page_size_and_cmp(page_size, MinOrder::default());
+            heap.extend(values.iter().copied());
+            assert_eq!(heap.peek(), Some(&0));
+            assert_eq!(heap.into_sorted_vec(), (0..10000).rev().collect::<Vec<_>>());
+        }
+
+        let heap: BHeap<_, MinOrder<_>> = values.into_iter().collect();
+        assert_eq!(heap.into_sorted_vec(), (0..10000).rev().collect::<Vec<_>>());
+    }
+}
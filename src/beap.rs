@@ -0,0 +1,318 @@
+use crate::heap::HeapOrder;
+
+// A beap (Munro and Suwanda's bi-parental heap) arranges its elements in a triangle, with row r holding
+// r + 1 of them, stored one row after another. Element (r, c) has up to two parents, (r - 1, c - 1) and
+// (r - 1, c), and has to be able to go at least as high as its two children (r + 1, c) and
+// (r + 1, c + 1). The triangle is only O(sqrt n) rows tall, so insert and pop are O(sqrt n) rather than
+// O(log n).
+//
+// What that buys is search. Tilting the triangle so that one axis runs down the (r, c) to (r + 1, c)
+// diagonals and the other down the (r, c) to (r + 1, c + 1) diagonals turns it into a grid that is sorted
+// along both axes. Starting from one corner, each comparison rules out a whole row or column of that grid,
+// so finding an element takes O(sqrt n) comparisons without any index on the side.
+#[derive(Debug, Clone)]
+pub struct Beap<T, Order: HeapOrder<T>> {
+    data: Vec<T>,
+    order: Order,
+}
+
+fn row_start(row: usize) -> usize {
+    row * (row + 1) / 2
+}
+
+fn row_of(index: usize) -> usize {
+    ((8 * index + 1).isqrt() - 1) / 2
+}
+
+impl<T, Order: HeapOrder<T>> Beap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            data: Vec::new(),
+            order,
+        }
+    }
+
+    // Sorting puts every element after both of its parents, which makes a valid beap in O(n log n)
+    pub fn from_vec_and_cmp(mut data: Vec<T>, order: Order) -> Self {
+        data.sort_by(|left, right| {
+            if order.left_can_go_above(left, right) {
+                std::cmp::Ordering::Less
+            } else if order.left_can_go_above(right, left) {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        });
+
+        Self {
+            data,
+            order,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    // Iterates over the elements in storage order, row by row
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    pub fn insert(&mut self, value: T) {
+        self.data.push(value);
+        self.sift_up(self.data.len() - 1);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let ret = self.data.swap_remove(0);
+        self.sift_down(0);
+        Some(ret)
+    }
+
+    // Finds an element that the order can't tell apart from value, meaning neither can go above the other
+    pub fn find(&self, value: &T) -> Option<&T> {
+        self.search(value).map(|index| &self.data[index])
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.search(value).is_some()
+    }
+
+    // Removes an element that the order can't tell apart from value
+    pub fn remove(&mut self, value: &T) -> Option<T> {
+        let index = self.search(value)?;
+        let ret = self.data.swap_remove(index);
+
+        // Whatever moved into the hole might belong further up or further down
+        if index < self.data.len() {
+            self.sift_up(index);
+            self.sift_down(index);
+        }
+
+        Some(ret)
+    }
+
+    // Pops into a new Vec and reverses it so that the top is last, like Heap's. Sorting the triangle in place
+    // would need a sift_down that stops short of the end of the storage, which this one doesn't.
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let mut ret = Vec::with_capacity(self.data.len());
+        ret.extend(std::iter::from_fn(|| self.pop()));
+        ret.reverse();
+        ret
+    }
+
+    fn index(&self, row: usize, column: usize) -> Option<usize> {
+        let index = row_start(row) + column;
+        (column <= row && index < self.data.len()).then_some(index)
+    }
+
+    fn goes_above(&self, left: usize, right: usize) -> bool {
+        self.order.left_can_go_above(&self.data[left], &self.data[right])
+    }
+
+    fn sift_up(&mut self, mut index: usize) {
+        loop {
+            let row = row_of(index);
+            let column = index - row_start(row);
+            if row == 0 {
+                break;
+            }
+
+            // Swapping with the lower parent keeps the other parent above both of them
+            let left_parent = (column > 0).then(|| row_start(row - 1) + column - 1);
+            let right_parent = (column < row).then(|| row_start(row - 1) + column);
+            let parent = match (left_parent, right_parent) {
+                (Some(left), Some(right)) => if self.goes_above(left, right) { right } else { left },
+                (left, right) => left.or(right).unwrap(),
+            };
+
+            if !self.goes_above(index, parent) {
+                break;
+            }
+
+            self.data.swap(index, parent);
+            index = parent;
+        }
+    }
+
+    fn sift_down(&mut self, mut index: usize) {
+        loop {
+            let row = row_of(index);
+            let column = index - row_start(row);
+
+            let child = match (self.index(row + 1, column), self.index(row + 1, column + 1)) {
+                (Some(left), Some(right)) => if self.goes_above(right, left) { right } else { left },
+                (Some(left), None) => left,
+                _ => break,
+            };
+
+            if !self.goes_above(child, index) {
+                break;
+            }
+
+            self.data.swap(index, child);
+            index = child;
+        }
+    }
+
+    fn search(&self, value: &T) -> Option<usize> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        // Work in the tilted grid, where down is the diagonal that keeps the column and right is the one
+        // that increases it. Moving down or right only ever goes down the heap. Start from the bottom of
+        // the first column.
+        let mut down = row_of(self.data.len() - 1);
+        let mut right = 0;
+
+        loop {
+            let index = match self.index(down + right, right) {
+                Some(index) => index,
+                None => {
+                    // Off the end of the beap, which means the rest of this diagonal is missing too
+                    down = down.checked_sub(1)?;
+                    continue;
+                }
+            };
+
+            if self.order.left_can_go_above(&self.data[index], value) {
+                // Everything at or above this position on the diagonal is above the value too
+                right += 1;
+            } else if self.order.left_can_go_above(value, &self.data[index]) {
+                // Everything from here along the rest of this diagonal is below the value
+                down = down.checked_sub(1)?;
+            } else {
+                return Some(index);
+            }
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for Beap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for Beap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> FromIterator<T> for Beap<T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        Self::from_vec_and_cmp(iter.into_iter().collect(), Order::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MaxOrder;
+    use rand::prelude::*;
+    use std::cell::Cell;
+
+    fn is_beap<T, Order: HeapOrder<T>>(beap: &Beap<T, Order>) -> bool {
+        (0..beap.len()).all(|index| {
+            let row = row_of(index);
+            let column = index - row_start(row);
+            [beap.index(row + 1, column), beap.index(row + 1, column + 1)]
+                .into_iter()
+                .flatten()
+                .all(|child| !beap.goes_above(child, index))
+        })
+    }
+
+    #[test]
+    fn test_random_operations() {
+        let mut rng = thread_rng();
+        let mut beap = Beap::<_, MaxOrder<_>>::new();
+        let mut reference: Vec<u32> = Vec::new();
+
+        for _ in 0..5000 {
+            match rng.gen_range(0..4) {
+                0 | 1 => {
+                    let value = rng.gen_range(0..500);
+                    beap.insert(value);
+                    reference.push(value);
+                }
+                2 => {
+                    let value = rng.gen_range(0..500);
+                    let position = reference.iter().position(|other| *other == value);
+                    assert_eq!(beap.contains(&value), position.is_some());
+                    assert_eq!(beap.remove(&value), position.map(|position| reference.swap_remove(position)));
+                }
+                _ => {
+                    let expected = reference.iter().copied().max();
+                    assert_eq!(beap.pop(), expected);
+                    if let Some(expected) = expected {
+                        reference.swap_remove(reference.iter().position(|other| *other == expected).unwrap());
+                    }
+                }
+            }
+
+            assert_eq!(beap.len(), reference.len());
+        }
+
+        assert!(is_beap(&beap));
+        reference.sort();
+        assert_eq!(beap.into_sorted_vec(), reference);
+    }
+
+    #[test]
+    fn test_search_is_sublinear() {
+        let mut values: Vec<u32> = (0..10000).map(|value| 2 * value).collect();
+        values.shuffle(&mut thread_rng());
+
+        let comparisons = Cell::new(0);
+        let counting_order = |left: &u32, right: &u32| {
+            comparisons.set(comparisons.get() + 1);
+            left < right
+        };
+
+        let beap = Beap::from_vec_and_cmp(values, counting_order);
+        assert!(is_beap(&beap));
+
+        for value in [0, 1, 9999, 10000, 19998, 20001] {
+            comparisons.set(0);
+            assert_eq!(beap.contains(&value), value % 2 == 0 && value < 20000);
+
+            // The grid is about 141 on a side, and each step rules out a row or a column with at most two
+            // comparisons
+            assert!(comparisons.get() <= 4 * 142, "{} comparisons looking for {}", comparisons.get(), value);
+        }
+    }
+}
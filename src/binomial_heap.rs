@@ -0,0 +1,250 @@
+use crate::heap::HeapOrder;
+
+// A binomial heap is a forest of binomial trees with at most one tree of each rank, where a tree of rank k
+// has exactly 2^k nodes. The forest looks like the binary representation of the heap's length, and
+// melding two heaps works just like adding two binary numbers, linking equal rank trees as the carry.
+// Every operation is O(log n) in the worst case, not just amortized.
+#[derive(Debug, Clone)]
+struct Node<T> {
+    value: T,
+    // children[i] is always a tree of rank i
+    children: Vec<Box<Node<T>>>,
+}
+
+type Forest<T> = Vec<Option<Box<Node<T>>>>;
+
+#[derive(Debug, Clone)]
+pub struct BinomialHeap<T, Order: HeapOrder<T>> {
+    // trees[k] is the tree of rank k, if there is one
+    trees: Forest<T>,
+    len: usize,
+    order: Order,
+}
+
+fn link<T>(mut left: Box<Node<T>>, mut right: Box<Node<T>>, order: &impl HeapOrder<T>) -> Box<Node<T>> {
+    // Both trees have the same rank, so putting one under the other gives a tree of the next rank up
+    if order.left_can_go_above(&right.value, &left.value) {
+        std::mem::swap(&mut left, &mut right);
+    }
+
+    left.children.push(right);
+    left
+}
+
+impl<T, Order: HeapOrder<T>> BinomialHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            trees: Vec::new(),
+            len: 0,
+            order,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.trees.clear();
+        self.len = 0;
+    }
+
+    // Iterates over the elements in no particular order
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            stack: self.trees.iter().flatten().map(|tree| &**tree).collect(),
+            remaining: self.len,
+        }
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.top_rank().map(|rank| &self.trees[rank].as_ref().unwrap().value)
+    }
+
+    pub fn insert(&mut self, value: T) {
+        let node = Box::new(Node {
+            value,
+            children: Vec::new(),
+        });
+
+        self.merge_forest(vec![Some(node)]);
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let rank = self.top_rank()?;
+        let root = self.trees[rank].take().unwrap();
+        let Node { value, children } = *root;
+
+        // The children of a rank k tree are one tree of each rank below k, so they are a forest already
+        self.merge_forest(children.into_iter().map(Some).collect());
+        self.len -= 1;
+
+        Some(value)
+    }
+
+    // Moves everything out of other and into this heap in O(log n). This assumes both heaps were built with
+    // equivalent orders.
+    pub fn meld(&mut self, mut other: Self) {
+        self.append(&mut other);
+    }
+
+    pub fn append(&mut self, other: &mut Self) {
+        self.merge_forest(std::mem::take(&mut other.trees));
+        self.len += std::mem::take(&mut other.len);
+    }
+
+    pub fn into_iter_sorted(self) -> IntoIterSorted<T, Order> {
+        IntoIterSorted { heap: self }
+    }
+
+    fn top_rank(&self) -> Option<usize> {
+        let mut ret: Option<usize> = None;
+        for (rank, tree) in self.trees.iter().enumerate() {
+            if let Some(tree) = tree {
+                if ret.is_none_or(|best| self.order.left_can_go_above(&tree.value, &self.trees[best].as_ref().unwrap().value)) {
+                    ret = Some(rank);
+                }
+            }
+        }
+
+        ret
+    }
+
+    fn merge_forest(&mut self, mut other: Forest<T>) {
+        let ranks = self.trees.len().max(other.len());
+        self.trees.resize_with(ranks, || None);
+
+        // Binary addition, one rank at a time
+        let mut carry = None;
+        for rank in 0..ranks {
+            let incoming = other.get_mut(rank).and_then(Option::take);
+            let mut trees = [self.trees[rank].take(), incoming, carry.take()].into_iter().flatten();
+
+            match (trees.next(), trees.next(), trees.next()) {
+                (Some(first), Some(second), third) => {
+                    carry = Some(link(first, second, &self.order));
+                    self.trees[rank] = third;
+                }
+                (first, _, _) => self.trees[rank] = first,
+            }
+        }
+
+        self.trees.extend(carry.map(Some));
+        while let Some(None) = self.trees.last() {
+            self.trees.pop();
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for BinomialHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for BinomialHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> FromIterator<T> for BinomialHeap<T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+pub struct Iter<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.children.iter().map(|child| &**child));
+        self.remaining -= 1;
+
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> { }
+
+pub struct IntoIterSorted<T, Order: HeapOrder<T>> {
+    heap: BinomialHeap<T, Order>,
+}
+
+impl<T, Order: HeapOrder<T>> Iterator for IntoIterSorted<T, Order> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.heap.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl<T, Order: HeapOrder<T>> ExactSizeIterator for IntoIterSorted<T, Order> { }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{MaxOrder, MinOrder};
+    use rand::prelude::*;
+
+    #[test]
+    fn test_forest_shape() {
+        let heap: BinomialHeap<_, MinOrder<_>> = (0..13).collect();
+
+        // 13 is 0b1101, so there should be trees of rank 0, 2 and 3
+        let shape: Vec<_> = heap.trees.iter().map(Option::is_some).collect();
+        assert_eq!(shape, vec![true, false, true, true]);
+        assert_eq!(heap.iter().count(), 13);
+    }
+
+    #[test]
+    fn test_insert_pop() {
+        let mut values: Vec<u32> = (0..1000).collect();
+        values.shuffle(&mut thread_rng());
+
+        let heap: BinomialHeap<_, MinOrder<_>> = values.into_iter().collect();
+        assert_eq!(heap.into_iter_sorted().collect::<Vec<_>>(), (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_meld() {
+        let mut left: BinomialHeap<_, MaxOrder<_>> = (0..500).step_by(2).collect();
+        let right: BinomialHeap<_, MaxOrder<_>> = (1..500).step_by(2).collect();
+
+        left.meld(right);
+        assert_eq!(left.len(), 500);
+        assert_eq!(left.peek(), Some(&499));
+        assert_eq!(left.into_iter_sorted().collect::<Vec<_>>(), (0..500).rev().collect::<Vec<_>>());
+    }
+}
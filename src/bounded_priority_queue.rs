@@ -0,0 +1,171 @@
+use crate::double_priority_queue::DoublePriorityQueue;
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+// What a BoundedPriorityQueue does with a new key when it is already full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    // Turn the new key away
+    Reject,
+    // Make room by taking out the lowest priority key, unless the new key would be the lowest itself
+    EvictLowest,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PushResult<K, P> {
+    // The key was new and there was room for it
+    Inserted,
+    // The key was already there, so its priority was changed and this is the old one
+    Updated(P),
+    // The queue was full and this key was taken out to make room
+    Evicted(K, P),
+    // The queue was full and the new key didn't go in, so it is handed back
+    Rejected(K, P),
+}
+
+// A keyed priority queue that holds at most capacity keys, with the highest priority on top. Once it is
+// full a push of a new key either fails or pushes out the lowest priority key, depending on the policy,
+// and whichever pair didn't make it is returned so the caller can log or recycle it. Changing the priority
+// of a key that is already there never evicts anything. It sits on a DoublePriorityQueue, so the lowest
+// key is as cheap to find as the highest.
+pub struct BoundedPriorityQueue<K: Hash + Eq, P: Ord> {
+    queue: DoublePriorityQueue<K, P>,
+    capacity: usize,
+    policy: EvictionPolicy,
+}
+
+impl<K: Hash + Eq, P: Ord> BoundedPriorityQueue<K, P> {
+    pub fn new(capacity: usize, policy: EvictionPolicy) -> Self {
+        Self {
+            queue: DoublePriorityQueue::with_capacity(capacity),
+            capacity,
+            policy,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn policy(&self) -> EvictionPolicy {
+        self.policy
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.queue.len() >= self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    // Iterates over the keys and their priorities in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &P)> + '_ {
+        self.queue.iter()
+    }
+
+    pub fn peek(&self) -> Option<(&K, &P)> {
+        self.queue.peek_max()
+    }
+
+    // The key that would be evicted next
+    pub fn peek_lowest(&self) -> Option<(&K, &P)> {
+        self.queue.peek_min()
+    }
+
+    pub fn get_priority<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&P> where K: Borrow<Q> {
+        self.queue.get_priority(key)
+    }
+
+    pub fn contains_key<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        self.queue.contains_key(key)
+    }
+
+    pub fn push(&mut self, key: K, priority: P) -> PushResult<K, P> {
+        if self.queue.contains_key(&key) {
+            return PushResult::Updated(self.queue.push(key, priority).unwrap());
+        }
+
+        if !self.is_full() {
+            self.queue.push(key, priority);
+            return PushResult::Inserted;
+        }
+
+        match self.policy {
+            EvictionPolicy::EvictLowest if self.queue.peek_min().is_some_and(|(_, lowest)| *lowest < priority) => {
+                let (evicted_key, evicted_priority) = self.queue.pop_min().unwrap();
+                self.queue.push(key, priority);
+                PushResult::Evicted(evicted_key, evicted_priority)
+            }
+            _ => PushResult::Rejected(key, priority),
+        }
+    }
+
+    // Returns the old priority, or None and changes nothing if the key isn't there
+    pub fn change_priority<Q: Hash + Eq + ?Sized>(&mut self, key: &Q, priority: P) -> Option<P> where K: Borrow<Q> {
+        self.queue.change_priority(key, priority)
+    }
+
+    pub fn remove<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<(K, P)> where K: Borrow<Q> {
+        self.queue.remove(key)
+    }
+
+    pub fn pop(&mut self) -> Option<(K, P)> {
+        self.queue.pop_max()
+    }
+
+    // The top of the queue is last, same as Heap
+    pub fn into_sorted_vec(self) -> Vec<(K, P)> {
+        self.queue.into_sorted_vec()
+    }
+}
+
+impl<K: Hash + Eq + std::fmt::Debug, P: Ord + std::fmt::Debug> std::fmt::Debug for BoundedPriorityQueue<K, P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_evict_lowest() {
+        let mut queue = BoundedPriorityQueue::new(3, EvictionPolicy::EvictLowest);
+        assert_eq!(queue.push("a", 5), PushResult::Inserted);
+        assert_eq!(queue.push("b", 2), PushResult::Inserted);
+        assert_eq!(queue.push("c", 8), PushResult::Inserted);
+        assert!(queue.is_full());
+
+        assert_eq!(queue.push("d", 6), PushResult::Evicted("b", 2));
+        assert_eq!(queue.push("e", 1), PushResult::Rejected("e", 1));
+        // A tie with the lowest loses, so the older key stays
+        assert_eq!(queue.push("f", 5), PushResult::Rejected("f", 5));
+        assert_eq!(queue.push("a", 9), PushResult::Updated(5));
+
+        assert_eq!(queue.peek_lowest(), Some((&"d", &6)));
+        assert_eq!(queue.into_sorted_vec(), vec![("d", 6), ("c", 8), ("a", 9)]);
+    }
+
+    #[test]
+    fn test_reject() {
+        let mut queue = BoundedPriorityQueue::new(2, EvictionPolicy::Reject);
+        queue.push(1, 10);
+        queue.push(2, 20);
+        assert_eq!(queue.push(3, 30), PushResult::Rejected(3, 30));
+        assert_eq!(queue.push(1, 15), PushResult::Updated(10));
+
+        assert_eq!(queue.pop(), Some((2, 20)));
+        assert_eq!(queue.push(3, 30), PushResult::Inserted);
+        assert_eq!(queue.len(), 2);
+    }
+}
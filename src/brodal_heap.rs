@@ -0,0 +1,407 @@
+use crate::heap::HeapOrder;
+
+// A heap with worst case, not amortized, bounds: O(1) insert, meld and peek and O(log n) pop. This is the
+// Brodal and Okasaki construction, which gets the same bounds as Brodal's queue out of two much simpler
+// layers, so nothing ever has to pay for earlier cheap operations with a sudden expensive one.
+//
+// The inner layer is a skew binomial heap. It is a list of trees in increasing rank, except that the two
+// smallest trees are allowed to share a rank. Inserting either makes a new rank 0 tree, or, if the two
+// smallest trees share a rank, skew links them together under the new element, which never cascades, so
+// insert is O(1) in the worst case. Trees are binomial trees whose nodes carry a short list of extra
+// elements that didn't win a skew link, which get inserted again when their node is popped.
+//
+// The outer layer bootstraps that into O(1) meld. A node is a value together with a skew binomial heap of
+// nodes that can't go above it, so the top is always at hand. Melding two nodes compares them and inserts
+// the loser into the winner's queue, which is a single O(1) skew insert. Popping takes the best node out
+// of the top's queue and melds the two queues together, which is O(log n).
+struct Tree<E> {
+    rank: usize,
+    root: E,
+    extra: Vec<E>,
+    // Highest rank last
+    children: Vec<Tree<E>>,
+}
+
+// The trees of a skew binomial heap, with the smallest rank last so that insert works on the end
+struct Queue<E> {
+    trees: Vec<Tree<E>>,
+}
+
+fn link<E>(left: Tree<E>, right: Tree<E>, above: &impl Fn(&E, &E) -> bool) -> Tree<E> {
+    let (mut parent, child) = if above(&right.root, &left.root) { (right, left) } else { (left, right) };
+    parent.rank += 1;
+    parent.children.push(child);
+    parent
+}
+
+impl<E> Queue<E> {
+    fn new() -> Self {
+        Self { trees: Vec::new() }
+    }
+
+    fn insert(&mut self, value: E, above: &impl Fn(&E, &E) -> bool) {
+        let len = self.trees.len();
+        if len >= 2 && self.trees[len - 1].rank == self.trees[len - 2].rank {
+            let first = self.trees.pop().unwrap();
+            let second = self.trees.pop().unwrap();
+
+            let tree = if !above(&first.root, &value) && !above(&second.root, &value) {
+                Tree {
+                    rank: first.rank + 1,
+                    root: value,
+                    extra: Vec::new(),
+                    children: vec![first, second],
+                }
+            } else {
+                let mut tree = link(first, second, above);
+                tree.extra.push(value);
+                tree
+            };
+
+            self.trees.push(tree);
+        } else {
+            self.trees.push(Tree {
+                rank: 0,
+                root: value,
+                extra: Vec::new(),
+                children: Vec::new(),
+            });
+        }
+    }
+
+    fn top_index(&self, above: &impl Fn(&E, &E) -> bool) -> Option<usize> {
+        let mut ret: Option<usize> = None;
+        for (index, tree) in self.trees.iter().enumerate() {
+            if ret.is_none_or(|best| above(&tree.root, &self.trees[best].root)) {
+                ret = Some(index);
+            }
+        }
+
+        ret
+    }
+
+    fn pop(&mut self, above: &impl Fn(&E, &E) -> bool) -> Option<E> {
+        let index = self.top_index(above)?;
+        let Tree { root, extra, children, .. } = self.trees.remove(index);
+
+        let children = Queue { trees: children.into_iter().rev().collect() };
+        self.meld(children, above);
+        for value in extra {
+            self.insert(value, above);
+        }
+
+        Some(root)
+    }
+
+    fn meld(&mut self, other: Queue<E>, above: &impl Fn(&E, &E) -> bool) {
+        // Binary addition again, once each side has had its one allowed pair of equal ranks linked
+        let mut left = std::mem::take(&mut self.trees);
+        let mut right = other.trees;
+        normalize(&mut left, above);
+        normalize(&mut right, above);
+
+        let mut ascending = Vec::with_capacity(left.len() + right.len());
+        let mut carry: Option<Tree<E>> = None;
+        loop {
+            let rank = [left.last(), right.last(), carry.as_ref()].into_iter().flatten().map(|tree| tree.rank).min();
+            let Some(rank) = rank else { break };
+
+            let mut trees = [
+                left.pop_if(|tree| tree.rank == rank),
+                right.pop_if(|tree| tree.rank == rank),
+                carry.take_if(|tree| tree.rank == rank),
+            ]
+            .into_iter()
+            .flatten();
+
+            match (trees.next(), trees.next(), trees.next()) {
+                (Some(first), Some(second), third) => {
+                    ascending.extend(third);
+                    carry = Some(link(first, second, above));
+                }
+                (first, _, _) => ascending.extend(first),
+            }
+        }
+
+        ascending.reverse();
+        self.trees = ascending;
+    }
+}
+
+fn normalize<E>(trees: &mut Vec<Tree<E>>, above: &impl Fn(&E, &E) -> bool) {
+    let len = trees.len();
+    if len >= 2 && trees[len - 1].rank == trees[len - 2].rank {
+        let first = trees.pop().unwrap();
+        let second = trees.pop().unwrap();
+        let mut tree = link(first, second, above);
+
+        // Every other tree has a rank of its own, but the new one can match the next tree up, and linking
+        // those can match the one after that, so this carries on up through however many ranks follow in a row
+        while let Some(next) = trees.pop_if(|next| next.rank == tree.rank) {
+            tree = link(tree, next, above);
+        }
+
+        trees.push(tree);
+    }
+}
+
+struct Node<T> {
+    value: T,
+    queue: Queue<Node<T>>,
+}
+
+pub struct BrodalHeap<T, Order: HeapOrder<T>> {
+    root: Option<Node<T>>,
+    len: usize,
+    order: Order,
+}
+
+impl<T, Order: HeapOrder<T>> BrodalHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            root: None,
+            len: 0,
+            order,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        // Nodes nest inside each other as deep as the heap is long in the worst case, so take them apart
+        // without recursing
+        let mut nodes: Vec<Node<T>> = self.root.take().into_iter().collect();
+        let mut trees: Vec<Tree<Node<T>>> = Vec::new();
+        loop {
+            while let Some(Tree { root, extra, children, .. }) = trees.pop() {
+                trees.extend(children);
+                nodes.push(root);
+                nodes.extend(extra);
+            }
+
+            match nodes.pop() {
+                Some(node) => trees.extend(node.queue.trees),
+                None => break,
+            }
+        }
+
+        self.len = 0;
+    }
+
+    // Iterates over the elements in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        let mut nodes: Vec<&Node<T>> = self.root.iter().collect();
+        let mut trees: Vec<&Tree<Node<T>>> = Vec::new();
+
+        std::iter::from_fn(move || {
+            while let Some(tree) = trees.pop() {
+                trees.extend(tree.children.iter());
+                nodes.push(&tree.root);
+                nodes.extend(tree.extra.iter());
+            }
+
+            let node = nodes.pop()?;
+            trees.extend(node.queue.trees.iter());
+            Some(&node.value)
+        })
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.root.as_ref().map(|root| &root.value)
+    }
+
+    pub fn insert(&mut self, value: T) {
+        let node = Node {
+            value,
+            queue: Queue::new(),
+        };
+
+        self.meld_node(node);
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let Node { value, mut queue } = self.root.take()?;
+        let above = |left: &Node<T>, right: &Node<T>| self.order.left_can_go_above(&left.value, &right.value);
+
+        if let Some(mut top) = queue.pop(&above) {
+            top.queue.meld(queue, &above);
+            self.root = Some(top);
+        }
+
+        self.len -= 1;
+        Some(value)
+    }
+
+    // Moves everything out of other and into this heap in O(1). This assumes both heaps were built with
+    // equivalent orders.
+    pub fn meld(&mut self, mut other: Self) {
+        self.append(&mut other);
+    }
+
+    pub fn append(&mut self, other: &mut Self) {
+        if let Some(node) = other.root.take() {
+            self.meld_node(node);
+            self.len += std::mem::take(&mut other.len);
+        }
+    }
+
+    pub fn into_iter_sorted(self) -> IntoIterSorted<T, Order> {
+        IntoIterSorted { heap: self }
+    }
+
+    fn meld_node(&mut self, node: Node<T>) {
+        let above = |left: &Node<T>, right: &Node<T>| self.order.left_can_go_above(&left.value, &right.value);
+        self.root = Some(match self.root.take() {
+            Some(root) => {
+                let (mut winner, loser) = if above(&node, &root) { (node, root) } else { (root, node) };
+                winner.queue.insert(loser, &above);
+                winner
+            }
+            None => node,
+        });
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Drop for BrodalHeap<T, Order> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for BrodalHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: std::fmt::Debug, Order: HeapOrder<T>> std::fmt::Debug for BrodalHeap<T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for BrodalHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> FromIterator<T> for BrodalHeap<T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+pub struct IntoIterSorted<T, Order: HeapOrder<T>> {
+    heap: BrodalHeap<T, Order>,
+}
+
+impl<T, Order: HeapOrder<T>> Iterator for IntoIterSorted<T, Order> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.heap.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl<T, Order: HeapOrder<T>> ExactSizeIterator for IntoIterSorted<T, Order> { }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{MaxOrder, MinOrder};
+    use rand::prelude::*;
+
+    #[test]
+    fn test_random_operations() {
+        let mut rng = thread_rng();
+        let mut heap = BrodalHeap::<_, MinOrder<_>>::new();
+        let mut reference = Vec::new();
+
+        for _ in 0..10000 {
+            if rng.gen_bool(0.6) {
+                let value = rng.gen_range(0..1000);
+                heap.insert(value);
+                reference.push(value);
+                reference.sort_by(|left, right| right.cmp(left));
+            } else {
+                assert_eq!(heap.pop(), reference.pop());
+            }
+
+            assert_eq!(heap.len(), reference.len());
+            assert_eq!(heap.peek(), reference.last());
+        }
+
+        assert_eq!(heap.iter().count(), reference.len());
+    }
+
+    #[test]
+    fn test_meld() {
+        let mut left: BrodalHeap<_, MaxOrder<_>> = (0..500).step_by(2).collect();
+        let right: BrodalHeap<_, MaxOrder<_>> = (1..500).step_by(2).collect();
+
+        left.meld(right);
+        assert_eq!(left.len(), 500);
+        assert_eq!(left.peek(), Some(&499));
+        assert_eq!(left.into_iter_sorted().collect::<Vec<_>>(), (0..500).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_normalize_cascade() {
+        let above = |left: &u32, right: &u32| left < right;
+        let mut values = 0..;
+        let mut tree = |rank| {
+            let mut trees: Vec<Tree<u32>> = Vec::new();
+            for value in values.by_ref().take(1 << rank as u32) {
+                let mut tree = Tree { rank: 0, root: value, extra: Vec::new(), children: Vec::new() };
+                while let Some(next) = trees.pop_if(|next| next.rank == tree.rank) {
+                    tree = link(tree, next, &above);
+                }
+
+                trees.push(tree);
+            }
+
+            trees.pop().unwrap()
+        };
+
+        // The pair of rank 0 trees links into rank 1, which runs into the rank 1 tree, and so on up to rank 4
+        let mut trees: Vec<Tree<u32>> = [3, 2, 1, 0, 0].into_iter().map(&mut tree).collect();
+        normalize(&mut trees, &above);
+        assert_eq!(trees.iter().map(|tree| tree.rank).collect::<Vec<_>>(), [4]);
+
+        let mut queue = Queue { trees };
+        let popped: Vec<u32> = std::iter::from_fn(|| queue.pop(&above)).collect();
+        assert_eq!(popped, (0..16).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_deep_drop() {
+        // Each new top nests the old top one level deeper
+        let heap: BrodalHeap<_, MinOrder<_>> = (0..1_000_000).rev().collect();
+        assert_eq!(heap.peek(), Some(&0));
+        drop(heap);
+    }
+}
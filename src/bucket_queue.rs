@@ -0,0 +1,140 @@
+use std::collections::VecDeque;
+
+// A bucket queue is a priority queue for small integer priorities: one bucket per priority, plus a bitmap
+// of which buckets have anything in them. Pushing appends to a bucket and popping takes from the first
+// occupied bucket, which the bitmap finds a word at a time, so both are O(1) for a fixed range. Lower
+// priorities come out first, and elements with the same priority come out in the order they were pushed.
+#[derive(Debug, Clone)]
+pub struct BucketQueue<T> {
+    buckets: Vec<VecDeque<T>>,
+    occupied: Vec<u64>,
+    len: usize,
+}
+
+impl<T> BucketQueue<T> {
+    // Priorities must be below range
+    pub fn with_range(range: usize) -> Self {
+        Self {
+            buckets: (0..range).map(|_| VecDeque::new()).collect(),
+            occupied: vec![0; range.div_ceil(64)],
+            len: 0,
+        }
+    }
+
+    pub fn range(&self) -> usize {
+        self.buckets.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.iter_mut().for_each(VecDeque::clear);
+        self.occupied.fill(0);
+        self.len = 0;
+    }
+
+    // Iterates over the priorities and elements in priority order
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> + '_ {
+        self.buckets.iter().enumerate().flat_map(|(priority, bucket)| bucket.iter().map(move |value| (priority, value)))
+    }
+
+    pub fn peek(&self) -> Option<(usize, &T)> {
+        let priority = self.top_priority()?;
+        self.buckets[priority].front().map(|value| (priority, value))
+    }
+
+    pub fn insert(&mut self, priority: usize, value: T) {
+        assert!(priority < self.range(), "Cannot insert priority {} into a bucket queue with range {}", priority, self.range());
+
+        self.buckets[priority].push_back(value);
+        self.occupied[priority / 64] |= 1 << (priority % 64);
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<(usize, T)> {
+        let priority = self.top_priority()?;
+        let bucket = &mut self.buckets[priority];
+        let value = bucket.pop_front().unwrap();
+
+        if bucket.is_empty() {
+            self.occupied[priority / 64] &= !(1 << (priority % 64));
+        }
+
+        self.len -= 1;
+        Some((priority, value))
+    }
+
+    // Takes every element of the given priority out of the queue, in the order they were pushed
+    pub fn drain_priority(&mut self, priority: usize) -> std::collections::vec_deque::IntoIter<T> {
+        let bucket = std::mem::take(&mut self.buckets[priority]);
+        self.occupied[priority / 64] &= !(1 << (priority % 64));
+        self.len -= bucket.len();
+        bucket.into_iter()
+    }
+
+    fn top_priority(&self) -> Option<usize> {
+        self.occupied
+            .iter()
+            .enumerate()
+            .find(|(_, word)| **word != 0)
+            .map(|(index, word)| index * 64 + word.trailing_zeros() as usize)
+    }
+}
+
+impl<T> Extend<(usize, T)> for BucketQueue<T> {
+    fn extend<IntoIter: IntoIterator<Item = (usize, T)>>(&mut self, iter: IntoIter) {
+        for (priority, value) in iter {
+            self.insert(priority, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_insert_pop() {
+        let mut rng = thread_rng();
+        let mut queue = BucketQueue::with_range(256);
+        let mut reference = Vec::new();
+
+        for sequence in 0..5000 {
+            if rng.gen_bool(0.6) {
+                let priority = rng.gen_range(0..256);
+                queue.insert(priority, sequence);
+                reference.push((priority, sequence));
+                reference.sort_by(|left, right| right.cmp(left));
+            } else {
+                // Ties come out first in first out, which is the same as sorting by sequence
+                assert_eq!(queue.pop(), reference.pop());
+            }
+
+            assert_eq!(queue.len(), reference.len());
+            assert_eq!(queue.peek().map(|(priority, value)| (priority, *value)), reference.last().copied());
+        }
+    }
+
+    #[test]
+    fn test_drain_priority() {
+        let mut queue = BucketQueue::with_range(100);
+        queue.extend([(70, 'a'), (3, 'b'), (70, 'c'), (99, 'd')]);
+
+        assert_eq!(queue.drain_priority(70).collect::<String>(), "ac");
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.iter().collect::<Vec<_>>(), vec![(3, &'b'), (99, &'d')]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_out_of_range() {
+        BucketQueue::with_range(10).insert(10, ());
+    }
+}
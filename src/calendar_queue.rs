@@ -0,0 +1,280 @@
+// A calendar queue (Brown, 1988) is laid out like a desk calendar: time is split into days of a fixed
+// width, and day d lives in bucket d mod the number of buckets, so each bucket holds that day from every
+// year. Popping looks at today's bucket and moves on a day at a time until it finds an event that is due,
+// and if a whole year goes by without one it jumps straight to the earliest event instead. With the day
+// width tuned to the spacing of the events, each bucket has only a couple of events in it and insert and
+// pop are O(1) on average, which beats a binary heap once the set of future events gets large.
+//
+// The number of buckets doubles when the queue has more than two events per bucket and halves when it has
+// less than one per two buckets. Each resize estimates a new day width from the spacing of the earliest
+// events. Events at the same time come out in the order they were inserted.
+pub trait Timestamp: Copy + PartialOrd {
+    fn as_f64(self) -> f64;
+}
+
+impl Timestamp for f64 {
+    fn as_f64(self) -> f64 {
+        self
+    }
+}
+
+impl Timestamp for u64 {
+    fn as_f64(self) -> f64 {
+        self as f64
+    }
+}
+
+// How many of the earliest events are used to estimate the day width on a resize
+const WIDTH_SAMPLE: usize = 25;
+
+// Days are kept well inside an i64 so that walking a year on from any of them can't overflow. Events too
+// far out to have a day of their own all share the last one, where they are still kept in order.
+const MAX_DAY: f64 = (1u64 << 62) as f64;
+
+#[derive(Debug, Clone)]
+pub struct CalendarQueue<K: Timestamp, T> {
+    // Each bucket is sorted latest first, so the next event in a bucket is at the end
+    buckets: Vec<Vec<(K, T)>>,
+    width: f64,
+    // The day the queue has got up to. Nothing in the queue is earlier than this.
+    day: i64,
+    len: usize,
+}
+
+impl<K: Timestamp, T> CalendarQueue<K, T> {
+    pub fn new() -> Self {
+        Self::with_width(1.0)
+    }
+
+    // Starts with a day width suited to the expected spacing of events. It gets re-estimated as the queue
+    // grows and shrinks, so this only has to be roughly right.
+    pub fn with_width(width: f64) -> Self {
+        assert!(width > 0.0 && width.is_finite(), "Calendar queue day width must be positive, not {}", width);
+
+        Self {
+            buckets: vec![Vec::new(), Vec::new()],
+            width,
+            day: 0,
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.iter_mut().for_each(Vec::clear);
+        self.len = 0;
+    }
+
+    pub fn bucket_count(&self) -> usize {
+        self.buckets.len()
+    }
+
+    pub fn width(&self) -> f64 {
+        self.width
+    }
+
+    // Iterates over the events in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &T)> + '_ {
+        self.buckets.iter().flatten().map(|(key, value)| (key, value))
+    }
+
+    pub fn peek(&self) -> Option<(&K, &T)> {
+        let (index, _) = self.find_next()?;
+        self.buckets[index].last().map(|(key, value)| (key, value))
+    }
+
+    pub fn insert(&mut self, key: K, value: T) {
+        assert!(!key.as_f64().is_nan(), "Cannot insert a NaN timestamp into a calendar queue");
+
+        let day = self.day_of(key);
+        if self.len == 0 || day < self.day {
+            self.day = day;
+        }
+
+        self.insert_into_bucket(day, key, value);
+        self.len += 1;
+
+        if self.len > 2 * self.buckets.len() {
+            self.resize(2 * self.buckets.len());
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<(K, T)> {
+        let (index, day) = self.find_next()?;
+        self.day = day;
+
+        let ret = self.buckets[index].pop();
+        self.len -= 1;
+
+        if self.buckets.len() > 2 && self.len < self.buckets.len() / 2 {
+            self.resize(self.buckets.len() / 2);
+        }
+
+        ret
+    }
+
+    fn day_of(&self, key: K) -> i64 {
+        (key.as_f64() / self.width).floor().clamp(-MAX_DAY, MAX_DAY) as i64
+    }
+
+    fn bucket_of(&self, day: i64) -> usize {
+        day.rem_euclid(self.buckets.len() as i64) as usize
+    }
+
+    fn insert_into_bucket(&mut self, day: i64, key: K, value: T) {
+        // Going in before anything at the same time means it comes out after it
+        let index = self.bucket_of(day);
+        let bucket = &mut self.buckets[index];
+        let position = bucket.partition_point(|(other, _)| *other > key);
+        bucket.insert(position, (key, value));
+    }
+
+    fn find_next(&self) -> Option<(usize, i64)> {
+        if self.len == 0 {
+            return None;
+        }
+
+        // Walk through a year of days looking for one whose earliest event is due
+        for day in self.day..self.day.saturating_add(self.buckets.len() as i64) {
+            let index = self.bucket_of(day);
+            if let Some((key, _)) = self.buckets[index].last() {
+                if self.day_of(*key) <= day {
+                    return Some((index, day));
+                }
+            }
+        }
+
+        // Everything is more than a year away, so find the earliest event directly
+        let mut ret: Option<(usize, K)> = None;
+        for (index, bucket) in self.buckets.iter().enumerate() {
+            if let Some((key, _)) = bucket.last() {
+                if ret.is_none_or(|(_, best)| *key < best) {
+                    ret = Some((index, *key));
+                }
+            }
+        }
+
+        ret.map(|(index, key)| (index, self.day_of(key)))
+    }
+
+    fn resize(&mut self, bucket_count: usize) {
+        // Take the events out in the order they would be popped, so that re-inserting them keeps ties in order
+        let old_buckets = std::mem::replace(&mut self.buckets, (0..bucket_count).map(|_| Vec::new()).collect());
+        let events: Vec<(K, T)> = old_buckets.into_iter().flat_map(|bucket| bucket.into_iter().rev()).collect();
+
+        let mut times: Vec<f64> = events.iter().map(|(key, _)| key.as_f64()).collect();
+        let sample_len = times.len().min(WIDTH_SAMPLE);
+        if sample_len > 1 {
+            times.select_nth_unstable_by(sample_len - 1, f64::total_cmp);
+            let sample = &times[..sample_len];
+            let earliest = sample.iter().copied().fold(f64::INFINITY, f64::min);
+            let latest = sample.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+            // Aim for a few events per day
+            let width = 3.0 * (latest - earliest) / (sample_len - 1) as f64;
+            if width > 0.0 && width.is_finite() {
+                self.width = width;
+            }
+        }
+
+        self.day = events.iter().map(|(key, _)| self.day_of(*key)).min().unwrap_or(0);
+        for (key, value) in events {
+            self.insert_into_bucket(self.day_of(key), key, value);
+        }
+    }
+}
+
+impl<K: Timestamp, T> Default for CalendarQueue<K, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Timestamp, T> Extend<(K, T)> for CalendarQueue<K, T> {
+    fn extend<IntoIter: IntoIterator<Item = (K, T)>>(&mut self, iter: IntoIter) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: Timestamp, T> FromIterator<(K, T)> for CalendarQueue<K, T> {
+    fn from_iter<IntoIter: IntoIterator<Item = (K, T)>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_simulation() {
+        // Each popped event schedules new ones a random distance into the future, and the queue grows and
+        // then drains so that it resizes in both directions
+        let mut rng = thread_rng();
+        let mut queue = CalendarQueue::new();
+        let mut reference: Vec<(f64, u32)> = Vec::new();
+        let mut now = 0.0;
+
+        for step in 0..20000u32 {
+            if step < 10000 || rng.gen_bool(0.1) {
+                let time = now + rng.gen_range(0.0..100.0);
+                queue.insert(time, step);
+                reference.push((time, step));
+                reference.sort_by(|left, right| right.0.total_cmp(&left.0).then(right.1.cmp(&left.1)));
+            }
+
+            if rng.gen_bool(0.5) || step >= 10000 {
+                let popped = queue.pop();
+                assert_eq!(popped, reference.pop());
+                if let Some((time, _)) = popped {
+                    now = time;
+                }
+            }
+
+            assert_eq!(queue.len(), reference.len());
+        }
+
+        assert!(queue.bucket_count() < 8);
+    }
+
+    #[test]
+    fn test_out_of_order_and_ties() {
+        let mut queue: CalendarQueue<u64, char> = [(10, 'a'), (1_000_000, 'b'), (10, 'c'), (3, 'd')].into_iter().collect();
+        assert_eq!(queue.pop(), Some((3, 'd')));
+        assert_eq!(queue.pop(), Some((10, 'a')));
+
+        // Inserting something earlier than the last popped event is allowed, it just comes out next
+        queue.insert(5, 'e');
+        assert_eq!(queue.peek(), Some((&5, &'e')));
+
+        let rest: String = std::iter::from_fn(|| queue.pop()).map(|(_, value)| value).collect();
+        assert_eq!(rest, "ecb");
+    }
+
+    #[test]
+    fn test_extreme_keys() {
+        let mut queue: CalendarQueue<u64, char> = [(u64::MAX, 'a'), (u64::MAX - 4096, 'b'), (0, 'c'), (u64::MAX, 'd')].into_iter().collect();
+        let popped: String = std::iter::from_fn(|| queue.pop()).map(|(_, value)| value).collect();
+        assert_eq!(popped, "cbad");
+
+        let mut queue = CalendarQueue::with_width(1e-300);
+        for (time, value) in [(f64::MAX, 'a'), (f64::INFINITY, 'b'), (-f64::MAX, 'c'), (1e300, 'd'), (f64::NEG_INFINITY, 'e')] {
+            queue.insert(time, value);
+        }
+
+        let popped: String = std::iter::from_fn(|| queue.pop()).map(|(_, value)| value).collect();
+        assert_eq!(popped, "ecdab");
+    }
+}
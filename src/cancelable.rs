@@ -0,0 +1,79 @@
+// A queue whose entries can be cancelled after they have gone in, as schedulers and timers need when most
+// entries are withdrawn before they ever come out. insert_with_handle hands back a token for the entry, and
+// cancel takes it out again. IndexedHeap, FibonacciHeap and AddressablePairingHeap cancel in O(log n), and
+// LazyDeleteHeap cancels in O(1) by leaving the entry in place to be swept out later, so code written
+// against this trait can pick whichever suits how often it cancels.
+pub trait CancelableQueue<T> {
+    type Handle: Copy + Eq;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn insert_with_handle(&mut self, value: T) -> Self::Handle;
+
+    // Returns false if the entry has already been popped or cancelled
+    fn cancel(&mut self, handle: Self::Handle) -> bool;
+
+    fn is_pending(&self, handle: Self::Handle) -> bool;
+    fn peek(&self) -> Option<&T>;
+    fn pop(&mut self) -> Option<T>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fibonacci_heap::FibonacciHeap;
+    use crate::heap::MinOrder;
+    use crate::indexed_heap::IndexedHeap;
+    use crate::lazy_delete_heap::LazyDeleteHeap;
+    use crate::pairing_heap::AddressablePairingHeap;
+    use rand::prelude::*;
+
+    fn check_random_operations<Q: CancelableQueue<u32>>(queue: &mut Q) {
+        let mut rng = thread_rng();
+        let mut pending: Vec<(Q::Handle, u32)> = Vec::new();
+        let mut finished: Vec<Q::Handle> = Vec::new();
+
+        for _ in 0..5000 {
+            match rng.gen_range(0..5) {
+                0 | 1 => {
+                    let value = rng.gen_range(0..1000);
+                    pending.push((queue.insert_with_handle(value), value));
+                }
+                2 if !pending.is_empty() => {
+                    let (handle, _) = pending.swap_remove(rng.gen_range(0..pending.len()));
+                    assert!(queue.cancel(handle));
+                    finished.push(handle);
+                }
+                3 if !finished.is_empty() => {
+                    let handle = finished[rng.gen_range(0..finished.len())];
+                    assert!(!queue.is_pending(handle));
+                    assert!(!queue.cancel(handle));
+                }
+                _ => {
+                    let expected = pending.iter().map(|(_, value)| *value).min();
+                    assert_eq!(queue.pop(), expected);
+
+                    // Several entries can share the value, and any of them could have been the one popped
+                    if let Some(position) = pending.iter().position(|(handle, value)| Some(*value) == expected && !queue.is_pending(*handle)) {
+                        finished.push(pending.swap_remove(position).0);
+                    }
+                }
+            }
+
+            assert_eq!(queue.len(), pending.len());
+            assert_eq!(queue.peek().copied(), pending.iter().map(|(_, value)| *value).min());
+        }
+    }
+
+    #[test]
+    fn test_implementations() {
+        check_random_operations(&mut IndexedHeap::<_, MinOrder<_>>::new());
+        check_random_operations(&mut FibonacciHeap::<_, MinOrder<_>>::new());
+        check_random_operations(&mut LazyDeleteHeap::<_, MinOrder<_>>::new());
+        check_random_operations(&mut AddressablePairingHeap::<_, MinOrder<_>>::new());
+    }
+}
@@ -0,0 +1,253 @@
+use crate::heap::{Heap, HeapOrder};
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+// A multi-producer single-consumer channel that hands messages to the receiver in priority order rather
+// than the order they were sent, so whatever is most urgent gets looked at first however much is queued up
+// behind it. It works like std's mpsc channel and uses the same error types: recv blocks until there is a
+// message, and fails once the channel is empty and every sender has gone, while send fails once the
+// receiver has gone. Behind it is just a Heap under a Mutex, with a Condvar to wake the receiver.
+struct State<T, Order: HeapOrder<T>> {
+    heap: Heap<T, Order>,
+    senders: usize,
+    receiver: bool,
+}
+
+struct Shared<T, Order: HeapOrder<T>> {
+    state: Mutex<State<T, Order>>,
+    ready: Condvar,
+}
+
+impl<T, Order: HeapOrder<T>> Shared<T, Order> {
+    fn lock(&self) -> MutexGuard<'_, State<T, Order>> {
+        // Nothing runs user code while holding the lock apart from the order, and a panic in there can't
+        // lose messages, so there is no reason to give up on a poisoned channel
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+pub struct Sender<T, Order: HeapOrder<T>> {
+    shared: Arc<Shared<T, Order>>,
+}
+
+pub struct Receiver<T, Order: HeapOrder<T>> {
+    shared: Arc<Shared<T, Order>>,
+}
+
+pub fn priority_channel<T, Order: HeapOrder<T> + Default>() -> (Sender<T, Order>, Receiver<T, Order>) {
+    priority_channel_with_cmp(Order::default())
+}
+
+pub fn priority_channel_with_cmp<T, Order: HeapOrder<T>>(order: Order) -> (Sender<T, Order>, Receiver<T, Order>) {
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            heap: Heap::with_capacity_and_cmp(0, order),
+            senders: 1,
+            receiver: true,
+        }),
+        ready: Condvar::new(),
+    });
+
+    (Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+impl<T, Order: HeapOrder<T>> Sender<T, Order> {
+    // Hands the message back if the receiver has gone
+    pub fn send(&self, value: T) -> Result<(), SendError<T>> {
+        let mut state = self.shared.lock();
+        if !state.receiver {
+            return Err(SendError(value));
+        }
+
+        state.heap.insert(value);
+        drop(state);
+        self.shared.ready.notify_one();
+        Ok(())
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Clone for Sender<T, Order> {
+    fn clone(&self) -> Self {
+        self.shared.lock().senders += 1;
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Drop for Sender<T, Order> {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock();
+        state.senders -= 1;
+        if state.senders == 0 {
+            // Wake the receiver so that it can see there is nothing more coming
+            drop(state);
+            self.shared.ready.notify_all();
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T>> std::fmt::Debug for Sender<T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sender").finish_non_exhaustive()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Receiver<T, Order> {
+    // How many messages are waiting
+    pub fn len(&self) -> usize {
+        self.shared.lock().heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Waits for the top message, failing only once the channel is empty and every sender has gone
+    pub fn recv(&self) -> Result<T, RecvError> {
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(value) = state.heap.pop() {
+                return Ok(value);
+            }
+
+            if state.senders == 0 {
+                return Err(RecvError);
+            }
+
+            state = self.shared.ready.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        let mut state = self.shared.lock();
+        match state.heap.pop() {
+            Some(value) => Ok(value),
+            None if state.senders == 0 => Err(TryRecvError::Disconnected),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        // No deadline could be that far off, so it is plain recv, still giving up if the senders all go
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            return self.recv().map_err(|RecvError| RecvTimeoutError::Disconnected);
+        };
+
+        let mut state = self.shared.lock();
+        loop {
+            if let Some(value) = state.heap.pop() {
+                return Ok(value);
+            }
+
+            if state.senders == 0 {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(RecvTimeoutError::Timeout);
+            }
+
+            state = self.shared.ready.wait_timeout(state, deadline - now).unwrap_or_else(|poisoned| poisoned.into_inner()).0;
+        }
+    }
+
+    // Blocks for each message in turn, ending once the channel is empty and every sender has gone
+    pub fn iter(&self) -> Iter<'_, T, Order> {
+        Iter { receiver: self }
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Drop for Receiver<T, Order> {
+    fn drop(&mut self) {
+        // Anything still queued is dropped here rather than whenever the last sender goes
+        let mut state = self.shared.lock();
+        state.receiver = false;
+        state.heap.clear();
+    }
+}
+
+impl<T, Order: HeapOrder<T>> std::fmt::Debug for Receiver<T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Receiver").finish_non_exhaustive()
+    }
+}
+
+pub struct Iter<'a, T, Order: HeapOrder<T>> {
+    receiver: &'a Receiver<T, Order>,
+}
+
+impl<'a, T, Order: HeapOrder<T>> Iterator for Iter<'a, T, Order> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl<'a, T, Order: HeapOrder<T>> IntoIterator for &'a Receiver<T, Order> {
+    type Item = T;
+    type IntoIter = Iter<'a, T, Order>;
+
+    fn into_iter(self) -> Iter<'a, T, Order> {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MaxOrder;
+
+    #[test]
+    fn test_priority_order() {
+        let (sender, receiver) = priority_channel::<u32, MaxOrder<_>>();
+        for value in [3, 9, 1, 7] {
+            sender.send(value).unwrap();
+        }
+
+        assert_eq!(receiver.len(), 4);
+        assert_eq!(receiver.recv(), Ok(9));
+        assert_eq!(receiver.try_recv(), Ok(7));
+        assert_eq!(receiver.recv_timeout(Duration::from_millis(10)), Ok(3));
+        assert_eq!(receiver.recv_timeout(Duration::from_millis(10)), Ok(1));
+
+        // A timeout that overflows the deadline still gets a value that is there
+        sender.send(5).unwrap();
+        assert_eq!(receiver.recv_timeout(Duration::MAX), Ok(5));
+        assert_eq!(receiver.recv_timeout(Duration::from_millis(10)), Err(RecvTimeoutError::Timeout));
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Empty));
+
+        drop(sender);
+        assert_eq!(receiver.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(receiver.recv(), Err(RecvError));
+    }
+
+    #[test]
+    fn test_threads() {
+        let (sender, receiver) = priority_channel::<u32, MaxOrder<_>>();
+        let senders: Vec<_> = (0..4).map(|_| sender.clone()).collect();
+        drop(sender);
+
+        std::thread::scope(|scope| {
+            for (thread, sender) in senders.into_iter().enumerate() {
+                scope.spawn(move || {
+                    for value in 0..250 {
+                        sender.send(thread as u32 * 250 + value).unwrap();
+                    }
+                });
+            }
+        });
+
+        // Everything was sent before the receiver looked, so it all comes out in order
+        let received: Vec<u32> = receiver.iter().collect();
+        assert_eq!(received, (0..1000).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_receiver_gone() {
+        let (sender, receiver) = priority_channel::<String, MaxOrder<_>>();
+        drop(receiver);
+        assert_eq!(sender.send("lost".to_string()), Err(SendError("lost".to_string())));
+    }
+}
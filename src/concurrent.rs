@@ -0,0 +1,532 @@
+use crate::heap::HeapOrder;
+#[cfg(feature = "epoch")]
+use crossbeam_epoch::Guard;
+use rand::prelude::*;
+use std::cell::UnsafeCell;
+#[cfg(feature = "epoch")]
+use std::collections::VecDeque;
+use std::ptr::null_mut;
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicU64, AtomicUsize};
+#[cfg(feature = "epoch")]
+use std::sync::Arc;
+
+// A lock-free priority queue, after Lindén and Jonsson's skiplist priority queue (2013). Insert and pop
+// are both linearizable and neither ever takes a lock, so a thread that is descheduled in the middle of
+// one can't hold anything else up, which is what ConcurrentHeap's shard locks can't promise.
+//
+// The entries are kept in a skiplist ordered by key, with ties in the order they were inserted. Popping
+// marks the pointer into the first live node on the bottom level, which makes deleted nodes a prefix of the
+// list that inserts can never get in front of, so every pop takes the real top rather than something
+// close to it. Once a pop has walked past enough deleted nodes it moves the head up past them in one go,
+// rather than every pop unlinking its own node from the front where they would all be fighting over it.
+// The upper levels are only a way of finding where an insert goes, and deleted nodes are unlinked from
+// them the usual way for a lock-free skiplist, by marking their own pointers and cutting them out from
+// the node before.
+//
+// Keys are compared by other threads for as long as their node is in the list, so pop hands back a clone
+// of the key and the node keeps the original. The memory for nodes that have been popped is only given
+// back when the queue is dropped, unless the epoch feature is on. Then every insert and pop pins itself
+// with crossbeam-epoch, so callers never need to know about epochs, and the pop that moves the head
+// retires the nodes it moved past. crossbeam-epoch only says when no thread can still be looking at them,
+// and the queue frees them itself the next time the head moves, so keys never have to outlive the queue.
+const MAX_HEIGHT: usize = 32;
+
+// How many deleted nodes a pop walks past before it moves the head up past them
+const MAX_DELETED_PREFIX: usize = 32;
+
+struct Node<K, V> {
+    // Only None for the head
+    key: Option<K>,
+    // Taken by whichever pop deletes the node
+    value: UnsafeCell<Option<V>>,
+    sequence: u64,
+    // Set until the insert has finished linking the node into the upper levels
+    inserting: AtomicBool,
+    next: Box<[AtomicPtr<Node<K, V>>]>,
+}
+
+impl<K, V> Node<K, V> {
+    fn new(key: Option<K>, value: Option<V>, sequence: u64, height: usize) -> Self {
+        Self {
+            key,
+            value: UnsafeCell::new(value),
+            sequence,
+            inserting: AtomicBool::new(true),
+            next: (0..height).map(|_| AtomicPtr::new(null_mut())).collect(),
+        }
+    }
+}
+
+// Nodes are at least pointer aligned, which leaves the bottom bit of a pointer to them free for a mark. On
+// the bottom level a mark means the node pointed to is deleted, and on the upper levels it means the node
+// the pointer belongs to is.
+fn is_marked<T>(pointer: *mut T) -> bool {
+    pointer.addr() & 1 != 0
+}
+
+fn marked<T>(pointer: *mut T) -> *mut T {
+    pointer.map_addr(|address| address | 1)
+}
+
+fn unmarked<T>(pointer: *mut T) -> *mut T {
+    pointer.map_addr(|address| address & !1)
+}
+
+fn random_height() -> usize {
+    // Each level has a quarter of the nodes of the one below
+    1 + (thread_rng().next_u32().trailing_zeros() as usize / 2).min(MAX_HEIGHT - 1)
+}
+
+pub struct LockFreePriorityQueue<K, V, Order: HeapOrder<K>> {
+    head: Box<Node<K, V>>,
+    order: Order,
+    sequence: AtomicU64,
+    len: AtomicUsize,
+    // Only one pop at a time moves the head
+    moving_head: AtomicBool,
+    // The first node the head ever moved past, so that drop can still get to everything
+    #[cfg(not(feature = "epoch"))]
+    oldest: AtomicPtr<Node<K, V>>,
+    // Nodes the head has moved past, oldest first, for freeing once nobody can be looking at them. Only
+    // ever touched by the pop that is moving the head.
+    #[cfg(feature = "epoch")]
+    retired: UnsafeCell<VecDeque<Retired<K, V>>>,
+}
+
+// The nodes from first up to end, which are safe to free once freeable is set
+#[cfg(feature = "epoch")]
+struct Retired<K, V> {
+    first: *mut Node<K, V>,
+    end: *mut Node<K, V>,
+    freeable: Arc<AtomicBool>,
+}
+
+// What an insert or pop holds for as long as it might be looking at nodes. Without the epoch feature nodes
+// are never freed early, so there is nothing to hold.
+#[cfg(not(feature = "epoch"))]
+struct Guard;
+
+// Keys are read by every thread and values are handed from one thread to another
+unsafe impl<K: Send + Sync, V: Send, Order: HeapOrder<K> + Send> Send for LockFreePriorityQueue<K, V, Order> { }
+unsafe impl<K: Send + Sync, V: Send, Order: HeapOrder<K> + Sync> Sync for LockFreePriorityQueue<K, V, Order> { }
+
+type Path<K, V> = [*mut Node<K, V>; MAX_HEIGHT];
+
+impl<K, V, Order: HeapOrder<K>> LockFreePriorityQueue<K, V, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            head: Box::new(Node::new(None, None, 0, MAX_HEIGHT)),
+            order,
+            sequence: AtomicU64::new(0),
+            len: AtomicUsize::new(0),
+            moving_head: AtomicBool::new(false),
+            #[cfg(not(feature = "epoch"))]
+            oldest: AtomicPtr::new(null_mut()),
+            #[cfg(feature = "epoch")]
+            retired: UnsafeCell::new(VecDeque::new()),
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    // Only a snapshot, since other threads can be inserting and popping at the same time
+    pub fn len(&self) -> usize {
+        self.len.load(SeqCst)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let height = random_height();
+        let sequence = self.sequence.fetch_add(1, SeqCst);
+        let node = Box::into_raw(Box::new(Node::new(Some(key), Some(value), sequence, height)));
+
+        // Count it before it goes in, so that a pop that gets to it first can't take the count below zero
+        self.len.fetch_add(1, SeqCst);
+
+        let _guard = self.pin();
+        unsafe {
+            // The node can't be freed while it is still inserting, so the key can be borrowed from it
+            let key = (*node).key.as_ref().unwrap();
+            let mut preds: Path<K, V> = [null_mut(); MAX_HEIGHT];
+            let mut succs: Path<K, V> = [null_mut(); MAX_HEIGHT];
+
+            // Linking it into the bottom level is what puts it in the queue
+            loop {
+                self.find(key, sequence, &mut preds, &mut succs);
+                (*node).next[0].store(succs[0], SeqCst);
+                if (*preds[0]).next[0].compare_exchange(succs[0], node, SeqCst, SeqCst).is_ok() {
+                    break;
+                }
+            }
+
+            // The rest is only to speed up finding things, so stop as soon as the node has been popped and
+            // its upper levels marked
+            'levels: for level in 1..height {
+                loop {
+                    let current = (*node).next[level].load(SeqCst);
+                    if is_marked(current) {
+                        break 'levels;
+                    }
+
+                    if current != succs[level] && (*node).next[level].compare_exchange(current, succs[level], SeqCst, SeqCst).is_err() {
+                        break 'levels;
+                    }
+
+                    if (*preds[level]).next[level].compare_exchange(succs[level], node, SeqCst, SeqCst).is_ok() {
+                        break;
+                    }
+
+                    self.find(key, sequence, &mut preds, &mut succs);
+                }
+            }
+
+            // The node may have been popped while this was linking it, after the pop had already unlinked
+            // it, in which case it needs unlinking again
+            if (1..height).any(|level| is_marked((*node).next[level].load(SeqCst))) {
+                self.find(key, sequence, &mut preds, &mut succs);
+            }
+
+            (*node).inserting.store(false, SeqCst);
+        }
+    }
+
+    // Pops the entry with the top key, returning a clone of the key since other threads may still be
+    // comparing against the original
+    pub fn pop(&self) -> Option<(K, V)> where K: Clone {
+        let guard = self.pin();
+        unsafe {
+            let head = self.head_ptr();
+            let observed_head = (*head).next[0].load(SeqCst);
+            let mut node = head;
+            let mut new_head: *mut Node<K, V> = null_mut();
+            let mut skipped = 0;
+
+            // Walk the deleted prefix, then try to mark the pointer into the first live node. If another pop
+            // marks it first, that node is deleted as well and the walk carries on past it.
+            let popped = loop {
+                let next = (*node).next[0].load(SeqCst);
+                if unmarked(next).is_null() {
+                    return None;
+                }
+
+                // The head can't move past a node that is still being linked in, since the insert still
+                // needs it
+                if new_head.is_null() && node != head && (*node).inserting.load(SeqCst) {
+                    new_head = node;
+                }
+
+                if is_marked(next) {
+                    node = unmarked(next);
+                    skipped += 1;
+                    continue;
+                }
+
+                let previous = (*node).next[0].fetch_or(1, SeqCst);
+                if !is_marked(previous) {
+                    break previous;
+                }
+
+                node = unmarked(previous);
+                skipped += 1;
+            };
+
+            let popped_node = &*popped;
+            let value = (*popped_node.value.get()).take().unwrap();
+            let key = popped_node.key.clone().unwrap();
+            self.len.fetch_sub(1, SeqCst);
+
+            // Mark it on the upper levels from the top down, then go through them cutting it out
+            for level in (1..popped_node.next.len()).rev() {
+                popped_node.next[level].fetch_or(1, SeqCst);
+            }
+
+            if popped_node.next.len() > 1 {
+                let mut preds: Path<K, V> = [null_mut(); MAX_HEIGHT];
+                let mut succs: Path<K, V> = [null_mut(); MAX_HEIGHT];
+                self.find(&key, popped_node.sequence, &mut preds, &mut succs);
+            }
+
+            if new_head.is_null() {
+                new_head = popped;
+            }
+
+            // Move the head past the deleted nodes, keeping the last one as the new front of the list. This
+            // only works if nothing else has moved it since the walk started.
+            if skipped >= MAX_DELETED_PREFIX && is_marked(observed_head) && unmarked(observed_head) != new_head && !self.moving_head.swap(true, SeqCst) {
+                if (*head).next[0].compare_exchange(observed_head, marked(new_head), SeqCst, SeqCst).is_ok() {
+                    self.retire(unmarked(observed_head), new_head, &guard);
+                }
+
+                self.moving_head.store(false, SeqCst);
+            }
+
+            Some((key, value))
+        }
+    }
+
+    #[cfg(not(feature = "epoch"))]
+    fn pin(&self) -> Guard {
+        Guard
+    }
+
+    #[cfg(feature = "epoch")]
+    fn pin(&self) -> Guard {
+        crossbeam_epoch::pin()
+    }
+
+    // Called with the nodes from first up to end once the head has moved past them, by the pop that moved it
+    #[cfg(not(feature = "epoch"))]
+    unsafe fn retire(&self, first: *mut Node<K, V>, _end: *mut Node<K, V>, _guard: &Guard) {
+        let _ = self.oldest.compare_exchange(null_mut(), first, SeqCst, SeqCst);
+    }
+
+    #[cfg(feature = "epoch")]
+    unsafe fn retire(&self, first: *mut Node<K, V>, end: *mut Node<K, V>, guard: &Guard) {
+        let mut preds: Path<K, V> = [null_mut(); MAX_HEIGHT];
+        let mut succs: Path<K, V> = [null_mut(); MAX_HEIGHT];
+        let mut node = first;
+        while node != end {
+            // The bottom level can no longer get to the node, but an upper level still can if its pop
+            // hasn't finished cutting it out, so make sure of that here before it goes. None of these nodes
+            // are still inserting, since the head never moves past one that is, so nothing links them in
+            // again afterwards.
+            let retiring = &*node;
+            let next = unmarked(retiring.next[0].load(SeqCst));
+            if retiring.next.len() > 1 {
+                for level in (1..retiring.next.len()).rev() {
+                    retiring.next[level].fetch_or(1, SeqCst);
+                }
+
+                self.find(retiring.key.as_ref().unwrap(), retiring.sequence, &mut preds, &mut succs);
+            }
+
+            node = next;
+        }
+
+        // Whatever crossbeam-epoch runs has to be fine to run after the queue has gone, so all it does is
+        // say the nodes can be freed and leaves the freeing to the queue
+        let freeable = Arc::new(AtomicBool::new(false));
+        let set = freeable.clone();
+        guard.defer(move || set.store(true, SeqCst));
+
+        let retired = &mut *self.retired.get();
+        retired.push_back(Retired { first, end, freeable });
+        while retired.front().is_some_and(|batch| batch.freeable.load(SeqCst)) {
+            let batch = retired.pop_front().unwrap();
+            free_nodes(batch.first, batch.end);
+        }
+    }
+
+    fn head_ptr(&self) -> *mut Node<K, V> {
+        &*self.head as *const Node<K, V> as *mut Node<K, V>
+    }
+
+    // Whether the node comes out before the given key and sequence number
+    unsafe fn comes_before(&self, node: *mut Node<K, V>, key: &K, sequence: u64) -> bool {
+        let node = &*node;
+        let node_key = node.key.as_ref().unwrap();
+        self.order.left_can_go_above(node_key, key) || (!self.order.left_can_go_above(key, node_key) && node.sequence < sequence)
+    }
+
+    // Finds the nodes either side of where the key and sequence number go on every level, cutting out any
+    // marked nodes on the upper levels on the way. On the bottom level the position is after all of the
+    // deleted nodes, whatever their keys.
+    unsafe fn find(&self, key: &K, sequence: u64, preds: &mut Path<K, V>, succs: &mut Path<K, V>) {
+        'retry: loop {
+            let mut pred = self.head_ptr();
+            for level in (1..MAX_HEIGHT).rev() {
+                let mut current = unmarked((*pred).next[level].load(SeqCst));
+                while !current.is_null() {
+                    let succ = (*current).next[level].load(SeqCst);
+                    if is_marked(succ) {
+                        if (*pred).next[level].compare_exchange(current, unmarked(succ), SeqCst, SeqCst).is_err() {
+                            continue 'retry;
+                        }
+
+                        current = unmarked(succ);
+                    } else if self.comes_before(current, key, sequence) {
+                        pred = current;
+                        current = succ;
+                    } else {
+                        break;
+                    }
+                }
+
+                preds[level] = pred;
+                succs[level] = current;
+            }
+
+            loop {
+                let next = (*pred).next[0].load(SeqCst);
+                let succ = unmarked(next);
+                if is_marked(next) {
+                    pred = succ;
+                } else if succ.is_null() || !self.comes_before(succ, key, sequence) {
+                    preds[0] = pred;
+                    succs[0] = succ;
+                    return;
+                } else {
+                    pred = succ;
+                }
+            }
+        }
+    }
+}
+
+impl<K, V, Order: HeapOrder<K> + Default> Default for LockFreePriorityQueue<K, V, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V, Order: HeapOrder<K>> std::fmt::Debug for LockFreePriorityQueue<K, V, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LockFreePriorityQueue").field("len", &self.len()).finish_non_exhaustive()
+    }
+}
+
+impl<K, V, Order: HeapOrder<K>> Drop for LockFreePriorityQueue<K, V, Order> {
+    fn drop(&mut self) {
+        // Every node there has ever been is still on the bottom level from the oldest one the head moved
+        // past, since nodes the head moves past keep pointing on to the ones after. With the epoch feature
+        // those are in retired instead, apart from the ones that have already been freed.
+        #[cfg(not(feature = "epoch"))]
+        let node = match self.oldest.load(SeqCst) {
+            oldest if oldest.is_null() => unmarked(self.head.next[0].load(SeqCst)),
+            oldest => oldest,
+        };
+
+        #[cfg(feature = "epoch")]
+        let node = {
+            for batch in self.retired.get_mut().drain(..) {
+                unsafe { free_nodes(batch.first, batch.end) };
+            }
+
+            unmarked(self.head.next[0].load(SeqCst))
+        };
+
+        unsafe { free_nodes(node, null_mut()) };
+    }
+}
+
+// Frees the nodes along the bottom level from first up to end
+unsafe fn free_nodes<K, V>(mut node: *mut Node<K, V>, end: *mut Node<K, V>) {
+    while node != end {
+        let next = unmarked((*node).next[0].load(SeqCst));
+        drop(Box::from_raw(node));
+        node = next;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MinOrder;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_sequential() {
+        let mut rng = thread_rng();
+        let queue = LockFreePriorityQueue::<u32, usize, MinOrder<_>>::new();
+        let mut reference = Vec::new();
+
+        for index in 0..5000 {
+            let key = rng.gen_range(0..100);
+            queue.insert(key, index);
+            reference.push((key, index));
+
+            if rng.gen_bool(0.4) {
+                // Ties come out in the order they went in, so the reference is sorted by key then index
+                reference.sort();
+                assert_eq!(queue.pop(), Some(reference.remove(0)));
+            }
+        }
+
+        reference.sort();
+        assert_eq!(queue.len(), reference.len());
+        assert_eq!(std::iter::from_fn(|| queue.pop()).collect::<Vec<_>>(), reference);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_threads() {
+        let queue = LockFreePriorityQueue::<u32, u32, MinOrder<_>>::new();
+        let popped = Mutex::new(Vec::new());
+
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let queue = &queue;
+                scope.spawn(move || {
+                    for value in 0..2000 {
+                        queue.insert((value * 7919) % 1000, thread * 2000 + value);
+                    }
+                });
+            }
+
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut mine = Vec::new();
+                    for _ in 0..1500 {
+                        if let Some((_, value)) = queue.pop() {
+                            mine.push(value);
+                        }
+                    }
+
+                    popped.lock().unwrap().extend(mine);
+                });
+            }
+        });
+
+        let mut seen = popped.into_inner().unwrap();
+        seen.extend(std::iter::from_fn(|| queue.pop()).map(|(_, value)| value));
+        seen.sort();
+        assert_eq!(seen, (0..8000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_drop() {
+        // Values that were never popped are dropped with the queue, and popped ones aren't dropped twice
+        let value = Arc::new(());
+        let queue = LockFreePriorityQueue::<u32, Arc<()>, MinOrder<_>>::new();
+        for key in 0..1000 {
+            queue.insert(key, value.clone());
+        }
+
+        for _ in 0..600 {
+            queue.pop();
+        }
+
+        assert_eq!(Arc::strong_count(&value), 401);
+        drop(queue);
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+
+    #[cfg(feature = "epoch")]
+    #[test]
+    fn test_reclaimed_while_in_use() {
+        // Popped nodes keep their keys until they are freed, so counting the keys shows how many are left
+        let key = Arc::new(());
+        let queue = LockFreePriorityQueue::<(u32, Arc<()>), (), MinOrder<_>>::new();
+        for round in 0..20 {
+            for index in 0..1000 {
+                queue.insert((round * 1000 + index, key.clone()), ());
+            }
+
+            while queue.pop().is_some() {}
+        }
+
+        assert!(Arc::strong_count(&key) < 5000, "{} popped keys still haven't been freed", Arc::strong_count(&key) - 1);
+        drop(queue);
+        assert_eq!(Arc::strong_count(&key), 1);
+    }
+}
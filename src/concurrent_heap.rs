@@ -0,0 +1,269 @@
+use crate::heap::{Heap, HeapOrder};
+use rand::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+// A heap that many threads can share, for schedulers where a single Mutex<Heap> would have every producer
+// and consumer queueing up on the same lock. The elements are spread over a number of shards, each its own
+// locked Heap. Inserts go to the next shard that isn't busy, and pop picks two shards at random and takes
+// the better of their tops, so neither has to wait for the whole structure.
+//
+// That makes pop approximate: what comes out is always near the top, but not necessarily the very top, and
+// with more shards it gets further from it. pop_exact locks every shard to find the real top, which is
+// exact but serializes with everything else, so it is for the occasional caller that needs it.
+pub struct ConcurrentHeap<T, Order: HeapOrder<T>> {
+    shards: Vec<Mutex<Heap<T, Order>>>,
+    next_shard: AtomicUsize,
+    len: AtomicUsize,
+}
+
+impl<T, Order: HeapOrder<T> + Clone> ConcurrentHeap<T, Order> {
+    // One shard per thread the machine can run at once
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        let shards = std::thread::available_parallelism().map_or(1, |threads| threads.get());
+        Self::with_shards_and_cmp(shards, order)
+    }
+
+    pub fn with_shards(shards: usize) -> Self where Order: Default {
+        Self::with_shards_and_cmp(shards, Order::default())
+    }
+
+    pub fn with_shards_and_cmp(shards: usize, order: Order) -> Self {
+        assert!(shards > 0, "Cannot create a ConcurrentHeap with no shards");
+
+        Self {
+            shards: (0..shards).map(|_| Mutex::new(Heap::with_capacity_and_cmp(0, order.clone()))).collect(),
+            next_shard: AtomicUsize::new(0),
+            len: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T>> ConcurrentHeap<T, Order> {
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    // Only a snapshot, since other threads can be inserting and popping at the same time
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn insert(&self, value: T) {
+        // Go round the shards from the next one along, taking the first that nobody else is holding. If
+        // they are all busy, wait for the one we started at.
+        let start = self.next_shard.fetch_add(1, Ordering::Relaxed) % self.shards.len();
+        let mut shard = (0..self.shards.len())
+            .find_map(|offset| self.shards[(start + offset) % self.shards.len()].try_lock().ok())
+            .unwrap_or_else(|| self.lock(start));
+
+        // Count it before it goes in, so that a pop that gets to it first can't take the count below zero
+        self.len.fetch_add(1, Ordering::Relaxed);
+        shard.insert(value);
+    }
+
+    fn insert_run(&self, run: Vec<T>) {
+        // The same choice of shard as insert. The batch has already been counted.
+        let start = self.next_shard.fetch_add(1, Ordering::Relaxed) % self.shards.len();
+        let mut shard = (0..self.shards.len())
+            .find_map(|offset| self.shards[(start + offset) % self.shards.len()].try_lock().ok())
+            .unwrap_or_else(|| self.lock(start));
+
+        shard.extend(run);
+    }
+
+    // Inserts everything with one lock per shard it goes to, rather than one per element. The values are
+    // split into a run for each shard, so a big batch is shared out much like inserting them one by one.
+    pub fn push_batch<IntoIter: IntoIterator<Item = T>>(&self, values: IntoIter) {
+        let mut values: Vec<T> = values.into_iter().collect();
+        if values.is_empty() {
+            return;
+        }
+
+        let per_shard = values.len().div_ceil(self.shards.len());
+        self.len.fetch_add(values.len(), Ordering::Relaxed);
+        while !values.is_empty() {
+            let run = values.split_off(values.len().saturating_sub(per_shard));
+            self.insert_run(run);
+        }
+    }
+
+    // Pops something close to the top, the better of the tops of two shards picked at random. Only returns
+    // None if every shard was empty when it looked.
+    pub fn pop(&self) -> Option<T> {
+        let mut rng = thread_rng();
+        let first = rng.gen_range(0..self.shards.len());
+        let ret = if self.shards.len() > 1 {
+            let second = (first + rng.gen_range(1..self.shards.len())) % self.shards.len();
+
+            // Always lock the lower index first, so two pops can't each be waiting for the other's shard
+            let (mut low, mut high) = (self.lock(first.min(second)), self.lock(first.max(second)));
+            let take_high = match (low.peek(), high.peek()) {
+                (Some(low_top), Some(high_top)) => high.order().left_can_go_above(high_top, low_top),
+                (low_top, _) => low_top.is_none(),
+            };
+
+            if take_high { high.pop() } else { low.pop() }
+        } else {
+            None
+        };
+
+        // Both shards were empty, so look through the rest before giving up
+        let ret = ret.or_else(|| (0..self.shards.len()).find_map(|offset| self.lock((first + offset) % self.shards.len()).pop()));
+        if ret.is_some() {
+            self.len.fetch_sub(1, Ordering::Relaxed);
+        }
+
+        ret
+    }
+
+    // Pops the real top of the heap, by locking every shard at once
+    pub fn pop_exact(&self) -> Option<T> {
+        let mut shards: Vec<MutexGuard<'_, Heap<T, Order>>> = (0..self.shards.len()).map(|index| self.lock(index)).collect();
+
+        let mut best: Option<usize> = None;
+        for (index, shard) in shards.iter().enumerate() {
+            if let Some(top) = shard.peek() {
+                if best.is_none_or(|best| shard.order().left_can_go_above(top, shards[best].peek().unwrap())) {
+                    best = Some(index);
+                }
+            }
+        }
+
+        let ret = shards[best?].pop();
+        self.len.fetch_sub(1, Ordering::Relaxed);
+        ret
+    }
+
+    // Pops the real top count elements, best first, locking every shard once for the whole batch rather
+    // than once per element. Returns fewer if there aren't that many.
+    pub fn pop_batch(&self, count: usize) -> Vec<T> {
+        let mut shards: Vec<MutexGuard<'_, Heap<T, Order>>> = (0..self.shards.len()).map(|index| self.lock(index)).collect();
+
+        let mut ret = Vec::with_capacity(count.min(shards.iter().map(|shard| shard.len()).sum()));
+        while ret.len() < count {
+            let mut best: Option<usize> = None;
+            for (index, shard) in shards.iter().enumerate() {
+                if let Some(top) = shard.peek() {
+                    if best.is_none_or(|best| shard.order().left_can_go_above(top, shards[best].peek().unwrap())) {
+                        best = Some(index);
+                    }
+                }
+            }
+
+            let Some(best) = best else {
+                break;
+            };
+
+            ret.extend(shards[best].pop());
+        }
+
+        self.len.fetch_sub(ret.len(), Ordering::Relaxed);
+        ret
+    }
+
+    // Takes everything out, in no particular order
+    pub fn into_vec(self) -> Vec<T> {
+        self.shards.into_iter().flat_map(|shard| shard.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()).into_vec()).collect()
+    }
+
+    fn lock(&self, index: usize) -> MutexGuard<'_, Heap<T, Order>> {
+        // Heap only moves elements by swapping them, so a thread that panicked part way through a sift can
+        // leave a shard slightly out of order but can't lose anything from it. Carry on with it.
+        self.shards[index].lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Clone + Default> Default for ConcurrentHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> std::fmt::Debug for ConcurrentHeap<T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ConcurrentHeap").field("shards", &self.shards.len()).field("len", &self.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MinOrder;
+
+    #[test]
+    fn test_threads() {
+        let heap = ConcurrentHeap::<u32, MinOrder<_>>::with_shards(4);
+        let popped = Mutex::new(Vec::new());
+
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let heap = &heap;
+                scope.spawn(move || {
+                    for value in 0..1000 {
+                        heap.insert(thread * 1000 + value);
+                    }
+                });
+            }
+
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..500 {
+                        while let Some(value) = heap.pop() {
+                            popped.lock().unwrap().push(value);
+                            if heap.len() < 100 {
+                                break;
+                            }
+                        }
+                    }
+                });
+            }
+        });
+
+        let mut seen = popped.into_inner().unwrap();
+        seen.extend(heap.into_vec());
+        seen.sort();
+        assert_eq!(seen, (0..4000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_pop_exact() {
+        let heap = ConcurrentHeap::<u32, MinOrder<_>>::with_shards(8);
+        for value in (0..200).rev() {
+            heap.insert(value);
+        }
+
+        assert_eq!(heap.len(), 200);
+        for expected in 0..200 {
+            assert_eq!(heap.pop_exact(), Some(expected));
+        }
+
+        assert_eq!(heap.pop_exact(), None);
+        assert_eq!(heap.pop(), None);
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn test_batches() {
+        let heap = ConcurrentHeap::<u32, MinOrder<_>>::with_shards(4);
+        heap.push_batch((0..1000).rev());
+        assert_eq!(heap.len(), 1000);
+
+        // Every shard got a share of the batch
+        assert!(heap.shards.iter().all(|shard| shard.lock().unwrap().len() == 250));
+
+        heap.push_batch(std::iter::empty());
+        assert_eq!(heap.pop_batch(10), (0..10).collect::<Vec<_>>());
+        assert_eq!(heap.pop_batch(2000), (10..1000).collect::<Vec<_>>());
+        assert!(heap.pop_batch(5).is_empty());
+        assert!(heap.is_empty());
+    }
+}
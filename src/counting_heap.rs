@@ -0,0 +1,169 @@
+use crate::heap::{Heap, HeapOrder};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// A heap for multisets with a lot of repetition. Each distinct value is in the heap once and has a count
+// in a hash map alongside it, so memory and the cost of each operation depend on the number of distinct
+// values rather than the number of insertions. Inserting a value that is already there just bumps its
+// count, and popping hands out clones of the top value until its count runs out.
+#[derive(Debug, Clone)]
+pub struct CountingHeap<T: Hash + Eq + Clone, Order: HeapOrder<T>> {
+    heap: Heap<T, Order>,
+    counts: HashMap<T, usize>,
+    len: usize,
+}
+
+impl<T: Hash + Eq + Clone, Order: HeapOrder<T>> CountingHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            heap: Heap::with_capacity_and_cmp(0, order),
+            counts: HashMap::new(),
+            len: 0,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        self.heap.order()
+    }
+
+    // The number of elements, counting every copy
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn distinct_len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.counts.clear();
+        self.len = 0;
+    }
+
+    pub fn count(&self, value: &T) -> usize {
+        self.counts.get(value).copied().unwrap_or(0)
+    }
+
+    // Iterates over the distinct values and their counts in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&T, usize)> + '_ {
+        self.counts.iter().map(|(value, count)| (value, *count))
+    }
+
+    pub fn peek(&self) -> Option<(&T, usize)> {
+        self.heap.peek().map(|value| (value, self.counts[value]))
+    }
+
+    pub fn insert(&mut self, value: T) {
+        self.insert_n(value, 1);
+    }
+
+    pub fn insert_n(&mut self, value: T, count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        match self.counts.get_mut(&value) {
+            Some(existing) => *existing += count,
+            None => {
+                self.counts.insert(value.clone(), count);
+                self.heap.insert(value);
+            }
+        }
+
+        self.len += count;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let top = self.heap.peek()?;
+        let count = self.counts.get_mut(top).unwrap();
+        if *count > 1 {
+            *count -= 1;
+            self.len -= 1;
+            Some(top.clone())
+        } else {
+            self.pop_all().map(|(value, _)| value)
+        }
+    }
+
+    // Pops every copy of the top value at once, along with how many there were
+    pub fn pop_all(&mut self) -> Option<(T, usize)> {
+        let value = self.heap.pop()?;
+        let count = self.counts.remove(&value).unwrap();
+        self.len -= count;
+
+        Some((value, count))
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.heap.shrink_to_fit();
+        self.counts.shrink_to_fit();
+    }
+}
+
+impl<T: Hash + Eq + Clone, Order: HeapOrder<T> + Default> Default for CountingHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Hash + Eq + Clone, Order: HeapOrder<T>> Extend<T> for CountingHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T: Hash + Eq + Clone, Order: HeapOrder<T> + Default> FromIterator<T> for CountingHeap<T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{MaxHeap, MaxOrder};
+    use rand::prelude::*;
+
+    #[test]
+    fn test_matches_plain_heap() {
+        let mut rng = thread_rng();
+        let values: Vec<u32> = (0..10000).map(|_| rng.gen_range(0..20)).collect();
+
+        let mut counting: CountingHeap<_, MaxOrder<_>> = values.iter().copied().collect();
+        let plain: MaxHeap<_> = values.into_iter().collect();
+
+        assert_eq!(counting.len(), 10000);
+        assert!(counting.distinct_len() <= 20);
+        assert_eq!(std::iter::from_fn(|| counting.pop()).collect::<Vec<_>>(), plain.into_iter_sorted().collect::<Vec<_>>());
+        assert!(counting.is_empty());
+    }
+
+    #[test]
+    fn test_counts() {
+        let mut heap = CountingHeap::<_, MaxOrder<_>>::new();
+        heap.insert_n("b", 1_000_000);
+        heap.insert("a");
+        heap.insert("c");
+        heap.insert("c");
+
+        assert_eq!(heap.peek(), Some((&"c", 2)));
+        assert_eq!(heap.pop(), Some("c"));
+        assert_eq!(heap.pop_all(), Some(("c", 1)));
+        assert_eq!(heap.count(&"b"), 1_000_000);
+        assert_eq!(heap.pop_all(), Some(("b", 1_000_000)));
+        assert_eq!(heap.len(), 1);
+    }
+}
@@ -0,0 +1,52 @@
+// A double ended priority queue can take elements off either end. It is implemented by MinMaxHeap and
+// IntervalHeap, so code that needs both ends can be written once and pointed at whichever of them
+// benchmarks better for the workload.
+pub trait DoubleEndedPriorityQueue<T> {
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn push(&mut self, value: T);
+    fn peek_min(&self) -> Option<&T>;
+    fn peek_max(&self) -> Option<&T>;
+    fn pop_min(&mut self) -> Option<T>;
+    fn pop_max(&mut self) -> Option<T>;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::interval_heap::IntervalHeap;
+    use crate::minmax_heap::MinMaxHeap;
+    use rand::prelude::*;
+
+    fn check_random_operations(queue: &mut impl DoubleEndedPriorityQueue<u32>) {
+        let mut rng = thread_rng();
+        let mut reference: Vec<u32> = Vec::new();
+
+        for _ in 0..5000 {
+            match rng.gen_range(0..4) {
+                0 | 1 => {
+                    let value = rng.gen_range(0..1000);
+                    queue.push(value);
+                    reference.push(value);
+                    reference.sort();
+                }
+                2 => assert_eq!(queue.pop_min(), (!reference.is_empty()).then(|| reference.remove(0))),
+                _ => assert_eq!(queue.pop_max(), reference.pop()),
+            }
+
+            assert_eq!(queue.len(), reference.len());
+            assert_eq!(queue.peek_min(), reference.first());
+            assert_eq!(queue.peek_max(), reference.last());
+        }
+    }
+
+    #[test]
+    fn test_implementations() {
+        check_random_operations(&mut MinMaxHeap::new());
+        check_random_operations(&mut IntervalHeap::new());
+    }
+}
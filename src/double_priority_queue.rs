@@ -0,0 +1,267 @@
+use crate::heap::HeapOrder;
+use crate::keyed_slots::KeyedSlots;
+use crate::raw::{heapify_up_tracked, remove_tracked, restore_tracked};
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+// A keyed double ended priority queue. Like PriorityQueue each key is in the queue at most once and its
+// priority can be looked up, changed or removed by key, but both the lowest and the highest priority can
+// be peeked and popped, which suits things like order books or caches that evict from either end.
+//
+// Every slot is in two heaps at once, a min heap and a max heap, and positions records where it is in both.
+// That costs a second heap of slot indices, but keeps every operation a plain O(log n) sift rather than
+// needing an interval heap's paired layout to keep track of. The positions are kept apart from the slots
+// so that the raw tracked functions can read priorities through the order while they write positions.
+#[derive(Clone, Copy)]
+enum Side {
+    Min,
+    Max,
+}
+
+pub struct DoublePriorityQueue<K: Hash + Eq, P: Ord> {
+    slots: KeyedSlots<K, P>,
+    min: Vec<usize>,
+    max: Vec<usize>,
+    // Indexed by slot and then by side
+    positions: Vec<[usize; 2]>,
+}
+
+impl<K: Hash + Eq, P: Ord> DoublePriorityQueue<K, P> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: KeyedSlots::with_capacity(capacity),
+            min: Vec::with_capacity(capacity),
+            max: Vec::with_capacity(capacity),
+            positions: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.min.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.min.clear();
+        self.max.clear();
+        self.positions.clear();
+    }
+
+    // Iterates over the keys and their priorities in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &P)> + '_ {
+        self.min.iter().map(|&slot| self.entry(slot))
+    }
+
+    pub fn peek_min(&self) -> Option<(&K, &P)> {
+        Some(self.entry(*self.min.first()?))
+    }
+
+    pub fn peek_max(&self) -> Option<(&K, &P)> {
+        Some(self.entry(*self.max.first()?))
+    }
+
+    pub fn get<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<(&K, &P)> where K: Borrow<Q> {
+        Some(self.entry(self.slots.find(key)?))
+    }
+
+    pub fn get_priority<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&P> where K: Borrow<Q> {
+        self.get(key).map(|(_, priority)| priority)
+    }
+
+    pub fn contains_key<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        self.slots.find(key).is_some()
+    }
+
+    // Adds the key with the given priority, or if it is already there changes its priority instead and
+    // returns the old one
+    pub fn push(&mut self, key: K, priority: P) -> Option<P> {
+        if let Some(slot) = self.slots.find(&key) {
+            return Some(self.set_priority(slot, priority));
+        }
+
+        // Slots are reused before new ones are made, so a slot is either already in positions or the next one
+        let position = self.min.len();
+        let slot = self.slots.insert(key, priority);
+        match self.positions.get_mut(slot) {
+            Some(old) => *old = [position; 2],
+            None => self.positions.push([position; 2]),
+        }
+
+        self.min.push(slot);
+        self.max.push(slot);
+        for (side, heap) in [(Side::Min, &mut self.min), (Side::Max, &mut self.max)] {
+            heapify_up_tracked(heap, position, &by_priority(&self.slots, side), &mut follow(&mut self.positions, side));
+        }
+
+        None
+    }
+
+    // Returns the old priority, or None and changes nothing if the key isn't there
+    pub fn change_priority<Q: Hash + Eq + ?Sized>(&mut self, key: &Q, priority: P) -> Option<P> where K: Borrow<Q> {
+        let slot = self.slots.find(key)?;
+        Some(self.set_priority(slot, priority))
+    }
+
+    pub fn remove<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<(K, P)> where K: Borrow<Q> {
+        let slot = self.slots.find(key)?;
+        Some(self.remove_slot(slot))
+    }
+
+    pub fn pop_min(&mut self) -> Option<(K, P)> {
+        let slot = *self.min.first()?;
+        Some(self.remove_slot(slot))
+    }
+
+    pub fn pop_max(&mut self) -> Option<(K, P)> {
+        let slot = *self.max.first()?;
+        Some(self.remove_slot(slot))
+    }
+
+    // Returned lowest first, so the maximum is last the same as Heap's into_sorted_vec
+    pub fn into_sorted_vec(mut self) -> Vec<(K, P)> {
+        let mut ret = Vec::with_capacity(self.len());
+        ret.extend(std::iter::from_fn(|| self.pop_min()));
+        ret
+    }
+
+    fn entry(&self, slot: usize) -> (&K, &P) {
+        (self.slots.key(slot), self.priority(slot))
+    }
+
+    fn priority(&self, slot: usize) -> &P {
+        self.slots.value(slot)
+    }
+
+    fn set_priority(&mut self, slot: usize, priority: P) -> P {
+        let ret = std::mem::replace(self.slots.value_mut(slot), priority);
+        for (side, heap) in [(Side::Min, &mut self.min), (Side::Max, &mut self.max)] {
+            restore_tracked(heap, self.positions[slot][side as usize], &by_priority(&self.slots, side), &mut follow(&mut self.positions, side));
+        }
+
+        ret
+    }
+
+    fn remove_slot(&mut self, slot: usize) -> (K, P) {
+        for (side, heap) in [(Side::Min, &mut self.min), (Side::Max, &mut self.max)] {
+            remove_tracked(heap, self.positions[slot][side as usize], &by_priority(&self.slots, side), &mut follow(&mut self.positions, side));
+            heap.pop();
+        }
+
+        self.slots.remove(slot)
+    }
+}
+
+// What the raw tracked functions sift one side's heap with, comparing slots by their priorities and writing
+// down each slot's new position on that side as it moves
+fn by_priority<K: Hash + Eq, P: Ord>(slots: &KeyedSlots<K, P>, side: Side) -> impl HeapOrder<usize> + '_ {
+    move |left: &usize, right: &usize| {
+        let (left, right) = (slots.value(*left), slots.value(*right));
+        match side {
+            Side::Min => left < right,
+            Side::Max => left > right,
+        }
+    }
+}
+
+fn follow(positions: &mut [[usize; 2]], side: Side) -> impl FnMut(&usize, usize) + '_ {
+    move |slot: &usize, position| positions[*slot][side as usize] = position
+}
+
+impl<K: Hash + Eq, P: Ord> Default for DoublePriorityQueue<K, P> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + std::fmt::Debug, P: Ord + std::fmt::Debug> std::fmt::Debug for DoublePriorityQueue<K, P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Hash + Eq, P: Ord> Extend<(K, P)> for DoublePriorityQueue<K, P> {
+    fn extend<IntoIter: IntoIterator<Item = (K, P)>>(&mut self, iter: IntoIter) {
+        for (key, priority) in iter {
+            self.push(key, priority);
+        }
+    }
+}
+
+impl<K: Hash + Eq, P: Ord> FromIterator<(K, P)> for DoublePriorityQueue<K, P> {
+    fn from_iter<IntoIter: IntoIterator<Item = (K, P)>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::prelude::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_random_operations() {
+        let mut rng = thread_rng();
+        let mut queue = DoublePriorityQueue::<u32, u32>::new();
+        let mut reference: HashMap<u32, u32> = HashMap::new();
+
+        for _ in 0..10000 {
+            let key = rng.gen_range(0..200);
+            match rng.gen_range(0..6) {
+                0 | 1 => {
+                    let priority = rng.gen_range(0..1000);
+                    assert_eq!(queue.push(key, priority), reference.insert(key, priority));
+                }
+                2 => {
+                    let priority = rng.gen_range(0..1000);
+                    let expected = reference.get_mut(&key).map(|old| std::mem::replace(old, priority));
+                    assert_eq!(queue.change_priority(&key, priority), expected);
+                }
+                3 => assert_eq!(queue.remove(&key), reference.remove_entry(&key)),
+                4 => {
+                    let popped = queue.pop_min();
+                    assert_eq!(popped.map(|(_, priority)| priority), reference.values().copied().min());
+                    if let Some((key, _)) = popped {
+                        reference.remove(&key);
+                    }
+                }
+                _ => {
+                    let popped = queue.pop_max();
+                    assert_eq!(popped.map(|(_, priority)| priority), reference.values().copied().max());
+                    if let Some((key, _)) = popped {
+                        reference.remove(&key);
+                    }
+                }
+            }
+
+            assert_eq!(queue.len(), reference.len());
+            assert_eq!(queue.get_priority(&key), reference.get(&key));
+            assert_eq!(queue.peek_min().map(|(_, &priority)| priority), reference.values().copied().min());
+            assert_eq!(queue.peek_max().map(|(_, &priority)| priority), reference.values().copied().max());
+        }
+    }
+
+    #[test]
+    fn test_order_book() {
+        // Resting orders by id and price, matched from the best price and cancelled by id
+        let mut bids: DoublePriorityQueue<&str, u32> = [("a", 101), ("b", 99), ("c", 103), ("d", 100)].into_iter().collect();
+        assert_eq!(bids.peek_max(), Some((&"c", &103)));
+        assert_eq!(bids.peek_min(), Some((&"b", &99)));
+
+        assert_eq!(bids.remove("c"), Some(("c", 103)));
+        assert_eq!(bids.change_priority("b", 104), Some(99));
+        assert_eq!(bids.pop_max(), Some(("b", 104)));
+        assert_eq!(bids.pop_min(), Some(("d", 100)));
+        assert_eq!(bids.into_sorted_vec(), vec![("a", 101)]);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::heap::HeapOrder;
+use crate::priority_queue::PriorityQueue;
+use crate::time::deadline_after;
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+// A map whose entries each have a deadline, for things like cache entries or sessions that go away once
+// they haven't been touched for a while. Inserting a key that is already there replaces its value and
+// pushes its deadline back, and pop_expired takes out everything whose deadline has passed. Under the hood
+// it is a PriorityQueue keyed the same way, with the earliest deadline on top.
+struct Expiry<V> {
+    deadline: Instant,
+    value: V,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct DeadlineOrder;
+
+impl<V> HeapOrder<Expiry<V>> for DeadlineOrder {
+    fn left_can_go_above(&self, left: &Expiry<V>, right: &Expiry<V>) -> bool {
+        left.deadline < right.deadline
+    }
+}
+
+pub struct ExpiryQueue<K: Hash + Eq, V> {
+    queue: PriorityQueue<K, Expiry<V>, DeadlineOrder>,
+}
+
+impl<K: Hash + Eq, V> ExpiryQueue<K, V> {
+    pub fn new() -> Self {
+        Self { queue: PriorityQueue::new() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+    }
+
+    // Iterates over the keys and values in no particular order, including any that have expired but haven't
+    // been popped yet
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> + '_ {
+        self.queue.iter().map(|(key, expiry)| (key, &expiry.value))
+    }
+
+    pub fn get<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&V> where K: Borrow<Q> {
+        self.queue.get_priority(key).map(|expiry| &expiry.value)
+    }
+
+    pub fn deadline<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<Instant> where K: Borrow<Q> {
+        self.queue.get_priority(key).map(|expiry| expiry.deadline)
+    }
+
+    pub fn contains_key<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        self.queue.contains_key(key)
+    }
+
+    // When the next entry is due, if there is one
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.queue.peek().map(|(_, expiry)| expiry.deadline)
+    }
+
+    // Inserts the key to expire ttl from now, returning the value it replaced if it was already there
+    pub fn insert(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        self.insert_at(key, value, deadline_after(ttl))
+    }
+
+    pub fn insert_at(&mut self, key: K, value: V, deadline: Instant) -> Option<V> {
+        self.queue.push(key, Expiry { deadline, value }).map(|expiry| expiry.value)
+    }
+
+    // Moves the key's deadline without changing its value. Returns false if the key isn't there.
+    pub fn refresh<Q: Hash + Eq + ?Sized>(&mut self, key: &Q, ttl: Duration) -> bool where K: Borrow<Q> {
+        self.refresh_at(key, deadline_after(ttl))
+    }
+
+    pub fn refresh_at<Q: Hash + Eq + ?Sized>(&mut self, key: &Q, deadline: Instant) -> bool where K: Borrow<Q> {
+        match self.queue.get_mut(key) {
+            Some(mut expiry) => {
+                expiry.deadline = deadline;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<(K, V)> where K: Borrow<Q> {
+        self.queue.remove(key).map(|(key, expiry)| (key, expiry.value))
+    }
+
+    // Takes out every entry whose deadline is at or before now, earliest first
+    pub fn pop_expired(&mut self, now: Instant) -> Vec<(K, V)> {
+        let mut ret = Vec::new();
+        while self.next_deadline().is_some_and(|deadline| deadline <= now) {
+            let (key, expiry) = self.queue.pop().unwrap();
+            ret.push((key, expiry.value));
+        }
+
+        ret
+    }
+}
+
+impl<K: Hash + Eq, V> Default for ExpiryQueue<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + std::fmt::Debug, V: std::fmt::Debug> std::fmt::Debug for ExpiryQueue<K, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_pop_expired() {
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+
+        let mut sessions = ExpiryQueue::new();
+        sessions.insert_at("carol", 3, at(30));
+        sessions.insert_at("alice", 1, at(10));
+        sessions.insert_at("bob", 2, at(20));
+        assert_eq!(sessions.next_deadline(), Some(at(10)));
+
+        assert!(sessions.pop_expired(at(5)).is_empty());
+        assert_eq!(sessions.pop_expired(at(20)), vec![("alice", 1), ("bob", 2)]);
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions.get("carol"), Some(&3));
+    }
+
+    #[test]
+    fn test_refresh() {
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+
+        let mut sessions = ExpiryQueue::new();
+        sessions.insert_at("alice", 1, at(10));
+        sessions.insert_at("bob", 2, at(20));
+
+        // Touching alice again pushes her past bob, both by re-inserting and by refreshing
+        assert_eq!(sessions.insert_at("alice", 10, at(25)), Some(1));
+        assert!(sessions.refresh_at("bob", at(15)));
+        assert!(!sessions.refresh_at("carol", at(15)));
+        assert_eq!(sessions.deadline("alice"), Some(at(25)));
+
+        assert_eq!(sessions.pop_expired(at(20)), vec![("bob", 2)]);
+        assert_eq!(sessions.pop_expired(at(30)), vec![("alice", 10)]);
+        assert!(sessions.is_empty());
+    }
+
+    #[test]
+    fn test_ttl_forever() {
+        let mut sessions = ExpiryQueue::new();
+        sessions.insert("alice", 1, Duration::MAX);
+        sessions.insert("bob", 2, Duration::from_secs(60));
+        assert!(sessions.refresh("bob", Duration::MAX));
+
+        let far_off = Instant::now() + Duration::from_secs(365 * 24 * 60 * 60);
+        assert!(sessions.pop_expired(far_off).is_empty());
+        assert_eq!(sessions.len(), 2);
+    }
+}
@@ -0,0 +1,459 @@
+use crate::heap::{heap_sort, Heap, HeapOrder};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Seek};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// A heap for more elements than fit in memory. New elements go into an ordinary in-memory buffer, and once
+// that holds more than the memory budget it is sorted and written out to a temporary file as a run. Only
+// the head of each run is kept in memory, in the same heap as the buffer, so the top of that heap is
+// always the top overall, and popping a run's head reads its next element in to replace it. This is the
+// merge phase of an external sort, done lazily, and it works just as well with inserts mixed in.
+//
+// Every run holds a file open, so runs are merged the way a sequence heap does it. Each run has a level,
+// starting at zero when it is spilled, and once MERGE_WIDTH runs share a level they are merged into a single
+// run a level up. No level ever has more than MERGE_WIDTH - 1 runs on it, and each element is only rewritten
+// once per level, so the I/O only grows by a logarithmic factor however big the heap gets. The files are
+// deleted as soon as their runs are used up, or when the heap is dropped.
+//
+// Nothing is lost if writing a run fails. A spill keeps the buffer until its run is safely written, and a
+// merge reads its runs through files of its own, so the runs it was merging are all still there.
+struct Entry<T> {
+    value: T,
+    // None for elements in the buffer, or the run this element is the head of
+    run: Option<usize>,
+}
+
+struct EntryOrder<Order>(Order);
+
+impl<T, Order: HeapOrder<T>> HeapOrder<Entry<T>> for EntryOrder<Order> {
+    fn left_can_go_above(&self, left: &Entry<T>, right: &Entry<T>) -> bool {
+        self.0.left_can_go_above(&left.value, &right.value)
+    }
+}
+
+const MERGE_WIDTH: usize = 8;
+
+static NEXT_FILE: AtomicU64 = AtomicU64::new(0);
+
+struct RunReader {
+    reader: BufReader<File>,
+    remaining: usize,
+}
+
+impl RunReader {
+    fn next<T: DeserializeOwned>(&mut self) -> io::Result<Option<T>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+
+        let ret = bincode::deserialize_from(&mut self.reader).map_err(io::Error::other)?;
+        self.remaining -= 1;
+        Ok(Some(ret))
+    }
+}
+
+struct Run {
+    path: PathBuf,
+    reader: RunReader,
+    level: usize,
+}
+
+impl Run {
+    fn next<T: DeserializeOwned>(&mut self) -> io::Result<Option<T>> {
+        self.reader.next()
+    }
+
+    // Another reader that carries on from where this one has got to, without moving this one
+    fn reopen(&mut self) -> io::Result<RunReader> {
+        let position = self.reader.reader.stream_position()?;
+        let mut file = File::open(&self.path)?;
+        file.seek(io::SeekFrom::Start(position))?;
+        Ok(RunReader { reader: BufReader::new(file), remaining: self.reader.remaining })
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        // Nothing useful to do if the file is already gone
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+struct RunWriter {
+    path: PathBuf,
+    // Only taken by finish, so that dropping a run that was never finished deletes its file
+    writer: Option<BufWriter<File>>,
+    len: usize,
+}
+
+impl RunWriter {
+    fn create(dir: &std::path::Path) -> io::Result<Self> {
+        loop {
+            let path = dir.join(format!("felicity-{}-{}.run", std::process::id(), NEXT_FILE.fetch_add(1, Ordering::Relaxed)));
+            match OpenOptions::new().read(true).write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    return Ok(Self {
+                        path,
+                        writer: Some(BufWriter::new(file)),
+                        len: 0,
+                    })
+                }
+                // Left behind by an earlier process with the same id
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    fn write<T: Serialize>(&mut self, value: &T) -> io::Result<()> {
+        bincode::serialize_into(self.writer.as_mut().unwrap(), value).map_err(io::Error::other)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    fn finish(mut self, level: usize) -> io::Result<Run> {
+        let file = match self.writer.take().unwrap().into_inner() {
+            Ok(file) => file,
+            Err(error) => {
+                let _ = std::fs::remove_file(&self.path);
+                return Err(error.into_error());
+            }
+        };
+
+        // The run deletes the file from here on if anything goes wrong
+        let mut ret = Run {
+            path: std::mem::take(&mut self.path),
+            reader: RunReader { reader: BufReader::new(file), remaining: self.len },
+            level,
+        };
+
+        ret.reader.reader.rewind()?;
+        Ok(ret)
+    }
+}
+
+impl Drop for RunWriter {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+pub struct ExternalHeap<T: Serialize + DeserializeOwned, Order: HeapOrder<T>> {
+    heap: Heap<Entry<T>, EntryOrder<Order>>,
+    runs: Vec<Option<Run>>,
+    buffered: usize,
+    len: usize,
+    memory_budget: usize,
+    dir: PathBuf,
+}
+
+impl<T: Serialize + DeserializeOwned, Order: HeapOrder<T>> ExternalHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self::with_budget_and_cmp(1 << 20, order)
+    }
+
+    pub fn with_budget(memory_budget: usize) -> Self where Order: Default {
+        Self::with_budget_and_cmp(memory_budget, Order::default())
+    }
+
+    pub fn with_budget_and_cmp(memory_budget: usize, order: Order) -> Self {
+        assert!(memory_budget > 0, "Cannot use a memory budget of {} elements", memory_budget);
+        Self {
+            heap: Heap::with_capacity_and_cmp(0, EntryOrder(order)),
+            runs: Vec::new(),
+            buffered: 0,
+            len: 0,
+            memory_budget,
+            dir: std::env::temp_dir(),
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.heap.order().0
+    }
+
+    // How many elements the buffer can hold before it gets written out, a little over a million by default
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget
+    }
+
+    // Where run files get created, the system temporary directory by default. Runs that already exist
+    // stay where they are.
+    pub fn set_spill_dir(&mut self, dir: impl Into<PathBuf>) {
+        self.dir = dir.into();
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // The number of runs that are on disk
+    pub fn run_count(&self) -> usize {
+        self.runs.iter().flatten().count()
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.runs.clear();
+        self.buffered = 0;
+        self.len = 0;
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.heap.peek().map(|entry| &entry.value)
+    }
+
+    // The value is in the heap even if this fails, the error only means the buffer couldn't be written out
+    // or the runs couldn't be merged, and both are tried again on the next insert
+    pub fn insert(&mut self, value: T) -> io::Result<()> {
+        self.heap.insert(Entry { value, run: None });
+        self.buffered += 1;
+        self.len += 1;
+
+        if self.buffered > self.memory_budget {
+            self.spill()?;
+        }
+
+        let mut level = 0;
+        while self.runs.iter().flatten().filter(|run| run.level == level).count() >= MERGE_WIDTH {
+            self.merge_level(level)?;
+            level += 1;
+        }
+
+        Ok(())
+    }
+
+    // If reading the replacement for a run's head fails, the error is returned and the rest of that run is
+    // lost along with the element that would have been popped
+    pub fn pop(&mut self) -> io::Result<Option<T>> {
+        let Some(Entry { value, run }) = self.heap.pop() else { return Ok(None) };
+        self.len -= 1;
+
+        match run {
+            None => self.buffered -= 1,
+            Some(index) => {
+                let next = match self.runs[index].as_mut().unwrap().next() {
+                    Ok(next) => next,
+                    Err(error) => {
+                        self.discard_run(index);
+                        return Err(error);
+                    }
+                };
+
+                match next {
+                    Some(next) => self.heap.insert(Entry { value: next, run: Some(index) }),
+                    None => self.runs[index] = None,
+                }
+            }
+        }
+
+        Ok(Some(value))
+    }
+
+    fn discard_run(&mut self, index: usize) {
+        if let Some(run) = self.runs[index].take() {
+            self.len -= run.reader.remaining;
+        }
+    }
+
+    // Writes the buffer out as a new run, top first, and puts it back if that fails
+    fn spill(&mut self) -> io::Result<()> {
+        let (buffer, heads): (Vec<_>, Vec<_>) = self.heap.drain().partition(|entry| entry.run.is_none());
+        self.heap.extend(heads);
+
+        let mut values: Vec<T> = buffer.into_iter().map(|entry| entry.value).collect();
+        heap_sort(&mut values, &self.heap.order().0);
+
+        let spilled = RunWriter::create(&self.dir).and_then(|mut writer| {
+            values.iter().rev().try_for_each(|value| writer.write(value))?;
+            let mut run = writer.finish(0)?;
+            Ok((run.next()?, run))
+        });
+
+        match spilled {
+            Ok((head, run)) => {
+                self.buffered = 0;
+                self.push_run(head, run);
+                Ok(())
+            }
+            Err(error) => {
+                self.heap.extend(values.into_iter().map(|value| Entry { value, run: None }));
+                Err(error)
+            }
+        }
+    }
+
+    // Merges every run on the level into one a level up. The runs are read through readers of their own and
+    // their heads are only borrowed, so until the new run is finished nothing has changed, and if writing it
+    // fails they are all still there.
+    fn merge_level(&mut self, level: usize) -> io::Result<()> {
+        let sources: Vec<usize> = (0..self.runs.len()).filter(|index| self.runs[*index].as_ref().is_some_and(|run| run.level == level)).collect();
+        let mut readers = Vec::with_capacity(sources.len());
+        for index in &sources {
+            readers.push(self.runs[*index].as_mut().unwrap().reopen()?);
+        }
+
+        let order = &self.heap.order().0;
+        let mut merge = Heap::with_capacity_and_cmp(sources.len(), |left: &(Head<'_, T>, usize), right: &(Head<'_, T>, usize)| {
+            order.left_can_go_above(left.0.get(), right.0.get())
+        });
+
+        for entry in self.heap.iter() {
+            if let Some(source) = sources.iter().position(|index| entry.run == Some(*index)) {
+                merge.insert((Head::Borrowed(&entry.value), source));
+            }
+        }
+
+        let mut writer = RunWriter::create(&self.dir)?;
+        while let Some((head, source)) = merge.pop() {
+            writer.write(head.get())?;
+            if let Some(next) = readers[source].next()? {
+                merge.insert((Head::Read(next), source));
+            }
+        }
+
+        let mut run = writer.finish(level + 1)?;
+        let head = run.next()?;
+
+        // Only now that the merged run is all there can the runs it came from go
+        let (_, rest): (Vec<_>, Vec<_>) = self.heap.drain().partition(|entry| entry.run.is_some_and(|index| sources.contains(&index)));
+        self.heap.extend(rest);
+        for index in sources {
+            self.runs[index] = None;
+        }
+
+        self.push_run(head, run);
+        Ok(())
+    }
+
+    fn push_run(&mut self, head: Option<T>, run: Run) {
+        let Some(head) = head else { return };
+
+        // Slots of runs that have been used up are free to use again, since nothing in the heap refers to them
+        let index = match self.runs.iter().position(Option::is_none) {
+            Some(index) => index,
+            None => {
+                self.runs.push(None);
+                self.runs.len() - 1
+            }
+        };
+
+        self.heap.insert(Entry { value: head, run: Some(index) });
+        self.runs[index] = Some(run);
+    }
+}
+
+// The value at the front of a run being merged, which is either its head in the heap or one read from its
+// file since
+enum Head<'a, T> {
+    Borrowed(&'a T),
+    Read(T),
+}
+
+impl<T> Head<'_, T> {
+    fn get(&self) -> &T {
+        match self {
+            Head::Borrowed(value) => value,
+            Head::Read(value) => value,
+        }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned, Order: HeapOrder<T> + Default> Default for ExternalHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MinOrder;
+    use rand::prelude::*;
+
+    fn run_files(heap: &ExternalHeap<u32, MinOrder<u32>>) -> Vec<PathBuf> {
+        heap.runs.iter().flatten().map(|run| run.path.clone()).collect()
+    }
+
+    #[test]
+    fn test_random_operations() {
+        let mut rng = thread_rng();
+        let mut heap = ExternalHeap::<u32, MinOrder<_>>::with_budget(16);
+        let mut reference = Vec::new();
+
+        for _ in 0..20000 {
+            if rng.gen_bool(0.7) {
+                let value = rng.gen_range(0..100000);
+                heap.insert(value).unwrap();
+                reference.push(value);
+                reference.sort_by(|left, right| right.cmp(left));
+            } else {
+                assert_eq!(heap.pop().unwrap(), reference.pop());
+            }
+
+            assert_eq!(heap.len(), reference.len());
+            assert_eq!(heap.peek(), reference.last());
+            assert!(heap.runs.iter().flatten().all(|run| heap.runs.iter().flatten().filter(|other| other.level == run.level).count() < MERGE_WIDTH));
+        }
+
+        while let Some(expected) = reference.pop() {
+            assert_eq!(heap.pop().unwrap(), Some(expected));
+        }
+
+        assert!(heap.pop().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_files_removed() {
+        let mut heap = ExternalHeap::<u32, MinOrder<_>>::with_budget(10);
+        for value in (0..100).rev() {
+            heap.insert(value).unwrap();
+        }
+
+        let files = run_files(&heap);
+        assert!(!files.is_empty());
+        assert!(files.iter().all(|path| path.exists()));
+
+        assert_eq!(heap.pop().unwrap(), Some(0));
+        drop(heap);
+        assert!(files.iter().all(|path| !path.exists()));
+    }
+
+    #[test]
+    fn test_failed_spill() {
+        let mut heap = ExternalHeap::<u32, MinOrder<_>>::with_budget(4);
+        heap.set_spill_dir(std::env::temp_dir().join("felicity-missing-dir"));
+        for value in 0..4 {
+            heap.insert(value).unwrap();
+        }
+
+        // The buffer couldn't be written, so it is all still in memory
+        assert!(heap.insert(4).is_err());
+        assert_eq!(heap.len(), 5);
+        assert_eq!(heap.peek(), Some(&0));
+        assert_eq!(heap.run_count(), 0);
+
+        // And goes out on the next insert once there is somewhere to put it
+        heap.set_spill_dir(std::env::temp_dir());
+        heap.insert(5).unwrap();
+        assert_eq!(heap.run_count(), 1);
+        for value in 0..6 {
+            assert_eq!(heap.pop().unwrap(), Some(value));
+        }
+
+        assert!(heap.is_empty());
+    }
+}
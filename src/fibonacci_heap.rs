@@ -0,0 +1,450 @@
+use crate::cancelable::CancelableQueue;
+use crate::heap::HeapOrder;
+
+// A Fibonacci heap is a collection of heap ordered trees whose roots sit in a circular list. Insert just
+// adds a new root, and decrease_key cuts the node out of its tree and makes it a root too, so both are
+// O(1) amortized. The trees only get tidied up (consolidated) when the top is popped, which is O(log n)
+// amortized. Marking nodes that have lost a child, and cutting them out when they lose a second one,
+// keeps the trees bushy enough for those bounds to hold.
+//
+// Nodes live in an arena and refer to each other by index, which is what lets a Handle find its node
+// again later. Each slot has a generation that is bumped when it is freed, so a handle to an element that
+// has already been removed never finds whatever moved into its slot afterwards.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: usize,
+    generation: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    value: T,
+    parent: Option<usize>,
+    child: Option<usize>,
+    left: usize,
+    right: usize,
+    degree: usize,
+    marked: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Slot<T> {
+    generation: u32,
+    node: Option<Node<T>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FibonacciHeap<T, Order: HeapOrder<T>> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    top: Option<usize>,
+    len: usize,
+    order: Order,
+}
+
+impl<T, Order: HeapOrder<T>> FibonacciHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            top: None,
+            len: 0,
+            order,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        for slot in self.slots.iter_mut() {
+            if slot.node.take().is_some() {
+                slot.generation = slot.generation.wrapping_add(1);
+            }
+        }
+
+        self.free = (0..self.slots.len()).rev().collect();
+        self.top = None;
+        self.len = 0;
+    }
+
+    // Iterates over the elements in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.slots.iter().filter_map(|slot| slot.node.as_ref().map(|node| &node.value))
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.top.map(|index| &self.node(index).value)
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.resolve(handle).map(|index| &self.node(index).value)
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.resolve(handle).is_some()
+    }
+
+    pub fn insert(&mut self, value: T) -> Handle {
+        let handle = self.allocate(value);
+        self.add_root(handle.index);
+        self.len += 1;
+
+        handle
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let top_index = self.top?;
+
+        // All of the top's children become roots
+        if let Some(child_index) = self.node_mut(top_index).child.take() {
+            let mut index = child_index;
+            loop {
+                let node = self.node_mut(index);
+                node.parent = None;
+                node.marked = false;
+                index = node.right;
+
+                if index == child_index {
+                    break;
+                }
+            }
+
+            self.splice(top_index, child_index);
+        }
+
+        let next_index = self.node(top_index).right;
+        self.unlink(top_index);
+
+        if next_index == top_index {
+            self.top = None;
+        } else {
+            self.top = Some(next_index);
+            self.consolidate();
+        }
+
+        self.len -= 1;
+        Some(self.release(top_index))
+    }
+
+    // Moves the element towards the top of the heap by replacing it with a value that can go at least as
+    // high. Handing in a value that belongs lower down is a bug, and panics.
+    pub fn decrease_key(&mut self, handle: Handle, value: T) {
+        let index = self.resolve(handle).expect("Handle does not refer to an element of this heap");
+        assert!(
+            !self.order.left_can_go_above(&self.node(index).value, &value),
+            "decrease_key cannot move an element further down the heap"
+        );
+
+        self.node_mut(index).value = value;
+
+        if let Some(parent_index) = self.node(index).parent {
+            if self.order.left_can_go_above(&self.node(index).value, &self.node(parent_index).value) {
+                self.cut(index, parent_index);
+                self.cascading_cut(parent_index);
+            }
+        }
+
+        if self.order.left_can_go_above(&self.node(index).value, &self.node(self.top.unwrap()).value) {
+            self.top = Some(index);
+        }
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        let index = self.resolve(handle)?;
+
+        // Make the node a root as if its key had been decreased, and then pretend it is the top so that pop
+        // takes it out and tidies up after it
+        if let Some(parent_index) = self.node(index).parent {
+            self.cut(index, parent_index);
+            self.cascading_cut(parent_index);
+        }
+
+        self.top = Some(index);
+        self.pop()
+    }
+
+    fn resolve(&self, handle: Handle) -> Option<usize> {
+        let slot = self.slots.get(handle.index)?;
+        if slot.generation == handle.generation && slot.node.is_some() {
+            Some(handle.index)
+        } else {
+            None
+        }
+    }
+
+    fn node(&self, index: usize) -> &Node<T> {
+        self.slots[index].node.as_ref().unwrap()
+    }
+
+    fn node_mut(&mut self, index: usize) -> &mut Node<T> {
+        self.slots[index].node.as_mut().unwrap()
+    }
+
+    fn allocate(&mut self, value: T) -> Handle {
+        let index = self.free.pop().unwrap_or_else(|| {
+            self.slots.push(Slot { generation: 0, node: None });
+            self.slots.len() - 1
+        });
+
+        self.slots[index].node = Some(Node {
+            value,
+            parent: None,
+            child: None,
+            left: index,
+            right: index,
+            degree: 0,
+            marked: false,
+        });
+
+        Handle {
+            index,
+            generation: self.slots[index].generation,
+        }
+    }
+
+    fn release(&mut self, index: usize) -> T {
+        let slot = &mut self.slots[index];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(index);
+
+        slot.node.take().unwrap().value
+    }
+
+    fn add_root(&mut self, index: usize) {
+        match self.top {
+            Some(top_index) => {
+                self.splice(top_index, index);
+                if self.order.left_can_go_above(&self.node(index).value, &self.node(top_index).value) {
+                    self.top = Some(index);
+                }
+            }
+            None => self.top = Some(index),
+        }
+    }
+
+    fn splice(&mut self, left_index: usize, right_index: usize) {
+        // Joins the circular list containing left_index with the one containing right_index
+        let left_right = self.node(left_index).right;
+        let right_left = self.node(right_index).left;
+
+        self.node_mut(left_index).right = right_index;
+        self.node_mut(right_index).left = left_index;
+        self.node_mut(left_right).left = right_left;
+        self.node_mut(right_left).right = left_right;
+    }
+
+    fn unlink(&mut self, index: usize) {
+        // Takes a node out of whatever circular list it is in, leaving it in a list on its own
+        let Node { left, right, .. } = *self.node(index);
+        self.node_mut(left).right = right;
+        self.node_mut(right).left = left;
+
+        let node = self.node_mut(index);
+        node.left = index;
+        node.right = index;
+    }
+
+    fn consolidate(&mut self) {
+        // Detach every root, and then link roots of equal degree until they all have different degrees
+        let mut roots = Vec::new();
+        let start_index = self.top.unwrap();
+        let mut index = start_index;
+        loop {
+            roots.push(index);
+            index = self.node(index).right;
+            if index == start_index {
+                break;
+            }
+        }
+
+        let mut by_degree: Vec<Option<usize>> = Vec::new();
+        for mut index in roots {
+            self.unlink(index);
+
+            let mut degree = self.node(index).degree;
+            loop {
+                if degree >= by_degree.len() {
+                    by_degree.resize(degree + 1, None);
+                }
+
+                match by_degree[degree].take() {
+                    Some(mut other_index) => {
+                        if self.order.left_can_go_above(&self.node(other_index).value, &self.node(index).value) {
+                            std::mem::swap(&mut index, &mut other_index);
+                        }
+
+                        self.link(other_index, index);
+                        degree += 1;
+                    }
+                    None => {
+                        by_degree[degree] = Some(index);
+                        break;
+                    }
+                }
+            }
+        }
+
+        self.top = None;
+        for index in by_degree.into_iter().flatten() {
+            self.add_root(index);
+        }
+    }
+
+    fn link(&mut self, child_index: usize, parent_index: usize) {
+        // Makes one detached root a child of another
+        match self.node(parent_index).child {
+            Some(first_child_index) => self.splice(first_child_index, child_index),
+            None => self.node_mut(parent_index).child = Some(child_index),
+        }
+
+        let child = self.node_mut(child_index);
+        child.parent = Some(parent_index);
+        child.marked = false;
+        self.node_mut(parent_index).degree += 1;
+    }
+
+    fn cut(&mut self, index: usize, parent_index: usize) {
+        // Moves a node from its parent's child list to the root list
+        let right = self.node(index).right;
+        let parent = self.node_mut(parent_index);
+        if parent.child == Some(index) {
+            parent.child = if right == index { None } else { Some(right) };
+        }
+        parent.degree -= 1;
+
+        self.unlink(index);
+
+        let node = self.node_mut(index);
+        node.parent = None;
+        node.marked = false;
+        self.add_root(index);
+    }
+
+    fn cascading_cut(&mut self, mut index: usize) {
+        // A node that has lost one child gets marked, and one that loses a second gets cut as well
+        while let Some(parent_index) = self.node(index).parent {
+            if !self.node(index).marked {
+                self.node_mut(index).marked = true;
+                break;
+            }
+
+            self.cut(index, parent_index);
+            index = parent_index;
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for FibonacciHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for FibonacciHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T>> CancelableQueue<T> for FibonacciHeap<T, Order> {
+    type Handle = Handle;
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn insert_with_handle(&mut self, value: T) -> Handle {
+        self.insert(value)
+    }
+
+    fn cancel(&mut self, handle: Handle) -> bool {
+        self.remove(handle).is_some()
+    }
+
+    fn is_pending(&self, handle: Handle) -> bool {
+        self.contains(handle)
+    }
+
+    fn peek(&self) -> Option<&T> {
+        self.peek()
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MinOrder;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_insert_pop() {
+        let mut values: Vec<u32> = (0..1000).collect();
+        values.shuffle(&mut thread_rng());
+
+        let mut heap = FibonacciHeap::<_, MinOrder<_>>::new();
+        heap.extend(values);
+        assert_eq!(heap.len(), 1000);
+
+        let popped: Vec<_> = std::iter::from_fn(|| heap.pop()).collect();
+        assert_eq!(popped, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_random_operations() {
+        let mut rng = thread_rng();
+        let mut heap = FibonacciHeap::<_, MinOrder<_>>::new();
+        let mut handles: Vec<(Handle, u32)> = Vec::new();
+
+        for _ in 0..10000 {
+            match rng.gen_range(0..5) {
+                0 | 1 => {
+                    let value = rng.gen_range(0..1000);
+                    handles.push((heap.insert(value), value));
+                }
+                2 if !handles.is_empty() => {
+                    let position = rng.gen_range(0..handles.len());
+                    let (handle, value) = &mut handles[position];
+                    *value = value.saturating_sub(rng.gen_range(0..100));
+                    heap.decrease_key(*handle, *value);
+                }
+                3 if !handles.is_empty() => {
+                    let (handle, value) = handles.swap_remove(rng.gen_range(0..handles.len()));
+                    assert_eq!(heap.remove(handle), Some(value));
+                    assert_eq!(heap.get(handle), None);
+                }
+                _ => {
+                    let expected = handles.iter().map(|(_, value)| *value).min();
+                    assert_eq!(heap.pop(), expected);
+                    if let Some(expected) = expected {
+                        let position = handles.iter().position(|(handle, value)| *value == expected && !heap.contains(*handle)).unwrap();
+                        handles.swap_remove(position);
+                    }
+                }
+            }
+
+            assert_eq!(heap.len(), handles.len());
+            assert_eq!(heap.peek().copied(), handles.iter().map(|(_, value)| *value).min());
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::heap::{Heap, HeapOrder};
+use crate::sorted_frontier::SortedFrontier;
+use std::sync::Arc;
+
+// A read-only heap that any number of threads can share, for when readers want a consistent view of the
+// queue while a writer builds the next one. Freezing a heap moves it behind an Arc without copying anything,
+// and cloning a FrozenHeap only bumps the count. Nothing can change it once it is frozen, so readers can
+// peek and walk it in order from as many threads as they like without a lock.
+pub struct FrozenHeap<T, Order: HeapOrder<T>> {
+    heap: Arc<Heap<T, Order>>,
+}
+
+impl<T, Order: HeapOrder<T>> Heap<T, Order> {
+    pub fn freeze(self) -> FrozenHeap<T, Order> {
+        FrozenHeap { heap: Arc::new(self) }
+    }
+}
+
+impl<T, Order: HeapOrder<T>> FrozenHeap<T, Order> {
+    pub fn order(&self) -> &Order {
+        self.heap.order()
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.heap.peek()
+    }
+
+    // Iterates over the elements in no particular order
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.heap.iter()
+    }
+
+    // Iterates over the elements from the top down without changing anything. Getting the first k elements
+    // is O(k log k).
+    pub fn iter_sorted(&self) -> IterSorted<'_, T, Order> {
+        IterSorted { heap: &self.heap, frontier: SortedFrontier::new(self.heap.len()) }
+    }
+
+    // Gets a Heap that can be changed again, taking this one back if nothing else is sharing it and
+    // copying it otherwise
+    pub fn thaw(self) -> Heap<T, Order> where T: Clone, Order: Clone {
+        Arc::unwrap_or_clone(self.heap)
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Clone for FrozenHeap<T, Order> {
+    fn clone(&self) -> Self {
+        Self { heap: self.heap.clone() }
+    }
+}
+
+impl<T: std::fmt::Debug, Order: HeapOrder<T>> std::fmt::Debug for FrozenHeap<T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+pub struct IterSorted<'a, T, Order: HeapOrder<T>> {
+    heap: &'a Heap<T, Order>,
+    frontier: SortedFrontier,
+}
+
+impl<'a, T, Order: HeapOrder<T>> Iterator for IterSorted<'a, T, Order> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let data = &self.heap.data;
+        let order = self.heap.order();
+        let position = self.frontier.next(|left, right| order.left_can_go_above(&data[left], &data[right]))?;
+        Some(&data[position])
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.frontier.remaining(), Some(self.frontier.remaining()))
+    }
+}
+
+impl<'a, T, Order: HeapOrder<T>> ExactSizeIterator for IterSorted<'a, T, Order> { }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{MaxHeap, MinHeap};
+    use rand::prelude::*;
+
+    #[test]
+    fn test_iter_sorted() {
+        let mut rng = thread_rng();
+        let values: Vec<u32> = (0..1000).map(|_| rng.gen_range(0..500)).collect();
+        let frozen = MinHeap::from(values.clone()).freeze();
+
+        let mut expected = values;
+        expected.sort();
+        assert_eq!(frozen.len(), 1000);
+        assert_eq!(frozen.peek(), expected.first());
+        assert_eq!(frozen.iter_sorted().len(), 1000);
+        assert_eq!(frozen.iter_sorted().copied().collect::<Vec<_>>(), expected);
+        assert_eq!(frozen.iter_sorted().take(3).copied().collect::<Vec<_>>(), expected[..3]);
+        assert_eq!(MinHeap::<u32>::new().freeze().iter_sorted().next(), None);
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let frozen: FrozenHeap<u32, _> = MaxHeap::from((0..1000).collect::<Vec<_>>()).freeze();
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                let frozen = frozen.clone();
+                scope.spawn(move || {
+                    assert_eq!(frozen.iter_sorted().copied().collect::<Vec<_>>(), (0..1000).rev().collect::<Vec<_>>());
+                });
+            }
+        });
+
+        // Nothing else is sharing it by now, so it goes back to being a heap without a copy
+        let mut heap = frozen.thaw();
+        heap.insert(5000);
+        assert_eq!(heap.pop(), Some(5000));
+        assert_eq!(heap.len(), 1000);
+    }
+}
@@ -0,0 +1,298 @@
+use crate::heap::HeapOrder;
+use crate::raw::{heapify_down, heapify_down_tracked, heapify_up, heapify_up_tracked, remove_tracked};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// A heap of heaps. Every element belongs to a group, each group keeps its own elements in a heap, and an
+// outer heap orders the groups by their top elements. The top of the outer heap's top group is the top
+// overall, so this can pop the best element overall or the best element of any one group, both in
+// O(log n). Popping by group is what per-tenant fair scheduling wants, e.g. serving tenants round robin
+// and each one's most urgent job first, while still being able to see which job is most urgent of all.
+//
+// Groups come into existence with their first element and go away with their last one. The outer heap
+// has to find a group again when its top changes, so positions remembers where each group is in it. That
+// sits beside the groups rather than in them so that the raw tracked functions can read the groups' tops
+// through the order while they write positions.
+struct Group<G, T> {
+    key: G,
+    items: Vec<T>,
+}
+
+pub struct GroupedHeap<G: Hash + Eq + Clone, T, Order: HeapOrder<T>> {
+    groups: Vec<Group<G, T>>,
+    index: HashMap<G, usize>,
+    // Indices into groups, as a heap ordered by each group's top element
+    outer: Vec<usize>,
+    // Where each group is in outer, indexed the same as groups
+    positions: Vec<usize>,
+    len: usize,
+    order: Order,
+}
+
+impl<G: Hash + Eq + Clone, T, Order: HeapOrder<T>> GroupedHeap<G, T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            groups: Vec::new(),
+            index: HashMap::new(),
+            outer: Vec::new(),
+            positions: Vec::new(),
+            len: 0,
+            order,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // The number of groups that have any elements
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn group_len(&self, group: &G) -> usize {
+        self.index.get(group).map_or(0, |&slot| self.groups[slot].items.len())
+    }
+
+    pub fn contains_group(&self, group: &G) -> bool {
+        self.index.contains_key(group)
+    }
+
+    pub fn clear(&mut self) {
+        self.groups.clear();
+        self.index.clear();
+        self.outer.clear();
+        self.positions.clear();
+        self.len = 0;
+    }
+
+    // Iterates over the groups in no particular order
+    pub fn groups(&self) -> impl Iterator<Item = &G> + '_ {
+        self.groups.iter().map(|group| &group.key)
+    }
+
+    // Iterates over every element and its group in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&G, &T)> + '_ {
+        self.groups.iter().flat_map(|group| group.items.iter().map(move |value| (&group.key, value)))
+    }
+
+    pub fn peek(&self) -> Option<(&G, &T)> {
+        let group = &self.groups[*self.outer.first()?];
+        Some((&group.key, &group.items[0]))
+    }
+
+    pub fn peek_group(&self, group: &G) -> Option<&T> {
+        self.index.get(group).map(|&slot| &self.groups[slot].items[0])
+    }
+
+    pub fn insert(&mut self, group: G, value: T) {
+        self.len += 1;
+
+        let Some(&slot) = self.index.get(&group) else {
+            let slot = self.groups.len();
+            self.index.insert(group.clone(), slot);
+            self.groups.push(Group { key: group, items: vec![value] });
+            self.positions.push(self.outer.len());
+            self.outer.push(slot);
+            self.outer_up(self.outer.len() - 1);
+            return;
+        };
+
+        let items = &mut self.groups[slot].items;
+        items.push(value);
+        let last = items.len() - 1;
+        heapify_up(items, last, &self.order);
+
+        // If that made a new top it can only move the group up
+        self.outer_up(self.positions[slot]);
+    }
+
+    // Pops the top element overall, along with the group it was in
+    pub fn pop(&mut self) -> Option<(G, T)> {
+        let slot = *self.outer.first()?;
+        let group = self.groups[slot].key.clone();
+        self.pop_slot(slot).map(|value| (group, value))
+    }
+
+    // Pops the top element of one group
+    pub fn pop_group(&mut self, group: &G) -> Option<T> {
+        let slot = *self.index.get(group)?;
+        self.pop_slot(slot)
+    }
+
+    // Takes every element of a group out at once, in no particular order
+    pub fn remove_group(&mut self, group: &G) -> Option<Vec<T>> {
+        let slot = *self.index.get(group)?;
+        let items = self.remove_slot(slot).items;
+        self.len -= items.len();
+        Some(items)
+    }
+
+    fn pop_slot(&mut self, slot: usize) -> Option<T> {
+        self.len -= 1;
+
+        if self.groups[slot].items.len() == 1 {
+            return self.remove_slot(slot).items.pop();
+        }
+
+        let items = &mut self.groups[slot].items;
+        let ret = items.swap_remove(0);
+        heapify_down(items, 0, &self.order);
+
+        // The group's new top can't go above its old one, so it can only move down
+        self.outer_down(self.positions[slot]);
+        Some(ret)
+    }
+
+    fn remove_slot(&mut self, slot: usize) -> Group<G, T> {
+        // Take the group out of the outer heap, and then out of the groups, where the last group moves into
+        // its slot
+        self.outer_remove(self.positions[slot]);
+        let group = self.groups.swap_remove(slot);
+        self.positions.swap_remove(slot);
+        self.index.remove(&group.key);
+        if let Some(moved) = self.groups.get(slot) {
+            self.outer[self.positions[slot]] = slot;
+            *self.index.get_mut(&moved.key).unwrap() = slot;
+        }
+
+        group
+    }
+
+    fn outer_up(&mut self, position: usize) {
+        heapify_up_tracked(&mut self.outer, position, &by_top(&self.groups, &self.order), &mut follow(&mut self.positions));
+    }
+
+    fn outer_down(&mut self, position: usize) {
+        heapify_down_tracked(&mut self.outer, position, &by_top(&self.groups, &self.order), &mut follow(&mut self.positions));
+    }
+
+    fn outer_remove(&mut self, position: usize) {
+        remove_tracked(&mut self.outer, position, &by_top(&self.groups, &self.order), &mut follow(&mut self.positions));
+        self.outer.pop();
+    }
+}
+
+// What the raw tracked functions sift the outer heap with, comparing groups by their tops and writing down
+// each group's new position as it moves
+fn by_top<'a, G, T>(groups: &'a [Group<G, T>], order: &'a impl HeapOrder<T>) -> impl HeapOrder<usize> + 'a {
+    move |left: &usize, right: &usize| order.left_can_go_above(&groups[*left].items[0], &groups[*right].items[0])
+}
+
+fn follow(positions: &mut [usize]) -> impl FnMut(&usize, usize) + '_ {
+    move |slot: &usize, position| positions[*slot] = position
+}
+
+impl<G: Hash + Eq + Clone, T, Order: HeapOrder<T> + Default> Default for GroupedHeap<G, T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Hash + Eq + Clone + std::fmt::Debug, T: std::fmt::Debug, Order: HeapOrder<T>> std::fmt::Debug for GroupedHeap<G, T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.groups.iter().map(|group| (&group.key, &group.items))).finish()
+    }
+}
+
+impl<G: Hash + Eq + Clone, T, Order: HeapOrder<T>> Extend<(G, T)> for GroupedHeap<G, T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = (G, T)>>(&mut self, iter: IntoIter) {
+        for (group, value) in iter {
+            self.insert(group, value);
+        }
+    }
+}
+
+impl<G: Hash + Eq + Clone, T, Order: HeapOrder<T> + Default> FromIterator<(G, T)> for GroupedHeap<G, T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = (G, T)>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MaxOrder;
+    use rand::prelude::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_random_operations() {
+        let mut rng = thread_rng();
+        let mut heap = GroupedHeap::<u8, u32, MaxOrder<_>>::new();
+        let mut reference: BTreeMap<u8, Vec<u32>> = BTreeMap::new();
+
+        for _ in 0..10000 {
+            let group = rng.gen_range(0..10);
+            match rng.gen_range(0..5) {
+                0 | 1 => {
+                    let value = rng.gen_range(0..1000);
+                    heap.insert(group, value);
+                    reference.entry(group).or_default().push(value);
+                }
+                2 => {
+                    let expected = reference.get_mut(&group).map(|values| {
+                        values.sort();
+                        values.pop().unwrap()
+                    });
+                    assert_eq!(heap.pop_group(&group), expected);
+                }
+                3 => {
+                    // Groups can tie for the top, so check the value and then follow whichever one it chose
+                    let popped = heap.pop();
+                    assert_eq!(popped.map(|(_, value)| value), reference.values().flatten().copied().max());
+                    if let Some((group, value)) = popped {
+                        let values = reference.get_mut(&group).unwrap();
+                        values.swap_remove(values.iter().position(|other| *other == value).unwrap());
+                    }
+                }
+                _ => {
+                    if rng.gen_bool(0.1) {
+                        let mut removed = heap.remove_group(&group);
+                        removed.iter_mut().for_each(|values| values.sort());
+                        assert_eq!(removed, reference.remove(&group).map(|mut values| { values.sort(); values }));
+                    }
+                }
+            }
+
+            reference.retain(|_, values| !values.is_empty());
+            assert_eq!(heap.len(), reference.values().map(Vec::len).sum::<usize>());
+            assert_eq!(heap.group_count(), reference.len());
+            assert_eq!(heap.peek().map(|(_, value)| *value), reference.values().flatten().copied().max());
+            for (group, values) in &reference {
+                assert_eq!(heap.peek_group(group), values.iter().max());
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_robin() {
+        let mut heap = GroupedHeap::<&str, u32, MaxOrder<_>>::new();
+        heap.extend([("a", 1), ("a", 5), ("a", 3), ("b", 2), ("c", 9), ("c", 4)]);
+        assert_eq!(heap.peek(), Some((&"c", &9)));
+
+        let mut served = Vec::new();
+        while !heap.is_empty() {
+            for tenant in ["a", "b", "c"] {
+                served.extend(heap.pop_group(&tenant));
+            }
+        }
+
+        assert_eq!(served, [5, 2, 9, 3, 4, 1]);
+        assert_eq!(heap.group_count(), 0);
+    }
+}
@@ -1,3 +1,5 @@
+use crate::raw::{heapify_down, heapify_in_place, heapify_up, is_heap};
+
 pub trait HeapOrder<T> {
     fn left_can_go_above(&self, left: &T, right: &T) -> bool;
 }
@@ -38,97 +40,282 @@ impl<T: Ord> HeapOrder<T> for MaxOrder<T> {
     }
 }
 
-fn heapify_in_place<T>(data: &mut [T], order: &impl HeapOrder<T>) {
-    for i in (0..(data.len() / 2)).rev() {
-        heapify_down(data, i, order);
+#[derive(Debug, Clone, Default)]
+pub struct ReverseOrder<Order>(pub Order);
+
+impl<T, Order: HeapOrder<T>> HeapOrder<T> for ReverseOrder<Order> {
+    fn left_can_go_above(&self, left: &T, right: &T) -> bool {
+        self.0.left_can_go_above(right, left)
     }
 }
 
-fn heapify_down<T>(data: &mut [T], mut top_index: usize, order: &impl HeapOrder<T>) {
-    loop {
-        let mut highest_index = top_index;
-        let right_child_index = 2 * (highest_index + 1);
-        let left_child_index = right_child_index - 1;
+pub trait Float: Copy {
+    fn total_cmp(&self, other: &Self) -> std::cmp::Ordering;
+}
 
-        if left_child_index < data.len() && order.left_can_go_above(&data[left_child_index], &data[highest_index]) {
-            highest_index = left_child_index;
-        }
+impl Float for f32 {
+    fn total_cmp(&self, other: &Self) -> std::cmp::Ordering {
+        f32::total_cmp(self, other)
+    }
+}
 
-        if right_child_index < data.len() && order.left_can_go_above(&data[right_child_index], &data[highest_index]) {
-            highest_index = right_child_index;
-        }
+impl Float for f64 {
+    fn total_cmp(&self, other: &Self) -> std::cmp::Ordering {
+        f64::total_cmp(self, other)
+    }
+}
 
-        if highest_index != top_index {
-            data.swap(top_index, highest_index);
-            top_index = highest_index;
-        } else {
-            break;
+// Orders floats smallest first using total_cmp. That means NaN is a real value rather than something that
+// breaks the heap: positive NaNs sort after infinity and so come out of a min heap last, negative NaNs sort
+// before negative infinity and come out first. Likewise -0.0 comes out before 0.0.
+#[derive(Debug, Clone, Default)]
+pub struct FloatOrder;
+
+impl<T: Float> HeapOrder<T> for FloatOrder {
+    fn left_can_go_above(&self, left: &T, right: &T) -> bool {
+        left.total_cmp(right).is_lt()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Sequenced<T> {
+    pub sequence: u64,
+    pub value: T,
+}
+
+impl<T> Sequenced<T> {
+    pub fn new(sequence: u64, value: T) -> Self {
+        Self {
+            sequence,
+            value,
         }
     }
+
+    pub fn into_inner(self) -> T {
+        self.value
+    }
 }
 
-fn heapify_up<T>(data: &mut [T], mut pos_index: usize, order: &impl HeapOrder<T>) {
-    while pos_index > 0 {
-        let parent_index = (pos_index - 1) / 2;
-        if order.left_can_go_above(&data[parent_index], &data[pos_index]) {
-            // The parent and node are in the correct order so we can stop
-            break;
+// Wraps another order so that values it considers equal come out in sequence order, lowest first. As long
+// as the sequence numbers are handed out in insertion order that gives FIFO behaviour for ties.
+#[derive(Debug, Clone, Default)]
+pub struct StableOrder<Order>(pub Order);
+
+impl<T, Order: HeapOrder<T>> HeapOrder<Sequenced<T>> for StableOrder<Order> {
+    fn left_can_go_above(&self, left: &Sequenced<T>, right: &Sequenced<T>) -> bool {
+        if self.0.left_can_go_above(&left.value, &right.value) {
+            true
+        } else if self.0.left_can_go_above(&right.value, &left.value) {
+            false
         } else {
-            // Swap the parent and the node and walk back up towards the root
-            data.swap(parent_index, pos_index);
-            pos_index = parent_index;
+            left.sequence < right.sequence
         }
     }
 }
 
-fn is_heap<T>(values: &[T], order: &impl HeapOrder<T>) -> bool {
-    // Iterate over the leaf nodes
-    for leaf_index in ((values.len() + 1) / 2)..values.len() {
-        let mut pos_index = leaf_index;
-        while pos_index > 0 {
-            let parent_index = (pos_index - 1) / 2;
-            if !order.left_can_go_above(&values[parent_index], &values[pos_index]) {
-                return false;
-            }
+pub struct KeyOrder<T, K, F: Fn(&T) -> K, Order: HeapOrder<K>> {
+    key: F,
+    order: Order,
+    _phantom: std::marker::PhantomData<fn(&T) -> K>,
+}
 
-            pos_index = parent_index;
+impl<T, K, F: Fn(&T) -> K, Order: HeapOrder<K>> KeyOrder<T, K, F, Order> {
+    pub fn new(key: F, order: Order) -> Self {
+        Self {
+            key,
+            order,
+            _phantom: std::marker::PhantomData,
         }
     }
+}
+
+impl<T, K, F: Fn(&T) -> K + Clone, Order: HeapOrder<K> + Clone> Clone for KeyOrder<T, K, F, Order> {
+    fn clone(&self) -> Self {
+        Self::new(self.key.clone(), self.order.clone())
+    }
+}
+
+impl<T, K, F: Fn(&T) -> K, Order: HeapOrder<K> + std::fmt::Debug> std::fmt::Debug for KeyOrder<T, K, F, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyOrder").field("order", &self.order).finish_non_exhaustive()
+    }
+}
+
+impl<T, K, F: Fn(&T) -> K, Order: HeapOrder<K>> HeapOrder<T> for KeyOrder<T, K, F, Order> {
+    fn left_can_go_above(&self, left: &T, right: &T) -> bool {
+        self.order.left_can_go_above(&(self.key)(left), &(self.key)(right))
+    }
+}
+
+fn priority_cmp<T>(left: &T, right: &T, order: &impl HeapOrder<T>) -> std::cmp::Ordering {
+    // Less means "higher priority", so sorting with this puts the top of the heap first
+    if order.left_can_go_above(left, right) {
+        std::cmp::Ordering::Less
+    } else if order.left_can_go_above(right, left) {
+        std::cmp::Ordering::Greater
+    } else {
+        std::cmp::Ordering::Equal
+    }
+}
+
+pub(crate) fn better_to_rebuild(heap_len: usize, tail_len: usize) -> bool {
+    // Rebuilding the whole heap costs about 2 * total comparisons, while sifting each of the new elements
+    // up costs about log2(heap_len) comparisons each. Same heuristic as std's BinaryHeap.
+    if heap_len == 0 {
+        return true;
+    }
+
+    let total_len = heap_len + tail_len;
+    let log2_heap_len = (usize::BITS - heap_len.leading_zeros() - 1) as usize;
+    if total_len <= 2048 {
+        2 * total_len < tail_len * log2_heap_len
+    } else {
+        2 * total_len < tail_len * 11
+    }
+}
+
+fn sort_heap<T>(data: &mut [T], order: &impl HeapOrder<T>) {
+    // Repeatedly move the top of the heap into the slot just past the end of the shrinking heap. The
+    // result is in reverse heap order, i.e. the top of the heap ends up last.
+    for end in (1..data.len()).rev() {
+        data.swap(0, end);
+        heapify_down(&mut data[..end], 0, order);
+    }
+}
 
-    return true;
+// Sorts the slice so that whatever would be at the top of a heap with this order ends up last, so MaxOrder
+// gives ascending order and MinOrder gives descending order.
+pub fn heap_sort<T>(data: &mut [T], order: &impl HeapOrder<T>) {
+    heapify_in_place(data, order);
+    sort_heap(data, order);
 }
 
 #[derive(Debug, Clone)]
 pub struct Heap<T, Order: HeapOrder<T>> {
+    // Visible to the rest of the crate so that wrappers like IndexedBy can sift the storage themselves
+    pub(crate) data: Vec<T>,
+    pub(crate) order: Order,
+    // The most elements try_insert will let in, however much room the Vec happens to have, or None if the
+    // heap was never bounded
+    pub(crate) bound: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HeapBuilder<T, Order = ()> {
     data: Vec<T>,
+    capacity: usize,
+    bound: Option<usize>,
     order: Order,
+    assume_heapified: bool,
 }
 
-impl<T, Order: HeapOrder<T>> Heap<T, Order> {
-    pub fn from_vec_and_cmp(mut data: Vec<T>, order: Order) -> Self {
-        heapify_in_place(&mut data, &order);
+impl<T> HeapBuilder<T, ()> {
+    // The order has to be supplied with .order() before the heap can be built
+    pub fn new() -> Self {
+        Self::with_order(())
+    }
+}
+
+impl<T> Default for HeapBuilder<T, ()> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
+impl<T, Order> HeapBuilder<T, Order> {
+    pub fn with_order(order: Order) -> Self {
         Self {
-            data,
+            data: Vec::new(),
+            capacity: 0,
+            bound: None,
             order,
+            assume_heapified: false,
         }
     }
 
-    pub fn with_capacity_and_cmp(capacity: usize, order: Order) -> Self {
-        Self {
-            data: Vec::with_capacity(capacity),
+    pub fn order<NewOrder: HeapOrder<T>>(self, order: NewOrder) -> HeapBuilder<T, NewOrder> {
+        HeapBuilder {
+            data: self.data,
+            capacity: self.capacity,
+            bound: self.bound,
             order,
+            assume_heapified: self.assume_heapified,
         }
     }
 
-    pub unsafe fn unsafe_from_heap_and_cmp(data: Vec<T>, order: Order) -> Self {
-        debug_assert!(is_heap(&data, &order), "Heap is not valid");
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
 
-        Self {
+    // Makes bound the most elements that try_insert will let into the heap, and reserves room for them all
+    // up front so that a heap only ever added to with try_insert never reallocates. Plain insert isn't
+    // bounded, since plenty of code inside the crate and out relies on it always going in.
+    pub fn bounded(mut self, bound: usize) -> Self {
+        self.capacity = self.capacity.max(bound);
+        self.bound = Some(bound);
+        self
+    }
+
+    pub fn items(mut self, items: impl IntoIterator<Item = T>) -> Self {
+        // Collecting a Vec's IntoIter reuses its allocation, so passing a Vec here doesn't copy it
+        if self.data.is_empty() {
+            self.data = items.into_iter().collect();
+        } else {
+            self.data.extend(items);
+        }
+
+        self
+    }
+
+    /// # Safety
+    ///
+    /// The items must already satisfy the heap property under the order that the heap is built with. This
+    /// is only checked in debug builds.
+    pub unsafe fn assume_heapified(mut self) -> Self {
+        self.assume_heapified = true;
+        self
+    }
+}
+
+impl<T, Order: HeapOrder<T>> HeapBuilder<T, Order> {
+    pub fn build(self) -> Heap<T, Order> {
+        let Self { mut data, capacity, bound, order, assume_heapified } = self;
+        data.reserve_exact(capacity.saturating_sub(data.len()));
+
+        if assume_heapified {
+            debug_assert!(is_heap(&data, &order), "Heap is not valid");
+        } else {
+            heapify_in_place(&mut data, &order);
+        }
+
+        Heap {
             data,
             order,
+            bound,
         }
     }
+}
+
+impl<T, Order: HeapOrder<T>> Heap<T, Order> {
+    pub fn builder() -> HeapBuilder<T, Order> where Order: Default {
+        HeapBuilder::with_order(Order::default())
+    }
+
+    pub fn from_vec_and_cmp(data: Vec<T>, order: Order) -> Self {
+        HeapBuilder::with_order(order).items(data).build()
+    }
+
+    pub fn with_capacity_and_cmp(capacity: usize, order: Order) -> Self {
+        HeapBuilder::with_order(order).capacity(capacity).build()
+    }
+
+    /// # Safety
+    ///
+    /// `data` must already satisfy the heap property under `order`. This is only checked in debug builds.
+    pub unsafe fn unsafe_from_heap_and_cmp(data: Vec<T>, order: Order) -> Self {
+        HeapBuilder::with_order(order).items(data).assume_heapified().build()
+    }
 
     pub fn try_from_heap_and_cmp(data: Vec<T>, order: Order) -> Option<Self> {
         if is_heap(&data, &order) {
@@ -141,9 +328,75 @@ impl<T, Order: HeapOrder<T>> Heap<T, Order> {
 
 impl<T, Order: HeapOrder<T>> Extend<T> for Heap<T, Order> {
     fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
-        for item in iter {
-            self.insert(item)
+        // Push everything onto the end, and then decide whether it is cheaper to sift each new element
+        // up or to heapify the whole lot again
+        let start = self.data.len();
+        self.data.extend(iter);
+        self.rebuild_tail(start);
+    }
+}
+
+// Two heaps are equal if they hold the same elements, regardless of where they ended up in the storage. The
+// orders aren't compared since there's generally no way to.
+impl<T: Ord, Order: HeapOrder<T>> PartialEq for Heap<T, Order> {
+    fn eq(&self, other: &Self) -> bool {
+        if self.data.len() != other.data.len() {
+            return false;
         }
+
+        let mut left: Vec<_> = self.data.iter().collect();
+        let mut right: Vec<_> = other.data.iter().collect();
+        left.sort_unstable();
+        right.sort_unstable();
+
+        left == right
+    }
+}
+
+impl<T: Ord, Order: HeapOrder<T>> Eq for Heap<T, Order> { }
+
+impl<T: std::hash::Hash, Order: HeapOrder<T>> std::hash::Hash for Heap<T, Order> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        // Hash each element on its own and combine them with an addition, which doesn't care about order. The
+        // per element hasher has fixed keys so equal heaps always produce the same combined value.
+        let combined = self.data.iter().fold(0u64, |combined, value| {
+            let mut hasher = std::hash::DefaultHasher::new();
+            value.hash(&mut hasher);
+            combined.wrapping_add(std::hash::Hasher::finish(&hasher))
+        });
+
+        state.write_usize(self.data.len());
+        state.write_u64(combined);
+    }
+}
+
+impl<T, Order: HeapOrder<T>> IntoIterator for Heap<T, Order> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, T, Order: HeapOrder<T>> IntoIterator for &'a Heap<T, Order> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<T, K: Ord, F: Fn(&T) -> K> Heap<T, KeyOrder<T, K, F, MinOrder<K>>> {
+    pub fn min_by_key(data: Vec<T>, key: F) -> Self {
+        Self::from_vec_and_cmp(data, KeyOrder::new(key, MinOrder::default()))
+    }
+}
+
+impl<T, K: Ord, F: Fn(&T) -> K> Heap<T, KeyOrder<T, K, F, MaxOrder<K>>> {
+    pub fn max_by_key(data: Vec<T>, key: F) -> Self {
+        Self::from_vec_and_cmp(data, KeyOrder::new(key, MaxOrder::default()))
     }
 }
 
@@ -160,6 +413,9 @@ impl<T: Ord> Heap<T, MaxOrder<T>> {
         Self::from_vec_and_cmp(data, MaxOrder::default())
     }
 
+    /// # Safety
+    ///
+    /// `data` must already be a valid max heap. See [`Heap::unsafe_from_heap_and_cmp`].
     pub unsafe fn unsafe_from_max_heap(data: Vec<T>) -> Self {
         Self::unsafe_from_heap_and_cmp(data, MaxOrder::default())
     }
@@ -167,6 +423,13 @@ impl<T: Ord> Heap<T, MaxOrder<T>> {
     pub fn try_from_max_heap(data: Vec<T>) -> Option<Self> {
         Self::try_from_heap_and_cmp(data, MaxOrder::default())
     }
+
+    // A vector sorted in descending order is already a max heap, so there is nothing to do beyond a
+    // sanity check in debug builds
+    pub fn from_sorted_vec_desc(data: Vec<T>) -> Self {
+        debug_assert!(data.is_sorted_by(|left, right| left >= right), "Vec is not sorted in descending order");
+        unsafe { Self::unsafe_from_max_heap(data) }
+    }
 }
 
 pub type MaxHeap<T> = Heap<T, MaxOrder<T>>;
@@ -185,13 +448,7 @@ impl<T: Ord> From<Vec<T>> for MaxHeap<T> {
 
 impl<T: Ord> FromIterator<T> for MaxHeap<T> {
     fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
-        let iter = iter.into_iter();
-        let mut ret = Self::with_capacity(iter.size_hint().0);
-        for item in iter {
-            ret.insert(item);
-        }
-        
-        ret
+        Self::from(iter.into_iter().collect::<Vec<_>>())
     }
 }
 
@@ -208,6 +465,9 @@ impl<T: Ord> Heap<T, MinOrder<T>> {
         Self::from_vec_and_cmp(data, MinOrder::default())
     }
 
+    /// # Safety
+    ///
+    /// `data` must already be a valid min heap. See [`Heap::unsafe_from_heap_and_cmp`].
     pub unsafe fn unsafe_from_min_heap(data: Vec<T>) -> Self {
         Self::unsafe_from_heap_and_cmp(data, MinOrder::default())
     }
@@ -215,6 +475,12 @@ impl<T: Ord> Heap<T, MinOrder<T>> {
     pub fn try_from_min_heap(data: Vec<T>) -> Option<Self> {
         Self::try_from_heap_and_cmp(data, MinOrder::default())
     }
+
+    // Likewise an ascending vector is already a min heap
+    pub fn from_sorted_vec(data: Vec<T>) -> Self {
+        debug_assert!(data.is_sorted(), "Vec is not sorted in ascending order");
+        unsafe { Self::unsafe_from_min_heap(data) }
+    }
 }
 
 pub type MinHeap<T> = Heap<T, MinOrder<T>>;
@@ -233,21 +499,82 @@ impl<T: Ord> From<Vec<T>> for MinHeap<T> {
 
 impl<T: Ord> FromIterator<T> for MinHeap<T> {
     fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
-        let iter = iter.into_iter();
-        let mut ret = Self::with_capacity(iter.size_hint().0);
-        for item in iter {
-            ret.insert(item);
-        }
-        
-        ret
+        Self::from(iter.into_iter().collect::<Vec<_>>())
+    }
+}
+
+impl<T: Float> Heap<T, FloatOrder> {
+    pub fn new() -> Self {
+        Self::float_min(Vec::new())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_cmp(capacity, FloatOrder)
+    }
+
+    pub fn float_min(data: Vec<T>) -> Self {
+        Self::from_vec_and_cmp(data, FloatOrder)
+    }
+}
+
+pub type FloatMinHeap<T> = Heap<T, FloatOrder>;
+
+impl<T: Float> Default for FloatMinHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float> From<Vec<T>> for FloatMinHeap<T> {
+    fn from(f: Vec<T>) -> Self {
+        Self::float_min(f)
+    }
+}
+
+impl<T: Float> Heap<T, ReverseOrder<FloatOrder>> {
+    pub fn new() -> Self {
+        Self::float_max(Vec::new())
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_and_cmp(capacity, ReverseOrder(FloatOrder))
+    }
+
+    pub fn float_max(data: Vec<T>) -> Self {
+        Self::from_vec_and_cmp(data, ReverseOrder(FloatOrder))
+    }
+}
+
+pub type FloatMaxHeap<T> = Heap<T, ReverseOrder<FloatOrder>>;
+
+impl<T: Float> Default for FloatMaxHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Float> From<Vec<T>> for FloatMaxHeap<T> {
+    fn from(f: Vec<T>) -> Self {
+        Self::float_max(f)
     }
 }
 
 impl<T, Order: HeapOrder<T>> Heap<T, Order> {
-    pub fn values(&self) -> &[T] {
+    pub fn as_slice(&self) -> &[T] {
         &self.data
     }
 
+    #[deprecated(note = "use as_slice instead")]
+    pub fn values(&self) -> &[T] {
+        self.as_slice()
+    }
+
+    // Gives mutable access to the storage in whatever order it happens to be in. The heap is rebuilt when
+    // the guard is dropped, so the caller can do whatever they like to the elements in the meantime.
+    pub fn as_mut_slice(&mut self) -> RebuildOnDrop<'_, T, Order> {
+        RebuildOnDrop { heap: self }
+    }
+
     pub fn order(&self) -> &Order {
         &self.order
     }
@@ -256,10 +583,134 @@ impl<T, Order: HeapOrder<T>> Heap<T, Order> {
         self.data.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    // The most elements try_insert will let in, if the heap was built bounded
+    pub fn bound(&self) -> Option<usize> {
+        self.bound
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional);
+    }
+
+    pub fn reserve_exact(&mut self, additional: usize) {
+        self.data.reserve_exact(additional);
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.data.shrink_to_fit();
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+
+    #[deprecated(note = "use into_vec instead")]
     pub fn to_values(self) -> Vec<T> {
+        self.into_vec()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    pub fn drain(&mut self) -> std::vec::Drain<'_, T> {
+        // An empty heap is trivially valid, and Vec's drain already empties the vector even if the
+        // iterator is dropped part way through, so there is nothing to restore afterwards
+        self.data.drain(..)
+    }
+
+    pub fn drain_sorted(&mut self) -> DrainSorted<'_, T, Order> {
+        DrainSorted { heap: self }
+    }
+
+    pub fn into_iter_sorted(self) -> IntoIterSorted<T, Order> {
+        IntoIterSorted { heap: self }
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    // Sorts the heap's storage in place and returns it. The top of the heap ends up at the end of the vector,
+    // so a MaxHeap gives ascending order like BinaryHeap::into_sorted_vec, and a MinHeap descending order.
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        sort_heap(&mut self.data, &self.order);
         self.data
     }
 
+    pub fn parent(&self, index: usize) -> Option<usize> {
+        if index > 0 && index < self.data.len() {
+            Some((index - 1) / 2)
+        } else {
+            None
+        }
+    }
+
+    // The children of a node are always next to each other, so they can be returned as a range. It will
+    // be empty for leaf nodes.
+    pub fn children(&self, index: usize) -> std::ops::Range<usize> {
+        let first_child_index = (2 * index + 1).min(self.data.len());
+        let end_index = (2 * index + 3).min(self.data.len());
+        first_child_index..end_index
+    }
+
+    pub fn levels(&self) -> Levels<'_, T> {
+        Levels {
+            remaining: &self.data,
+            width: 1,
+        }
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    pub fn peek_n(&self, count: usize) -> Vec<&T> {
+        let count = count.min(self.data.len());
+        let mut ret = Vec::with_capacity(count);
+
+        // The next best element is always a child of one we've already taken, so keep a heap of the
+        // candidate indices. It never holds more than count + 1 of them.
+        let data = &self.data;
+        let order = &self.order;
+        let mut candidates = Heap::with_capacity_and_cmp(count + 1, |left: &usize, right: &usize| {
+            order.left_can_go_above(&data[*left], &data[*right])
+        });
+
+        if count > 0 {
+            candidates.insert(0);
+        }
+
+        while ret.len() < count {
+            let index = candidates.pop().unwrap();
+            ret.push(&data[index]);
+
+            for child_index in [2 * index + 1, 2 * index + 2] {
+                if child_index < data.len() {
+                    candidates.insert(child_index);
+                }
+            }
+        }
+
+        ret
+    }
+
+    pub fn peek_mut(&mut self) -> Option<PeekMut<'_, T, Order>> {
+        if self.data.is_empty() {
+            None
+        } else {
+            Some(PeekMut { heap: self, modified: false })
+        }
+    }
+
     pub fn insert(&mut self, value: T) {
         // Insert the new item in the left most open slot. Which in practise just means "push it to the end"
         let new_node_index = self.data.len();
@@ -270,49 +721,396 @@ impl<T, Order: HeapOrder<T>> Heap<T, Order> {
         heapify_up(&mut self.data, new_node_index, &self.order);
     }
 
-    pub fn remove(&mut self, index: usize) -> T {
-        // Take out the element we are removing, and put the last element in it's
-        // place. I've never understood what this function was for, but now I know.
-        let ret = self.data.swap_remove(index);
+    pub fn append(&mut self, other: &mut Self) {
+        // Always append the smaller heap onto the larger one. This assumes both heaps were built with
+        // equivalent orders, which they will be unless the order carries state.
+        if self.data.len() < other.data.len() {
+            std::mem::swap(&mut self.data, &mut other.data);
+        }
+
+        let start = self.data.len();
+        self.data.append(&mut other.data);
+        self.rebuild_tail(start);
+    }
 
-        if !self.data.is_empty() {
-            if self.order.left_can_go_above(&self.data[index], &ret) {
-                // The new element can be higher up the tree than the original element, so we
-                // do an up-heapify to make sure the heap property is maintained
+    fn rebuild_tail(&mut self, start: usize) {
+        // Everything before start is a valid heap, everything from start onwards is unordered
+        let tail_len = self.data.len() - start;
+        if tail_len == 0 {
+            return;
+        }
+
+        if better_to_rebuild(start, tail_len) {
+            heapify_in_place(&mut self.data, &self.order);
+        } else {
+            for index in start..self.data.len() {
                 heapify_up(&mut self.data, index, &self.order);
-            } else if self.order.left_can_go_above(&ret, &self.data[index]) {
-                // The value we removed could go below the value we replaced it with, so down-heapify
-                heapify_down(&mut self.data, index, &self.order);
             }
         }
-
-        ret
     }
-}
 
-impl<T: std::fmt::Debug, Order: HeapOrder<T>> Heap<T, Order> {
-    pub fn tree_format(&self) -> TreeFormatHeap<'_, T, Order> {
-        TreeFormatHeap(self)
+    pub fn pop_n(&mut self, count: usize) -> Vec<T> {
+        let count = count.min(self.data.len());
+        let mut ret = Vec::with_capacity(count);
+        ret.extend(std::iter::from_fn(|| self.pop()).take(count));
+        ret
     }
-}
-
-#[repr(transparent)]
-pub struct TreeFormatHeap<'a, T: std::fmt::Debug, Order: HeapOrder<T>>(&'a Heap<T, Order>);
 
-impl<'a, T: std::fmt::Debug, Order: HeapOrder<T>> std::fmt::Debug for TreeFormatHeap<'a, T, Order> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut row_width = 1;
-        let mut row_start = 0;
-        let values = self.0.values();
+    pub fn pop_all_top(&mut self) -> Vec<T> {
+        let mut ret = Vec::new();
+        ret.extend(self.pop());
 
-        for (idx, value) in values.iter().enumerate() {
-            if idx == row_start + row_width {
-                writeln!(f)?;
-                row_start += row_width;
-                row_width *= 2;
+        // Nothing left in the heap can go above the first value we popped, so anything that the first value
+        // can't go above either must be equal to it
+        while let Some(top) = self.data.first() {
+            if self.order.left_can_go_above(&ret[0], top) {
+                break;
             }
 
-            write!(f, "{}: {:?} ", idx, value)?;
+            ret.extend(self.pop());
+        }
+
+        ret
+    }
+
+    pub fn push_pop(&mut self, value: T) -> T {
+        match self.data.first() {
+            // If the current top can go above the new value then it is the one that comes out, and the
+            // new value takes its place at the root
+            Some(top) if self.order.left_can_go_above(top, &value) => {
+                let ret = std::mem::replace(&mut self.data[0], value);
+                heapify_down(&mut self.data, 0, &self.order);
+                ret
+            }
+
+            // Otherwise the new value would have gone straight to the top and come straight back out
+            _ => value,
+        }
+    }
+
+    pub fn replace(&mut self, value: T) -> Option<T> {
+        if self.data.is_empty() {
+            self.data.push(value);
+            return None;
+        }
+
+        // Unlike push_pop, the old top always comes out, even if the new value would have beaten it
+        let ret = std::mem::replace(&mut self.data[0], value);
+        heapify_down(&mut self.data, 0, &self.order);
+
+        Some(ret)
+    }
+
+    pub fn retain(&mut self, mut f: impl FnMut(&T) -> bool) {
+        let original_len = self.data.len();
+        self.data.retain(|value| f(value));
+
+        // Only pay for the rebuild if something was actually removed
+        if self.data.len() != original_len {
+            heapify_in_place(&mut self.data, &self.order);
+        }
+    }
+
+    // Inserts the value only if the heap is under the bound it was built with, handing it back otherwise. A
+    // heap that was never bounded takes everything.
+    pub fn try_insert(&mut self, value: T) -> Result<(), T> {
+        if self.bound.is_some_and(|bound| self.data.len() >= bound) {
+            Err(value)
+        } else {
+            self.insert(value);
+            Ok(())
+        }
+    }
+
+    pub fn truncate_top(&mut self, len: usize) {
+        if len >= self.data.len() {
+            return;
+        }
+
+        // Throw away everything but the best len elements and then rebuild what is left. That is O(n)
+        // overall rather than popping len elements one at a time.
+        self.partition_top(len);
+        self.data.truncate(len);
+        heapify_in_place(&mut self.data, &self.order);
+    }
+
+    pub fn split_off_top(&mut self, len: usize) -> Self where Order: Clone {
+        if len >= self.data.len() {
+            let data = std::mem::take(&mut self.data);
+            return unsafe { Self::unsafe_from_heap_and_cmp(data, self.order.clone()) };
+        }
+
+        self.partition_top(len);
+        let rest = self.data.split_off(len);
+        let top = std::mem::replace(&mut self.data, rest);
+        heapify_in_place(&mut self.data, &self.order);
+
+        Self::from_vec_and_cmp(top, self.order.clone())
+    }
+
+    fn partition_top(&mut self, len: usize) {
+        // Rearrange the storage so that the best len elements are at the front, in no particular order. The
+        // heap property is broken afterwards so the caller has to rebuild.
+        if len > 0 && len < self.data.len() {
+            let order = &self.order;
+            self.data.select_nth_unstable_by(len - 1, |left, right| priority_cmp(left, right, order));
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        // Same idea as remove, but the replacement is at the root so it can only ever move down
+        let ret = self.data.swap_remove(0);
+        heapify_down(&mut self.data, 0, &self.order);
+
+        Some(ret)
+    }
+
+    pub fn try_remove(&mut self, index: usize) -> Option<T> {
+        if index < self.data.len() {
+            Some(self.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.data.len(), "Cannot remove index {} from a heap of length {}", index, self.data.len());
+
+        // Take out the element we are removing, and put the last element in it's
+        // place. I've never understood what this function was for, but now I know.
+        let ret = self.data.swap_remove(index);
+
+        // If we removed the last element then nothing got moved into its place, so there is nothing to fix
+        if index < self.data.len() {
+            self.restore_after_replace(index, &ret);
+        }
+
+        ret
+    }
+
+    pub fn update(&mut self, index: usize, value: T) -> T {
+        assert!(index < self.data.len(), "Cannot update index {} in a heap of length {}", index, self.data.len());
+
+        let ret = std::mem::replace(&mut self.data[index], value);
+        self.restore_after_replace(index, &ret);
+        ret
+    }
+
+    fn restore_after_replace(&mut self, index: usize, old_value: &T) {
+        // The value at index used to be old_value, which was in the right place. Comparing against that tells
+        // us which direction the new value might need to move in, so we only ever need one sift.
+        if self.order.left_can_go_above(&self.data[index], old_value) {
+            // The new element can be higher up the tree than the original element, so we
+            // do an up-heapify to make sure the heap property is maintained
+            heapify_up(&mut self.data, index, &self.order);
+        } else if self.order.left_can_go_above(old_value, &self.data[index]) {
+            // The value we removed could go below the value we replaced it with, so down-heapify
+            heapify_down(&mut self.data, index, &self.order);
+        }
+    }
+
+    pub fn remove_item(&mut self, value: &T) -> Option<T> where T: PartialEq {
+        let index = self.position(|item| item == value)?;
+        Some(self.remove(index))
+    }
+
+    pub fn contains(&self, value: &T) -> bool where T: PartialEq {
+        self.data.contains(value)
+    }
+
+    // The index returned is only valid until the heap is next modified, since any insert or remove
+    // can move elements around
+    pub fn position(&self, predicate: impl FnMut(&T) -> bool) -> Option<usize> {
+        self.data.iter().position(predicate)
+    }
+}
+
+pub struct RebuildOnDrop<'a, T, Order: HeapOrder<T>> {
+    heap: &'a mut Heap<T, Order>,
+}
+
+impl<'a, T, Order: HeapOrder<T>> std::ops::Deref for RebuildOnDrop<'a, T, Order> {
+    type Target = [T];
+
+    fn deref(&self) -> &[T] {
+        &self.heap.data
+    }
+}
+
+impl<'a, T, Order: HeapOrder<T>> std::ops::DerefMut for RebuildOnDrop<'a, T, Order> {
+    fn deref_mut(&mut self) -> &mut [T] {
+        &mut self.heap.data
+    }
+}
+
+impl<'a, T, Order: HeapOrder<T>> Drop for RebuildOnDrop<'a, T, Order> {
+    fn drop(&mut self) {
+        heapify_in_place(&mut self.heap.data, &self.heap.order);
+    }
+}
+
+pub struct PeekMut<'a, T, Order: HeapOrder<T>> {
+    heap: &'a mut Heap<T, Order>,
+    modified: bool,
+}
+
+impl<'a, T, Order: HeapOrder<T>> PeekMut<'a, T, Order> {
+    pub fn pop(mut this: Self) -> T {
+        // The top is coming out anyway, so there is no point sifting it down first
+        this.modified = false;
+        this.heap.pop().unwrap()
+    }
+}
+
+impl<'a, T, Order: HeapOrder<T>> std::ops::Deref for PeekMut<'a, T, Order> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.heap.data[0]
+    }
+}
+
+impl<'a, T, Order: HeapOrder<T>> std::ops::DerefMut for PeekMut<'a, T, Order> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.modified = true;
+        &mut self.heap.data[0]
+    }
+}
+
+impl<'a, T, Order: HeapOrder<T>> Drop for PeekMut<'a, T, Order> {
+    fn drop(&mut self) {
+        // The caller may have changed the top value so that it no longer belongs there. It can only
+        // ever need to move down, since there is nothing above it.
+        if self.modified {
+            heapify_down(&mut self.heap.data, 0, &self.heap.order);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IntoIterSorted<T, Order: HeapOrder<T>> {
+    heap: Heap<T, Order>,
+}
+
+impl<T, Order: HeapOrder<T>> Iterator for IntoIterSorted<T, Order> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.heap.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl<T, Order: HeapOrder<T>> ExactSizeIterator for IntoIterSorted<T, Order> { }
+
+impl<T, Order: HeapOrder<T>> std::iter::FusedIterator for IntoIterSorted<T, Order> { }
+
+pub struct DrainSorted<'a, T, Order: HeapOrder<T>> {
+    heap: &'a mut Heap<T, Order>,
+}
+
+impl<'a, T, Order: HeapOrder<T>> Iterator for DrainSorted<'a, T, Order> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.heap.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl<'a, T, Order: HeapOrder<T>> ExactSizeIterator for DrainSorted<'a, T, Order> { }
+
+impl<'a, T, Order: HeapOrder<T>> std::iter::FusedIterator for DrainSorted<'a, T, Order> { }
+
+impl<'a, T, Order: HeapOrder<T>> Drop for DrainSorted<'a, T, Order> {
+    fn drop(&mut self) {
+        // Whatever the caller didn't take is dropped, same as Vec::drain. No need to pop them in
+        // order for that.
+        self.heap.clear();
+    }
+}
+
+// Yields each level of the implicit tree in turn, root first. Every level is twice as wide as the one above
+// it, apart from the last which may be partially filled.
+#[derive(Debug, Clone)]
+pub struct Levels<'a, T> {
+    remaining: &'a [T],
+    width: usize,
+}
+
+impl<'a, T> Iterator for Levels<'a, T> {
+    type Item = &'a [T];
+
+    fn next(&mut self) -> Option<&'a [T]> {
+        if self.remaining.is_empty() {
+            return None;
+        }
+
+        let (level, remaining) = self.remaining.split_at(self.width.min(self.remaining.len()));
+        self.remaining = remaining;
+        self.width *= 2;
+
+        Some(level)
+    }
+}
+
+impl<'a, T> std::iter::FusedIterator for Levels<'a, T> { }
+
+impl<T: std::fmt::Debug, Order: HeapOrder<T>> Heap<T, Order> {
+    pub fn tree_format(&self) -> TreeFormatHeap<'_, T, Order> {
+        TreeFormatHeap(self)
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut ret = Vec::new();
+        self.write_dot(&mut ret).expect("Writing to a Vec cannot fail");
+        String::from_utf8(ret).expect("DOT output is always UTF-8")
+    }
+
+    // Writes the implicit tree as a Graphviz digraph, with one node per element labelled with its Debug output
+    pub fn write_dot(&self, mut w: impl std::io::Write) -> std::io::Result<()> {
+        writeln!(w, "digraph heap {{")?;
+
+        for (index, value) in self.data.iter().enumerate() {
+            let label = format!("{:?}", value).replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(w, "    n{} [label=\"{}\"];", index, label)?;
+        }
+
+        for index in 1..self.data.len() {
+            writeln!(w, "    n{} -> n{};", (index - 1) / 2, index)?;
+        }
+
+        writeln!(w, "}}")
+    }
+}
+
+#[repr(transparent)]
+pub struct TreeFormatHeap<'a, T: std::fmt::Debug, Order: HeapOrder<T>>(&'a Heap<T, Order>);
+
+impl<'a, T: std::fmt::Debug, Order: HeapOrder<T>> std::fmt::Debug for TreeFormatHeap<'a, T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut row_width = 1;
+        let mut row_start = 0;
+        let values = self.0.as_slice();
+
+        for (idx, value) in values.iter().enumerate() {
+            if idx == row_start + row_width {
+                writeln!(f)?;
+                row_start += row_width;
+                row_width *= 2;
+            }
+
+            write!(f, "{}: {:?} ", idx, value)?;
         }
 
         Ok(())
@@ -331,7 +1129,7 @@ mod test {
     }
 
     fn check_heap<T, Order: HeapOrder<T>>(heap: &Heap<T, Order>) -> bool {
-        is_heap(heap.values(), heap.order())
+        is_heap(heap.as_slice(), heap.order())
     }
 
     #[test]
@@ -340,6 +1138,18 @@ mod test {
         assert!(is_heap(&[4, 3, 2, 1], &MaxOrder(std::marker::PhantomData)));
         assert!(is_heap(&[4, 2, 3, 1], &MaxOrder(std::marker::PhantomData)));
         assert!(!is_heap(&[4, 2, 3, 7], &MaxOrder(std::marker::PhantomData)));
+        assert!(is_heap(&[4, 4, 4, 1], &MaxOrder(std::marker::PhantomData)));
+    }
+
+    #[test]
+    fn test_from_sorted_vec() {
+        let heap = MinHeap::from_sorted_vec(vec![1, 1, 2, 3, 3, 3, 4]);
+        assert!(check_heap(&heap));
+        assert_eq!(heap.peek(), Some(&1));
+
+        let heap = MaxHeap::from_sorted_vec_desc(vec![4, 3, 3, 3, 2, 1, 1]);
+        assert!(check_heap(&heap));
+        assert_eq!(heap.peek(), Some(&4));
     }
 
     #[test]
@@ -352,4 +1162,437 @@ mod test {
             assert!(check_heap(&heap));
         }
     }
+
+    #[test]
+    fn test_extend() {
+        for (initial_len, extra_len) in [(0, 1000), (1000, 1), (1000, 10), (10, 1000)] {
+            let mut heap = MinHeap::from(shuffle_vec((0..initial_len).collect()));
+            heap.extend(shuffle_vec((initial_len..initial_len + extra_len).collect()));
+
+            assert!(check_heap(&heap));
+            assert_eq!(heap.len(), initial_len + extra_len);
+        }
+
+        let heap: MaxHeap<_> = shuffle_vec((0..1000).collect()).into_iter().collect();
+        assert!(check_heap(&heap));
+    }
+
+    #[test]
+    fn test_key_order() {
+        let words = vec!["felicity", "a", "heap", "is", "nice"];
+
+        let heap = Heap::min_by_key(words.clone(), |word: &&str| word.len());
+        assert!(check_heap(&heap));
+        assert_eq!(heap.peek(), Some(&"a"));
+
+        let heap = Heap::max_by_key(words, |word: &&str| word.len());
+        assert!(check_heap(&heap));
+        assert_eq!(heap.peek(), Some(&"felicity"));
+    }
+
+    #[test]
+    fn test_reverse_order() {
+        let by_len = |left: &&str, right: &&str| left.len() < right.len();
+        let words = vec!["felicity", "a", "heap", "is", "nice"];
+
+        let heap = Heap::from_vec_and_cmp(words.clone(), by_len);
+        assert_eq!(heap.peek(), Some(&"a"));
+
+        let heap = Heap::from_vec_and_cmp(words, ReverseOrder(by_len));
+        assert!(check_heap(&heap));
+        assert_eq!(heap.peek(), Some(&"felicity"));
+    }
+
+    #[test]
+    fn test_float_order() {
+        let values = vec![1.5, f64::NAN, -2.0, f64::INFINITY, 0.0, -f64::NAN];
+
+        let heap = FloatMinHeap::from(values.clone());
+        let popped: Vec<_> = heap.into_iter_sorted().collect();
+        assert!(popped[0].is_nan() && popped[0].is_sign_negative());
+        assert_eq!(&popped[1..5], &[-2.0, 0.0, 1.5, f64::INFINITY]);
+        assert!(popped[5].is_nan());
+
+        let mut heap = FloatMaxHeap::with_capacity(values.len());
+        heap.extend(values.into_iter().filter(|value| !value.is_nan()));
+        assert_eq!(heap.into_iter_sorted().collect::<Vec<_>>(), vec![f64::INFINITY, 1.5, 0.0, -2.0]);
+    }
+
+    #[test]
+    fn test_stable_order() {
+        let by_priority = |left: &(u32, char), right: &(u32, char)| left.0 < right.0;
+        let mut heap = Heap::with_capacity_and_cmp(8, StableOrder(by_priority));
+
+        let tasks = [(2, 'a'), (1, 'b'), (2, 'c'), (1, 'd'), (2, 'e'), (1, 'f')];
+        heap.extend(tasks.into_iter().enumerate().map(|(sequence, task)| Sequenced::new(sequence as u64, task)));
+
+        let popped: String = heap.into_iter_sorted().map(|task| task.into_inner().1).collect();
+        assert_eq!(popped, "bdface");
+    }
+
+    #[test]
+    fn test_as_mut_slice() {
+        let mut heap = MinHeap::from(shuffle_vec((0..100).collect()));
+        heap.as_mut_slice().iter_mut().for_each(|value| *value = 99 - *value);
+
+        assert!(check_heap(&heap));
+        assert_eq!(heap.peek(), Some(&0));
+        assert_eq!(heap.into_vec().len(), 100);
+    }
+
+    #[test]
+    fn test_multiset_equality() {
+        let hash = |heap: &MinHeap<i32>| {
+            let mut hasher = std::hash::DefaultHasher::new();
+            std::hash::Hash::hash(heap, &mut hasher);
+            std::hash::Hasher::finish(&hasher)
+        };
+
+        let left: MinHeap<_> = (0..100).chain(0..10).collect();
+        let right = MinHeap::from(shuffle_vec((0..100).chain(0..10).collect()));
+        assert_eq!(left, right);
+        assert_eq!(hash(&left), hash(&right));
+
+        let different: MinHeap<_> = (0..100).chain(1..11).collect();
+        assert_ne!(left, different);
+        assert_ne!(left, MinHeap::from(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn test_builder() {
+        let heap = HeapBuilder::new()
+            .capacity(100)
+            .items(shuffle_vec((0..10).collect()))
+            .items(10..20)
+            .order(MinOrder::default())
+            .build();
+        assert!(check_heap(&heap));
+        assert!(heap.capacity() >= 100);
+        assert_eq!(heap.peek(), Some(&0));
+
+        let heap = unsafe { MaxHeap::builder().items(vec![9, 5, 8, 1]).assume_heapified() }.build();
+        assert_eq!(heap.as_slice(), &[9, 5, 8, 1]);
+    }
+
+    #[test]
+    fn test_peek() {
+        let mut heap = MinHeap::new();
+        assert_eq!(heap.peek(), None);
+        assert!(heap.peek_mut().is_none());
+
+        heap.extend(shuffle_vec((0..100).collect()));
+        assert_eq!(heap.peek(), Some(&0));
+
+        *heap.peek_mut().unwrap() = 1000;
+        assert!(check_heap(&heap));
+        assert_eq!(heap.peek(), Some(&1));
+
+        let top = heap.peek_mut().unwrap();
+        assert_eq!(PeekMut::pop(top), 1);
+        assert!(check_heap(&heap));
+        assert_eq!(heap.peek(), Some(&2));
+    }
+
+    #[test]
+    fn test_peek_n() {
+        let heap = MinHeap::from(shuffle_vec((0..1000).collect()));
+        assert_eq!(heap.peek_n(5), vec![&0, &1, &2, &3, &4]);
+        assert_eq!(heap.peek_n(0), Vec::<&i32>::new());
+        assert_eq!(heap.peek_n(2000).len(), 1000);
+        assert!(heap.peek_n(2000).is_sorted());
+    }
+
+    #[test]
+    fn test_split_off_top() {
+        let mut heap = MaxHeap::from(shuffle_vec((0..100).collect()));
+        let top = heap.split_off_top(10);
+        assert!(check_heap(&top) && check_heap(&heap));
+        assert_eq!(top.into_iter_sorted().collect::<Vec<_>>(), (90..100).rev().collect::<Vec<_>>());
+        assert_eq!(heap.peek(), Some(&89));
+
+        let everything = heap.split_off_top(1000);
+        assert!(heap.is_empty());
+        assert_eq!(everything.len(), 90);
+    }
+
+    #[test]
+    fn test_pop() {
+        let mut heap = MaxHeap::from(shuffle_vec((0..1000).collect()));
+        for expected in (0..1000).rev() {
+            assert_eq!(heap.pop(), Some(expected));
+            assert!(check_heap(&heap));
+        }
+
+        assert_eq!(heap.pop(), None);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut heap = MinHeap::with_capacity(100);
+        heap.extend(0..100);
+        heap.clear();
+
+        assert!(heap.is_empty());
+        assert!(heap.capacity() >= 100);
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let heap = MinHeap::from(shuffle_vec((0..100).collect()));
+
+        let mut borrowed: Vec<_> = (&heap).into_iter().copied().collect();
+        let mut owned: Vec<_> = heap.into_iter().collect();
+        borrowed.sort();
+        owned.sort();
+
+        assert_eq!(borrowed, (0..100).collect::<Vec<_>>());
+        assert_eq!(owned, borrowed);
+    }
+
+    #[test]
+    fn test_drain() {
+        let mut heap = MinHeap::with_capacity(100);
+        heap.extend(0..100);
+
+        let mut drained: Vec<_> = heap.drain().collect();
+        drained.sort();
+        assert_eq!(drained, (0..100).collect::<Vec<_>>());
+        assert!(heap.is_empty());
+
+        heap.extend(0..100);
+        heap.drain().take(10).for_each(drop);
+        assert!(heap.is_empty());
+        assert!(heap.into_vec().capacity() >= 100);
+    }
+
+    #[test]
+    fn test_sorted_iterators() {
+        let heap = MaxHeap::from(shuffle_vec((0..100).collect()));
+        assert_eq!(heap.into_iter_sorted().collect::<Vec<_>>(), (0..100).rev().collect::<Vec<_>>());
+
+        let mut heap = MinHeap::from(shuffle_vec((0..100).collect()));
+        assert_eq!(heap.drain_sorted().take(10).collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn test_into_sorted_vec() {
+        let heap = MaxHeap::from(shuffle_vec((0..1000).collect()));
+        assert_eq!(heap.into_sorted_vec(), (0..1000).collect::<Vec<_>>());
+
+        let heap = MinHeap::from(shuffle_vec((0..1000).collect()));
+        assert_eq!(heap.into_sorted_vec(), (0..1000).rev().collect::<Vec<_>>());
+
+        assert!(MinHeap::<i32>::new().into_sorted_vec().is_empty());
+    }
+
+    #[test]
+    fn test_heap_sort() {
+        let mut values: Vec<_> = shuffle_vec((0..1000).collect());
+        heap_sort(&mut values, &MaxOrder::default());
+        assert_eq!(values, (0..1000).collect::<Vec<_>>());
+
+        let mut words = ["felicity", "a", "heap", "is", "nice"];
+        heap_sort(&mut words, &|left: &&str, right: &&str| left.len() < right.len());
+        assert_eq!(words.map(str::len), [8, 4, 4, 2, 1]);
+
+        heap_sort(&mut [0u32; 0], &MinOrder::default());
+    }
+
+    #[test]
+    fn test_append() {
+        for (left_len, right_len) in [(0, 10), (10, 0), (1000, 3), (3, 1000), (500, 500)] {
+            let mut left = MinHeap::from(shuffle_vec((0..left_len).collect()));
+            let mut right = MinHeap::from(shuffle_vec((left_len..left_len + right_len).collect()));
+
+            left.append(&mut right);
+            assert!(right.is_empty());
+            assert!(check_heap(&left));
+            assert_eq!(left.into_iter_sorted().collect::<Vec<_>>(), (0..left_len + right_len).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_retain() {
+        let mut heap = MinHeap::from(shuffle_vec((0..1000).collect()));
+        heap.retain(|value| value % 3 == 0);
+
+        assert!(check_heap(&heap));
+        assert_eq!(heap.into_iter_sorted().collect::<Vec<_>>(), (0..1000).step_by(3).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_pop_n() {
+        let mut heap = MaxHeap::from(shuffle_vec((0..100).collect()));
+        assert_eq!(heap.pop_n(5), vec![99, 98, 97, 96, 95]);
+        assert!(check_heap(&heap));
+        assert_eq!(heap.pop_n(0), Vec::<i32>::new());
+        assert_eq!(heap.pop_n(1000).len(), 95);
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn test_pop_all_top() {
+        let mut heap = Heap::min_by_key(shuffle_vec((0..10).collect()), |value: &i32| value / 3);
+        let mut popped = heap.pop_all_top();
+        popped.sort();
+        assert_eq!(popped, vec![0, 1, 2]);
+        assert_eq!(heap.len(), 7);
+        assert!(check_heap(&heap));
+
+        assert_eq!(Heap::min_by_key(vec![9], |value: &i32| value / 3).pop_all_top(), vec![9]);
+        assert!(MinHeap::<i32>::new().pop_all_top().is_empty());
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let mut heap = MinHeap::new();
+        assert_eq!(heap.push_pop(5), 5);
+        assert!(heap.is_empty());
+
+        heap.extend([10, 20, 30]);
+        assert_eq!(heap.push_pop(5), 5);
+        assert_eq!(heap.push_pop(25), 10);
+        assert!(check_heap(&heap));
+        assert_eq!(heap.into_iter_sorted().collect::<Vec<_>>(), vec![20, 25, 30]);
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut heap = MinHeap::new();
+        assert_eq!(heap.replace(5), None);
+        assert_eq!(heap.replace(1), Some(5));
+
+        heap.extend([10, 20, 30]);
+        assert_eq!(heap.replace(25), Some(1));
+        assert!(check_heap(&heap));
+        assert_eq!(heap.into_iter_sorted().collect::<Vec<_>>(), vec![10, 20, 25, 30]);
+    }
+
+    #[test]
+    fn test_try_insert() {
+        let mut heap: MinHeap<u32> = HeapBuilder::with_order(MinOrder::default()).bounded(4).build();
+        assert_eq!(heap.bound(), Some(4));
+        assert!(heap.capacity() >= 4);
+
+        for value in 0..4 {
+            assert_eq!(heap.try_insert(value), Ok(()));
+        }
+
+        // However much room the Vec has, the bound is what was asked for, and stays put when the storage
+        // changes underneath it
+        assert_eq!(heap.try_insert(4), Err(4));
+        heap.reserve(100);
+        assert_eq!(heap.try_insert(4), Err(4));
+        assert_eq!(heap.clone().try_insert(4), Err(4));
+        assert_eq!(heap.pop(), Some(0));
+        assert_eq!(heap.try_insert(5), Ok(()));
+        assert!(check_heap(&heap));
+
+        // Zero sized types get a Vec with endless room, but still the bound they were given
+        let mut heap = HeapBuilder::with_order(|_: &(), _: &()| false).bounded(2).build();
+        assert_eq!(heap.try_insert(()), Ok(()));
+        assert_eq!(heap.try_insert(()), Ok(()));
+        assert_eq!(heap.try_insert(()), Err(()));
+
+        // A capacity is only room to grow into, not a bound
+        let mut heap = MinHeap::with_capacity(0);
+        assert_eq!(heap.bound(), None);
+        assert_eq!(heap.try_insert(1), Ok(()));
+        assert_eq!(MinHeap::new().try_insert(1), Ok(()));
+    }
+
+    #[test]
+    fn test_truncate_top() {
+        let mut heap = MaxHeap::from(shuffle_vec((0..1000).collect()));
+        heap.truncate_top(2000);
+        assert_eq!(heap.len(), 1000);
+
+        heap.truncate_top(10);
+        assert!(check_heap(&heap));
+        assert_eq!(heap.clone().into_iter_sorted().collect::<Vec<_>>(), (990..1000).rev().collect::<Vec<_>>());
+
+        heap.truncate_top(0);
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn test_remove_item() {
+        let mut heap = MinHeap::from(shuffle_vec((0..100).collect()));
+        assert_eq!(heap.remove_item(&1000), None);
+
+        for value in shuffle_vec((0..100).collect()) {
+            assert_eq!(heap.remove_item(&value), Some(value));
+            assert!(check_heap(&heap));
+        }
+
+        assert!(heap.is_empty());
+    }
+
+    #[test]
+    fn test_try_remove() {
+        let mut heap = MinHeap::from(vec![3, 1, 2]);
+        assert_eq!(heap.try_remove(3), None);
+        assert_eq!(heap.try_remove(0), Some(1));
+        assert_eq!(heap.try_remove(1), Some(3));
+        assert_eq!(heap.try_remove(0), Some(2));
+        assert_eq!(heap.try_remove(0), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cannot remove index 5 from a heap of length 3")]
+    fn test_remove_out_of_range() {
+        MinHeap::from(vec![3, 1, 2]).remove(5);
+    }
+
+    #[test]
+    fn test_update() {
+        let mut heap = MinHeap::from(shuffle_vec((0..100).collect()));
+        let mut rng = thread_rng();
+
+        for _ in 0..1000 {
+            let index = rng.gen_range(0..heap.len());
+            let value = rng.gen_range(0..1000);
+            let expected = heap.as_slice()[index];
+
+            assert_eq!(heap.update(index, value), expected);
+            assert!(check_heap(&heap));
+        }
+    }
+
+    #[test]
+    fn test_navigation() {
+        let heap = MinHeap::from((0..10).collect::<Vec<_>>());
+        assert_eq!(heap.parent(0), None);
+        assert_eq!(heap.parent(4), Some(1));
+        assert_eq!(heap.parent(10), None);
+        assert_eq!(heap.children(0), 1..3);
+        assert_eq!(heap.children(4), 9..10);
+        assert!(heap.children(5).is_empty());
+
+        let levels: Vec<_> = heap.levels().collect();
+        assert_eq!(levels, vec![&[0][..], &[1, 2], &[3, 4, 5, 6], &[7, 8, 9]]);
+        assert_eq!(MinHeap::<i32>::new().levels().count(), 0);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let heap = MinHeap::from(vec![1, 2, 3]);
+        assert_eq!(heap.to_dot(), "digraph heap {\n    n0 [label=\"1\"];\n    n1 [label=\"2\"];\n    n2 [label=\"3\"];\n    n0 -> n1;\n    n0 -> n2;\n}\n");
+        assert_eq!(MinHeap::<i32>::new().to_dot(), "digraph heap {\n}\n");
+
+        // Debug output for strings includes quotes, which have to be escaped
+        assert!(MinHeap::from(vec!["a"]).to_dot().contains(r#"n0 [label="\"a\""];"#));
+    }
+
+    #[test]
+    fn test_search() {
+        let heap = MinHeap::from(shuffle_vec((0..100).collect()));
+        assert!(heap.contains(&42));
+        assert!(!heap.contains(&100));
+
+        let index = heap.position(|value| *value == 42).unwrap();
+        assert_eq!(heap.as_slice()[index], 42);
+        assert_eq!(heap.position(|value| *value > 100), None);
+    }
 }
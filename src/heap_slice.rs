@@ -0,0 +1,148 @@
+use crate::heap::HeapOrder;
+use crate::raw::{heapify_down, heapify_in_place, heapify_up, is_heap};
+
+// A heap over a buffer that somebody else owns. The heap occupies the front of the slice and the rest of
+// it is spare room. Popping doesn't move anything out of the slice, it just moves the top of the heap to
+// the slot immediately after the heap and shrinks the heap by one, so popping everything leaves the
+// slice sorted with the top of the heap last.
+#[derive(Debug)]
+pub struct HeapSlice<'a, T, Order: HeapOrder<T>> {
+    data: &'a mut [T],
+    len: usize,
+    order: Order,
+}
+
+impl<'a, T, Order: HeapOrder<T>> HeapSlice<'a, T, Order> {
+    pub fn from_slice_and_cmp(data: &'a mut [T], order: Order) -> Self {
+        heapify_in_place(data, &order);
+
+        Self {
+            len: data.len(),
+            data,
+            order,
+        }
+    }
+
+    // Uses the first len elements of the slice as the heap, leaving the rest as spare room for push
+    pub fn with_len_and_cmp(data: &'a mut [T], len: usize, order: Order) -> Self {
+        assert!(len <= data.len(), "HeapSlice length {} is longer than the slice ({})", len, data.len());
+        heapify_in_place(&mut data[..len], &order);
+
+        Self {
+            data,
+            len,
+            order,
+        }
+    }
+
+    pub fn try_from_heap_and_cmp(data: &'a mut [T], order: Order) -> Option<Self> {
+        if is_heap(data, &order) {
+            Some(Self {
+                len: data.len(),
+                data,
+                order,
+            })
+        } else {
+            None
+        }
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.data[..self.len]
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn into_slice(self) -> &'a mut [T] {
+        self.data
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.as_slice().first()
+    }
+
+    // The value overwrites whatever was in the next spare slot, which is dropped. If the slice is full
+    // the value is handed back instead.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.len == self.data.len() {
+            return Err(value);
+        }
+
+        self.data[self.len] = value;
+        self.len += 1;
+        heapify_up(&mut self.data[..self.len], self.len - 1, &self.order);
+
+        Ok(())
+    }
+
+    // Returns a reference to the popped value, which now lives just past the end of the heap
+    pub fn pop(&mut self) -> Option<&mut T> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.len -= 1;
+        self.data.swap(0, self.len);
+        heapify_down(&mut self.data[..self.len], 0, &self.order);
+
+        Some(&mut self.data[self.len])
+    }
+
+    pub fn replace(&mut self, value: T) -> Option<T> {
+        if self.len == 0 {
+            return self.push(value).err();
+        }
+
+        let ret = std::mem::replace(&mut self.data[0], value);
+        heapify_down(&mut self.data[..self.len], 0, &self.order);
+
+        Some(ret)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{MaxOrder, MinOrder};
+
+    #[test]
+    fn test_sort_subrange() {
+        let mut values = [9, 8, 7, 3, 1, 2, 5, 4, 6, 0];
+        let mut heap = HeapSlice::from_slice_and_cmp(&mut values[2..8], MaxOrder::default());
+
+        assert_eq!(heap.peek(), Some(&7));
+        while heap.pop().is_some() { }
+
+        assert_eq!(values, [9, 8, 1, 2, 3, 4, 5, 7, 6, 0]);
+    }
+
+    #[test]
+    fn test_push_pop() {
+        let mut buffer = [0; 4];
+        let mut heap = HeapSlice::with_len_and_cmp(&mut buffer, 0, MinOrder::default());
+
+        for value in [30, 10, 40, 20] {
+            assert_eq!(heap.push(value), Ok(()));
+        }
+
+        assert_eq!(heap.push(50), Err(50));
+        assert_eq!(heap.replace(25), Some(10));
+        assert_eq!(heap.pop().copied(), Some(20));
+        assert_eq!(heap.as_slice().len(), 3);
+        assert!(is_heap(heap.as_slice(), heap.order()));
+    }
+}
@@ -0,0 +1,565 @@
+use crate::heap::Heap;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// Huffman coding, the textbook use for a min heap. Every symbol starts out as a leaf weighted by how often
+// it appears, and the two lightest trees in the heap are repeatedly joined under a new node until only
+// one is left. Reading the path down to each leaf, 0 for left and 1 for right, gives a prefix-free code
+// where the most frequent symbols have the shortest codes.
+//
+// The tree itself is awkward to send to whoever has to decode, so a CanonicalCode throws away everything
+// but how long each symbol's code is and deals the codes back out from that, shortest first and in symbol
+// order within each length, the same way DEFLATE does. Both ends only need the lengths to agree on the codes,
+// and decoding can count its way through a table of lengths rather than walking a tree.
+//
+// Codes are kept to 64 bits so that each fits in a u64. A plain Huffman tree can go far deeper than that,
+// with lots of symbols that hardly ever appear or weights that grow like the Fibonacci numbers, so when it
+// does the longest codes are shortened the way JPEG does it, taking codes off the bottom of the tree and
+// hanging them beneath a shorter one. The code is then a little longer than it could be, but only for
+// symbols so rare it makes no difference.
+const MAX_CODE_LENGTH: usize = 64;
+
+// The bits of a code, packed into the bottom len bits of a u64 with the first bit the most significant
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Code {
+    bits: u64,
+    len: u8,
+}
+
+impl Code {
+    pub fn new(bits: u64, len: u8) -> Self {
+        assert!(len <= 64 && (len == 64 || bits >> len == 0), "Cannot fit the bits in a code of that length");
+        Self { bits, len }
+    }
+
+    pub fn bits(&self) -> u64 {
+        self.bits
+    }
+
+    pub fn len(&self) -> u8 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // The bits from first to last
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).rev().map(|bit| self.bits >> bit & 1 != 0)
+    }
+
+    // The code with one more bit on the end. Trees from from_frequencies never go deep enough for this to
+    // panic.
+    fn then(self, bit: bool) -> Self {
+        assert!(self.len < 64, "Cannot have a code over 64 bits long");
+        Self { bits: self.bits << 1 | bit as u64, len: self.len + 1 }
+    }
+}
+
+impl std::fmt::Display for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.iter().try_for_each(|bit| f.write_str(if bit { "1" } else { "0" }))
+    }
+}
+
+impl std::fmt::Debug for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Code({})", self)
+    }
+}
+
+// Encoded codes one after another, packed eight bits to a byte with the first bit the most significant
+#[derive(Clone, Default, PartialEq, Eq, Hash)]
+pub struct Bits {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl Bits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Takes the first len bits of the bytes, anything after them is cleared
+    pub fn from_bytes(mut bytes: Vec<u8>, len: usize) -> Self {
+        assert!(len <= bytes.len() * 8, "Cannot take more bits than there are in the bytes");
+        bytes.truncate(len.div_ceil(8));
+        if !len.is_multiple_of(8) {
+            *bytes.last_mut().unwrap() &= !(0xff >> (len % 8));
+        }
+
+        Self { bytes, len }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push_bit(&mut self, bit: bool) {
+        if self.len.is_multiple_of(8) {
+            self.bytes.push(0);
+        }
+
+        *self.bytes.last_mut().unwrap() |= (bit as u8) << (7 - self.len % 8);
+        self.len += 1;
+    }
+
+    pub fn push(&mut self, code: Code) {
+        code.iter().for_each(|bit| self.push_bit(bit));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(|bit| self.bytes[bit / 8] >> (7 - bit % 8) & 1 != 0)
+    }
+}
+
+impl FromIterator<bool> for Bits {
+    fn from_iter<IntoIter: IntoIterator<Item = bool>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        iter.into_iter().for_each(|bit| ret.push_bit(bit));
+        ret
+    }
+}
+
+impl std::fmt::Display for Bits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.iter().try_for_each(|bit| f.write_str(if bit { "1" } else { "0" }))
+    }
+}
+
+impl std::fmt::Debug for Bits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Bits({})", self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HuffmanTree<S> {
+    Leaf { frequency: usize, symbol: S },
+    Internal { frequency: usize, left: Box<Self>, right: Box<Self> },
+}
+
+impl<S: Hash + Eq + Clone> HuffmanTree<S> {
+    // Builds the tree for the given symbols and how often each one appears, or None if there are none. No
+    // code in it is over 64 bits long.
+    pub fn from_frequencies<IntoIter: IntoIterator<Item = (S, usize)>>(frequencies: IntoIter) -> Option<Self> {
+        let leaves: Vec<(S, usize)> = frequencies.into_iter().collect();
+        if leaves.len() <= 1 {
+            return leaves.into_iter().next().map(|(symbol, frequency)| Self::Leaf { frequency, symbol });
+        }
+
+        // The tree that only ever joins the two lightest would be too deep to even walk recursively if
+        // there were enough rare symbols, so it is only worked out as far as how deep each leaf goes, by
+        // joining positions rather than nodes. Every node is joined under one made after it, so parents can
+        // be filled in from the root back down.
+        let mut parents = vec![0; 2 * leaves.len() - 1];
+        let mut heap = Heap::min_by_key(leaves.iter().map(|(_, frequency)| *frequency).zip(0..).collect(), |(frequency, _): &(usize, usize)| *frequency);
+        for node in leaves.len()..parents.len() {
+            let (left_frequency, left) = heap.pop().unwrap();
+            let (right_frequency, right) = heap.pop().unwrap();
+            (parents[left], parents[right]) = (node, node);
+            // Counts near the top of a usize just stop there, the tree is as good as it can be either way
+            heap.insert((left_frequency.saturating_add(right_frequency), node));
+        }
+
+        let mut depths = vec![0; parents.len()];
+        for node in (0..parents.len() - 1).rev() {
+            depths[node] = depths[parents[node]] + 1;
+        }
+
+        // How many codes there are of each length, with the longest ones taken two at a time off the bottom.
+        // One goes up to where their parent was and the other goes beneath the deepest code that is short
+        // enough, along with the code that was there.
+        let mut counts = vec![0usize; depths[..leaves.len()].iter().max().unwrap() + 1];
+        for depth in &depths[..leaves.len()] {
+            counts[*depth] += 1;
+        }
+
+        for length in (MAX_CODE_LENGTH + 1..counts.len()).rev() {
+            while counts[length] > 0 {
+                let shorter = (1..length - 1).rev().find(|shorter| counts[*shorter] > 0).unwrap();
+                counts[length] -= 2;
+                counts[length - 1] += 1;
+                counts[shorter + 1] += 2;
+                counts[shorter] -= 1;
+            }
+        }
+
+        // The shortest codes go to the most frequent symbols, and are dealt out in order, which fills in the
+        // tree from the left
+        let mut leaves: Vec<(usize, usize, S)> = leaves.into_iter().map(|(symbol, frequency)| (0, frequency, symbol)).collect();
+        leaves.sort_by_key(|leaf| std::cmp::Reverse(leaf.1));
+        let lengths = counts.iter().enumerate().flat_map(|(length, count)| std::iter::repeat_n(length, *count));
+        for (leaf, length) in leaves.iter_mut().zip(lengths) {
+            leaf.0 = length;
+        }
+
+        leaves.sort_by_key(|leaf| leaf.0);
+        Some(Self::from_lengths(&mut leaves.into_iter().peekable(), 0))
+    }
+
+    // Builds the part of the tree below a code of the given length from leaves in code order
+    fn from_lengths<Leaves: Iterator<Item = (usize, usize, S)>>(leaves: &mut std::iter::Peekable<Leaves>, depth: usize) -> Self {
+        if leaves.peek().unwrap().0 == depth {
+            let (_, frequency, symbol) = leaves.next().unwrap();
+            return Self::Leaf { frequency, symbol };
+        }
+
+        let left = Box::new(Self::from_lengths(leaves, depth + 1));
+        let right = Box::new(Self::from_lengths(leaves, depth + 1));
+        Self::Internal { frequency: left.frequency().saturating_add(right.frequency()), left, right }
+    }
+
+    // Counts how often each symbol appears and builds the tree for that
+    pub fn from_symbols<IntoIter: IntoIterator<Item = S>>(symbols: IntoIter) -> Option<Self> {
+        let mut frequencies = HashMap::new();
+        for symbol in symbols {
+            *frequencies.entry(symbol).or_insert(0) += 1;
+        }
+
+        Self::from_frequencies(frequencies)
+    }
+
+    pub fn frequency(&self) -> usize {
+        match self {
+            Self::Leaf { frequency, .. } | Self::Internal { frequency, .. } => *frequency,
+        }
+    }
+
+    // The code for every symbol in the tree
+    pub fn build_codes(&self) -> HashMap<S, Code> {
+        let mut codes = HashMap::new();
+        match self {
+            // A tree with only one symbol would give it an empty code, which can't be decoded since there
+            // would be no telling how many of it there were, so it gets a code of its own
+            Self::Leaf { symbol, .. } => {
+                codes.insert(symbol.clone(), Code::new(0, 1));
+            }
+            Self::Internal { .. } => self.build_codes_with_prefix(Code::default(), &mut codes),
+        }
+
+        codes
+    }
+
+    fn build_codes_with_prefix(&self, prefix: Code, codes: &mut HashMap<S, Code>) {
+        match self {
+            Self::Leaf { symbol, .. } => {
+                codes.insert(symbol.clone(), prefix);
+            }
+            Self::Internal { left, right, .. } => {
+                left.build_codes_with_prefix(prefix.then(false), codes);
+                right.build_codes_with_prefix(prefix.then(true), codes);
+            }
+        }
+    }
+
+    // How long each symbol's code is, which is all a CanonicalCode needs. Panics if any is over 64 bits, which
+    // is only possible in a tree put together by hand.
+    pub fn code_lengths(&self) -> HashMap<S, u8> {
+        let mut lengths = HashMap::new();
+        match self {
+            Self::Leaf { symbol, .. } => {
+                lengths.insert(symbol.clone(), 1);
+            }
+            Self::Internal { .. } => self.code_lengths_with_depth(0, &mut lengths),
+        }
+
+        lengths
+    }
+
+    fn code_lengths_with_depth(&self, depth: u8, lengths: &mut HashMap<S, u8>) {
+        match self {
+            Self::Leaf { symbol, .. } => {
+                lengths.insert(symbol.clone(), depth);
+            }
+            Self::Internal { .. } if depth as usize == MAX_CODE_LENGTH => panic!("Cannot have a code over 64 bits long"),
+            Self::Internal { left, right, .. } => {
+                left.code_lengths_with_depth(depth + 1, lengths);
+                right.code_lengths_with_depth(depth + 1, lengths);
+            }
+        }
+    }
+
+    // Reassigns the codes canonically. The codes are just as short as the tree's own, they only differ in
+    // which bits go with which symbol.
+    pub fn canonical(&self) -> CanonicalCode<S> where S: Ord {
+        // Only a tree put together by hand can have codes that long
+        CanonicalCode::from_lengths(self.code_lengths()).expect("Cannot build a canonical code with codes over 64 bits long")
+    }
+
+    // Encodes the symbols one after another, or returns None if any of them aren't in the tree
+    pub fn encode<'a, IntoIter: IntoIterator<Item = &'a S>>(&self, symbols: IntoIter) -> Option<Bits> where S: 'a {
+        encode(&self.build_codes(), symbols)
+    }
+
+    // Walks the tree for each code in turn. Returns None if the bits stop part way through a code.
+    pub fn decode(&self, bits: &Bits) -> Option<Vec<S>> {
+        let mut ret = Vec::new();
+        let mut node = self;
+        for bit in bits.iter() {
+            node = match (node, bit) {
+                (Self::Leaf { .. }, false) => node,
+                (Self::Internal { left, .. }, false) => left,
+                (Self::Internal { right, .. }, true) => right,
+                (Self::Leaf { .. }, true) => return None,
+            };
+
+            if let Self::Leaf { symbol, .. } = node {
+                ret.push(symbol.clone());
+                node = self;
+            }
+        }
+
+        // Anywhere but back at the root means the last code was cut short
+        (std::ptr::eq(node, self)).then_some(ret)
+    }
+}
+
+fn encode<'a, S: Hash + Eq + 'a, IntoIter: IntoIterator<Item = &'a S>>(codes: &HashMap<S, Code>, symbols: IntoIter) -> Option<Bits> {
+    let mut ret = Bits::new();
+    for symbol in symbols {
+        ret.push(*codes.get(symbol)?);
+    }
+
+    Some(ret)
+}
+
+#[derive(Debug, Clone)]
+pub struct CanonicalCode<S> {
+    // The symbols in the order their codes were dealt out, so shortest first and in symbol order within each
+    // length
+    symbols: Vec<S>,
+    // How many codes there are of each length, starting from zero
+    counts: Vec<usize>,
+    codes: HashMap<S, Code>,
+}
+
+impl<S: Ord + Hash + Clone> CanonicalCode<S> {
+    // Deals out codes for symbols with the given code lengths. Symbols with a length of zero are left out,
+    // as they are in DEFLATE, so a whole alphabet's lengths can be sent. Returns None if a symbol is there
+    // twice, if a length is over 64 or if there are more short codes than there is room for, although leaving
+    // codes unused is fine.
+    pub fn from_lengths<IntoIter: IntoIterator<Item = (S, u8)>>(lengths: IntoIter) -> Option<Self> {
+        let mut lengths: Vec<(u8, S)> = lengths.into_iter().filter(|(_, length)| *length > 0).map(|(symbol, length)| (length, symbol)).collect();
+        lengths.sort();
+        if lengths.windows(2).any(|pair| pair[0].1 == pair[1].1) || lengths.last().is_some_and(|(length, _)| *length > 64) {
+            return None;
+        }
+
+        let mut counts = vec![0; lengths.last().map_or(0, |(length, _)| *length as usize + 1)];
+        let mut codes = HashMap::with_capacity(lengths.len());
+        let mut next: u128 = 0;
+        let mut previous_length = 0;
+        for (length, symbol) in &lengths {
+            // Each length carries on from one past the last code of the length before, with a bit on the end
+            next <<= length - previous_length;
+            previous_length = *length;
+            if next >> length != 0 {
+                return None;
+            }
+
+            counts[*length as usize] += 1;
+            codes.insert(symbol.clone(), Code::new(next as u64, *length));
+            next += 1;
+        }
+
+        Some(Self { symbols: lengths.into_iter().map(|(_, symbol)| symbol).collect(), counts, codes })
+    }
+
+    // The symbols and their code lengths in code order, which is everything there is to know about the code
+    pub fn lengths(&self) -> impl Iterator<Item = (&S, u8)> + '_ {
+        self.symbols.iter().map(|symbol| (symbol, self.codes[symbol].len()))
+    }
+
+    pub fn codes(&self) -> &HashMap<S, Code> {
+        &self.codes
+    }
+
+    // Encodes the symbols one after another, or returns None if any of them don't have a code
+    pub fn encode<'a, IntoIter: IntoIterator<Item = &'a S>>(&self, symbols: IntoIter) -> Option<Bits> where S: 'a {
+        encode(&self.codes, symbols)
+    }
+
+    // Decodes without any tree. The codes of each length are consecutive numbers, so after each bit it only
+    // takes a subtraction to tell whether the bits so far are one of them, and which. Returns None if the
+    // bits run into a code that isn't used or if they stop part way through a code.
+    pub fn decode(&self, bits: &Bits) -> Option<Vec<S>> {
+        let mut ret = Vec::new();
+        let mut decoding = Decoding::default();
+        for bit in bits.iter() {
+            match self.decode_bit(&mut decoding, bit) {
+                Decoded::Symbol(symbol) => ret.push(symbol.clone()),
+                Decoded::Incomplete => (),
+                Decoded::Unused => return None,
+            }
+        }
+
+        decoding.is_empty().then_some(ret)
+    }
+
+    pub(crate) fn decode_bit(&self, decoding: &mut Decoding, bit: bool) -> Decoded<'_, S> {
+        decoding.code |= bit as u128;
+        decoding.length += 1;
+        let Some(&count) = self.counts.get(decoding.length) else {
+            return Decoded::Unused;
+        };
+
+        let count = count as u128;
+        if decoding.code - decoding.first < count {
+            let symbol = &self.symbols[decoding.index + (decoding.code - decoding.first) as usize];
+            *decoding = Decoding::default();
+            Decoded::Symbol(symbol)
+        } else {
+            decoding.index += count as usize;
+            decoding.first = (decoding.first + count) << 1;
+            decoding.code <<= 1;
+            Decoded::Incomplete
+        }
+    }
+}
+
+// How far decoding has got through a code. The bits of the code so far, the first code of that length and
+// where its symbol is.
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Decoding {
+    code: u128,
+    first: u128,
+    index: usize,
+    length: usize,
+}
+
+impl Decoding {
+    // Whether it is between codes rather than part way through one
+    pub(crate) fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+}
+
+pub(crate) enum Decoded<'a, S> {
+    Symbol(&'a S),
+    Incomplete,
+    Unused,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::prelude::*;
+
+    fn bits(bits: &str) -> Bits {
+        bits.chars().map(|bit| bit == '1').collect()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = thread_rng();
+        let text: Vec<char> = (0..2000).map(|_| *b"aaaaaaaabbbbccd .".choose(&mut rng).unwrap() as char).collect();
+        let tree = HuffmanTree::from_symbols(text.iter().copied()).unwrap();
+        assert_eq!(tree.frequency(), 2000);
+
+        let encoded = tree.encode(&text).unwrap();
+        assert_eq!(tree.decode(&encoded), Some(text));
+        assert_eq!(tree.encode(&['z']), None);
+        assert_eq!(encoded.as_bytes().len(), encoded.len().div_ceil(8));
+        let truncated = Bits::from_bytes(encoded.clone().into_bytes(), encoded.len() - 1);
+        assert_ne!(tree.decode(&truncated).map(|decoded| decoded.len()), Some(2000));
+        assert_eq!(Bits::from_bytes(encoded.clone().into_bytes(), encoded.len()), encoded);
+    }
+
+    #[test]
+    fn test_code_lengths() {
+        // The example from CLRS, where the optimal code takes 224 bits for every 100 symbols
+        let frequencies = [('a', 45), ('b', 13), ('c', 12), ('d', 16), ('e', 9), ('f', 5)];
+        let codes = HuffmanTree::from_frequencies(frequencies).unwrap().build_codes();
+        assert_eq!(frequencies.iter().map(|(symbol, frequency)| frequency * codes[symbol].len() as usize).sum::<usize>(), 224);
+        assert_eq!(codes[&'a'].len(), 1);
+
+        // No code is the start of another
+        for (symbol, code) in &codes {
+            let starts_with = |other: &Code| other.len() >= code.len() && other.bits() >> (other.len() - code.len()) == code.bits();
+            assert!(codes.iter().all(|(other, other_code)| other == symbol || !starts_with(other_code)));
+        }
+    }
+
+    #[test]
+    fn test_single_symbol() {
+        let tree = HuffmanTree::from_symbols("aaaa".chars()).unwrap();
+        assert_eq!(tree.encode(&['a', 'a']), Some(bits("00")));
+        assert_eq!(tree.decode(&bits("000")), Some(vec!['a'; 3]));
+        assert_eq!(HuffmanTree::<char>::from_frequencies([]), None);
+
+        let canonical = tree.canonical();
+        assert_eq!(canonical.encode(&['a', 'a']), Some(bits("00")));
+        assert_eq!(canonical.decode(&bits("1")), None);
+    }
+
+    #[test]
+    fn test_long_codes() {
+        // A byte table with only a few bytes in use, where the unused ones would otherwise pile up as a chain
+        // hundreds of codes deep
+        let mut frequencies = [0; 256];
+        frequencies[b'a' as usize] = 5;
+        frequencies[b'b' as usize] = 3;
+        frequencies[b'c' as usize] = 1;
+        let tree = HuffmanTree::from_frequencies((0..=255u8).zip(frequencies)).unwrap();
+        let lengths = tree.code_lengths();
+        assert_eq!(lengths.len(), 256);
+        assert_eq!(lengths.values().max(), Some(&64));
+        assert_eq!(lengths[&b'a'], 1);
+
+        let canonical = tree.canonical();
+        let text = b"abacabaccba\0\xff".to_vec();
+        assert_eq!(canonical.decode(&canonical.encode(&text).unwrap()), Some(text.clone()));
+        assert_eq!(tree.decode(&tree.encode(&text).unwrap()), Some(text));
+
+        // And weights that grow like the Fibonacci numbers, which make the deepest tree there can be
+        let mut fibonacci = vec![1usize, 1];
+        while fibonacci.len() < 90 {
+            fibonacci.push(fibonacci[fibonacci.len() - 1] + fibonacci[fibonacci.len() - 2]);
+        }
+
+        let tree = HuffmanTree::from_frequencies((0..).zip(fibonacci)).unwrap();
+        assert!(tree.code_lengths().values().all(|length| *length <= 64));
+        assert!(CanonicalCode::from_lengths(tree.code_lengths()).is_some());
+        assert_eq!(tree.build_codes().len(), 90);
+
+        let tree = HuffmanTree::from_frequencies([('a', usize::MAX), ('b', usize::MAX), ('c', 1)]).unwrap();
+        assert_eq!(tree.frequency(), usize::MAX);
+        assert_eq!(tree.decode(&tree.encode(&['c', 'a', 'b']).unwrap()), Some(vec!['c', 'a', 'b']));
+    }
+
+    #[test]
+    fn test_canonical() {
+        // The example from RFC 1951
+        let canonical = CanonicalCode::from_lengths("ABCDEFGH".chars().zip([3, 3, 3, 3, 3, 2, 4, 4])).unwrap();
+        let codes: Vec<_> = "ABCDEFGH".chars().map(|symbol| canonical.codes()[&symbol].to_string()).collect();
+        assert_eq!(codes, ["010", "011", "100", "101", "110", "00", "1110", "1111"]);
+        assert_eq!(canonical.lengths().map(|(symbol, _)| *symbol).collect::<String>(), "FABCDEGH");
+        assert_eq!(canonical.decode(&bits("0011101111010")), Some(vec!['F', 'G', 'H', 'A']));
+        assert_eq!(canonical.decode(&bits("0011")), None);
+
+        // Sending only the lengths is enough to decode
+        let mut rng = thread_rng();
+        let text: Vec<u8> = (0..2000).map(|_| *b"aaaaaaaabbbbccd .".choose(&mut rng).unwrap()).collect();
+        let canonical = HuffmanTree::from_symbols(text.iter().copied()).unwrap().canonical();
+        let received = CanonicalCode::from_lengths(canonical.lengths().map(|(symbol, length)| (*symbol, length))).unwrap();
+        assert_eq!(received.decode(&canonical.encode(&text).unwrap()), Some(text));
+
+        assert!(CanonicalCode::from_lengths([('a', 1), ('b', 1), ('c', 1)]).is_none());
+        assert!(CanonicalCode::from_lengths([('a', 1), ('a', 2)]).is_none());
+        assert!(CanonicalCode::from_lengths([('a', 65)]).is_none());
+    }
+}
@@ -0,0 +1,334 @@
+use crate::huffman::{CanonicalCode, Code, Decoded, Decoding, HuffmanTree};
+use std::io::{self, Read, Write};
+
+// Huffman coding bytes as they stream through, so that it can sit between a file or socket and whatever
+// reads or writes it without the whole thing being in memory at once. The code has to be settled before the
+// first byte, so it comes from a sample of what is to come, or from some other agreed table of lengths.
+//
+// The encoded bits don't have to end on a byte boundary, so the code has one more symbol past the bytes to
+// mark where they stop, the same way DEFLATE has an end of block code. Whatever bits come after it in the
+// last byte are just padding.
+pub const END_OF_STREAM: u16 = 256;
+
+// How much encoded output builds up before it goes to the writer
+const BUFFER_SIZE: usize = 8192;
+
+impl CanonicalCode<u16> {
+    // A code for streams, where the bytes that show up most in the sample get the shortest codes. Every byte
+    // gets a code whether it is in the sample or not, so anything at all can still be encoded with it.
+    pub fn for_bytes(sample: &[u8]) -> Self {
+        let mut frequencies = [1; 256];
+        for byte in sample {
+            frequencies[*byte as usize] += 1;
+        }
+
+        let frequencies = (0..).zip(frequencies).chain([(END_OF_STREAM, 1)]);
+        HuffmanTree::from_frequencies(frequencies).unwrap().canonical()
+    }
+}
+
+pub struct HuffmanEncoder<W: Write> {
+    // Only taken by finish
+    writer: Option<W>,
+    code: CanonicalCode<u16>,
+    // Whole bytes that haven't gone to the writer yet
+    buffer: Vec<u8>,
+    // And the bits after them that don't make up a byte yet, in the bottom pending_len bits
+    pending: u128,
+    pending_len: u32,
+}
+
+impl<W: Write> HuffmanEncoder<W> {
+    // Only bytes with a code can be written, and writing any other fails with InvalidInput
+    pub fn new(writer: W, code: CanonicalCode<u16>) -> Self {
+        assert!(code.codes().contains_key(&END_OF_STREAM), "Cannot encode a stream without a code for its end");
+        Self { writer: Some(writer), code, buffer: Vec::with_capacity(BUFFER_SIZE), pending: 0, pending_len: 0 }
+    }
+
+    // Writes the end of the stream and pads out the last byte. Dropping the encoder does the same, but
+    // can't say if it failed.
+    pub fn finish(mut self) -> io::Result<W> {
+        // Taking the writer even if it fails, or dropping would try to finish all over again
+        let ret = self.finish_stream();
+        let writer = self.writer.take().unwrap();
+        ret.map(|()| writer)
+    }
+
+    fn finish_stream(&mut self) -> io::Result<()> {
+        let end = self.code.codes()[&END_OF_STREAM];
+        self.push(end);
+        if self.pending_len > 0 {
+            let padding = 8 - self.pending_len;
+            self.push(Code::new(0, padding as u8));
+        }
+
+        self.flush()
+    }
+
+    fn push(&mut self, code: Code) {
+        self.pending = self.pending << code.len() | code.bits() as u128;
+        self.pending_len += code.len() as u32;
+        while self.pending_len >= 8 {
+            self.pending_len -= 8;
+            self.buffer.push((self.pending >> self.pending_len) as u8);
+        }
+
+        self.pending &= (1 << self.pending_len) - 1;
+    }
+
+    // Keeps hold of whatever the writer doesn't take, so that a failed write never loses anything
+    fn write_buffer(&mut self) -> io::Result<()> {
+        let writer = self.writer.as_mut().unwrap();
+        let mut written = 0;
+        let ret = loop {
+            if written == self.buffer.len() {
+                break Ok(());
+            }
+
+            match writer.write(&self.buffer[written..]) {
+                Ok(0) => break Err(io::Error::new(io::ErrorKind::WriteZero, "Cannot write the encoded bytes")),
+                Ok(count) => written += count,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => (),
+                Err(error) => break Err(error),
+            }
+        };
+
+        self.buffer.drain(..written);
+        ret
+    }
+}
+
+impl<W: Write> Write for HuffmanEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Anything that was buffered before goes out first, so that if it fails none of buf has been taken
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.write_buffer()?;
+        }
+
+        // Only takes as much of buf as fills the buffer, so a huge write is encoded a buffer at a time as
+        // write_all comes back for the rest rather than all at once
+        for (count, byte) in buf.iter().enumerate() {
+            if self.buffer.len() >= BUFFER_SIZE {
+                return Ok(count);
+            }
+
+            let Some(&code) = self.code.codes().get(&(*byte as u16)) else {
+                if count == 0 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "Cannot encode a byte without a code"));
+                }
+
+                return Ok(count);
+            };
+
+            self.push(code);
+        }
+
+        Ok(buf.len())
+    }
+
+    // Everything but the last few bits, which have to wait for the rest of their byte
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_buffer()?;
+        self.writer.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for HuffmanEncoder<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.finish_stream();
+        }
+    }
+}
+
+impl<W: Write + std::fmt::Debug> std::fmt::Debug for HuffmanEncoder<W> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HuffmanEncoder").field("writer", &self.writer).field("buffered", &self.buffer.len()).finish()
+    }
+}
+
+pub struct HuffmanDecoder<R: Read> {
+    reader: R,
+    code: CanonicalCode<u16>,
+    buffer: Box<[u8]>,
+    start: usize,
+    end: usize,
+    // The next bit of buffer[start], counting from the most significant
+    bit: u32,
+    decoding: Decoding,
+    // Set once the end of the stream has been decoded
+    done: bool,
+    // Set once the bits stop making sense, after handing back whatever decoded before that
+    corrupt: bool,
+}
+
+impl<R: Read> HuffmanDecoder<R> {
+    pub fn new(reader: R, code: CanonicalCode<u16>) -> Self {
+        Self {
+            reader,
+            code,
+            buffer: vec![0; BUFFER_SIZE].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            bit: 0,
+            decoding: Decoding::default(),
+            done: false,
+            corrupt: false,
+        }
+    }
+}
+
+fn corrupt() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Cannot decode bits that aren't a code for a byte")
+}
+
+impl<R: Read> Read for HuffmanDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.corrupt {
+            return Err(corrupt());
+        }
+
+        let mut count = 0;
+        while count < buf.len() && !self.done {
+            if self.start == self.end {
+                // Only wait on the reader for more if there is nothing to hand back yet
+                if count > 0 {
+                    break;
+                }
+
+                self.end = match self.reader.read(&mut self.buffer) {
+                    Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Cannot decode a stream that stops before its end")),
+                    Ok(end) => end,
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(error) => return Err(error),
+                };
+                self.start = 0;
+            }
+
+            let bit = self.buffer[self.start] >> (7 - self.bit) & 1 != 0;
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.start += 1;
+            }
+
+            match self.code.decode_bit(&mut self.decoding, bit) {
+                Decoded::Symbol(&END_OF_STREAM) => self.done = true,
+                Decoded::Symbol(&symbol) if symbol < END_OF_STREAM => {
+                    buf[count] = symbol as u8;
+                    count += 1;
+                }
+                Decoded::Incomplete => (),
+                Decoded::Symbol(_) | Decoded::Unused => {
+                    self.corrupt = true;
+                    return if count > 0 { Ok(count) } else { Err(corrupt()) };
+                }
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+impl<R: Read + std::fmt::Debug> std::fmt::Debug for HuffmanDecoder<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HuffmanDecoder").field("reader", &self.reader).field("done", &self.done).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut rng = thread_rng();
+        let text: Vec<u8> = (0..50000).map(|_| *b"aaaaaaaabbbbccd .".choose(&mut rng).unwrap()).collect();
+        let code = CanonicalCode::for_bytes(&text[..1000]);
+
+        // Written in uneven pieces, including bytes that weren't in the sample
+        let mut encoder = HuffmanEncoder::new(Vec::new(), code.clone());
+        for piece in text.chunks(777) {
+            encoder.write_all(piece).unwrap();
+        }
+        encoder.write_all(b"xyz\xff").unwrap();
+        let encoded = encoder.finish().unwrap();
+        assert!(encoded.len() < text.len() / 2);
+
+        // And read back a little at a time
+        let mut decoder = HuffmanDecoder::new(encoded.as_slice(), code);
+        let mut decoded = Vec::new();
+        let mut piece = [0; 100];
+        loop {
+            match decoder.read(&mut piece).unwrap() {
+                0 => break,
+                count => decoded.extend_from_slice(&piece[..count]),
+            }
+        }
+
+        assert_eq!(&decoded[..text.len()], text);
+        assert_eq!(&decoded[text.len()..], b"xyz\xff");
+    }
+
+    #[test]
+    fn test_large_write() {
+        struct Chunks(Vec<usize>);
+
+        impl Write for Chunks {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.push(buf.len());
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let text = vec![7; 1 << 20];
+        let mut encoder = HuffmanEncoder::new(Chunks(Vec::new()), CanonicalCode::for_bytes(b"abc"));
+        assert!(encoder.write(&text).unwrap() < text.len());
+        encoder.write_all(&text).unwrap();
+
+        let chunks = encoder.finish().unwrap().0;
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| *chunk < BUFFER_SIZE + 8));
+    }
+
+    #[test]
+    fn test_drop_finishes() {
+        let code = CanonicalCode::for_bytes(b"hello");
+        let mut encoded = Vec::new();
+        {
+            let mut encoder = HuffmanEncoder::new(&mut encoded, code.clone());
+            encoder.write_all(b"hello, hello").unwrap();
+        }
+
+        let mut decoded = String::new();
+        HuffmanDecoder::new(encoded.as_slice(), code.clone()).read_to_string(&mut decoded).unwrap();
+        assert_eq!(decoded, "hello, hello");
+
+        // Without the end of the stream there is no telling how much was lost
+        let error = HuffmanDecoder::new(&encoded[..encoded.len() - 1], code).read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_bytes_without_codes() {
+        let code = CanonicalCode::from_lengths([(b'a' as u16, 1), (END_OF_STREAM, 2)]).unwrap();
+        let mut encoder = HuffmanEncoder::new(Vec::new(), code.clone());
+        assert_eq!(encoder.write(b"aab").unwrap(), 2);
+        assert_eq!(encoder.write(b"b").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+
+        // 0 0 10 and then padding
+        let encoded = encoder.finish().unwrap();
+        assert_eq!(encoded, [0b0010_0000]);
+
+        // 11 isn't used, so reading gives back the a before it and then fails
+        let mut decoder = HuffmanDecoder::new(&[0b0110_0000][..], code);
+        let mut decoded = [0; 4];
+        assert_eq!(decoder.read(&mut decoded).unwrap(), 1);
+        assert_eq!(decoder.read(&mut decoded).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
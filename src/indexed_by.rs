@@ -0,0 +1,200 @@
+use crate::heap::{Heap, HeapOrder};
+use crate::raw::{remove_tracked, restore_tracked};
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+// A Heap with a secondary index, for element types that already carry their own key. The key is pulled out
+// of each element with a closure and the index maps it to where the element is in the heap, kept up to
+// date on every move, so elements can be looked up in O(1) and removed or replaced in O(log n) without
+// switching over to PriorityQueue and splitting the elements into keys and priorities. Each key can only be
+// in the heap once, and inserting an element whose key is already there replaces the old one.
+//
+// The key closure is called on every move, so it wants to be cheap, like copying out an id field.
+pub struct IndexedBy<T, K: Hash + Eq, F: Fn(&T) -> K, Order: HeapOrder<T>> {
+    heap: Heap<T, Order>,
+    index: HashMap<K, usize>,
+    key: F,
+}
+
+impl<T, K: Hash + Eq, F: Fn(&T) -> K, Order: HeapOrder<T>> IndexedBy<T, K, F, Order> {
+    pub fn new(key: F) -> Self where Order: Default {
+        Self::with_cmp(Order::default(), key)
+    }
+
+    pub fn with_cmp(order: Order, key: F) -> Self {
+        Self::from_heap(Heap::with_capacity_and_cmp(0, order), key)
+    }
+
+    // Indexes an existing heap. Panics if two of its elements have the same key.
+    pub fn from_heap(heap: Heap<T, Order>, key: F) -> Self {
+        let mut index = HashMap::with_capacity(heap.len());
+        for (position, value) in heap.data.iter().enumerate() {
+            assert!(index.insert(key(value), position).is_none(), "Cannot index a heap with duplicate keys");
+        }
+
+        Self { heap, index, key }
+    }
+
+    pub fn order(&self) -> &Order {
+        self.heap.order()
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.index.clear();
+    }
+
+    // Iterates over the elements in no particular order
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.heap.data.iter()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.heap.peek()
+    }
+
+    pub fn get<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&T> where K: Borrow<Q> {
+        self.index.get(key).map(|&position| &self.heap.data[position])
+    }
+
+    pub fn contains_key<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        self.index.contains_key(key)
+    }
+
+    // Where the element with this key currently is in the heap's storage
+    pub fn position<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<usize> where K: Borrow<Q> {
+        self.index.get(key).copied()
+    }
+
+    // Adds the element, or if an element with the same key is already there replaces it and returns the old
+    // one
+    pub fn insert(&mut self, value: T) -> Option<T> {
+        let key = (self.key)(&value);
+        if let Some(&position) = self.index.get(&key) {
+            let ret = std::mem::replace(&mut self.heap.data[position], value);
+            self.restore(position);
+            return Some(ret);
+        }
+
+        let position = self.heap.data.len();
+        self.index.insert(key, position);
+        self.heap.data.push(value);
+        self.restore(position);
+        None
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.heap.is_empty() {
+            None
+        } else {
+            Some(self.remove_at(0))
+        }
+    }
+
+    pub fn remove<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<T> where K: Borrow<Q> {
+        let position = *self.index.get(key)?;
+        Some(self.remove_at(position))
+    }
+
+    pub fn into_heap(self) -> Heap<T, Order> {
+        self.heap
+    }
+
+    fn remove_at(&mut self, position: usize) -> T {
+        let Self { heap, index, key } = self;
+        let mut on_moved = |value: &T, position| *index.get_mut(&key(value)).unwrap() = position;
+        remove_tracked(&mut heap.data, position, &heap.order, &mut on_moved);
+
+        let ret = heap.data.pop().unwrap();
+        index.remove(&key(&ret));
+        ret
+    }
+
+    fn restore(&mut self, position: usize) {
+        let Self { heap, index, key } = self;
+        let mut on_moved = |value: &T, position| *index.get_mut(&key(value)).unwrap() = position;
+        restore_tracked(&mut heap.data, position, &heap.order, &mut on_moved);
+    }
+}
+
+impl<T: std::fmt::Debug, K: Hash + Eq, F: Fn(&T) -> K, Order: HeapOrder<T>> std::fmt::Debug for IndexedBy<T, K, F, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, K: Hash + Eq, F: Fn(&T) -> K, Order: HeapOrder<T>> Extend<T> for IndexedBy<T, K, F, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{KeyOrder, MaxHeap, MinOrder};
+    use rand::prelude::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Job {
+        id: u32,
+        deadline: u32,
+    }
+
+    #[test]
+    fn test_random_operations() {
+        let mut rng = thread_rng();
+        let order = KeyOrder::new(|job: &Job| job.deadline, MinOrder::default());
+        let mut jobs = IndexedBy::with_cmp(order, |job: &Job| job.id);
+        let mut reference: HashMap<u32, u32> = HashMap::new();
+
+        for _ in 0..5000 {
+            let id = rng.gen_range(0..300);
+            match rng.gen_range(0..4) {
+                0 | 1 => {
+                    let deadline = rng.gen_range(0..1000);
+                    let old = jobs.insert(Job { id, deadline }).map(|job| job.deadline);
+                    assert_eq!(old, reference.insert(id, deadline));
+                }
+                2 => assert_eq!(jobs.remove(&id).map(|job| job.deadline), reference.remove(&id)),
+                _ => {
+                    let popped = jobs.pop();
+                    assert_eq!(popped.as_ref().map(|job| job.deadline), reference.values().copied().min());
+                    if let Some(job) = popped {
+                        reference.remove(&job.id);
+                    }
+                }
+            }
+
+            assert_eq!(jobs.len(), reference.len());
+            assert_eq!(jobs.get(&id).map(|job| job.deadline), reference.get(&id).copied());
+        }
+
+        for job in jobs.iter() {
+            assert_eq!(jobs.get(&job.id), Some(job));
+        }
+    }
+
+    #[test]
+    fn test_from_heap() {
+        let heap: MaxHeap<(u32, char)> = vec![(3, 'c'), (9, 'a'), (5, 'b')].into();
+        let mut indexed = IndexedBy::from_heap(heap, |&(_, name): &(u32, char)| name);
+
+        assert_eq!(indexed.get(&'b'), Some(&(5, 'b')));
+        assert_eq!(indexed.insert((10, 'c')), Some((3, 'c')));
+        assert_eq!(indexed.position(&'c'), Some(0));
+        assert_eq!(indexed.remove(&'a'), Some((9, 'a')));
+        assert_eq!(indexed.into_heap().into_sorted_vec(), vec![(5, 'b'), (10, 'c')]);
+    }
+}
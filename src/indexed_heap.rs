@@ -0,0 +1,357 @@
+use crate::cancelable::CancelableQueue;
+use crate::heap::HeapOrder;
+use crate::raw::{heapify_down_tracked, heapify_up_tracked, remove_tracked, restore_tracked};
+
+// A binary heap that hands out a Handle for every element it inserts and keeps track of where each one is,
+// so that an element can be found again to change its key or take it out, all in O(log n). This is the
+// structure Dijkstra's and Prim's algorithms expect, where the distance to a vertex keeps getting better
+// while it waits in the queue.
+//
+// The elements sit in an ordinary array heap together with their slot number, and the slots record where
+// each element is in the heap, updated on every swap. Slots are reused once their element has gone, and
+// as in FibonacciHeap each one has a generation that is bumped when it is freed, so a stale handle never
+// finds whatever moved into its slot afterwards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: usize,
+    generation: u32,
+}
+
+#[derive(Debug, Clone)]
+struct Entry<T> {
+    value: T,
+    slot: usize,
+}
+
+#[derive(Debug, Clone)]
+struct Slot {
+    generation: u32,
+    // Where the element is in the heap, or None if the slot is free
+    position: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IndexedHeap<T, Order: HeapOrder<T>> {
+    data: Vec<Entry<T>>,
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+    order: Order,
+}
+
+impl<T, Order: HeapOrder<T>> IndexedHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self::with_capacity_and_cmp(0, order)
+    }
+
+    pub fn with_capacity_and_cmp(capacity: usize, order: Order) -> Self {
+        Self {
+            data: Vec::with_capacity(capacity),
+            slots: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            order,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.data.drain(..) {
+            let slot = &mut self.slots[entry.slot];
+            slot.generation = slot.generation.wrapping_add(1);
+            slot.position = None;
+        }
+
+        self.free = (0..self.slots.len()).rev().collect();
+    }
+
+    // Iterates over the elements and their handles in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (Handle, &T)> + '_ {
+        self.data.iter().map(|entry| (self.handle(entry.slot), &entry.value))
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first().map(|entry| &entry.value)
+    }
+
+    pub fn peek_with_handle(&self) -> Option<(Handle, &T)> {
+        self.data.first().map(|entry| (self.handle(entry.slot), &entry.value))
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.resolve(handle).map(|position| &self.data[position].value)
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.resolve(handle).is_some()
+    }
+
+    pub fn insert(&mut self, value: T) -> Handle {
+        let slot = self.free.pop().unwrap_or_else(|| {
+            self.slots.push(Slot { generation: 0, position: None });
+            self.slots.len() - 1
+        });
+
+        let position = self.data.len();
+        self.slots[slot].position = Some(position);
+        self.data.push(Entry { value, slot });
+        heapify_up_tracked(&mut self.data, position, &by_value(&self.order), &mut follow(&mut self.slots));
+
+        self.handle(slot)
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.pop_with_handle().map(|(_, value)| value)
+    }
+
+    // Pops the top element along with the handle it was inserted under, which is no longer valid
+    pub fn pop_with_handle(&mut self) -> Option<(Handle, T)> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let handle = self.handle(self.data[0].slot);
+        Some((handle, self.remove_at(0)))
+    }
+
+    // Moves the element towards the top of the heap by replacing it with a value that can go at least as
+    // high. Handing in a value that belongs lower down is a bug, and panics.
+    pub fn decrease_key(&mut self, handle: Handle, value: T) {
+        let position = self.resolve(handle).expect("Handle does not refer to an element of this heap");
+        assert!(
+            !self.order.left_can_go_above(&self.data[position].value, &value),
+            "decrease_key cannot move an element further down the heap"
+        );
+
+        self.data[position].value = value;
+        heapify_up_tracked(&mut self.data, position, &by_value(&self.order), &mut follow(&mut self.slots));
+    }
+
+    // The opposite of decrease_key, replacing the element with a value that can go no higher
+    pub fn increase_key(&mut self, handle: Handle, value: T) {
+        let position = self.resolve(handle).expect("Handle does not refer to an element of this heap");
+        assert!(
+            !self.order.left_can_go_above(&value, &self.data[position].value),
+            "increase_key cannot move an element further up the heap"
+        );
+
+        self.data[position].value = value;
+        heapify_down_tracked(&mut self.data, position, &by_value(&self.order), &mut follow(&mut self.slots));
+    }
+
+    // Replaces the element with any value, wherever it belongs, and returns the old one
+    pub fn update(&mut self, handle: Handle, value: T) -> T {
+        let position = self.resolve(handle).expect("Handle does not refer to an element of this heap");
+        let ret = std::mem::replace(&mut self.data[position].value, value);
+        restore_tracked(&mut self.data, position, &by_value(&self.order), &mut follow(&mut self.slots));
+        ret
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        let position = self.resolve(handle)?;
+        Some(self.remove_at(position))
+    }
+
+    // Pops into a new Vec, dropping the slot numbers on the way, and reverses it so that the top is last like
+    // Heap's
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let mut ret = Vec::with_capacity(self.len());
+        ret.extend(std::iter::from_fn(|| self.pop()));
+        ret.reverse();
+        ret
+    }
+
+    fn handle(&self, slot: usize) -> Handle {
+        Handle {
+            index: slot,
+            generation: self.slots[slot].generation,
+        }
+    }
+
+    fn resolve(&self, handle: Handle) -> Option<usize> {
+        let slot = self.slots.get(handle.index)?;
+        if slot.generation == handle.generation {
+            slot.position
+        } else {
+            None
+        }
+    }
+
+    fn remove_at(&mut self, position: usize) -> T {
+        remove_tracked(&mut self.data, position, &by_value(&self.order), &mut follow(&mut self.slots));
+
+        let entry = self.data.pop().unwrap();
+        let slot = &mut self.slots[entry.slot];
+        slot.generation = slot.generation.wrapping_add(1);
+        slot.position = None;
+        self.free.push(entry.slot);
+        entry.value
+    }
+}
+
+// The raw tracked functions do the sifting, comparing entries by their values and keeping each slot's
+// position up to date as its entry moves
+fn by_value<T>(order: &impl HeapOrder<T>) -> impl HeapOrder<Entry<T>> + '_ {
+    move |left: &Entry<T>, right: &Entry<T>| order.left_can_go_above(&left.value, &right.value)
+}
+
+fn follow<T>(slots: &mut [Slot]) -> impl FnMut(&Entry<T>, usize) + '_ {
+    move |entry: &Entry<T>, position| slots[entry.slot].position = Some(position)
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for IndexedHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for IndexedHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> FromIterator<T> for IndexedHeap<T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+impl<T, Order: HeapOrder<T>> CancelableQueue<T> for IndexedHeap<T, Order> {
+    type Handle = Handle;
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn insert_with_handle(&mut self, value: T) -> Handle {
+        self.insert(value)
+    }
+
+    fn cancel(&mut self, handle: Handle) -> bool {
+        self.remove(handle).is_some()
+    }
+
+    fn is_pending(&self, handle: Handle) -> bool {
+        self.contains(handle)
+    }
+
+    fn peek(&self) -> Option<&T> {
+        self.peek()
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{KeyOrder, MinOrder};
+    use rand::prelude::*;
+
+    #[test]
+    fn test_random_operations() {
+        let mut rng = thread_rng();
+        let mut heap = IndexedHeap::<u32, MinOrder<_>>::new();
+        let mut reference: Vec<(Handle, u32)> = Vec::new();
+
+        for _ in 0..10000 {
+            match rng.gen_range(0..6) {
+                0 | 1 => {
+                    let value = rng.gen_range(0..1000);
+                    reference.push((heap.insert(value), value));
+                }
+                2 if !reference.is_empty() => {
+                    let index = rng.gen_range(0..reference.len());
+                    let (handle, value) = reference[index];
+                    let new_value = rng.gen_range(0..=value);
+                    heap.decrease_key(handle, new_value);
+                    reference[index].1 = new_value;
+                }
+                3 if !reference.is_empty() => {
+                    let index = rng.gen_range(0..reference.len());
+                    let (handle, value) = reference[index];
+                    let new_value = rng.gen_range(value..1000);
+                    heap.increase_key(handle, new_value);
+                    reference[index].1 = new_value;
+                }
+                4 if !reference.is_empty() => {
+                    let (handle, value) = reference.swap_remove(rng.gen_range(0..reference.len()));
+                    assert_eq!(heap.remove(handle), Some(value));
+                    assert!(!heap.contains(handle));
+                }
+                _ => {
+                    let popped = heap.pop_with_handle();
+                    assert_eq!(popped.map(|(_, value)| value), reference.iter().map(|(_, value)| *value).min());
+                    if let Some((handle, _)) = popped {
+                        reference.retain(|(other, _)| *other != handle);
+                    }
+                }
+            }
+
+            assert_eq!(heap.len(), reference.len());
+            assert!(reference.iter().all(|(handle, value)| heap.get(*handle) == Some(value)));
+        }
+    }
+
+    #[test]
+    fn test_dijkstra() {
+        // A small graph where the direct edges are never the shortest way round
+        let edges: [&[(usize, u32)]; 5] = [&[(1, 10), (2, 3)], &[(3, 2)], &[(1, 4), (3, 8), (4, 2)], &[(4, 5)], &[(3, 1)]];
+
+        let mut heap = IndexedHeap::with_cmp(KeyOrder::new(|entry: &(u32, usize)| entry.0, MinOrder::default()));
+        let mut handles: Vec<Option<Handle>> = vec![None; edges.len()];
+        let mut distances: Vec<Option<u32>> = vec![None; edges.len()];
+        handles[0] = Some(heap.insert((0, 0)));
+
+        while let Some((distance, vertex)) = heap.pop() {
+            distances[vertex] = Some(distance);
+            for &(next, weight) in edges[vertex] {
+                if distances[next].is_some() {
+                    continue;
+                }
+
+                let candidate = (distance + weight, next);
+                match handles[next].filter(|handle| heap.contains(*handle)) {
+                    Some(handle) if heap.get(handle).unwrap().0 > candidate.0 => heap.decrease_key(handle, candidate),
+                    Some(_) => {}
+                    None => handles[next] = Some(heap.insert(candidate)),
+                }
+            }
+        }
+
+        assert_eq!(distances, [Some(0), Some(7), Some(3), Some(6), Some(5)]);
+    }
+
+    #[test]
+    fn test_stale_handle() {
+        let mut heap = IndexedHeap::<_, MinOrder<_>>::new();
+        let first = heap.insert(1);
+        assert_eq!(heap.pop(), Some(1));
+
+        // The new element reuses the slot, but not the generation
+        let second = heap.insert(2);
+        assert!(!heap.contains(first));
+        assert_eq!(heap.remove(first), None);
+        assert_eq!(heap.get(second), Some(&2));
+    }
+}
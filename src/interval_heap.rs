@@ -0,0 +1,347 @@
+use crate::depq::DoubleEndedPriorityQueue;
+
+// An interval heap stores two elements per node, a low one and a high one, so each node describes an
+// interval. The intervals nest: every node's interval contains the intervals of its children. That makes
+// the lows a min heap and the highs a max heap sharing one tree, which is half as deep as a min-max heap
+// holding the same elements. The API matches MinMaxHeap so the two can be swapped for each other.
+//
+// The nodes are stored flattened, so node n has its low at 2n and its high at 2n + 1. The last node may
+// only have a low, in which case that one element acts as both ends of the interval.
+
+fn low_index(node: usize) -> usize {
+    2 * node
+}
+
+fn parent_node(index: usize) -> Option<usize> {
+    let node = index / 2;
+    if node == 0 {
+        None
+    } else {
+        Some((node - 1) / 2)
+    }
+}
+
+fn bubble_up_min<T: Ord>(data: &mut [T], mut index: usize) {
+    while let Some(parent) = parent_node(index) {
+        let parent_index = low_index(parent);
+        if data[index] < data[parent_index] {
+            data.swap(index, parent_index);
+            index = parent_index;
+        } else {
+            break;
+        }
+    }
+}
+
+fn bubble_up_max<T: Ord>(data: &mut [T], mut index: usize) {
+    while let Some(parent) = parent_node(index) {
+        let parent_index = low_index(parent) + 1;
+        if data[index] > data[parent_index] {
+            data.swap(index, parent_index);
+            index = parent_index;
+        } else {
+            break;
+        }
+    }
+}
+
+fn sift_down_min<T: Ord>(data: &mut [T], mut index: usize) {
+    loop {
+        let node = index / 2;
+        let mut best_index = None;
+        for child in [2 * node + 1, 2 * node + 2] {
+            let child_index = low_index(child);
+            if child_index < data.len() && best_index.is_none_or(|best_index| data[child_index] < data[best_index]) {
+                best_index = Some(child_index);
+            }
+        }
+
+        match best_index {
+            Some(best_index) if data[best_index] < data[index] => {
+                data.swap(best_index, index);
+
+                // The value we moved down might not fit under the child's high, in which case it becomes the
+                // high and the old high carries on down the min side instead
+                if best_index + 1 < data.len() && data[best_index] > data[best_index + 1] {
+                    data.swap(best_index, best_index + 1);
+                }
+
+                index = best_index;
+            }
+
+            _ => break,
+        }
+    }
+}
+
+fn sift_down_max<T: Ord>(data: &mut [T], mut index: usize) {
+    loop {
+        let node = index / 2;
+        let mut best_index = None;
+        for child in [2 * node + 1, 2 * node + 2] {
+            // A node with a single element uses it as its high as well as its low
+            let child_index = (low_index(child) + 1).min(data.len().saturating_sub(1));
+            if child_index >= low_index(child) && best_index.is_none_or(|best_index| data[child_index] > data[best_index]) {
+                best_index = Some(child_index);
+            }
+        }
+
+        match best_index {
+            Some(best_index) if data[best_index] > data[index] => {
+                data.swap(best_index, index);
+
+                if best_index % 2 == 0 {
+                    // That was a single element node, which is always the last one, so there is nowhere further to go
+                    break;
+                }
+
+                if data[best_index - 1] > data[best_index] {
+                    data.swap(best_index - 1, best_index);
+                }
+
+                index = best_index;
+            }
+
+            _ => break,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IntervalHeap<T: Ord> {
+    data: Vec<T>,
+}
+
+impl<T: Ord> IntervalHeap<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            data: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn from_vec(mut data: Vec<T>) -> Self {
+        // Work from the last node back to the root, sorting out each node's own interval and then pushing
+        // both ends down into the subtree, which is already valid
+        for node in (0..data.len().div_ceil(2)).rev() {
+            let index = low_index(node);
+            if index + 1 < data.len() {
+                if data[index] > data[index + 1] {
+                    data.swap(index, index + 1);
+                }
+
+                sift_down_min(&mut data, index);
+                sift_down_max(&mut data, index + 1);
+            }
+        }
+
+        Self {
+            data,
+        }
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    pub fn insert(&mut self, value: T) {
+        let index = self.data.len();
+        self.data.push(value);
+
+        if index % 2 == 1 {
+            // The new value completes a node, so it has to be ordered against the low that is already there
+            if self.data[index - 1] > self.data[index] {
+                self.data.swap(index - 1, index);
+                bubble_up_min(&mut self.data, index - 1);
+            } else {
+                bubble_up_max(&mut self.data, index);
+            }
+        } else if let Some(parent) = parent_node(index) {
+            // The new value is on its own in a new node, so it is either below its parent's interval, above it,
+            // or already fine
+            let parent_index = low_index(parent);
+            if self.data[index] < self.data[parent_index] {
+                bubble_up_min(&mut self.data, index);
+            } else if self.data[index] > self.data[parent_index + 1] {
+                bubble_up_max(&mut self.data, index);
+            }
+        }
+    }
+
+    pub fn peek_min(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    pub fn peek_max(&self) -> Option<&T> {
+        self.data.get(1).or_else(|| self.data.first())
+    }
+
+    pub fn pop_min(&mut self) -> Option<T> {
+        if self.data.len() <= 2 {
+            return if self.data.is_empty() { None } else { Some(self.data.remove(0)) };
+        }
+
+        let ret = self.data.swap_remove(0);
+        if self.data[0] > self.data[1] {
+            self.data.swap(0, 1);
+        }
+
+        sift_down_min(&mut self.data, 0);
+        Some(ret)
+    }
+
+    pub fn pop_max(&mut self) -> Option<T> {
+        if self.data.len() <= 2 {
+            return self.data.pop();
+        }
+
+        let ret = self.data.swap_remove(1);
+        if self.data[0] > self.data[1] {
+            self.data.swap(0, 1);
+        }
+
+        sift_down_max(&mut self.data, 1);
+        Some(ret)
+    }
+}
+
+impl<T: Ord> DoubleEndedPriorityQueue<T> for IntervalHeap<T> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn push(&mut self, value: T) {
+        self.insert(value);
+    }
+
+    fn peek_min(&self) -> Option<&T> {
+        self.peek_min()
+    }
+
+    fn peek_max(&self) -> Option<&T> {
+        self.peek_max()
+    }
+
+    fn pop_min(&mut self) -> Option<T> {
+        self.pop_min()
+    }
+
+    fn pop_max(&mut self) -> Option<T> {
+        self.pop_max()
+    }
+}
+
+impl<T: Ord> Default for IntervalHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> From<Vec<T>> for IntervalHeap<T> {
+    fn from(f: Vec<T>) -> Self {
+        Self::from_vec(f)
+    }
+}
+
+impl<T: Ord> FromIterator<T> for IntervalHeap<T> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        Self::from_vec(iter.into_iter().collect())
+    }
+}
+
+impl<T: Ord> Extend<T> for IntervalHeap<T> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T: Ord> IntoIterator for IntervalHeap<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::prelude::*;
+
+    fn is_interval_heap<T: Ord>(data: &[T]) -> bool {
+        (0..data.len()).all(|index| {
+            // Each node is ordered, and each end of it is inside the parent's interval
+            let node_ok = index % 2 == 0 || data[index - 1] <= data[index];
+            let parent_ok = parent_node(index).is_none_or(|parent| {
+                data[low_index(parent)] <= data[index] && data[index] <= data[low_index(parent) + 1]
+            });
+            node_ok && parent_ok
+        })
+    }
+
+    #[test]
+    fn test_from_vec() {
+        let mut rng = thread_rng();
+        for len in 0..200 {
+            let mut values: Vec<u32> = (0..len).collect();
+            values.shuffle(&mut rng);
+
+            let heap = IntervalHeap::from(values);
+            assert!(is_interval_heap(heap.as_slice()));
+        }
+    }
+
+    #[test]
+    fn test_random_operations() {
+        let mut rng = thread_rng();
+        let mut heap = IntervalHeap::new();
+        let mut reference = Vec::new();
+
+        for _ in 0..5000 {
+            match rng.gen_range(0..4) {
+                0 | 1 => {
+                    let value = rng.gen_range(0..100);
+                    heap.insert(value);
+                    reference.push(value);
+                    reference.sort();
+                }
+                2 => assert_eq!(heap.pop_min(), if reference.is_empty() { None } else { Some(reference.remove(0)) }),
+                _ => assert_eq!(heap.pop_max(), reference.pop()),
+            }
+
+            assert!(is_interval_heap(heap.as_slice()));
+            assert_eq!(heap.peek_min(), reference.first());
+            assert_eq!(heap.peek_max(), reference.last());
+        }
+    }
+}
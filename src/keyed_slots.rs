@@ -0,0 +1,193 @@
+use std::borrow::Borrow;
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash};
+
+// Storage for the keyed queues. Each key and its value live in a slot, which the queues' heaps refer to
+// by index, and slots are reused once their key has gone. Finding a slot from a key goes through an index
+// of key hashes rather than a HashMap of keys, so that each key only has to be stored once and doesn't
+// have to be Clone. Every hash maps to the first slot with that hash, and slots that share a hash are
+// chained together through next.
+#[derive(Debug, Clone)]
+struct Item<K, V> {
+    key: K,
+    value: V,
+    hash: u64,
+    // The next slot whose key has the same hash
+    next: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct KeyedSlots<K, V> {
+    items: Vec<Option<Item<K, V>>>,
+    free: Vec<usize>,
+    index: HashMap<u64, usize>,
+    hasher: RandomState,
+}
+
+impl<K: Hash + Eq, V> KeyedSlots<K, V> {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            items: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            index: HashMap::with_capacity(capacity),
+            hasher: RandomState::new(),
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.items.clear();
+        self.free.clear();
+        self.index.clear();
+    }
+
+    pub(crate) fn key(&self, slot: usize) -> &K {
+        &self.item(slot).key
+    }
+
+    pub(crate) fn value(&self, slot: usize) -> &V {
+        &self.item(slot).value
+    }
+
+    pub(crate) fn value_mut(&mut self, slot: usize) -> &mut V {
+        &mut self.items[slot].as_mut().unwrap().value
+    }
+
+    pub(crate) fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.hasher.hash_one(key)
+    }
+
+    pub(crate) fn find<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<usize> where K: Borrow<Q> {
+        self.find_hashed(self.hash(key), key)
+    }
+
+    // For callers that already have the key's hash, so that a find followed by an insert only hashes once
+    pub(crate) fn find_hashed<Q: Eq + ?Sized>(&self, hash: u64, key: &Q) -> Option<usize> where K: Borrow<Q> {
+        let mut next = self.index.get(&hash).copied();
+        while let Some(slot) = next {
+            let item = self.item(slot);
+            if item.key.borrow() == key {
+                return Some(slot);
+            }
+
+            next = item.next;
+        }
+
+        None
+    }
+
+    // The key must not already be there
+    pub(crate) fn insert(&mut self, key: K, value: V) -> usize {
+        self.insert_hashed(self.hash(&key), key, value)
+    }
+
+    pub(crate) fn insert_hashed(&mut self, hash: u64, key: K, value: V) -> usize {
+        let item = Item {
+            key,
+            value,
+            hash,
+            next: self.index.get(&hash).copied(),
+        };
+
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.items[slot] = Some(item);
+                slot
+            }
+            None => {
+                self.items.push(Some(item));
+                self.items.len() - 1
+            }
+        };
+
+        self.index.insert(hash, slot);
+        slot
+    }
+
+    pub(crate) fn remove(&mut self, slot: usize) -> (K, V) {
+        let item = self.items[slot].take().unwrap();
+        self.free.push(slot);
+
+        if self.index[&item.hash] == slot {
+            match item.next {
+                Some(next) => self.index.insert(item.hash, next),
+                None => self.index.remove(&item.hash),
+            };
+        } else {
+            let mut previous = self.index[&item.hash];
+            while self.item(previous).next != Some(slot) {
+                previous = self.item(previous).next.unwrap();
+            }
+
+            self.items[previous].as_mut().unwrap().next = item.next;
+        }
+
+        (item.key, item.value)
+    }
+
+    fn item(&self, slot: usize) -> &Item<K, V> {
+        self.items[slot].as_ref().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_slot_reuse() {
+        let mut slots = KeyedSlots::with_capacity(0);
+        let a = slots.insert("a", 1);
+        let b = slots.insert("b", 2);
+        assert_eq!(slots.remove(a), ("a", 1));
+
+        // The freed slot goes to the next key rather than a new one being made
+        let c = slots.insert("c", 3);
+        assert_eq!(c, a);
+        assert_eq!((slots.key(c), slots.value(c)), (&"c", &3));
+        assert_eq!(slots.find("b"), Some(b));
+        assert_eq!(slots.insert("d", 4), 2);
+    }
+
+    #[test]
+    fn test_stale_keys() {
+        let mut slots = KeyedSlots::with_capacity(0);
+        let a = slots.insert(String::from("a"), 1);
+        slots.remove(a);
+        assert_eq!(slots.find("a"), None);
+
+        // A key that used to be in a reused slot isn't found there, only the key that is there now
+        let b = slots.insert(String::from("b"), 2);
+        assert_eq!(b, a);
+        assert_eq!(slots.find("a"), None);
+        assert_eq!(slots.find("b"), Some(b));
+        *slots.value_mut(b) += 10;
+        assert_eq!(slots.value(b), &12);
+    }
+
+    #[test]
+    fn test_remove_shared_hash() {
+        let mut slots = KeyedSlots::with_capacity(0);
+        let keys: Vec<usize> = (0..3).map(|key| slots.insert(key, key * 10)).collect();
+
+        // Chain every key under the same hash, the way colliding hashes would be
+        slots.index.clear();
+        for (slot, item) in slots.items.iter_mut().enumerate() {
+            let item = item.as_mut().unwrap();
+            item.hash = 7;
+            item.next = slot.checked_sub(1);
+        }
+        slots.index.insert(7, 2);
+
+        let find = |slots: &KeyedSlots<usize, usize>, key: usize| slots.find_hashed(7, &key);
+        assert_eq!(find(&slots, 1), Some(keys[1]));
+
+        // From the middle of the chain, then the head, then the last one left
+        assert_eq!(slots.remove(keys[1]), (1, 10));
+        assert_eq!((find(&slots, 0), find(&slots, 1), find(&slots, 2)), (Some(keys[0]), None, Some(keys[2])));
+        assert_eq!(slots.remove(keys[2]), (2, 20));
+        assert_eq!(find(&slots, 0), Some(keys[0]));
+        assert_eq!(slots.remove(keys[0]), (0, 0));
+        assert!(slots.index.is_empty());
+    }
+}
@@ -0,0 +1,244 @@
+use crate::cancelable::CancelableQueue;
+use crate::heap::{Heap, HeapOrder, Sequenced, StableOrder};
+use std::collections::HashSet;
+
+// A heap that can delete any element in O(1) by leaving it where it is and forgetting its id. Dead
+// elements get skipped when they reach the top, and once they make up more than the compaction threshold
+// of the heap they are all swept out in one O(n) pass. Elements that compare equal come out in insertion
+// order, as a side effect of the ids being handed out in sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EntryId(u64);
+
+#[derive(Debug, Clone)]
+pub struct LazyDeleteHeap<T, Order: HeapOrder<T>> {
+    heap: Heap<Sequenced<T>, StableOrder<Order>>,
+    live: HashSet<u64>,
+    next_sequence: u64,
+    compaction_threshold: f64,
+}
+
+impl<T, Order: HeapOrder<T>> LazyDeleteHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            heap: Heap::with_capacity_and_cmp(0, StableOrder(order)),
+            live: HashSet::new(),
+            next_sequence: 0,
+            compaction_threshold: 0.5,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.heap.order().0
+    }
+
+    // The fraction of the underlying heap that can be dead before it gets compacted, one half by default
+    pub fn compaction_threshold(&self) -> f64 {
+        self.compaction_threshold
+    }
+
+    pub fn set_compaction_threshold(&mut self, compaction_threshold: f64) {
+        assert!((0.0..=1.0).contains(&compaction_threshold), "Compaction threshold must be between 0 and 1, not {}", compaction_threshold);
+        self.compaction_threshold = compaction_threshold;
+        self.maybe_compact();
+    }
+
+    // The number of live elements
+    pub fn len(&self) -> usize {
+        self.live.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live.is_empty()
+    }
+
+    // The number of deleted elements that are still taking up space
+    pub fn garbage(&self) -> usize {
+        self.heap.len() - self.live.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.live.clear();
+    }
+
+    pub fn contains(&self, id: EntryId) -> bool {
+        self.live.contains(&id.0)
+    }
+
+    // Iterates over the live elements in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (EntryId, &T)> + '_ {
+        self.heap
+            .iter()
+            .filter(|entry| self.live.contains(&entry.sequence))
+            .map(|entry| (EntryId(entry.sequence), &entry.value))
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        // The top is never dead, as pop and remove both clear dead elements off the top
+        self.heap.peek().map(|entry| &entry.value)
+    }
+
+    pub fn insert(&mut self, value: T) -> EntryId {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+
+        self.heap.insert(Sequenced::new(sequence, value));
+        self.live.insert(sequence);
+        EntryId(sequence)
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.pop_with_id().map(|(_, value)| value)
+    }
+
+    pub fn pop_with_id(&mut self) -> Option<(EntryId, T)> {
+        let entry = self.heap.pop()?;
+        self.live.remove(&entry.sequence);
+        self.skip_dead();
+        self.maybe_compact();
+
+        Some((EntryId(entry.sequence), entry.value))
+    }
+
+    // Returns false if the element had already been popped or removed
+    pub fn remove(&mut self, id: EntryId) -> bool {
+        if !self.live.remove(&id.0) {
+            return false;
+        }
+
+        self.skip_dead();
+        self.maybe_compact();
+        true
+    }
+
+    // Sweeps every dead element out of the heap now
+    pub fn compact(&mut self) {
+        let live = &self.live;
+        self.heap.retain(|entry| live.contains(&entry.sequence));
+    }
+
+    pub fn shrink_to_fit(&mut self) {
+        self.compact();
+        self.heap.shrink_to_fit();
+        self.live.shrink_to_fit();
+    }
+
+    fn skip_dead(&mut self) {
+        while let Some(entry) = self.heap.peek() {
+            if self.live.contains(&entry.sequence) {
+                break;
+            }
+
+            self.heap.pop();
+        }
+    }
+
+    fn maybe_compact(&mut self) {
+        if self.garbage() as f64 > self.compaction_threshold * self.heap.len() as f64 {
+            self.compact();
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for LazyDeleteHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for LazyDeleteHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T>> CancelableQueue<T> for LazyDeleteHeap<T, Order> {
+    type Handle = EntryId;
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn insert_with_handle(&mut self, value: T) -> EntryId {
+        self.insert(value)
+    }
+
+    fn cancel(&mut self, id: EntryId) -> bool {
+        self.remove(id)
+    }
+
+    fn is_pending(&self, id: EntryId) -> bool {
+        self.contains(id)
+    }
+
+    fn peek(&self) -> Option<&T> {
+        self.peek()
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MinOrder;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_random_operations() {
+        let mut rng = thread_rng();
+        let mut heap = LazyDeleteHeap::<_, MinOrder<_>>::new();
+        let mut reference: Vec<(EntryId, u32)> = Vec::new();
+
+        for _ in 0..10000 {
+            match rng.gen_range(0..4) {
+                0 | 1 => {
+                    let value = rng.gen_range(0..1000);
+                    reference.push((heap.insert(value), value));
+                }
+                2 if !reference.is_empty() => {
+                    let (id, _) = reference.swap_remove(rng.gen_range(0..reference.len()));
+                    assert!(heap.remove(id));
+                    assert!(!heap.remove(id));
+                }
+                _ => {
+                    let popped = heap.pop_with_id();
+                    let expected = reference.iter().min_by_key(|(id, value)| (*value, id.0)).copied();
+                    assert_eq!(popped, expected);
+                    reference.retain(|(id, _)| Some(*id) != popped.map(|(id, _)| id));
+                }
+            }
+
+            assert_eq!(heap.len(), reference.len());
+            assert_eq!(heap.peek(), reference.iter().map(|(_, value)| value).min());
+            assert!(heap.garbage() as f64 <= 0.5 * (heap.len() + heap.garbage()) as f64 + 1.0);
+        }
+    }
+
+    #[test]
+    fn test_compaction() {
+        let mut heap = LazyDeleteHeap::<_, MinOrder<_>>::new();
+        let ids: Vec<_> = (0..100).map(|value| heap.insert(value)).collect();
+
+        // Removing from the bottom of the heap leaves garbage behind until the threshold is crossed
+        for id in &ids[50..70] {
+            heap.remove(*id);
+        }
+
+        assert_eq!(heap.garbage(), 20);
+        heap.set_compaction_threshold(0.1);
+        assert_eq!(heap.garbage(), 0);
+        assert_eq!(heap.iter().count(), 80);
+
+        assert!(heap.remove(ids[0]));
+        assert_eq!(heap.peek(), Some(&1));
+    }
+}
@@ -0,0 +1,251 @@
+use crate::heap::HeapOrder;
+
+// A leftist heap is a heap ordered binary tree where every node's left subtree has a right spine at least
+// as long as its right subtree's. That keeps the right spine of the whole tree O(log n) long, and since
+// merging only ever walks down right spines it is O(log n) in the worst case. Insert and pop are both
+// just merges.
+struct Node<T> {
+    value: T,
+    // The length of the right spine starting at this node
+    rank: usize,
+    left: Option<Box<Node<T>>>,
+    right: Option<Box<Node<T>>>,
+}
+
+fn rank<T>(node: &Option<Box<Node<T>>>) -> usize {
+    node.as_ref().map_or(0, |node| node.rank)
+}
+
+fn merge<T>(left: Option<Box<Node<T>>>, right: Option<Box<Node<T>>>, order: &impl HeapOrder<T>) -> Option<Box<Node<T>>> {
+    match (left, right) {
+        (Some(mut left), Some(mut right)) => {
+            if order.left_can_go_above(&right.value, &left.value) {
+                std::mem::swap(&mut left, &mut right);
+            }
+
+            // Merge into the right spine, and then swap the children if that made the right side too long
+            left.right = merge(left.right.take(), Some(right), order);
+            if rank(&left.left) < rank(&left.right) {
+                std::mem::swap(&mut left.left, &mut left.right);
+            }
+
+            left.rank = rank(&left.right) + 1;
+            Some(left)
+        }
+        (left, right) => left.or(right),
+    }
+}
+
+pub struct LeftistHeap<T, Order: HeapOrder<T>> {
+    root: Option<Box<Node<T>>>,
+    len: usize,
+    order: Order,
+}
+
+impl<T, Order: HeapOrder<T>> LeftistHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            root: None,
+            len: 0,
+            order,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        // Left spines can be as long as the heap, so take the tree apart without recursing
+        let mut stack: Vec<_> = self.root.take().into_iter().collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+
+        self.len = 0;
+    }
+
+    // Iterates over the elements in no particular order
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            stack: self.root.as_deref().into_iter().collect(),
+            remaining: self.len,
+        }
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.root.as_ref().map(|root| &root.value)
+    }
+
+    pub fn insert(&mut self, value: T) {
+        let node = Box::new(Node {
+            value,
+            rank: 1,
+            left: None,
+            right: None,
+        });
+
+        self.root = merge(self.root.take(), Some(node), &self.order);
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let root = *self.root.take()?;
+        self.root = merge(root.left, root.right, &self.order);
+        self.len -= 1;
+
+        Some(root.value)
+    }
+
+    // Moves everything out of other and into this heap in O(log n). This assumes both heaps were built with
+    // equivalent orders.
+    pub fn meld(&mut self, mut other: Self) {
+        self.append(&mut other);
+    }
+
+    pub fn append(&mut self, other: &mut Self) {
+        self.root = merge(self.root.take(), other.root.take(), &self.order);
+        self.len += std::mem::take(&mut other.len);
+    }
+
+    pub fn into_iter_sorted(self) -> IntoIterSorted<T, Order> {
+        IntoIterSorted { heap: self }
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Drop for LeftistHeap<T, Order> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for LeftistHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: std::fmt::Debug, Order: HeapOrder<T>> std::fmt::Debug for LeftistHeap<T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for LeftistHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> FromIterator<T> for LeftistHeap<T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+pub struct Iter<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.left.as_deref());
+        self.stack.extend(node.right.as_deref());
+        self.remaining -= 1;
+
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> { }
+
+pub struct IntoIterSorted<T, Order: HeapOrder<T>> {
+    heap: LeftistHeap<T, Order>,
+}
+
+impl<T, Order: HeapOrder<T>> Iterator for IntoIterSorted<T, Order> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.heap.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl<T, Order: HeapOrder<T>> ExactSizeIterator for IntoIterSorted<T, Order> { }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{MaxOrder, MinOrder};
+    use rand::prelude::*;
+
+    fn is_leftist<T, Order: HeapOrder<T>>(node: &Option<Box<Node<T>>>, order: &Order) -> bool {
+        match node {
+            Some(node) => {
+                let children_ok = [&node.left, &node.right].into_iter().flatten().all(|child| !order.left_can_go_above(&child.value, &node.value));
+                children_ok && rank(&node.left) >= rank(&node.right) && node.rank == rank(&node.right) + 1
+                    && is_leftist(&node.left, order) && is_leftist(&node.right, order)
+            }
+            None => true,
+        }
+    }
+
+    #[test]
+    fn test_insert_pop() {
+        let mut values: Vec<u32> = (0..1000).collect();
+        values.shuffle(&mut thread_rng());
+
+        let heap: LeftistHeap<_, MinOrder<_>> = values.into_iter().collect();
+        assert!(is_leftist(&heap.root, heap.order()));
+        assert_eq!(heap.iter().count(), 1000);
+        assert_eq!(heap.into_iter_sorted().collect::<Vec<_>>(), (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_meld() {
+        let mut left: LeftistHeap<_, MaxOrder<_>> = (0..500).step_by(2).collect();
+        let right: LeftistHeap<_, MaxOrder<_>> = (1..500).step_by(2).collect();
+
+        left.meld(right);
+        assert!(is_leftist(&left.root, left.order()));
+        assert_eq!(left.len(), 500);
+        assert_eq!(left.into_iter_sorted().collect::<Vec<_>>(), (0..500).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_deep_drop() {
+        // Inserting in reverse order builds one long left spine
+        let heap: LeftistHeap<_, MinOrder<_>> = (0..1_000_000).rev().collect();
+        assert_eq!(heap.peek(), Some(&0));
+        drop(heap);
+    }
+}
@@ -1 +1,56 @@
-pub mod heap;
\ No newline at end of file
+pub mod async_priority_queue;
+pub mod b_heap;
+pub mod beap;
+pub mod binomial_heap;
+#[cfg(feature = "brodal")]
+pub mod brodal_heap;
+pub mod bounded_priority_queue;
+pub mod bucket_queue;
+pub mod calendar_queue;
+pub mod cancelable;
+pub mod channel;
+pub mod concurrent;
+pub mod concurrent_heap;
+pub mod counting_heap;
+pub mod depq;
+pub mod double_priority_queue;
+#[cfg(feature = "external")]
+pub mod external_heap;
+pub mod expiry_queue;
+pub mod fibonacci_heap;
+pub mod frozen_heap;
+pub mod grouped_heap;
+pub mod heap;
+pub mod heap_slice;
+pub mod huffman;
+pub mod huffman_stream;
+pub mod indexed_by;
+pub mod indexed_heap;
+pub mod interval_heap;
+mod keyed_slots;
+pub mod lazy_delete_heap;
+pub mod leftist_heap;
+pub mod minmax_heap;
+pub mod monotone_priority_queue;
+pub mod pairing_heap;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod priority_deque;
+pub mod priority_pool;
+pub mod priority_queue;
+pub mod priority_semaphore;
+pub mod radix_heap;
+pub mod raw;
+pub mod simd_heap;
+pub mod skew_heap;
+pub mod soft_heap;
+mod sorted_frontier;
+pub mod stable_heap;
+pub mod sync_heap;
+pub mod time;
+pub mod timing_wheel;
+pub mod top_k;
+pub mod tracked_heap;
+pub mod treap;
+pub mod veb_heap;
+pub mod weak_heap;
@@ -0,0 +1,309 @@
+use crate::depq::DoubleEndedPriorityQueue;
+
+// A min-max heap is a binary heap where the levels alternate between min levels and max levels. Every
+// node on a min level is no bigger than anything below it, and every node on a max level is no smaller
+// than anything below it. That puts the minimum at the root and the maximum in one of the root's
+// children, so both ends of the queue can be reached in O(1) and removed in O(log n).
+
+fn is_min_level(index: usize) -> bool {
+    // The level of a node is floor(log2(index + 1)), and the root level is a min level
+    (usize::BITS - (index + 1).leading_zeros() - 1).is_multiple_of(2)
+}
+
+fn goes_above<T: Ord>(left: &T, right: &T, min_level: bool) -> bool {
+    if min_level {
+        left < right
+    } else {
+        left > right
+    }
+}
+
+fn bubble_up<T: Ord>(data: &mut [T], mut index: usize) {
+    if index == 0 {
+        return;
+    }
+
+    // First decide whether the new node belongs on the min levels or the max levels. If it is on a min
+    // level but is bigger than its parent (which is on a max level) then it belongs on the max levels, and
+    // vice versa.
+    let mut min_level = is_min_level(index);
+    let parent_index = (index - 1) / 2;
+    if goes_above(&data[index], &data[parent_index], !min_level) {
+        data.swap(index, parent_index);
+        index = parent_index;
+        min_level = !min_level;
+    }
+
+    // Then it only needs to be compared against its grandparents, which are on levels of the same kind
+    while index > 2 {
+        let grandparent_index = ((index - 1) / 2 - 1) / 2;
+        if goes_above(&data[index], &data[grandparent_index], min_level) {
+            data.swap(index, grandparent_index);
+            index = grandparent_index;
+        } else {
+            break;
+        }
+    }
+}
+
+fn trickle_down<T: Ord>(data: &mut [T], mut index: usize) {
+    let min_level = is_min_level(index);
+
+    loop {
+        // Find the best of the children and grandchildren. The children are on the opposite kind of level,
+        // but they still count because one of them might be the only descendant.
+        let first_child_index = 2 * index + 1;
+        if first_child_index >= data.len() {
+            break;
+        }
+
+        let first_grandchild_index = 2 * first_child_index + 1;
+        let candidates = (first_child_index..(first_child_index + 2).min(data.len()))
+            .chain(first_grandchild_index..(first_grandchild_index + 4).min(data.len()));
+
+        let mut best_index = first_child_index;
+        for candidate_index in candidates {
+            if goes_above(&data[candidate_index], &data[best_index], min_level) {
+                best_index = candidate_index;
+            }
+        }
+
+        if !goes_above(&data[best_index], &data[index], min_level) {
+            break;
+        }
+
+        data.swap(best_index, index);
+
+        if best_index < first_grandchild_index {
+            // A child has no descendants on levels of our kind, so we are done
+            break;
+        }
+
+        // The value we pushed down to the grandchild might now be on the wrong side of the grandchild's
+        // parent, which is on the opposite kind of level
+        let parent_index = (best_index - 1) / 2;
+        if goes_above(&data[parent_index], &data[best_index], min_level) {
+            data.swap(parent_index, best_index);
+        }
+
+        index = best_index;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MinMaxHeap<T: Ord> {
+    data: Vec<T>,
+}
+
+impl<T: Ord> MinMaxHeap<T> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            data: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn from_vec(mut data: Vec<T>) -> Self {
+        // Same bottom up construction as a normal heap, just with the min-max trickle down
+        for index in (0..(data.len() / 2)).rev() {
+            trickle_down(&mut data, index);
+        }
+
+        Self {
+            data,
+        }
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    pub fn insert(&mut self, value: T) {
+        let new_node_index = self.data.len();
+        self.data.push(value);
+        bubble_up(&mut self.data, new_node_index);
+    }
+
+    pub fn peek_min(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    pub fn peek_max(&self) -> Option<&T> {
+        self.max_index().map(|index| &self.data[index])
+    }
+
+    pub fn pop_min(&mut self) -> Option<T> {
+        self.remove_at(0)
+    }
+
+    pub fn pop_max(&mut self) -> Option<T> {
+        let index = self.max_index()?;
+        self.remove_at(index)
+    }
+
+    fn max_index(&self) -> Option<usize> {
+        // The maximum is whichever of the root's children is bigger, unless the root is all there is
+        match self.data.len() {
+            0 => None,
+            1 => Some(0),
+            2 => Some(1),
+            _ => Some(if self.data[1] >= self.data[2] { 1 } else { 2 }),
+        }
+    }
+
+    fn remove_at(&mut self, index: usize) -> Option<T> {
+        if index >= self.data.len() {
+            return None;
+        }
+
+        // This is only ever used for the root or one of its children, and the last element can always go
+        // in either of those without upsetting anything above it
+        let ret = self.data.swap_remove(index);
+        if index < self.data.len() {
+            trickle_down(&mut self.data, index);
+        }
+
+        Some(ret)
+    }
+}
+
+impl<T: Ord> DoubleEndedPriorityQueue<T> for MinMaxHeap<T> {
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn push(&mut self, value: T) {
+        self.insert(value);
+    }
+
+    fn peek_min(&self) -> Option<&T> {
+        self.peek_min()
+    }
+
+    fn peek_max(&self) -> Option<&T> {
+        self.peek_max()
+    }
+
+    fn pop_min(&mut self) -> Option<T> {
+        self.pop_min()
+    }
+
+    fn pop_max(&mut self) -> Option<T> {
+        self.pop_max()
+    }
+}
+
+impl<T: Ord> Default for MinMaxHeap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> From<Vec<T>> for MinMaxHeap<T> {
+    fn from(f: Vec<T>) -> Self {
+        Self::from_vec(f)
+    }
+}
+
+impl<T: Ord> FromIterator<T> for MinMaxHeap<T> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        Self::from_vec(iter.into_iter().collect())
+    }
+}
+
+impl<T: Ord> Extend<T> for MinMaxHeap<T> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T: Ord> IntoIterator for MinMaxHeap<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::prelude::*;
+
+    fn is_minmax_heap<T: Ord>(data: &[T]) -> bool {
+        // Every node has to be on the right side of all of its ancestors on levels of its own kind, and
+        // checking the closest one of each kind is enough
+        (1..data.len()).all(|index| {
+            let parent_index = (index - 1) / 2;
+            let parent_ok = !goes_above(&data[index], &data[parent_index], !is_min_level(index));
+            let grandparent_ok = index < 3 || !goes_above(&data[index], &data[(parent_index - 1) / 2], is_min_level(index));
+            parent_ok && grandparent_ok
+        })
+    }
+
+    #[test]
+    fn test_from_vec() {
+        let mut rng = thread_rng();
+        for len in 0..200 {
+            let mut values: Vec<u32> = (0..len).collect();
+            values.shuffle(&mut rng);
+
+            let heap = MinMaxHeap::from(values);
+            assert!(is_minmax_heap(heap.as_slice()));
+        }
+    }
+
+    #[test]
+    fn test_random_operations() {
+        let mut rng = thread_rng();
+        let mut heap = MinMaxHeap::new();
+        let mut reference = Vec::new();
+
+        for _ in 0..5000 {
+            match rng.gen_range(0..4) {
+                0 | 1 => {
+                    let value = rng.gen_range(0..100);
+                    heap.insert(value);
+                    reference.push(value);
+                    reference.sort();
+                }
+                2 => assert_eq!(heap.pop_min(), if reference.is_empty() { None } else { Some(reference.remove(0)) }),
+                _ => assert_eq!(heap.pop_max(), reference.pop()),
+            }
+
+            assert!(is_minmax_heap(heap.as_slice()));
+            assert_eq!(heap.peek_min(), reference.first());
+            assert_eq!(heap.peek_max(), reference.last());
+        }
+    }
+}
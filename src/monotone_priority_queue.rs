@@ -0,0 +1,147 @@
+use crate::heap::{Heap, HeapOrder};
+
+// A heap that checks it is being used monotonically, meaning that nothing inserted could go above the last
+// element popped. Dijkstra's algorithm and discrete event simulations both rely on that without saying so,
+// and breaking it usually means a negative edge weight or an event scheduled in the past, which otherwise
+// goes unnoticed. It is also what structures like RadixHeap need, so code that passes here can switch to
+// one of those.
+//
+// insert panics on a violation in debug builds and lets it through in release builds, like the radix
+// heap does. try_insert checks in every build and hands the value back instead of inserting it.
+#[derive(Debug, Clone)]
+pub struct MonotonePriorityQueue<T: Clone, Order: HeapOrder<T>> {
+    heap: Heap<T, Order>,
+    last: Option<T>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonotonicityError<T>(pub T);
+
+impl<T> std::fmt::Display for MonotonicityError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Cannot insert a value that goes above the last value popped")
+    }
+}
+
+impl<T: std::fmt::Debug> std::error::Error for MonotonicityError<T> { }
+
+impl<T: Clone, Order: HeapOrder<T>> MonotonePriorityQueue<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            heap: Heap::with_capacity_and_cmp(0, order),
+            last: None,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        self.heap.order()
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    // Forgets the last element popped as well, so anything can be inserted again
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.last = None;
+    }
+
+    // The last element popped, which nothing inserted can go above
+    pub fn last_popped(&self) -> Option<&T> {
+        self.last.as_ref()
+    }
+
+    // Iterates over the elements in no particular order
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.heap.iter()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.heap.peek()
+    }
+
+    pub fn is_monotone(&self, value: &T) -> bool {
+        self.last.as_ref().is_none_or(|last| !self.heap.order().left_can_go_above(value, last))
+    }
+
+    pub fn insert(&mut self, value: T) {
+        debug_assert!(self.is_monotone(&value), "Cannot insert a value that goes above the last value popped");
+        self.heap.insert(value);
+    }
+
+    pub fn try_insert(&mut self, value: T) -> Result<(), MonotonicityError<T>> {
+        if !self.is_monotone(&value) {
+            return Err(MonotonicityError(value));
+        }
+
+        self.heap.insert(value);
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let ret = self.heap.pop()?;
+        self.last = Some(ret.clone());
+        Some(ret)
+    }
+}
+
+impl<T: Clone, Order: HeapOrder<T> + Default> Default for MonotonePriorityQueue<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, Order: HeapOrder<T>> Extend<T> for MonotonePriorityQueue<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T: Clone, Order: HeapOrder<T> + Default> FromIterator<T> for MonotonePriorityQueue<T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MinOrder;
+
+    #[test]
+    fn test_try_insert() {
+        let mut queue: MonotonePriorityQueue<_, MinOrder<_>> = [5, 3, 8].into_iter().collect();
+        assert_eq!(queue.pop(), Some(3));
+
+        assert_eq!(queue.try_insert(3), Ok(()));
+        assert_eq!(queue.try_insert(2), Err(MonotonicityError(2)));
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.last_popped(), Some(&3));
+
+        queue.clear();
+        assert_eq!(queue.try_insert(0), Ok(()));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn test_non_monotone_insert() {
+        let mut queue = MonotonePriorityQueue::<_, MinOrder<_>>::new();
+        queue.insert(10);
+        queue.pop();
+        queue.insert(9);
+    }
+}
@@ -0,0 +1,619 @@
+use crate::cancelable::CancelableQueue;
+use crate::heap::HeapOrder;
+
+// A pairing heap is a heap ordered tree where each node keeps its children in a linked list. Inserting
+// and melding just link one root under the other, which is O(1). All the real work happens in pop, which
+// merges the root's children back together in pairs, for amortized O(log n).
+//
+// Nodes are stored left-child right-sibling, so each node points at its first child and its next
+// sibling.
+struct Node<T> {
+    value: T,
+    child: Option<Box<Node<T>>>,
+    sibling: Option<Box<Node<T>>>,
+}
+
+pub struct PairingHeap<T, Order: HeapOrder<T>> {
+    root: Option<Box<Node<T>>>,
+    len: usize,
+    order: Order,
+}
+
+fn link<T>(mut left: Box<Node<T>>, mut right: Box<Node<T>>, order: &impl HeapOrder<T>) -> Box<Node<T>> {
+    // Whichever root can't go above the other becomes the first child of the other. Ties go to the left, so
+    // older roots stay on top.
+    if order.left_can_go_above(&right.value, &left.value) {
+        std::mem::swap(&mut left, &mut right);
+    }
+
+    right.sibling = left.child.take();
+    left.child = Some(right);
+    left
+}
+
+fn merge_pairs<T>(mut first: Option<Box<Node<T>>>, order: &impl HeapOrder<T>) -> Option<Box<Node<T>>> {
+    // First pass, left to right: link the children together in pairs
+    let mut pairs = Vec::new();
+    while let Some(mut left) = first {
+        first = left.sibling.take();
+        match first {
+            Some(mut right) => {
+                first = right.sibling.take();
+                pairs.push(link(left, right, order));
+            }
+            None => pairs.push(left),
+        }
+    }
+
+    // Second pass, right to left: fold the pairs into a single tree
+    let mut ret = pairs.pop()?;
+    while let Some(pair) = pairs.pop() {
+        ret = link(pair, ret, order);
+    }
+
+    Some(ret)
+}
+
+impl<T, Order: HeapOrder<T>> PairingHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            root: None,
+            len: 0,
+            order,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        if let Some(root) = self.root.take() {
+            drop_tree(root);
+        }
+
+        self.len = 0;
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.root.as_ref().map(|root| &root.value)
+    }
+
+    pub fn insert(&mut self, value: T) {
+        let node = Box::new(Node {
+            value,
+            child: None,
+            sibling: None,
+        });
+
+        self.root = Some(match self.root.take() {
+            Some(root) => link(root, node, &self.order),
+            None => node,
+        });
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let mut root = self.root.take()?;
+        self.root = merge_pairs(root.child.take(), &self.order);
+        self.len -= 1;
+
+        Some(root.value)
+    }
+
+    // Moves everything out of other and into this heap in O(1). This assumes both heaps were built with
+    // equivalent orders.
+    pub fn meld(&mut self, mut other: Self) {
+        self.append(&mut other);
+    }
+
+    pub fn append(&mut self, other: &mut Self) {
+        self.root = match (self.root.take(), other.root.take()) {
+            (Some(left), Some(right)) => Some(link(left, right, &self.order)),
+            (left, right) => left.or(right),
+        };
+
+        self.len += std::mem::take(&mut other.len);
+    }
+
+    // Iterates over the elements in no particular order
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            stack: self.root.iter().map(|root| &**root).collect(),
+            remaining: self.len,
+        }
+    }
+
+    pub fn into_iter_sorted(self) -> IntoIterSorted<T, Order> {
+        IntoIterSorted { heap: self }
+    }
+}
+
+fn drop_tree<T>(root: Box<Node<T>>) {
+    // The default drop would recurse down the sibling lists, which can be as long as the heap, so
+    // take the tree apart with an explicit stack instead
+    let mut stack = vec![root];
+    while let Some(mut node) = stack.pop() {
+        stack.extend(node.child.take());
+        stack.extend(node.sibling.take());
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Drop for PairingHeap<T, Order> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for PairingHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: std::fmt::Debug, Order: HeapOrder<T>> std::fmt::Debug for PairingHeap<T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for PairingHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> FromIterator<T> for PairingHeap<T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+pub struct Iter<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.child.as_deref());
+        self.stack.extend(node.sibling.as_deref());
+        self.remaining -= 1;
+
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> { }
+
+pub struct IntoIterSorted<T, Order: HeapOrder<T>> {
+    heap: PairingHeap<T, Order>,
+}
+
+impl<T, Order: HeapOrder<T>> Iterator for IntoIterSorted<T, Order> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.heap.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl<T, Order: HeapOrder<T>> ExactSizeIterator for IntoIterSorted<T, Order> { }
+
+// An addressable version of the pairing heap, for graph algorithms where the point of a pairing heap is cheap
+// decrease_key. Nodes live in an arena as in FibonacciHeap, so that a Handle can find its node again, and
+// each one also points back at its previous sibling, or its parent if it is the first child, so any node
+// can be cut out of its sibling list in O(1).
+//
+// decrease_key cuts the node's subtree out and links it with the root, which is O(1) in the worst case.
+// Its amortized cost is subtler, since the pop that later tidies up pays for making the tree wider, and is
+// known to be somewhere between O(log log n) and O(log n), while being very fast in practice. remove cuts
+// the node out the same way and merges its children back in, which costs about the same as a pop.
+//
+// Handles are indices into the arena, so unlike PairingHeap this can't meld two heaps in O(1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    index: usize,
+    generation: u32,
+}
+
+#[derive(Debug, Clone)]
+struct ArenaNode<T> {
+    value: T,
+    child: Option<usize>,
+    sibling: Option<usize>,
+    // The previous sibling, or the parent for a first child, and None for the root
+    previous: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+struct Slot<T> {
+    generation: u32,
+    node: Option<ArenaNode<T>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AddressablePairingHeap<T, Order: HeapOrder<T>> {
+    slots: Vec<Slot<T>>,
+    free: Vec<usize>,
+    root: Option<usize>,
+    len: usize,
+    order: Order,
+}
+
+impl<T, Order: HeapOrder<T>> AddressablePairingHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            slots: Vec::new(),
+            free: Vec::new(),
+            root: None,
+            len: 0,
+            order,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        for slot in self.slots.iter_mut() {
+            if slot.node.take().is_some() {
+                slot.generation = slot.generation.wrapping_add(1);
+            }
+        }
+
+        self.free = (0..self.slots.len()).rev().collect();
+        self.root = None;
+        self.len = 0;
+    }
+
+    // Iterates over the elements in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.slots.iter().filter_map(|slot| slot.node.as_ref().map(|node| &node.value))
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.root.map(|index| &self.node(index).value)
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.resolve(handle).map(|index| &self.node(index).value)
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.resolve(handle).is_some()
+    }
+
+    pub fn insert(&mut self, value: T) -> Handle {
+        let index = self.free.pop().unwrap_or_else(|| {
+            self.slots.push(Slot { generation: 0, node: None });
+            self.slots.len() - 1
+        });
+
+        self.slots[index].node = Some(ArenaNode {
+            value,
+            child: None,
+            sibling: None,
+            previous: None,
+        });
+
+        self.add_root(index);
+        self.len += 1;
+
+        Handle {
+            index,
+            generation: self.slots[index].generation,
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let index = self.root?;
+        let child = self.node_mut(index).child.take();
+        self.root = self.merge_pairs(child);
+        Some(self.release(index))
+    }
+
+    // Moves the element towards the top of the heap by replacing it with a value that can go at least as
+    // high. Handing in a value that belongs lower down is a bug, and panics.
+    pub fn decrease_key(&mut self, handle: Handle, value: T) {
+        let index = self.resolve(handle).expect("Handle does not refer to an element of this heap");
+        assert!(
+            !self.order.left_can_go_above(&self.node(index).value, &value),
+            "decrease_key cannot move an element further down the heap"
+        );
+
+        self.node_mut(index).value = value;
+        if self.root != Some(index) {
+            self.cut(index);
+            self.add_root(index);
+        }
+    }
+
+    pub fn remove(&mut self, handle: Handle) -> Option<T> {
+        let index = self.resolve(handle)?;
+        if self.root == Some(index) {
+            return self.pop();
+        }
+
+        // Cut the node out, and put its children back as if it had been the root of its own heap
+        self.cut(index);
+        let child = self.node_mut(index).child.take();
+        if let Some(subtree) = self.merge_pairs(child) {
+            self.add_root(subtree);
+        }
+
+        Some(self.release(index))
+    }
+
+    pub fn into_iter_sorted(self) -> impl Iterator<Item = T> {
+        let mut heap = self;
+        std::iter::from_fn(move || heap.pop())
+    }
+
+    fn resolve(&self, handle: Handle) -> Option<usize> {
+        let slot = self.slots.get(handle.index)?;
+        (slot.generation == handle.generation && slot.node.is_some()).then_some(handle.index)
+    }
+
+    fn node(&self, index: usize) -> &ArenaNode<T> {
+        self.slots[index].node.as_ref().unwrap()
+    }
+
+    fn node_mut(&mut self, index: usize) -> &mut ArenaNode<T> {
+        self.slots[index].node.as_mut().unwrap()
+    }
+
+    fn release(&mut self, index: usize) -> T {
+        let slot = &mut self.slots[index];
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free.push(index);
+        self.len -= 1;
+
+        slot.node.take().unwrap().value
+    }
+
+    fn add_root(&mut self, index: usize) {
+        self.root = Some(match self.root {
+            Some(root) => self.link(root, index),
+            None => index,
+        });
+    }
+
+    // Links two detached trees and returns the root of the result. Ties go to the left, like link above.
+    fn link(&mut self, left: usize, right: usize) -> usize {
+        let (parent, child) = if self.order.left_can_go_above(&self.node(right).value, &self.node(left).value) {
+            (right, left)
+        } else {
+            (left, right)
+        };
+
+        let first = self.node(parent).child;
+        if let Some(first) = first {
+            self.node_mut(first).previous = Some(child);
+        }
+
+        let node = self.node_mut(child);
+        node.sibling = first;
+        node.previous = Some(parent);
+        self.node_mut(parent).child = Some(child);
+        parent
+    }
+
+    // Detaches a node, along with its subtree, from its parent and siblings
+    fn cut(&mut self, index: usize) {
+        let ArenaNode { sibling, previous, .. } = *self.node(index);
+        let previous = previous.unwrap();
+
+        if self.node(previous).child == Some(index) {
+            self.node_mut(previous).child = sibling;
+        } else {
+            self.node_mut(previous).sibling = sibling;
+        }
+
+        if let Some(sibling) = sibling {
+            self.node_mut(sibling).previous = Some(previous);
+        }
+
+        let node = self.node_mut(index);
+        node.sibling = None;
+        node.previous = None;
+    }
+
+    fn merge_pairs(&mut self, mut first: Option<usize>) -> Option<usize> {
+        // The same two passes as merge_pairs above
+        let mut pairs = Vec::new();
+        while let Some(left) = first {
+            let right = self.detach_first(left);
+            first = right.and_then(|right| self.detach_first(right));
+            pairs.push(match right {
+                Some(right) => self.link(left, right),
+                None => left,
+            });
+        }
+
+        let mut ret = pairs.pop()?;
+        while let Some(pair) = pairs.pop() {
+            ret = self.link(pair, ret);
+        }
+
+        Some(ret)
+    }
+
+    // Detaches the first node of a sibling list and returns the rest of the list
+    fn detach_first(&mut self, index: usize) -> Option<usize> {
+        let node = self.node_mut(index);
+        node.previous = None;
+        node.sibling.take()
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for AddressablePairingHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for AddressablePairingHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T>> CancelableQueue<T> for AddressablePairingHeap<T, Order> {
+    type Handle = Handle;
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn insert_with_handle(&mut self, value: T) -> Handle {
+        self.insert(value)
+    }
+
+    fn cancel(&mut self, handle: Handle) -> bool {
+        self.remove(handle).is_some()
+    }
+
+    fn is_pending(&self, handle: Handle) -> bool {
+        self.contains(handle)
+    }
+
+    fn peek(&self) -> Option<&T> {
+        self.peek()
+    }
+
+    fn pop(&mut self) -> Option<T> {
+        self.pop()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{MaxOrder, MinOrder};
+    use rand::prelude::*;
+
+    #[test]
+    fn test_insert_pop() {
+        let mut values: Vec<u32> = (0..1000).collect();
+        values.shuffle(&mut thread_rng());
+
+        let heap: PairingHeap<_, MinOrder<_>> = values.iter().copied().collect();
+        assert_eq!(heap.len(), 1000);
+        assert_eq!(heap.iter().count(), 1000);
+        assert_eq!(heap.into_iter_sorted().collect::<Vec<_>>(), (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_meld() {
+        let mut left: PairingHeap<_, MaxOrder<_>> = (0..500).step_by(2).collect();
+        let right: PairingHeap<_, MaxOrder<_>> = (1..500).step_by(2).collect();
+
+        left.meld(right);
+        assert_eq!(left.len(), 500);
+        assert_eq!(left.peek(), Some(&499));
+        assert_eq!(left.into_iter_sorted().collect::<Vec<_>>(), (0..500).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_deep_drop() {
+        // Inserting in order builds a root with a very long list of children
+        let mut heap: PairingHeap<_, MinOrder<_>> = (0..1_000_000).collect();
+        assert_eq!(heap.pop(), Some(0));
+        drop(heap);
+    }
+
+    #[test]
+    fn test_addressable_random_operations() {
+        let mut rng = thread_rng();
+        let mut heap = AddressablePairingHeap::<u32, MinOrder<_>>::new();
+        let mut reference: Vec<(Handle, u32)> = Vec::new();
+
+        for _ in 0..10000 {
+            match rng.gen_range(0..5) {
+                0 | 1 => {
+                    let value = rng.gen_range(0..1000);
+                    reference.push((heap.insert(value), value));
+                }
+                2 if !reference.is_empty() => {
+                    let index = rng.gen_range(0..reference.len());
+                    let (handle, value) = reference[index];
+                    let new_value = rng.gen_range(0..=value);
+                    heap.decrease_key(handle, new_value);
+                    reference[index].1 = new_value;
+                }
+                3 if !reference.is_empty() => {
+                    let (handle, value) = reference.swap_remove(rng.gen_range(0..reference.len()));
+                    assert_eq!(heap.remove(handle), Some(value));
+                    assert!(!heap.contains(handle));
+                }
+                _ => {
+                    let expected = reference.iter().map(|(_, value)| *value).min();
+                    assert_eq!(heap.pop(), expected);
+                    if let Some(position) = reference.iter().position(|(handle, _)| !heap.contains(*handle)) {
+                        reference.swap_remove(position);
+                    }
+                }
+            }
+
+            assert_eq!(heap.len(), reference.len());
+            assert_eq!(heap.peek().copied(), reference.iter().map(|(_, value)| *value).min());
+        }
+
+        assert!(reference.iter().all(|(handle, value)| heap.get(*handle) == Some(value)));
+    }
+
+    #[test]
+    fn test_addressable_decrease_to_top() {
+        let mut heap = AddressablePairingHeap::<_, MinOrder<_>>::new();
+        let handles: Vec<_> = (10..20).map(|value| heap.insert(value)).collect();
+        assert_eq!(heap.pop(), Some(10));
+
+        heap.decrease_key(handles[9], 0);
+        assert_eq!(heap.peek(), Some(&0));
+        assert_eq!(heap.remove(handles[5]), Some(15));
+        assert_eq!(heap.into_iter_sorted().collect::<Vec<_>>(), [0, 11, 12, 13, 14, 16, 17, 18]);
+    }
+}
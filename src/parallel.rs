@@ -0,0 +1,299 @@
+use crate::heap::{better_to_rebuild, Heap, HeapOrder, PeekMut};
+use crate::raw::heapify_up;
+use rayon::prelude::*;
+
+// Building heaps on the rayon thread pool, for heaps of hundreds of millions of elements where even the O(n)
+// heapify takes long enough to matter. Collecting into the backing Vec is rayon's own parallel collect, and
+// the heapify is the usual bottom-up one, except that the two subtrees under a node are heapified at the
+// same time before the node itself is sifted down into them. Subtrees only share their ancestors, so the
+// threads never touch the same elements.
+//
+// There is also a parallel k-way merge of sorted runs, for the merge phase of an external sort. The runs are
+// shared out between the threads, each of which merges its share with a tournament heap of their heads,
+// and then the much smaller number of merged runs is merged once more on the calling thread.
+
+// Subtrees smaller than this are heapified on the thread that got to them, since below it handing half of
+// one to another thread costs more than the sifting it saves
+const SEQUENTIAL_SUBTREE: usize = 1 << 14;
+
+// Rearranges data so that it satisfies the heap property under order, like raw::heapify_in_place but with
+// the work split over the thread pool
+pub fn par_heapify_in_place<T: Send, Order: HeapOrder<T> + Sync>(data: &mut [T], order: &Order) {
+    let len = data.len();
+    unsafe { heapify_subtree(Storage(data.as_mut_ptr()), len, 0, len, order) }
+}
+
+// The heap's storage, shared between the threads heapifying its subtrees. Each of them only ever goes
+// through it to the elements of its own subtree.
+struct Storage<T>(*mut T);
+
+impl<T> Clone for Storage<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Storage<T> {}
+
+unsafe impl<T: Send> Send for Storage<T> {}
+unsafe impl<T: Send> Sync for Storage<T> {}
+
+impl<T> Storage<T> {
+    unsafe fn get<'a>(self, index: usize) -> &'a T {
+        &*self.0.add(index)
+    }
+
+    unsafe fn swap(self, left: usize, right: usize) {
+        std::ptr::swap(self.0.add(left), self.0.add(right));
+    }
+}
+
+// Safety: storage must point at len elements, and nothing else can be using the subtree under index while
+// this runs. size is roughly how many elements are in that subtree.
+unsafe fn heapify_subtree<T: Send, Order: HeapOrder<T> + Sync>(storage: Storage<T>, len: usize, index: usize, size: usize, order: &Order) {
+    // Anything in the back half has no children, so is already a heap on its own
+    if index >= len / 2 {
+        return;
+    }
+
+    let (left, right) = (2 * index + 1, 2 * index + 2);
+    if size > SEQUENTIAL_SUBTREE {
+        rayon::join(
+            || unsafe { heapify_subtree(storage, len, left, size / 2, order) },
+            || unsafe { heapify_subtree(storage, len, right, size / 2, order) },
+        );
+    } else {
+        heapify_subtree(storage, len, left, size / 2, order);
+        heapify_subtree(storage, len, right, size / 2, order);
+    }
+
+    sift_down(storage, len, index, order);
+}
+
+// raw::heapify_down, but through the shared storage, since taking a slice of all of it from more than one
+// thread at once would be undefined behaviour even though they never look at the same elements
+unsafe fn sift_down<T, Order: HeapOrder<T>>(storage: Storage<T>, len: usize, mut index: usize, order: &Order) {
+    loop {
+        let mut highest = index;
+        for child in [2 * index + 1, 2 * index + 2] {
+            if child < len && order.left_can_go_above(storage.get(child), storage.get(highest)) {
+                highest = child;
+            }
+        }
+
+        if highest == index {
+            return;
+        }
+
+        storage.swap(index, highest);
+        index = highest;
+    }
+}
+
+impl<T: Send, Order: HeapOrder<T> + Sync> Heap<T, Order> {
+    // Like from_vec_and_cmp, but heapifies on the thread pool
+    pub fn par_from_vec_and_cmp(mut data: Vec<T>, order: Order) -> Self {
+        par_heapify_in_place(&mut data, &order);
+        Self { data, order, bound: None }
+    }
+}
+
+impl<T: Send, Order: HeapOrder<T> + Default + Sync> FromParallelIterator<T> for Heap<T, Order> {
+    fn from_par_iter<IntoIter: IntoParallelIterator<Item = T>>(par_iter: IntoIter) -> Self {
+        Self::par_from_vec_and_cmp(par_iter.into_par_iter().collect(), Order::default())
+    }
+}
+
+impl<T: Send, Order: HeapOrder<T> + Sync> ParallelExtend<T> for Heap<T, Order> {
+    fn par_extend<IntoIter: IntoParallelIterator<Item = T>>(&mut self, par_iter: IntoIter) {
+        // The same choice as extend between sifting each new element up and heapifying the whole lot again,
+        // except that only the heapify can be spread over the threads
+        let start = self.data.len();
+        self.data.par_extend(par_iter);
+        if better_to_rebuild(start, self.data.len() - start) {
+            par_heapify_in_place(&mut self.data, &self.order);
+        } else {
+            for index in start..self.data.len() {
+                heapify_up(&mut self.data, index, &self.order);
+            }
+        }
+    }
+}
+
+struct Head<T> {
+    value: T,
+    source: usize,
+}
+
+// Orders heads by the caller's order, and breaks ties by taking the earlier source first so that the merge
+// is stable
+struct HeadOrder<'a, Order>(&'a Order);
+
+impl<'a, T, Order: HeapOrder<T>> HeapOrder<Head<T>> for HeadOrder<'a, Order> {
+    fn left_can_go_above(&self, left: &Head<T>, right: &Head<T>) -> bool {
+        self.0.left_can_go_above(&left.value, &right.value) || (!self.0.left_can_go_above(&right.value, &left.value) && left.source < right.source)
+    }
+}
+
+// A k-way merge on one thread, with the head of each source that still has any in a heap
+struct Merge<'a, T, Order: HeapOrder<T>> {
+    heads: Heap<Head<T>, HeadOrder<'a, Order>>,
+    sources: Vec<std::vec::IntoIter<T>>,
+}
+
+impl<'a, T, Order: HeapOrder<T>> Merge<'a, T, Order> {
+    fn new(sources: Vec<Vec<T>>, order: &'a Order) -> Self {
+        let mut sources: Vec<_> = sources.into_iter().map(|source| source.into_iter()).collect();
+        let heads = sources.iter_mut().enumerate().filter_map(|(source, values)| Some(Head { value: values.next()?, source })).collect();
+        Self { heads: Heap::from_vec_and_cmp(heads, HeadOrder(order)), sources }
+    }
+
+    fn len(&self) -> usize {
+        self.heads.len() + self.sources.iter().map(|source| source.len()).sum::<usize>()
+    }
+}
+
+impl<'a, T, Order: HeapOrder<T>> Iterator for Merge<'a, T, Order> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        // Replacing the top with the next from the same source saves the sift up that popping and then
+        // inserting it would need
+        let mut top = self.heads.peek_mut()?;
+        match self.sources[top.source].next() {
+            Some(next) => Some(std::mem::replace(&mut top.value, next)),
+            None => Some(PeekMut::pop(top).value),
+        }
+    }
+}
+
+// Merges the sources down to about one run per thread, in parallel. Each thread gets consecutive sources, so
+// the merged runs are in the same order as the sources they came from.
+fn merge_per_thread<T: Send, Order: HeapOrder<T> + Sync>(sources: Vec<Vec<T>>, order: &Order) -> Vec<Vec<T>> {
+    let per_thread = sources.len().div_ceil(rayon::current_num_threads()).max(1);
+    let mut sources = sources.into_iter();
+    let shares: Vec<Vec<Vec<T>>> = std::iter::from_fn(|| Some(sources.by_ref().take(per_thread).collect::<Vec<_>>()).filter(|share| !share.is_empty())).collect();
+
+    shares
+        .into_par_iter()
+        .map(|share| {
+            // Nothing to merge if a thread only got one source
+            if share.len() == 1 {
+                share.into_iter().next().unwrap()
+            } else {
+                let merge = Merge::new(share, order);
+                let mut merged = Vec::with_capacity(merge.len());
+                merged.extend(merge);
+                merged
+            }
+        })
+        .collect()
+}
+
+// Merges sources that are each already sorted so that their tops under order come first, into a single run
+// in the same order. Equal elements come out in the order of the sources they were in.
+pub fn par_merge_sorted<T: Send, Order: HeapOrder<T> + Sync>(sources: Vec<Vec<T>>, order: &Order) -> Vec<T> {
+    let merge = Merge::new(merge_per_thread(sources, order), order);
+    let mut ret = Vec::with_capacity(merge.len());
+    ret.extend(merge);
+    ret
+}
+
+// Like par_merge_sorted, but the final merge is done as the chunks are asked for, so the whole output never
+// has to be in memory at once on top of the runs it is merged from
+pub fn par_merge_sorted_chunks<T: Send, Order: HeapOrder<T> + Sync>(sources: Vec<Vec<T>>, order: &Order, chunk_size: usize) -> MergeChunks<'_, T, Order> {
+    assert!(chunk_size > 0, "Cannot merge into chunks of no elements");
+    MergeChunks { merge: Merge::new(merge_per_thread(sources, order), order), chunk_size }
+}
+
+// Chunks of chunk_size elements from par_merge_sorted_chunks, apart from the last chunk which can be smaller
+pub struct MergeChunks<'a, T, Order: HeapOrder<T>> {
+    merge: Merge<'a, T, Order>,
+    chunk_size: usize,
+}
+
+impl<'a, T, Order: HeapOrder<T>> Iterator for MergeChunks<'a, T, Order> {
+    type Item = Vec<T>;
+
+    fn next(&mut self) -> Option<Vec<T>> {
+        let mut chunk = Vec::with_capacity(self.chunk_size.min(self.merge.len()));
+        chunk.extend(self.merge.by_ref().take(self.chunk_size));
+        (!chunk.is_empty()).then_some(chunk)
+    }
+}
+
+impl<'a, T, Order: HeapOrder<T>> std::fmt::Debug for MergeChunks<'a, T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MergeChunks").field("remaining", &self.merge.len()).field("chunk_size", &self.chunk_size).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{KeyOrder, MaxHeap, MinHeap, MinOrder};
+    use crate::raw::is_heap;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_from_par_iter() {
+        // Big enough that the top few levels get split over the threads
+        let mut rng = thread_rng();
+        let values: Vec<u32> = (0..200_000).map(|_| rng.gen()).collect();
+
+        let heap: MinHeap<u32> = values.par_iter().copied().collect();
+        assert!(is_heap(&heap.data, heap.order()));
+
+        let mut expected = values;
+        expected.sort_by(|left, right| right.cmp(left));
+        assert_eq!(heap.into_sorted_vec(), expected);
+    }
+
+    #[test]
+    fn test_par_extend() {
+        let mut heap: MaxHeap<u32> = (0..100_000).collect();
+
+        // A few more gets sifted up, and lots more rebuilds the whole heap
+        heap.par_extend(100_000..100_010u32);
+        assert!(is_heap(&heap.data, heap.order()));
+        heap.par_extend((0..300_000u32).into_par_iter().map(|value| value * 7 % 300_000));
+        assert!(is_heap(&heap.data, heap.order()));
+
+        assert_eq!(heap.len(), 400_010);
+        assert_eq!(heap.pop(), Some(299_999));
+        assert_eq!(heap.pop(), Some(299_998));
+    }
+
+    #[test]
+    fn test_merge_sorted() {
+        let mut rng = thread_rng();
+        let sources: Vec<Vec<u32>> = (0..100)
+            .map(|_| {
+                let mut source: Vec<u32> = (0..rng.gen_range(0..2000)).map(|_| rng.gen_range(0..10000)).collect();
+                source.sort();
+                source
+            })
+            .collect();
+
+        let mut expected: Vec<u32> = sources.iter().flatten().copied().collect();
+        expected.sort();
+
+        let order = MinOrder::default();
+        assert_eq!(par_merge_sorted(sources.clone(), &order), expected);
+
+        let chunks: Vec<Vec<u32>> = par_merge_sorted_chunks(sources, &order, 1000).collect();
+        assert!(chunks.iter().rev().skip(1).all(|chunk| chunk.len() == 1000));
+        assert_eq!(chunks.concat(), expected);
+    }
+
+    #[test]
+    fn test_merge_sorted_is_stable() {
+        // Every source has the same keys, so they should come out grouped by key and then in source order
+        let sources: Vec<Vec<(u32, usize)>> = (0..50).map(|source| (0..10).map(|key| (key, source)).collect()).collect();
+        let order = KeyOrder::new(|&(key, _): &(u32, usize)| key, MinOrder::default());
+
+        let expected: Vec<(u32, usize)> = (0..10).flat_map(|key| (0..50).map(move |source| (key, source))).collect();
+        assert_eq!(par_merge_sorted(sources, &order), expected);
+        assert_eq!(par_merge_sorted(Vec::<Vec<u32>>::new(), &MinOrder::default()), Vec::<u32>::new());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::interval_heap::IntervalHeap;
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+// A work-stealing deque for jobs with priorities, along the lines of crossbeam-deque's Worker and
+// Stealer, but ordered by priority rather than by when the jobs were pushed. The thread that owns the
+// Worker pushes jobs and pops its best one, and any other thread with a Stealer takes jobs from the other
+// end, the worst first, so the owner keeps its most urgent work and the thieves get what it would have left
+// until last. It is the concurrent counterpart to GroupedHeap, with a deque per worker thread rather than a
+// heap per group.
+//
+// Both ends of an IntervalHeap are O(1) to peek and O(log n) to pop, so that is what sits behind the lock.
+// The larger jobs are the better ones, the same as a MaxHeap, so wrap them in Reverse to run the smallest
+// first.
+pub struct Worker<T: Ord> {
+    jobs: Arc<Mutex<IntervalHeap<T>>>,
+    // Only the thread that owns it can pop the best job, so a Worker can be sent but not shared
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+pub struct Stealer<T: Ord> {
+    jobs: Arc<Mutex<IntervalHeap<T>>>,
+}
+
+fn lock<T: Ord>(jobs: &Mutex<IntervalHeap<T>>) -> MutexGuard<'_, IntervalHeap<T>> {
+    // Comparisons are the only user code that runs under the lock, and a panic in one of those can leave
+    // the heap out of order but can't lose jobs
+    jobs.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+impl<T: Ord> Worker<T> {
+    pub fn new() -> Self {
+        Self { jobs: Arc::new(Mutex::new(IntervalHeap::new())), _not_sync: PhantomData }
+    }
+
+    pub fn stealer(&self) -> Stealer<T> {
+        Stealer { jobs: self.jobs.clone() }
+    }
+
+    // Only a snapshot, since thieves can be taking jobs at the same time
+    pub fn len(&self) -> usize {
+        lock(&self.jobs).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&self, job: T) {
+        lock(&self.jobs).insert(job);
+    }
+
+    pub fn pop_best(&self) -> Option<T> {
+        lock(&self.jobs).pop_max()
+    }
+}
+
+impl<T: Ord> Default for Worker<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord + std::fmt::Debug> std::fmt::Debug for Worker<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(lock(&self.jobs).iter()).finish()
+    }
+}
+
+impl<T: Ord> Stealer<T> {
+    // Only a snapshot, since the owner and other thieves can be changing it at the same time
+    pub fn len(&self) -> usize {
+        lock(&self.jobs).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn steal_worst(&self) -> Option<T> {
+        lock(&self.jobs).pop_min()
+    }
+
+    // Takes the worst half of the jobs, rounding up, worst first
+    pub fn steal_half(&self) -> Vec<T> {
+        let mut jobs = lock(&self.jobs);
+        let count = jobs.len().div_ceil(2);
+        std::iter::from_fn(|| jobs.pop_min()).take(count).collect()
+    }
+
+    // Steals half the jobs into the thief's own Worker and returns the best of them to run straight away.
+    // Only one of the two locks is held at a time, so two workers stealing from each other can't deadlock.
+    pub fn steal_half_into(&self, thief: &Worker<T>) -> Option<T> {
+        let mut stolen = self.steal_half();
+        let best = stolen.pop();
+        lock(&thief.jobs).extend(stolen);
+        best
+    }
+}
+
+impl<T: Ord> Clone for Stealer<T> {
+    fn clone(&self) -> Self {
+        Self { jobs: self.jobs.clone() }
+    }
+}
+
+impl<T: Ord + std::fmt::Debug> std::fmt::Debug for Stealer<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(lock(&self.jobs).iter()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::cmp::Reverse;
+
+    #[test]
+    fn test_ends() {
+        let worker = Worker::new();
+        let stealer = worker.stealer();
+        for job in [5, 1, 9, 3, 7, 2] {
+            worker.push(job);
+        }
+
+        assert_eq!(worker.pop_best(), Some(9));
+        assert_eq!(stealer.steal_worst(), Some(1));
+        assert_eq!(stealer.steal_half(), vec![2, 3]);
+        assert_eq!(stealer.len(), 2);
+
+        let thief = Worker::new();
+        thief.push(4);
+        assert_eq!(stealer.steal_half_into(&thief), Some(5));
+        assert_eq!(worker.pop_best(), Some(7));
+        assert_eq!(thief.pop_best(), Some(4));
+        assert_eq!(stealer.steal_half_into(&thief), None);
+        assert!(thief.is_empty());
+    }
+
+    #[test]
+    fn test_threads() {
+        // Smallest first, with the owner working through its jobs while three thieves help out
+        let worker = Worker::new();
+        for job in 0..10000u32 {
+            worker.push(Reverse(job));
+        }
+
+        let mut done = Vec::new();
+        let stolen: Vec<Vec<u32>> = std::thread::scope(|scope| {
+            let thieves: Vec<_> = (0..3)
+                .map(|_| {
+                    let stealer = worker.stealer();
+                    scope.spawn(move || {
+                        let thief = Worker::new();
+                        let mut mine = Vec::new();
+                        while let Some(Reverse(job)) = thief.pop_best().or_else(|| stealer.steal_half_into(&thief)) {
+                            mine.push(job);
+                        }
+
+                        mine
+                    })
+                })
+                .collect();
+
+            while let Some(Reverse(job)) = worker.pop_best() {
+                done.push(job);
+            }
+
+            thieves.into_iter().map(|thief| thief.join().unwrap()).collect()
+        });
+
+        // The owner always takes its best job, so whatever it did itself came out in order
+        assert!(done.is_sorted());
+        done.extend(stolen.into_iter().flatten());
+        done.sort();
+        assert_eq!(done, (0..10000).collect::<Vec<_>>());
+    }
+}
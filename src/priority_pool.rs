@@ -0,0 +1,207 @@
+use crate::heap::{Heap, HeapOrder};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+// A work-stealing scheduler for jobs with priorities, which leaves running them to whoever owns the threads.
+// Each worker has its own heap, pushes to it and pops the best of its own jobs, so workers that have work
+// mostly only ever take their own lock. A worker that runs dry looks at the tops of everyone else's heaps,
+// picks the one with the best job, and takes the better half of that worker's jobs, so the most urgent work
+// spreads out rather than waiting behind whatever the other worker is busy with.
+//
+// Stealing locks every other worker at once to compare their tops, which is a lot more than a pop, but it
+// only happens when a worker has nothing else to do.
+pub struct PriorityPool<T, Order: HeapOrder<T>> {
+    workers: Vec<Mutex<Heap<T, Order>>>,
+    next_worker: AtomicUsize,
+    len: AtomicUsize,
+}
+
+impl<T, Order: HeapOrder<T> + Clone> PriorityPool<T, Order> {
+    pub fn new(workers: usize) -> Self where Order: Default {
+        Self::with_cmp(workers, Order::default())
+    }
+
+    pub fn with_cmp(workers: usize, order: Order) -> Self {
+        assert!(workers > 0, "Cannot create a PriorityPool with no workers");
+
+        Self {
+            workers: (0..workers).map(|_| Mutex::new(Heap::with_capacity_and_cmp(0, order.clone()))).collect(),
+            next_worker: AtomicUsize::new(0),
+            len: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T>> PriorityPool<T, Order> {
+    pub fn worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    // Only a snapshot, since the workers can be pushing and popping at the same time
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn worker(&self, index: usize) -> Worker<'_, T, Order> {
+        assert!(index < self.workers.len(), "Cannot get worker {} of a pool of {}", index, self.workers.len());
+        Worker { pool: self, index }
+    }
+
+    // Pushes a job from outside the pool, sharing them out between the workers in turn
+    pub fn push(&self, job: T) {
+        let index = self.next_worker.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        self.worker(index).push(job);
+    }
+
+    // Takes every job that hasn't been popped, in no particular order
+    pub fn into_vec(self) -> Vec<T> {
+        self.workers.into_iter().flat_map(|worker| worker.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()).into_vec()).collect()
+    }
+
+    fn lock(&self, index: usize) -> MutexGuard<'_, Heap<T, Order>> {
+        // A panic part way through a sift can leave a heap slightly out of order, but can't lose any jobs
+        self.workers[index].lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn steal(&self, thief: usize) -> Option<T> {
+        // Lock everyone else in index order, which is the order every other thief locks in too
+        let mut victims: Vec<MutexGuard<'_, Heap<T, Order>>> = (0..self.workers.len()).filter(|&index| index != thief).map(|index| self.lock(index)).collect();
+
+        let mut best: Option<usize> = None;
+        for (position, victim) in victims.iter().enumerate() {
+            if let Some(top) = victim.peek() {
+                if best.is_none_or(|best| victim.order().left_can_go_above(top, victims[best].peek().unwrap())) {
+                    best = Some(position);
+                }
+            }
+        }
+
+        let victim = &mut victims[best?];
+        let count = victim.len().div_ceil(2);
+        let mut stolen: Vec<T> = std::iter::from_fn(|| victim.pop()).take(count).collect();
+        drop(victims);
+
+        // The best of them is the one to run next, and the rest go on the thief's own heap
+        let ret = stolen.remove(0);
+        if !stolen.is_empty() {
+            self.lock(thief).extend(stolen);
+        }
+
+        Some(ret)
+    }
+}
+
+impl<T, Order: HeapOrder<T>> std::fmt::Debug for PriorityPool<T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PriorityPool").field("workers", &self.workers.len()).field("len", &self.len()).finish()
+    }
+}
+
+// One worker's view of a PriorityPool, for the thread doing that worker's jobs
+pub struct Worker<'a, T, Order: HeapOrder<T>> {
+    pool: &'a PriorityPool<T, Order>,
+    index: usize,
+}
+
+impl<'a, T, Order: HeapOrder<T>> Worker<'a, T, Order> {
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    // How many jobs this worker has of its own
+    pub fn len(&self) -> usize {
+        self.pool.lock(self.index).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push(&self, job: T) {
+        // Count it before it goes in, so that a pop that gets to it first can't take the count below zero
+        self.pool.len.fetch_add(1, Ordering::Relaxed);
+        self.pool.lock(self.index).insert(job);
+    }
+
+    // Pops this worker's best job, or steals if it has none. Only returns None if every worker was empty.
+    pub fn pop(&self) -> Option<T> {
+        let local = self.pool.lock(self.index).pop();
+        let ret = local.or_else(|| self.pool.steal(self.index));
+        if ret.is_some() {
+            self.pool.len.fetch_sub(1, Ordering::Relaxed);
+        }
+
+        ret
+    }
+}
+
+impl<'a, T, Order: HeapOrder<T>> std::fmt::Debug for Worker<'a, T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Worker").field("index", &self.index).finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MaxOrder;
+
+    #[test]
+    fn test_steal_best_half() {
+        let pool = PriorityPool::<u32, MaxOrder<_>>::new(3);
+        for job in [1, 2, 3] {
+            pool.worker(0).push(job);
+        }
+
+        for job in [10, 20, 30, 40, 50] {
+            pool.worker(1).push(job);
+        }
+
+        // Worker 1 has the best job, so worker 2 takes 50, 40 and 30 from it and runs 50 first
+        let thief = pool.worker(2);
+        assert_eq!(thief.pop(), Some(50));
+        assert_eq!(thief.len(), 2);
+        assert_eq!(pool.worker(1).len(), 2);
+        assert_eq!(thief.pop(), Some(40));
+        assert_eq!(pool.len(), 6);
+    }
+
+    #[test]
+    fn test_threads() {
+        let pool = PriorityPool::<u32, MaxOrder<_>>::new(4);
+        for job in 0..4000 {
+            pool.worker(0).push(job);
+        }
+
+        let done = Mutex::new(Vec::new());
+        std::thread::scope(|scope| {
+            for index in 0..4 {
+                let (pool, done) = (&pool, &done);
+                scope.spawn(move || {
+                    let worker = pool.worker(index);
+                    let mut mine = Vec::new();
+                    while let Some(job) = worker.pop() {
+                        // Some jobs make more work, like a crawler finding new links
+                        if job < 10000 && job % 100 == 0 {
+                            worker.push(job + 10000);
+                        }
+
+                        mine.push(job);
+                    }
+
+                    done.lock().unwrap().extend(mine);
+                });
+            }
+        });
+
+        let mut done = done.into_inner().unwrap();
+        done.extend(pool.into_vec());
+        done.sort();
+        let expected: Vec<u32> = (0..4000).chain((0..4000).step_by(100).map(|job| job + 10000)).collect();
+        assert_eq!(done, expected);
+    }
+}
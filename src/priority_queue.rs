@@ -0,0 +1,694 @@
+use crate::heap::{better_to_rebuild, HeapOrder, MaxOrder};
+use crate::keyed_slots::KeyedSlots;
+use crate::raw::{heapify_in_place, heapify_up_tracked, remove_tracked, restore_tracked};
+use crate::sorted_frontier::SortedFrontier;
+use std::borrow::Borrow;
+use std::hash::Hash;
+
+// A keyed priority queue, which works like a map from keys to priorities where the entry with the top
+// priority can be popped. Each key is in the queue at most once, and its priority can be looked up,
+// changed or removed by key in O(log n). By default the highest priority comes out first.
+//
+// The keys and priorities live in slots, which the heap refers to by index, and positions records where
+// each slot is in the heap in return. That is kept apart from the slots so that the raw tracked functions
+// can read priorities through the order while they write positions.
+pub struct PriorityQueue<K: Hash + Eq, P, Order: HeapOrder<P> = MaxOrder<P>> {
+    slots: KeyedSlots<K, P>,
+    // Slot indices, as a heap ordered by their priorities
+    heap: Vec<usize>,
+    positions: Vec<usize>,
+    order: Order,
+}
+
+impl<K: Hash + Eq, P, Order: HeapOrder<P>> PriorityQueue<K, P, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self::with_capacity_and_cmp(0, order)
+    }
+
+    pub fn with_capacity_and_cmp(capacity: usize, order: Order) -> Self {
+        Self {
+            slots: KeyedSlots::with_capacity(capacity),
+            heap: Vec::with_capacity(capacity),
+            positions: Vec::with_capacity(capacity),
+            order,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.heap.clear();
+        self.positions.clear();
+    }
+
+    // Iterates over the keys and their priorities in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &P)> + '_ {
+        self.heap.iter().map(|&slot| self.key_and_priority(slot))
+    }
+
+    // Iterates over the keys and their priorities from the top of the queue down, without changing it.
+    // This walks the heap with a second, smaller heap of the positions that could come next, so taking the
+    // first k entries costs O(k log k).
+    pub fn iter_sorted(&self) -> IterSorted<'_, K, P, Order> {
+        IterSorted { queue: self, frontier: SortedFrontier::new(self.heap.len()) }
+    }
+
+    pub fn peek(&self) -> Option<(&K, &P)> {
+        Some(self.key_and_priority(*self.heap.first()?))
+    }
+
+    pub fn get<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<(&K, &P)> where K: Borrow<Q> {
+        Some(self.key_and_priority(self.slots.find(key)?))
+    }
+
+    // Edits the key's priority in place through a guard, which moves the key to its new place in the heap when
+    // it is dropped, the same as Heap's peek_mut
+    pub fn get_mut<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<PriorityMut<'_, K, P, Order>> where K: Borrow<Q> {
+        let slot = self.slots.find(key)?;
+        Some(PriorityMut { queue: self, slot, modified: false })
+    }
+
+    pub fn get_priority<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> Option<&P> where K: Borrow<Q> {
+        self.get(key).map(|(_, priority)| priority)
+    }
+
+    pub fn contains_key<Q: Hash + Eq + ?Sized>(&self, key: &Q) -> bool where K: Borrow<Q> {
+        self.slots.find(key).is_some()
+    }
+
+    // Adds the key with the given priority, or if it is already there changes its priority instead and
+    // returns the old one
+    pub fn push(&mut self, key: K, priority: P) -> Option<P> {
+        if let Some(slot) = self.slots.find(&key) {
+            return Some(self.set_priority(slot, priority));
+        }
+
+        self.push_new(self.slots.hash(&key), key, priority);
+        None
+    }
+
+    // Looks the key up once, for inserting or updating it in place without going back to the queue
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, P, Order> {
+        let hash = self.slots.hash(&key);
+        match self.slots.find_hashed(hash, &key) {
+            Some(slot) => Entry::Occupied(OccupiedEntry { queue: self, slot }),
+            None => Entry::Vacant(VacantEntry { queue: self, key, hash }),
+        }
+    }
+
+    // Adds the key, or if it is already there keeps whichever of the old and new priorities can go higher.
+    // Returns the one that lost, or None if the key is new. Ties keep the old priority.
+    pub fn push_or_update(&mut self, key: K, priority: P) -> Option<P> {
+        let Some(slot) = self.slots.find(&key) else {
+            self.push(key, priority);
+            return None;
+        };
+
+        if self.order.left_can_go_above(&priority, self.priority(slot)) {
+            Some(self.set_priority(slot, priority))
+        } else {
+            Some(priority)
+        }
+    }
+
+    // The same as push_or_update, but only says whether the new priority went in
+    pub fn push_if_better(&mut self, key: K, priority: P) -> bool {
+        let Some(slot) = self.slots.find(&key) else {
+            self.push(key, priority);
+            return true;
+        };
+
+        let better = self.order.left_can_go_above(&priority, self.priority(slot));
+        if better {
+            self.set_priority(slot, priority);
+        }
+
+        better
+    }
+
+    // Returns the old priority, or None and changes nothing if the key isn't there
+    pub fn change_priority<Q: Hash + Eq + ?Sized>(&mut self, key: &Q, priority: P) -> Option<P> where K: Borrow<Q> {
+        let slot = self.slots.find(key)?;
+        Some(self.set_priority(slot, priority))
+    }
+
+    // Pushes every key and priority, the same as calling push for each of them. When the batch is large
+    // compared with the queue, the priorities are all written first and then the whole heap is rebuilt in
+    // one O(n) pass, rather than sifting each key into place one at a time.
+    pub fn update_priorities<IntoIter: IntoIterator<Item = (K, P)>>(&mut self, iter: IntoIter) {
+        let updates: Vec<(K, P)> = iter.into_iter().collect();
+        if !better_to_rebuild(self.heap.len(), updates.len()) {
+            for (key, priority) in updates {
+                self.push(key, priority);
+            }
+
+            return;
+        }
+
+        for (key, priority) in updates {
+            self.push_unsorted(key, priority);
+        }
+
+        self.rebuild();
+    }
+
+    // Copies out every key and priority, so that the queue can be put back the way it is now with restore
+    pub fn snapshot(&self) -> QueueSnapshot<K, P> where K: Clone, P: Clone {
+        QueueSnapshot {
+            entries: self.iter().map(|(key, priority)| (key.clone(), priority.clone())).collect(),
+        }
+    }
+
+    // Throws away everything in the queue and replaces it with the snapshot. Nothing about the snapshot is
+    // taken on trust, the heap is rebuilt under this queue's order, so it can come from a queue with a
+    // different order or have been built by hand.
+    pub fn restore(&mut self, snapshot: QueueSnapshot<K, P>) {
+        self.clear();
+        for (key, priority) in snapshot.entries {
+            self.push_unsorted(key, priority);
+        }
+
+        self.rebuild();
+    }
+
+    pub fn remove<Q: Hash + Eq + ?Sized>(&mut self, key: &Q) -> Option<(K, P)> where K: Borrow<Q> {
+        let slot = self.slots.find(key)?;
+        Some(self.remove_slot(slot))
+    }
+
+    pub fn pop(&mut self) -> Option<(K, P)> {
+        let slot = *self.heap.first()?;
+        Some(self.remove_slot(slot))
+    }
+
+    // Popped one at a time rather than sorted in place, since the heap only holds slot indices and each entry
+    // has to come out of its slot. Reversed so that the top of the queue is last, like Heap's.
+    pub fn into_sorted_vec(mut self) -> Vec<(K, P)> {
+        let mut ret = Vec::with_capacity(self.len());
+        ret.extend(std::iter::from_fn(|| self.pop()));
+        ret.reverse();
+        ret
+    }
+
+    fn push_new(&mut self, hash: u64, key: K, priority: P) -> usize {
+        let slot = self.slots.insert_hashed(hash, key, priority);
+        self.push_slot(slot);
+        heapify_up_tracked(&mut self.heap, self.positions[slot], &by_priority(&self.slots, &self.order), &mut follow(&mut self.positions));
+        slot
+    }
+
+    // Pushes without moving anything in the heap, which leaves it to a rebuild to put right
+    fn push_unsorted(&mut self, key: K, priority: P) {
+        match self.slots.find(&key) {
+            Some(slot) => *self.slots.value_mut(slot) = priority,
+            None => {
+                let slot = self.slots.insert(key, priority);
+                self.push_slot(slot);
+            }
+        }
+    }
+
+    // Slots are reused before new ones are made, so a slot is either already in positions or the next one
+    fn push_slot(&mut self, slot: usize) {
+        let position = self.heap.len();
+        self.heap.push(slot);
+        match self.positions.get_mut(slot) {
+            Some(old) => *old = position,
+            None => self.positions.push(position),
+        }
+    }
+
+    fn rebuild(&mut self) {
+        heapify_in_place(&mut self.heap, &by_priority(&self.slots, &self.order));
+        for (position, &slot) in self.heap.iter().enumerate() {
+            self.positions[slot] = position;
+        }
+    }
+
+    fn key_and_priority(&self, slot: usize) -> (&K, &P) {
+        (self.slots.key(slot), self.priority(slot))
+    }
+
+    fn priority(&self, slot: usize) -> &P {
+        self.slots.value(slot)
+    }
+
+    fn set_priority(&mut self, slot: usize, priority: P) -> P {
+        let ret = std::mem::replace(self.slots.value_mut(slot), priority);
+        self.restore_slot(slot);
+        ret
+    }
+
+    fn remove_slot(&mut self, slot: usize) -> (K, P) {
+        remove_tracked(&mut self.heap, self.positions[slot], &by_priority(&self.slots, &self.order), &mut follow(&mut self.positions));
+        self.heap.pop();
+        self.slots.remove(slot)
+    }
+
+    fn goes_above(&self, left: usize, right: usize) -> bool {
+        self.order.left_can_go_above(self.priority(self.heap[left]), self.priority(self.heap[right]))
+    }
+
+    // Moves the slot to its new place in the heap after its priority has changed
+    fn restore_slot(&mut self, slot: usize) {
+        restore_tracked(&mut self.heap, self.positions[slot], &by_priority(&self.slots, &self.order), &mut follow(&mut self.positions));
+    }
+}
+
+// What the raw tracked functions sift the heap of slot indices with, comparing slots by their priorities
+// and writing down each slot's new position as it moves
+fn by_priority<'a, K: Hash + Eq, P>(slots: &'a KeyedSlots<K, P>, order: &'a impl HeapOrder<P>) -> impl HeapOrder<usize> + 'a {
+    move |left: &usize, right: &usize| order.left_can_go_above(slots.value(*left), slots.value(*right))
+}
+
+fn follow(positions: &mut [usize]) -> impl FnMut(&usize, usize) + '_ {
+    move |slot: &usize, position| positions[*slot] = position
+}
+
+// The keys and priorities of a PriorityQueue at some point in time, from PriorityQueue::snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueueSnapshot<K, P> {
+    entries: Vec<(K, P)>,
+}
+
+impl<K, P> QueueSnapshot<K, P> {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Iterates over the keys and their priorities in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &P)> + '_ {
+        self.entries.iter().map(|(key, priority)| (key, priority))
+    }
+}
+
+impl<K, P> FromIterator<(K, P)> for QueueSnapshot<K, P> {
+    fn from_iter<IntoIter: IntoIterator<Item = (K, P)>>(iter: IntoIter) -> Self {
+        Self { entries: iter.into_iter().collect() }
+    }
+}
+
+pub struct IterSorted<'a, K: Hash + Eq, P, Order: HeapOrder<P>> {
+    queue: &'a PriorityQueue<K, P, Order>,
+    frontier: SortedFrontier,
+}
+
+impl<'a, K: Hash + Eq, P, Order: HeapOrder<P>> Iterator for IterSorted<'a, K, P, Order> {
+    type Item = (&'a K, &'a P);
+
+    fn next(&mut self) -> Option<(&'a K, &'a P)> {
+        let queue = self.queue;
+        let position = self.frontier.next(|left, right| queue.goes_above(left, right))?;
+        Some(queue.key_and_priority(queue.heap[position]))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.frontier.remaining(), Some(self.frontier.remaining()))
+    }
+}
+
+impl<'a, K: Hash + Eq, P, Order: HeapOrder<P>> ExactSizeIterator for IterSorted<'a, K, P, Order> { }
+
+pub struct PriorityMut<'a, K: Hash + Eq, P, Order: HeapOrder<P>> {
+    queue: &'a mut PriorityQueue<K, P, Order>,
+    slot: usize,
+    modified: bool,
+}
+
+impl<'a, K: Hash + Eq, P, Order: HeapOrder<P>> PriorityMut<'a, K, P, Order> {
+    pub fn key(this: &Self) -> &K {
+        this.queue.slots.key(this.slot)
+    }
+
+    pub fn remove(mut this: Self) -> (K, P) {
+        // The key is coming out anyway, so there is no point moving it first
+        this.modified = false;
+        this.queue.remove_slot(this.slot)
+    }
+}
+
+impl<'a, K: Hash + Eq, P, Order: HeapOrder<P>> std::ops::Deref for PriorityMut<'a, K, P, Order> {
+    type Target = P;
+
+    fn deref(&self) -> &P {
+        self.queue.priority(self.slot)
+    }
+}
+
+impl<'a, K: Hash + Eq, P, Order: HeapOrder<P>> std::ops::DerefMut for PriorityMut<'a, K, P, Order> {
+    fn deref_mut(&mut self) -> &mut P {
+        self.modified = true;
+        self.queue.slots.value_mut(self.slot)
+    }
+}
+
+impl<'a, K: Hash + Eq, P, Order: HeapOrder<P>> Drop for PriorityMut<'a, K, P, Order> {
+    fn drop(&mut self) {
+        // Unlike PeekMut the key can be anywhere in the heap, so it might have to move either way
+        if self.modified {
+            self.queue.restore_slot(self.slot);
+        }
+    }
+}
+
+// A view into a single key of a PriorityQueue, from PriorityQueue::entry. Unlike HashMap's entries these
+// only hand out shared references to the priority, because changing it has to go back through the queue to
+// move the key to its new place in the heap.
+pub enum Entry<'a, K: Hash + Eq, P, Order: HeapOrder<P>> {
+    Occupied(OccupiedEntry<'a, K, P, Order>),
+    Vacant(VacantEntry<'a, K, P, Order>),
+}
+
+pub struct OccupiedEntry<'a, K: Hash + Eq, P, Order: HeapOrder<P>> {
+    queue: &'a mut PriorityQueue<K, P, Order>,
+    slot: usize,
+}
+
+pub struct VacantEntry<'a, K: Hash + Eq, P, Order: HeapOrder<P>> {
+    queue: &'a mut PriorityQueue<K, P, Order>,
+    key: K,
+    hash: u64,
+}
+
+impl<'a, K: Hash + Eq, P, Order: HeapOrder<P>> Entry<'a, K, P, Order> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    pub fn or_insert(self, priority: P) -> &'a P {
+        self.or_insert_with(|| priority)
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> P>(self, priority: F) -> &'a P {
+        match self {
+            Entry::Occupied(entry) => entry.into_priority(),
+            Entry::Vacant(entry) => entry.insert(priority()),
+        }
+    }
+
+    // Changes the priority in place if the key is there, and then moves it to its new place in the heap
+    pub fn and_modify_priority<F: FnOnce(&mut P)>(mut self, f: F) -> Self {
+        if let Entry::Occupied(entry) = &mut self {
+            entry.modify_priority(f);
+        }
+
+        self
+    }
+}
+
+impl<'a, K: Hash + Eq, P, Order: HeapOrder<P>> OccupiedEntry<'a, K, P, Order> {
+    pub fn key(&self) -> &K {
+        self.queue.slots.key(self.slot)
+    }
+
+    pub fn priority(&self) -> &P {
+        self.queue.priority(self.slot)
+    }
+
+    pub fn into_priority(self) -> &'a P {
+        self.queue.priority(self.slot)
+    }
+
+    // Returns the old priority
+    pub fn set_priority(&mut self, priority: P) -> P {
+        self.queue.set_priority(self.slot, priority)
+    }
+
+    pub fn modify_priority<F: FnOnce(&mut P)>(&mut self, f: F) {
+        f(self.queue.slots.value_mut(self.slot));
+        self.queue.restore_slot(self.slot);
+    }
+
+    pub fn remove(self) -> (K, P) {
+        self.queue.remove_slot(self.slot)
+    }
+}
+
+impl<'a, K: Hash + Eq, P, Order: HeapOrder<P>> VacantEntry<'a, K, P, Order> {
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    pub fn into_key(self) -> K {
+        self.key
+    }
+
+    pub fn insert(self, priority: P) -> &'a P {
+        let slot = self.queue.push_new(self.hash, self.key, priority);
+        self.queue.priority(slot)
+    }
+}
+
+impl<K: Hash + Eq, P, Order: HeapOrder<P> + Default> Default for PriorityQueue<K, P, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + std::fmt::Debug, P: std::fmt::Debug, Order: HeapOrder<P>> std::fmt::Debug for PriorityQueue<K, P, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Hash + Eq, P, Order: HeapOrder<P>> Extend<(K, P)> for PriorityQueue<K, P, Order> {
+    fn extend<IntoIter: IntoIterator<Item = (K, P)>>(&mut self, iter: IntoIter) {
+        for (key, priority) in iter {
+            self.push(key, priority);
+        }
+    }
+}
+
+impl<K: Hash + Eq, P, Order: HeapOrder<P> + Default> FromIterator<(K, P)> for PriorityQueue<K, P, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = (K, P)>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MinOrder;
+    use rand::prelude::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_random_operations() {
+        let mut rng = thread_rng();
+        let mut queue = PriorityQueue::<u32, u32>::new();
+        let mut reference: HashMap<u32, u32> = HashMap::new();
+
+        for _ in 0..10000 {
+            let key = rng.gen_range(0..200);
+            match rng.gen_range(0..5) {
+                0 | 1 => {
+                    let priority = rng.gen_range(0..1000);
+                    assert_eq!(queue.push(key, priority), reference.insert(key, priority));
+                }
+                2 => {
+                    let priority = rng.gen_range(0..1000);
+                    let expected = reference.get_mut(&key).map(|old| std::mem::replace(old, priority));
+                    assert_eq!(queue.change_priority(&key, priority), expected);
+                }
+                3 => assert_eq!(queue.remove(&key), reference.remove_entry(&key)),
+                _ => {
+                    let popped = queue.pop();
+                    assert_eq!(popped.map(|(_, priority)| priority), reference.values().copied().max());
+                    if let Some((key, _)) = popped {
+                        reference.remove(&key);
+                    }
+                }
+            }
+
+            assert_eq!(queue.len(), reference.len());
+            assert_eq!(queue.get_priority(&key), reference.get(&key));
+        }
+
+        assert!(reference.iter().all(|(key, priority)| queue.get_priority(key) == Some(priority)));
+    }
+
+    #[test]
+    fn test_keep_best() {
+        // An A* style open set, where a lower cost is better
+        let mut open = PriorityQueue::<char, u32, MinOrder<_>>::new();
+        assert!(open.push_if_better('a', 10));
+        assert!(!open.push_if_better('a', 12));
+        assert!(!open.push_if_better('a', 10));
+        assert!(open.push_if_better('a', 7));
+
+        assert_eq!(open.push_or_update('b', 5), None);
+        assert_eq!(open.push_or_update('b', 9), Some(9));
+        assert_eq!(open.push_or_update('b', 3), Some(5));
+
+        assert_eq!(open.pop(), Some(('b', 3)));
+        assert_eq!(open.pop(), Some(('a', 7)));
+    }
+
+    #[test]
+    fn test_update_priorities() {
+        let mut rng = thread_rng();
+        let mut queue: PriorityQueue<u32, u32> = (0..1000).map(|key| (key, rng.gen_range(0..10000))).collect();
+        let mut reference: HashMap<u32, u32> = queue.iter().map(|(&key, &priority)| (key, priority)).collect();
+
+        // A small batch goes through push, a big one rebuilds the heap, and both can add new keys
+        for batch_len in [5, 2000] {
+            let batch: Vec<(u32, u32)> = (0..batch_len).map(|_| (rng.gen_range(0..1200), rng.gen_range(0..10000))).collect();
+            reference.extend(batch.iter().copied());
+            queue.update_priorities(batch);
+            assert_eq!(queue.len(), reference.len());
+        }
+
+        let mut expected: Vec<u32> = reference.into_values().collect();
+        expected.sort();
+        let popped: Vec<u32> = queue.into_sorted_vec().into_iter().map(|(_, priority)| priority).collect();
+        assert_eq!(popped, expected);
+    }
+
+    #[test]
+    fn test_iter_sorted() {
+        let mut rng = thread_rng();
+        let queue: PriorityQueue<u32, u32, MinOrder<_>> = (0..500).map(|key| (key, rng.gen_range(0..100))).collect();
+
+        let sorted: Vec<(&u32, &u32)> = queue.iter_sorted().collect();
+        assert_eq!(sorted.len(), 500);
+        assert!(sorted.windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        assert!(sorted.iter().all(|&(key, priority)| queue.get_priority(key) == Some(priority)));
+
+        let top: Vec<u32> = queue.iter_sorted().take(10).map(|(_, &priority)| priority).collect();
+        let mut expected: Vec<u32> = queue.iter().map(|(_, &priority)| priority).collect();
+        expected.sort();
+        assert_eq!(top, expected[..10]);
+        assert_eq!(queue.len(), 500);
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut queue: PriorityQueue<char, u32> = [('a', 5), ('b', 3), ('c', 8)].into_iter().collect();
+
+        let mut priority = queue.get_mut(&'b').unwrap();
+        assert_eq!(PriorityMut::key(&priority), &'b');
+        *priority += 10;
+        drop(priority);
+        assert_eq!(queue.peek(), Some((&'b', &13)));
+
+        *queue.get_mut(&'b').unwrap() = 1;
+        assert_eq!(queue.peek(), Some((&'c', &8)));
+
+        assert_eq!(PriorityMut::remove(queue.get_mut(&'c').unwrap()), ('c', 8));
+        assert!(queue.get_mut(&'c').is_none());
+        assert_eq!(queue.into_sorted_vec(), vec![('b', 1), ('a', 5)]);
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let mut queue: PriorityQueue<&str, u32> = [("build", 5), ("test", 3), ("deploy", 1)].into_iter().collect();
+        let checkpoint = queue.snapshot();
+        assert_eq!(checkpoint.len(), 3);
+
+        // A transaction that goes wrong halfway through
+        queue.pop();
+        queue.change_priority("deploy", 10);
+        queue.push("rollback", 7);
+
+        queue.restore(checkpoint.clone());
+        assert_eq!(queue.snapshot().iter().count(), 3);
+        assert!(!queue.contains_key("rollback"));
+        assert_eq!(queue.into_sorted_vec(), vec![("deploy", 1), ("test", 3), ("build", 5)]);
+
+        // A snapshot from a max queue can be restored into a min queue, which rebuilds it the other way up
+        let mut reversed = PriorityQueue::<&str, u32, MinOrder<_>>::new();
+        reversed.restore(checkpoint.iter().map(|(&key, &priority)| (key, priority)).collect());
+        assert_eq!(reversed.pop(), Some(("deploy", 1)));
+    }
+
+    #[test]
+    fn test_entry() {
+        // Counting words, where the most common comes out first
+        let mut counts = PriorityQueue::<&str, u32>::new();
+        for word in "the cat and the dog and the bird".split(' ') {
+            counts.entry(word).and_modify_priority(|count| *count += 1).or_insert(1);
+        }
+
+        assert_eq!(counts.peek(), Some((&"the", &3)));
+        assert_eq!(*counts.entry("cat").or_insert_with(|| unreachable!()), 1);
+
+        match counts.entry("and") {
+            Entry::Occupied(mut entry) => {
+                assert_eq!(entry.set_priority(10), 2);
+                assert_eq!(entry.priority(), &10);
+            }
+            Entry::Vacant(_) => unreachable!(),
+        }
+
+        match counts.entry("fish") {
+            Entry::Occupied(_) => unreachable!(),
+            Entry::Vacant(entry) => assert_eq!(entry.into_key(), "fish"),
+        }
+
+        assert_eq!(counts.pop(), Some(("and", 10)));
+        assert_eq!(counts.pop(), Some(("the", 3)));
+        assert_eq!(counts.len(), 3);
+    }
+
+    #[test]
+    fn test_borrowed_keys() {
+        let mut queue: PriorityQueue<String, u32> = [("low".to_string(), 1), ("high".to_string(), 9)].into_iter().collect();
+        assert_eq!(queue.peek(), Some((&"high".to_string(), &9)));
+
+        assert_eq!(queue.change_priority("low", 10), Some(1));
+        assert!(queue.contains_key("high"));
+        assert_eq!(queue.pop(), Some(("low".to_string(), 10)));
+        assert_eq!(queue.remove("high"), Some(("high".to_string(), 9)));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_hash_collisions() {
+        // Every key hashes the same, so everything goes through the chains
+        #[derive(Debug, PartialEq, Eq)]
+        struct Colliding(u32);
+
+        impl Hash for Colliding {
+            fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+                0.hash(state);
+            }
+        }
+
+        let mut queue = PriorityQueue::<Colliding, u32>::new();
+        for key in 0..50 {
+            queue.push(Colliding(key), key);
+        }
+
+        for key in (0..50).step_by(3) {
+            assert_eq!(queue.remove(&Colliding(key)), Some((Colliding(key), key)));
+        }
+
+        assert_eq!(queue.get_priority(&Colliding(49)), Some(&49));
+        assert_eq!(queue.pop(), Some((Colliding(49), 49)));
+        assert_eq!(queue.len(), 32);
+    }
+}
@@ -0,0 +1,246 @@
+use crate::heap::{Heap, HeapOrder};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+// A counting semaphore where the threads waiting for a permit queue up by priority rather than by when they
+// arrived, for admission control where the important requests should get in first when things are busy.
+// Each waiter sits in a Heap, and a permit that comes back goes straight to the top waiter rather than
+// to whichever thread gets to the lock first. Waiters with equal priority get permits in the order they
+// started waiting.
+//
+// Strict priorities can leave low priority waiters waiting forever while there is always something more
+// important arriving, so with_aging makes waiters gain a level of priority every time a given step of time
+// goes by while they wait. Every waiter ages at the same rate, so how they compare never changes while they
+// wait, and the heap never needs reordering.
+struct Waiter {
+    ready: Condvar,
+    // Only ever changed with the semaphore's lock held
+    granted: AtomicBool,
+}
+
+struct Waiting {
+    // The priority scaled by the aging step, less when it started waiting, so that waiting longer counts for
+    // as much as a higher priority. Without aging it is just the priority.
+    rank: i128,
+    sequence: u64,
+    waiter: Arc<Waiter>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct WaitingOrder;
+
+impl HeapOrder<Waiting> for WaitingOrder {
+    fn left_can_go_above(&self, left: &Waiting, right: &Waiting) -> bool {
+        (left.rank, right.sequence) > (right.rank, left.sequence)
+    }
+}
+
+struct State {
+    permits: usize,
+    waiting: Heap<Waiting, WaitingOrder>,
+    next_sequence: u64,
+}
+
+pub struct PrioritySemaphore {
+    state: Mutex<State>,
+    // How long a waiter has to wait to gain a level of priority, or None if they never do
+    aging: Option<Duration>,
+    created: Instant,
+}
+
+impl PrioritySemaphore {
+    pub fn new(permits: usize) -> Self {
+        Self::with_aging_option(permits, None)
+    }
+
+    // Waiters gain a level of priority for every step they spend waiting
+    pub fn with_aging(permits: usize, step: Duration) -> Self {
+        assert!(!step.is_zero(), "Cannot age waiters with a step of no time");
+        Self::with_aging_option(permits, Some(step))
+    }
+
+    fn with_aging_option(permits: usize, aging: Option<Duration>) -> Self {
+        Self {
+            state: Mutex::new(State { permits, waiting: Heap::with_capacity_and_cmp(0, WaitingOrder), next_sequence: 0 }),
+            aging,
+            created: Instant::now(),
+        }
+    }
+
+    pub fn available_permits(&self) -> usize {
+        self.lock().permits
+    }
+
+    // How many threads are waiting for a permit
+    pub fn waiting(&self) -> usize {
+        self.lock().waiting.len()
+    }
+
+    // Gets a permit without waiting, but only if nobody is already waiting for one
+    pub fn try_acquire(&self) -> Option<Permit<'_>> {
+        let mut state = self.lock();
+        if state.permits > 0 && state.waiting.is_empty() {
+            state.permits -= 1;
+            Some(Permit { semaphore: self })
+        } else {
+            None
+        }
+    }
+
+    // Waits for a permit, behind anyone waiting with a higher priority
+    pub fn acquire(&self, priority: u32) -> Permit<'_> {
+        self.acquire_until(priority, None).unwrap()
+    }
+
+    // Like acquire, but gives up with None once the timeout has gone by
+    pub fn acquire_timeout(&self, priority: u32, timeout: Duration) -> Option<Permit<'_>> {
+        // Past what an Instant can hold there is no deadline, and acquire_until waits as long as acquire would
+        self.acquire_until(priority, Instant::now().checked_add(timeout))
+    }
+
+    // Puts permits in on top of the ones the semaphore started with, handing them out to anyone waiting
+    pub fn add_permits(&self, permits: usize) {
+        let mut state = self.lock();
+        state.permits += permits;
+        Self::grant(&mut state);
+    }
+
+    fn acquire_until(&self, priority: u32, deadline: Option<Instant>) -> Option<Permit<'_>> {
+        let mut state = self.lock();
+        if state.permits > 0 && state.waiting.is_empty() {
+            state.permits -= 1;
+            return Some(Permit { semaphore: self });
+        }
+
+        let rank = match self.aging {
+            Some(step) => i128::from(priority) * step.as_nanos() as i128 - self.created.elapsed().as_nanos() as i128,
+            None => i128::from(priority),
+        };
+
+        let waiter = Arc::new(Waiter { ready: Condvar::new(), granted: AtomicBool::new(false) });
+        let sequence = state.next_sequence;
+        state.next_sequence += 1;
+        state.waiting.insert(Waiting { rank, sequence, waiter: waiter.clone() });
+
+        while !waiter.granted.load(Ordering::Relaxed) {
+            state = match deadline {
+                None => waiter.ready.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner()),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        // Nobody has granted it a permit, since that would have been under the lock, so it
+                        // is still waiting and has to come out
+                        state.waiting.retain(|waiting| !Arc::ptr_eq(&waiting.waiter, &waiter));
+                        return None;
+                    }
+
+                    waiter.ready.wait_timeout(state, deadline - now).unwrap_or_else(|poisoned| poisoned.into_inner()).0
+                }
+            };
+        }
+
+        Some(Permit { semaphore: self })
+    }
+
+    // Hands out whatever permits there are to the top waiters
+    fn grant(state: &mut State) {
+        while state.permits > 0 {
+            let Some(waiting) = state.waiting.pop() else {
+                break;
+            };
+
+            state.permits -= 1;
+            waiting.waiter.granted.store(true, Ordering::Relaxed);
+            waiting.waiter.ready.notify_one();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // Nothing can panic while the lock is held, short of running out of memory
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl std::fmt::Debug for PrioritySemaphore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.lock();
+        f.debug_struct("PrioritySemaphore").field("permits", &state.permits).field("waiting", &state.waiting.len()).field("aging", &self.aging).finish()
+    }
+}
+
+// A permit from a PrioritySemaphore, which goes back when this is dropped
+pub struct Permit<'a> {
+    semaphore: &'a PrioritySemaphore,
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        let mut state = self.semaphore.lock();
+        state.permits += 1;
+        PrioritySemaphore::grant(&mut state);
+    }
+}
+
+impl<'a> std::fmt::Debug for Permit<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Permit").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Starts a thread for each priority in turn, each waiting until the one before is queued up, and then
+    // lets them all through one at a time and returns the order they got their permits in
+    fn admission_order(semaphore: &PrioritySemaphore, priorities: &[u32], gap: Duration) -> Vec<u32> {
+        let held = semaphore.acquire(0);
+        let admitted = Mutex::new(Vec::new());
+
+        std::thread::scope(|scope| {
+            for (index, &priority) in priorities.iter().enumerate() {
+                let admitted = &admitted;
+                scope.spawn(move || {
+                    let _permit = semaphore.acquire(priority);
+                    admitted.lock().unwrap().push(priority);
+                });
+
+                while semaphore.waiting() <= index {
+                    std::thread::yield_now();
+                }
+
+                std::thread::sleep(gap);
+            }
+
+            drop(held);
+        });
+
+        admitted.into_inner().unwrap()
+    }
+
+    #[test]
+    fn test_priority_order() {
+        let semaphore = PrioritySemaphore::new(1);
+        assert_eq!(admission_order(&semaphore, &[1, 5, 3, 5, 2], Duration::ZERO), vec![5, 5, 3, 2, 1]);
+        assert_eq!(semaphore.available_permits(), 1);
+
+        let permit = semaphore.try_acquire();
+        assert!(permit.is_some());
+        assert!(semaphore.try_acquire().is_none());
+        assert!(semaphore.acquire_timeout(9, Duration::from_millis(10)).is_none());
+        assert_eq!(semaphore.waiting(), 0);
+
+        semaphore.add_permits(1);
+        assert!(semaphore.acquire_timeout(9, Duration::from_millis(10)).is_some());
+        assert!(semaphore.acquire_timeout(9, Duration::MAX).is_some());
+    }
+
+    #[test]
+    fn test_aging() {
+        // Each waiter arrives 30ms after the last, so with a level per 10ms it has to be more than three
+        // levels higher to get in ahead of it
+        let semaphore = PrioritySemaphore::with_aging(1, Duration::from_millis(10));
+        assert_eq!(admission_order(&semaphore, &[0, 2, 10], Duration::from_millis(30)), vec![10, 0, 2]);
+    }
+}
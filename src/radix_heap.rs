@@ -0,0 +1,172 @@
+// A radix heap is a min heap for integer keys that only works when the keys that get popped never go
+// down, which is true of Dijkstra's algorithm and of event simulations that never schedule anything in the
+// past. Elements are bucketed by the highest bit in which their key differs from the last key popped, so
+// bucket 0 holds keys equal to it and bucket k holds keys that first differ from it in bit k - 1. Popping
+// from an empty bucket 0 takes the first non-empty bucket, finds its smallest key and redistributes its
+// elements, all of which land in strictly lower buckets. Each element can only move down BITS times, so
+// everything is O(1) amortized for a fixed key width.
+//
+// Inserting a key below the last popped key breaks the bucketing, which is checked in debug builds.
+pub trait RadixKey: Copy + Ord + Default {
+    const BITS: u32;
+
+    // The number of the highest bit in which the keys differ, counting from one, or zero if they are equal
+    fn radix_distance(self, other: Self) -> u32;
+}
+
+impl RadixKey for u32 {
+    const BITS: u32 = u32::BITS;
+
+    fn radix_distance(self, other: Self) -> u32 {
+        u32::BITS - (self ^ other).leading_zeros()
+    }
+}
+
+impl RadixKey for u64 {
+    const BITS: u32 = u64::BITS;
+
+    fn radix_distance(self, other: Self) -> u32 {
+        u64::BITS - (self ^ other).leading_zeros()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RadixHeap<K: RadixKey, T> {
+    buckets: Vec<Vec<(K, T)>>,
+    last: K,
+    len: usize,
+}
+
+impl<K: RadixKey, T> RadixHeap<K, T> {
+    pub fn new() -> Self {
+        Self {
+            buckets: (0..=K::BITS).map(|_| Vec::new()).collect(),
+            last: K::default(),
+            len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.buckets.iter_mut().for_each(Vec::clear);
+        self.last = K::default();
+        self.len = 0;
+    }
+
+    // The last key popped, which is the smallest key it is still valid to insert
+    pub fn last_key(&self) -> K {
+        self.last
+    }
+
+    // Iterates over the keys and elements in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &T)> + '_ {
+        self.buckets.iter().flatten().map(|(key, value)| (key, value))
+    }
+
+    pub fn peek_key(&self) -> Option<K> {
+        let bucket = self.buckets.iter().find(|bucket| !bucket.is_empty())?;
+        bucket.iter().map(|(key, _)| *key).min()
+    }
+
+    pub fn insert(&mut self, key: K, value: T) {
+        debug_assert!(key >= self.last, "Radix heap keys cannot go below the last key popped");
+
+        self.buckets[key.radix_distance(self.last) as usize].push((key, value));
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<(K, T)> {
+        if self.buckets[0].is_empty() {
+            let index = self.buckets.iter().position(|bucket| !bucket.is_empty())?;
+            let bucket = std::mem::take(&mut self.buckets[index]);
+
+            self.last = bucket.iter().map(|(key, _)| *key).min().unwrap();
+            for (key, value) in bucket {
+                self.buckets[key.radix_distance(self.last) as usize].push((key, value));
+            }
+        }
+
+        let ret = self.buckets[0].pop()?;
+        self.len -= 1;
+        Some(ret)
+    }
+}
+
+impl<K: RadixKey, T> Default for RadixHeap<K, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: RadixKey, T> Extend<(K, T)> for RadixHeap<K, T> {
+    fn extend<IntoIter: IntoIterator<Item = (K, T)>>(&mut self, iter: IntoIter) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: RadixKey, T> FromIterator<(K, T)> for RadixHeap<K, T> {
+    fn from_iter<IntoIter: IntoIterator<Item = (K, T)>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_insert_pop() {
+        let mut keys: Vec<u64> = (0..1000).map(|key| key * 7919).collect();
+        keys.shuffle(&mut thread_rng());
+
+        let mut heap: RadixHeap<_, _> = keys.iter().map(|&key| (key, key / 7919)).collect();
+        assert_eq!(heap.len(), 1000);
+        assert_eq!(heap.peek_key(), Some(0));
+
+        let popped: Vec<_> = std::iter::from_fn(|| heap.pop()).map(|(_, value)| value).collect();
+        assert_eq!(popped, (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_monotone_operations() {
+        // Interleave pops with inserts at or after the last key, the way Dijkstra would use it
+        let mut rng = thread_rng();
+        let mut heap = RadixHeap::<u32, ()>::new();
+        let mut reference = Vec::new();
+
+        for _ in 0..10000 {
+            if rng.gen_bool(0.6) {
+                let key = heap.last_key() + rng.gen_range(0..1000);
+                heap.insert(key, ());
+                reference.push(key);
+                reference.sort_by(|left, right| right.cmp(left));
+            } else {
+                assert_eq!(heap.pop().map(|(key, _)| key), reference.pop());
+            }
+
+            assert_eq!(heap.peek_key(), reference.last().copied());
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn test_non_monotone_insert() {
+        let mut heap = RadixHeap::<u32, ()>::new();
+        heap.insert(10, ());
+        heap.pop();
+        heap.insert(5, ());
+    }
+}
@@ -0,0 +1,193 @@
+// Slice level heap primitives. Heap and friends are built on these, but they are public so that heap
+// order can be maintained over storage you own, e.g. a heap embedded in some larger buffer. Nothing here
+// allocates and nothing checks that the slice was a valid heap to begin with.
+
+use crate::heap::HeapOrder;
+
+// Rearranges data so that it satisfies the heap property under order, in O(n)
+pub fn heapify_in_place<T>(data: &mut [T], order: &impl HeapOrder<T>) {
+    for i in (0..(data.len() / 2)).rev() {
+        heapify_down(data, i, order);
+    }
+}
+
+// Moves the element at top_index down until neither of its children belongs above it. Everything below
+// top_index must already be a valid heap.
+pub fn heapify_down<T>(data: &mut [T], mut top_index: usize, order: &impl HeapOrder<T>) {
+    loop {
+        let mut highest_index = top_index;
+        let right_child_index = 2 * (highest_index + 1);
+        let left_child_index = right_child_index - 1;
+
+        if left_child_index < data.len() && order.left_can_go_above(&data[left_child_index], &data[highest_index]) {
+            highest_index = left_child_index;
+        }
+
+        if right_child_index < data.len() && order.left_can_go_above(&data[right_child_index], &data[highest_index]) {
+            highest_index = right_child_index;
+        }
+
+        if highest_index != top_index {
+            data.swap(top_index, highest_index);
+            top_index = highest_index;
+        } else {
+            break;
+        }
+    }
+}
+
+// Moves the element at pos_index up until its parent belongs above it. Everything else must already be a
+// valid heap.
+pub fn heapify_up<T>(data: &mut [T], mut pos_index: usize, order: &impl HeapOrder<T>) {
+    while pos_index > 0 {
+        let parent_index = (pos_index - 1) / 2;
+        if order.left_can_go_above(&data[parent_index], &data[pos_index]) {
+            // The parent and node are in the correct order so we can stop
+            break;
+        } else {
+            // Swap the parent and the node and walk back up towards the root
+            data.swap(parent_index, pos_index);
+            pos_index = parent_index;
+        }
+    }
+}
+
+pub fn is_heap<T>(values: &[T], order: &impl HeapOrder<T>) -> bool {
+    // Every node just has to not belong above its parent. Checking for "can't go above" rather
+    // than "parent can go above" means runs of equal values are allowed.
+    (1..values.len()).all(|index| !order.left_can_go_above(&values[index], &values[(index - 1) / 2]))
+}
+
+// Like heapify_down, but calls on_moved with each element and its new index every time one moves, and
+// returns where the element that started at top_index ended up
+pub fn heapify_down_tracked<T>(data: &mut [T], mut top_index: usize, order: &impl HeapOrder<T>, on_moved: &mut impl FnMut(&T, usize)) -> usize {
+    loop {
+        let mut highest_index = top_index;
+        for child_index in [2 * top_index + 1, 2 * top_index + 2] {
+            if child_index < data.len() && order.left_can_go_above(&data[child_index], &data[highest_index]) {
+                highest_index = child_index;
+            }
+        }
+
+        if highest_index == top_index {
+            return top_index;
+        }
+
+        data.swap(top_index, highest_index);
+        on_moved(&data[top_index], top_index);
+        on_moved(&data[highest_index], highest_index);
+        top_index = highest_index;
+    }
+}
+
+// Like heapify_up, but calls on_moved with each element and its new index every time one moves, and
+// returns where the element that started at pos_index ended up
+pub fn heapify_up_tracked<T>(data: &mut [T], mut pos_index: usize, order: &impl HeapOrder<T>, on_moved: &mut impl FnMut(&T, usize)) -> usize {
+    while pos_index > 0 {
+        let parent_index = (pos_index - 1) / 2;
+        if !order.left_can_go_above(&data[pos_index], &data[parent_index]) {
+            break;
+        }
+
+        data.swap(parent_index, pos_index);
+        on_moved(&data[pos_index], pos_index);
+        on_moved(&data[parent_index], parent_index);
+        pos_index = parent_index;
+    }
+
+    pos_index
+}
+
+// For an element that has changed and might have to go either way. Returns where it ended up.
+pub fn restore_tracked<T>(data: &mut [T], index: usize, order: &impl HeapOrder<T>, on_moved: &mut impl FnMut(&T, usize)) -> usize {
+    let index = heapify_up_tracked(data, index, order, on_moved);
+    heapify_down_tracked(data, index, order, on_moved)
+}
+
+// Takes the element at index out of the heap by swapping the last element into its place and moving that
+// to where it belongs. The element being removed is left at the end of data for the caller to pop, and
+// everything before it is a valid heap again. on_moved hears about every move but that last one.
+pub fn remove_tracked<T>(data: &mut [T], index: usize, order: &impl HeapOrder<T>, on_moved: &mut impl FnMut(&T, usize)) {
+    let last = data.len() - 1;
+    data.swap(index, last);
+    if index < last {
+        on_moved(&data[index], index);
+        restore_tracked(&mut data[..last], index, order, on_moved);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::prelude::*;
+
+    // Values tagged with an id, and where each id is according to the callbacks
+    struct Tracked {
+        data: Vec<(u32, usize)>,
+        positions: Vec<usize>,
+    }
+
+    impl Tracked {
+        fn new(rng: &mut impl Rng, len: usize) -> Self {
+            let mut data: Vec<(u32, usize)> = (0..len).map(|id| (rng.gen_range(0..50), id)).collect();
+            heapify_in_place(&mut data, &order);
+            let mut positions = vec![0; len];
+            for (index, &(_, id)) in data.iter().enumerate() {
+                positions[id] = index;
+            }
+
+            Self { data, positions }
+        }
+
+        fn check(&self) {
+            assert!(is_heap(&self.data, &order));
+            for (index, &(_, id)) in self.data.iter().enumerate() {
+                assert_eq!(self.positions[id], index);
+            }
+        }
+    }
+
+    fn order(left: &(u32, usize), right: &(u32, usize)) -> bool {
+        left.0 < right.0
+    }
+
+    #[test]
+    fn test_tracked_sifts() {
+        let mut rng = thread_rng();
+        let mut tracked = Tracked::new(&mut rng, 200);
+        for _ in 0..1000 {
+            let Tracked { data, positions } = &mut tracked;
+            let mut on_moved = |&(_, id): &(u32, usize), index| positions[id] = index;
+
+            // A changed value only has to move one way, so the one way sifts do when it is known which
+            let index = rng.gen_range(0..data.len());
+            let id = data[index].1;
+            let old = std::mem::replace(&mut data[index].0, rng.gen_range(0..50));
+            let ended = if data[index].0 < old {
+                heapify_up_tracked(data, index, &order, &mut on_moved)
+            } else if rng.gen_bool(0.5) {
+                heapify_down_tracked(data, index, &order, &mut on_moved)
+            } else {
+                restore_tracked(data, index, &order, &mut on_moved)
+            };
+
+            assert_eq!(data[ended].1, id);
+            tracked.check();
+        }
+    }
+
+    #[test]
+    fn test_remove_tracked() {
+        let mut rng = thread_rng();
+        let mut tracked = Tracked::new(&mut rng, 200);
+        while !tracked.data.is_empty() {
+            let Tracked { data, positions } = &mut tracked;
+            let index = rng.gen_range(0..data.len());
+            let removed = data[index];
+            remove_tracked(data, index, &order, &mut |&(_, id), index| positions[id] = index);
+
+            assert_eq!(data.pop(), Some(removed));
+            tracked.check();
+        }
+    }
+}
@@ -0,0 +1,364 @@
+// A 4-ary min heap specialised for primitive keys. Most of the time in a heap of small keys goes on
+// sift down, and a 4-ary heap is both shallower than a binary one and has each node's children next to
+// each other in memory, where they can be compared all at once. Here they always are: the storage is
+// padded out with sentinel keys that nothing can go below, so every node with children has a full chunk of
+// four, and picking the smallest of them is a fixed sequence of comparisons with no branches on the data.
+//
+// With the simd feature, that selection uses SSE on x86_64 for f32 keys, SSE4.1 for u32 keys and AVX2 for
+// u64 keys, detected at run time. Without it, or on other machines, it is a branchless tournament of three
+// scalar comparisons, which the compiler turns into conditional moves.
+//
+// The order is fixed to smallest first, so for a max heap store the keys inverted, with !key for the
+// integers or -key for floats.
+mod sealed {
+    pub trait Sealed { }
+}
+
+pub trait SimdKey: Copy + PartialOrd + sealed::Sealed {
+    // Goes below every key that can be inserted, so it never moves up past one
+    const SENTINEL: Self;
+
+    fn is_valid(self) -> bool {
+        true
+    }
+
+    #[doc(hidden)]
+    fn sift_down(data: &mut [Self], len: usize, index: usize);
+}
+
+// The position of the smallest of four keys, the first one if there is a tie
+#[inline(always)]
+fn portable_min_index4<K: SimdKey>(keys: &[K; 4]) -> usize {
+    let left = (keys[1] < keys[0]) as usize;
+    let right = 2 + (keys[3] < keys[2]) as usize;
+    [left, right][(keys[right] < keys[left]) as usize]
+}
+
+#[inline(always)]
+fn sift_down_with<K: SimdKey>(data: &mut [K], len: usize, mut index: usize, min_index4: impl Fn(&[K; 4]) -> usize) {
+    loop {
+        let first_child = 4 * index + 1;
+        if first_child >= len {
+            break;
+        }
+
+        let children: &[K; 4] = data[first_child..first_child + 4].try_into().unwrap();
+        let child = first_child + min_index4(children);
+        if data[child] >= data[index] {
+            break;
+        }
+
+        data.swap(index, child);
+        index = child;
+    }
+}
+
+impl sealed::Sealed for u32 { }
+
+impl SimdKey for u32 {
+    const SENTINEL: Self = u32::MAX;
+
+    fn sift_down(data: &mut [Self], len: usize, index: usize) {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if std::arch::is_x86_feature_detected!("sse4.1") {
+            // SAFETY: the CPU supports SSE4.1
+            return unsafe { x86::sift_down_u32(data, len, index) };
+        }
+
+        sift_down_with(data, len, index, portable_min_index4)
+    }
+}
+
+impl sealed::Sealed for u64 { }
+
+impl SimdKey for u64 {
+    const SENTINEL: Self = u64::MAX;
+
+    fn sift_down(data: &mut [Self], len: usize, index: usize) {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if std::arch::is_x86_feature_detected!("avx2") {
+            // SAFETY: the CPU supports AVX2
+            return unsafe { x86::sift_down_u64(data, len, index) };
+        }
+
+        sift_down_with(data, len, index, portable_min_index4)
+    }
+}
+
+impl sealed::Sealed for f32 { }
+
+impl SimdKey for f32 {
+    const SENTINEL: Self = f32::INFINITY;
+
+    // NaN can't be ordered against anything, so would break the heap
+    fn is_valid(self) -> bool {
+        !self.is_nan()
+    }
+
+    fn sift_down(data: &mut [Self], len: usize, index: usize) {
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        // SAFETY: SSE is part of the x86_64 baseline, so there is nothing to detect
+        return unsafe { x86::sift_down_f32(data, len, index) };
+
+        #[allow(unreachable_code)]
+        sift_down_with(data, len, index, portable_min_index4)
+    }
+}
+
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod x86 {
+    use super::sift_down_with;
+    use std::arch::x86_64::*;
+
+    // Each of these folds the four lanes down to their minimum, spreads it back across every lane, and
+    // then finds the first lane that equals it from the comparison mask.
+
+    #[target_feature(enable = "sse4.1")]
+    fn min_index4_u32(keys: &[u32; 4]) -> usize {
+        // SAFETY: the slice is four u32s, and loadu has no alignment requirement
+        let keys = unsafe { _mm_loadu_si128(keys.as_ptr() as *const __m128i) };
+        let min = _mm_min_epu32(keys, _mm_shuffle_epi32::<0b10_11_00_01>(keys));
+        let min = _mm_min_epu32(min, _mm_shuffle_epi32::<0b01_00_11_10>(min));
+        _mm_movemask_ps(_mm_castsi128_ps(_mm_cmpeq_epi32(keys, min))).trailing_zeros() as usize
+    }
+
+    #[target_feature(enable = "avx2")]
+    fn min_index4_u64(keys: &[u64; 4]) -> usize {
+        // SAFETY: the slice is four u64s, and loadu has no alignment requirement
+        let keys = unsafe { _mm256_loadu_si256(keys.as_ptr() as *const __m256i) };
+
+        // AVX2 only compares signed 64 bit lanes, so flip the top bit to make unsigned order signed order
+        let keys = _mm256_xor_si256(keys, _mm256_set1_epi64x(i64::MIN));
+        let swapped = _mm256_permute4x64_epi64::<0b10_11_00_01>(keys);
+        let min = _mm256_blendv_epi8(keys, swapped, _mm256_cmpgt_epi64(keys, swapped));
+        let swapped = _mm256_permute4x64_epi64::<0b01_00_11_10>(min);
+        let min = _mm256_blendv_epi8(min, swapped, _mm256_cmpgt_epi64(min, swapped));
+        _mm256_movemask_pd(_mm256_castsi256_pd(_mm256_cmpeq_epi64(keys, min))).trailing_zeros() as usize
+    }
+
+    #[target_feature(enable = "sse")]
+    fn min_index4_f32(keys: &[f32; 4]) -> usize {
+        // SAFETY: the slice is four f32s, and loadu has no alignment requirement
+        let keys = unsafe { _mm_loadu_ps(keys.as_ptr()) };
+        let min = _mm_min_ps(keys, _mm_shuffle_ps::<0b10_11_00_01>(keys, keys));
+        let min = _mm_min_ps(min, _mm_shuffle_ps::<0b01_00_11_10>(min, min));
+        _mm_movemask_ps(_mm_cmpeq_ps(keys, min)).trailing_zeros() as usize
+    }
+
+    // The whole loop is compiled with the feature enabled so that the selection inlines into it
+    #[target_feature(enable = "sse4.1")]
+    pub(super) fn sift_down_u32(data: &mut [u32], len: usize, index: usize) {
+        sift_down_with(data, len, index, |keys| min_index4_u32(keys))
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub(super) fn sift_down_u64(data: &mut [u64], len: usize, index: usize) {
+        sift_down_with(data, len, index, |keys| min_index4_u64(keys))
+    }
+
+    #[target_feature(enable = "sse")]
+    pub(super) fn sift_down_f32(data: &mut [f32], len: usize, index: usize) {
+        sift_down_with(data, len, index, |keys| min_index4_f32(keys))
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+        use crate::simd_heap::portable_min_index4;
+        use rand::prelude::*;
+
+        #[test]
+        fn test_matches_portable() {
+            let mut rng = thread_rng();
+            for _ in 0..10000 {
+                // A small range so that there are plenty of ties
+                let small: [u64; 4] = std::array::from_fn(|_| rng.gen_range(0..4));
+                let large: [u64; 4] = std::array::from_fn(|_| rng.gen_range(u64::MAX - 3..=u64::MAX));
+                for keys in [small, large] {
+                    if std::arch::is_x86_feature_detected!("avx2") {
+                        assert_eq!(unsafe { min_index4_u64(&keys) }, portable_min_index4(&keys));
+                    }
+
+                    let keys = keys.map(|key| key as u32);
+                    if std::arch::is_x86_feature_detected!("sse4.1") {
+                        assert_eq!(unsafe { min_index4_u32(&keys) }, portable_min_index4(&keys));
+                    }
+
+                    let keys = keys.map(|key| key as f32 - 2.0);
+                    assert_eq!(unsafe { min_index4_f32(&keys) }, portable_min_index4(&keys));
+                }
+            }
+        }
+    }
+}
+
+fn padded_len(len: usize) -> usize {
+    1 + 4 * len.saturating_sub(1).div_ceil(4)
+}
+
+#[derive(Debug, Clone)]
+pub struct SimdMinHeap<K: SimdKey> {
+    // The root followed by whole chunks of four, with everything past len set to the sentinel
+    data: Vec<K>,
+    len: usize,
+}
+
+impl<K: SimdKey> SimdMinHeap<K> {
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut data = Vec::with_capacity(padded_len(capacity));
+        data.push(K::SENTINEL);
+        Self { data, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.data.truncate(1);
+        self.data[0] = K::SENTINEL;
+        self.len = 0;
+    }
+
+    // Iterates over the keys in no particular order
+    pub fn iter(&self) -> std::slice::Iter<'_, K> {
+        self.data[..self.len].iter()
+    }
+
+    pub fn peek(&self) -> Option<K> {
+        (self.len > 0).then(|| self.data[0])
+    }
+
+    pub fn insert(&mut self, key: K) {
+        assert!(key.is_valid(), "Cannot insert a key that can't be ordered");
+
+        if self.data.len() < padded_len(self.len + 1) {
+            self.data.extend([K::SENTINEL; 4]);
+        }
+
+        let mut index = self.len;
+        self.data[index] = key;
+        self.len += 1;
+
+        while index > 0 {
+            let parent = (index - 1) / 4;
+            if self.data[index] >= self.data[parent] {
+                break;
+            }
+
+            self.data.swap(index, parent);
+            index = parent;
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<K> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.len -= 1;
+        let last = std::mem::replace(&mut self.data[self.len], K::SENTINEL);
+        let ret = if self.len > 0 {
+            let ret = std::mem::replace(&mut self.data[0], last);
+            K::sift_down(&mut self.data, self.len, 0);
+            ret
+        } else {
+            last
+        };
+
+        self.data.truncate(padded_len(self.len));
+        Some(ret)
+    }
+
+    // Pops into a new Vec and reverses it so that the top is last, like Heap's. Sorting in place would put
+    // keys past len, where sift_down counts on there only being sentinels.
+    pub fn into_sorted_vec(mut self) -> Vec<K> {
+        let mut ret = Vec::with_capacity(self.len);
+        ret.extend(std::iter::from_fn(|| self.pop()));
+        ret.reverse();
+        ret
+    }
+}
+
+impl<K: SimdKey> Default for SimdMinHeap<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: SimdKey> Extend<K> for SimdMinHeap<K> {
+    fn extend<IntoIter: IntoIterator<Item = K>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<K: SimdKey> FromIterator<K> for SimdMinHeap<K> {
+    fn from_iter<IntoIter: IntoIterator<Item = K>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::distributions::{Distribution, Standard};
+    use rand::prelude::*;
+
+    fn check_random_operations<K: SimdKey + std::fmt::Debug>(mut key: impl FnMut(&mut ThreadRng) -> K) {
+        let mut rng = thread_rng();
+        let mut heap = SimdMinHeap::new();
+        let mut reference: Vec<K> = Vec::new();
+
+        for _ in 0..5000 {
+            if rng.gen_bool(0.6) {
+                let key = key(&mut rng);
+                heap.insert(key);
+                reference.push(key);
+                reference.sort_by(|left, right| right.partial_cmp(left).unwrap());
+            } else {
+                assert_eq!(heap.pop(), reference.pop());
+            }
+
+            assert_eq!(heap.len(), reference.len());
+            assert_eq!(heap.peek(), reference.last().copied());
+            assert_eq!(heap.data.len(), padded_len(heap.len()));
+        }
+
+        reference.reverse();
+        assert_eq!(heap.into_sorted_vec().into_iter().rev().collect::<Vec<_>>(), reference);
+    }
+
+    fn any<K>(rng: &mut ThreadRng) -> K where Standard: Distribution<K> {
+        rng.gen()
+    }
+
+    #[test]
+    fn test_random_operations() {
+        check_random_operations::<u32>(any);
+        check_random_operations::<u64>(any);
+        check_random_operations::<f32>(|rng| rng.gen_range(-1000.0..1000.0));
+
+        // Keys equal to the sentinel have to come out too
+        check_random_operations::<u32>(|rng| u32::MAX - rng.gen_range(0..3));
+        check_random_operations::<f32>(|rng| if rng.gen_bool(0.2) { f32::INFINITY } else { rng.gen() });
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_nan() {
+        let mut heap = SimdMinHeap::new();
+        heap.insert(f32::NAN);
+    }
+}
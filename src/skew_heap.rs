@@ -0,0 +1,241 @@
+use crate::heap::HeapOrder;
+
+// A skew heap is the self-adjusting version of a leftist heap. Merging walks down the right spines as
+// before, but instead of keeping ranks to decide when to swap children it swaps them at every node on
+// the merge path. That is enough to make merge O(log n) amortized with no bookkeeping at all, although a
+// single merge can be O(n), so the merge here is iterative rather than recursive.
+struct Node<T> {
+    value: T,
+    left: Option<Box<Node<T>>>,
+    right: Option<Box<Node<T>>>,
+}
+
+fn merge<T>(mut left: Option<Box<Node<T>>>, mut right: Option<Box<Node<T>>>, order: &impl HeapOrder<T>) -> Option<Box<Node<T>>> {
+    // Walk down the two right spines, taking whichever node goes higher each time, just like merging two
+    // sorted lists
+    let mut path = Vec::new();
+    let mut ret = loop {
+        match (left, right) {
+            (Some(mut left_node), Some(mut right_node)) => {
+                if order.left_can_go_above(&right_node.value, &left_node.value) {
+                    std::mem::swap(&mut left_node, &mut right_node);
+                }
+
+                left = left_node.right.take();
+                right = Some(right_node);
+                path.push(left_node);
+            }
+            (left, right) => break left.or(right),
+        }
+    };
+
+    // Then hang the merged result back on the way up, swapping the children of every node on the path
+    while let Some(mut node) = path.pop() {
+        node.right = node.left.take();
+        node.left = ret;
+        ret = Some(node);
+    }
+
+    ret
+}
+
+pub struct SkewHeap<T, Order: HeapOrder<T>> {
+    root: Option<Box<Node<T>>>,
+    len: usize,
+    order: Order,
+}
+
+impl<T, Order: HeapOrder<T>> SkewHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            root: None,
+            len: 0,
+            order,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        // Paths in a skew heap can be as long as the heap, so take the tree apart without recursing
+        let mut stack: Vec<_> = self.root.take().into_iter().collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+
+        self.len = 0;
+    }
+
+    // Iterates over the elements in no particular order
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            stack: self.root.as_deref().into_iter().collect(),
+            remaining: self.len,
+        }
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.root.as_ref().map(|root| &root.value)
+    }
+
+    pub fn insert(&mut self, value: T) {
+        let node = Box::new(Node {
+            value,
+            left: None,
+            right: None,
+        });
+
+        self.root = merge(self.root.take(), Some(node), &self.order);
+        self.len += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let root = *self.root.take()?;
+        self.root = merge(root.left, root.right, &self.order);
+        self.len -= 1;
+
+        Some(root.value)
+    }
+
+    // Moves everything out of other and into this heap in O(log n) amortized. This assumes both heaps were built with
+    // equivalent orders.
+    pub fn meld(&mut self, mut other: Self) {
+        self.append(&mut other);
+    }
+
+    pub fn append(&mut self, other: &mut Self) {
+        self.root = merge(self.root.take(), other.root.take(), &self.order);
+        self.len += std::mem::take(&mut other.len);
+    }
+
+    pub fn into_iter_sorted(self) -> IntoIterSorted<T, Order> {
+        IntoIterSorted { heap: self }
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Drop for SkewHeap<T, Order> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for SkewHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: std::fmt::Debug, Order: HeapOrder<T>> std::fmt::Debug for SkewHeap<T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for SkewHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> FromIterator<T> for SkewHeap<T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+pub struct Iter<'a, T> {
+    stack: Vec<&'a Node<T>>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let node = self.stack.pop()?;
+        self.stack.extend(node.left.as_deref());
+        self.stack.extend(node.right.as_deref());
+        self.remaining -= 1;
+
+        Some(&node.value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> { }
+
+pub struct IntoIterSorted<T, Order: HeapOrder<T>> {
+    heap: SkewHeap<T, Order>,
+}
+
+impl<T, Order: HeapOrder<T>> Iterator for IntoIterSorted<T, Order> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.heap.pop()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.heap.len(), Some(self.heap.len()))
+    }
+}
+
+impl<T, Order: HeapOrder<T>> ExactSizeIterator for IntoIterSorted<T, Order> { }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{MaxOrder, MinOrder};
+    use rand::prelude::*;
+
+    #[test]
+    fn test_insert_pop() {
+        let mut values: Vec<u32> = (0..1000).collect();
+        values.shuffle(&mut thread_rng());
+
+        let heap: SkewHeap<_, MinOrder<_>> = values.into_iter().collect();
+        assert_eq!(heap.iter().count(), 1000);
+        assert_eq!(heap.into_iter_sorted().collect::<Vec<_>>(), (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_meld() {
+        let mut left: SkewHeap<_, MaxOrder<_>> = (0..500).step_by(2).collect();
+        let right: SkewHeap<_, MaxOrder<_>> = (1..500).step_by(2).collect();
+
+        left.meld(right);
+        assert_eq!(left.len(), 500);
+        assert_eq!(left.into_iter_sorted().collect::<Vec<_>>(), (0..500).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_long_paths() {
+        // Inserting in order builds long paths, which would overflow the stack if merge or drop recursed
+        let mut heap: SkewHeap<_, MaxOrder<_>> = (0..1_000_000).collect();
+        assert_eq!(heap.pop(), Some(999_999));
+        assert_eq!(heap.pop(), Some(999_998));
+        drop(heap);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::heap::HeapOrder;
+
+// A soft heap (Chazelle, in the simpler binary tree form of Kaplan and Zwick) is a heap that is allowed to
+// lie a little. Nodes hold lists of elements that all share a common key, which is only an upper bound
+// for the elements in the list. An element whose own value goes above the common key it is filed under is
+// "corrupted": it will come out later than it should. In exchange, inserts cost O(log 1/epsilon) amortized
+// and pops O(log n), which is the scan of the O(log n) trees for the best key on top of an O(1) amortized
+// share of refilling lists, and at any moment at most epsilon * n of the elements inserted so far are
+// corrupted. That is exactly the guarantee linear time selection and minimum spanning tree algorithms need.
+//
+// Corruption is visible through the API: pop_with_key returns the common key an element was popped with,
+// which differs from the element exactly when it was corrupted, and corrupted() lists the elements that
+// are currently corrupted.
+#[derive(Debug, Clone)]
+struct Node<T> {
+    list: Vec<T>,
+    key: T,
+    rank: usize,
+    // How many elements this node wants in its list before it stops pulling them up from its children
+    size: usize,
+    left: Option<Box<Node<T>>>,
+    right: Option<Box<Node<T>>>,
+}
+
+impl<T> Node<T> {
+    fn is_leaf(&self) -> bool {
+        self.left.is_none() && self.right.is_none()
+    }
+}
+
+fn sift<T: Clone>(node: &mut Node<T>, order: &impl HeapOrder<T>) {
+    while node.list.len() < node.size && !node.is_leaf() {
+        // Always pull from the child with the better key
+        let swap_children = match (&node.left, &node.right) {
+            (None, _) => true,
+            (Some(left), Some(right)) => order.left_can_go_above(&right.key, &left.key),
+            _ => false,
+        };
+
+        if swap_children {
+            std::mem::swap(&mut node.left, &mut node.right);
+        }
+
+        let child = node.left.as_mut().unwrap();
+        node.list.append(&mut child.list);
+        node.key = child.key.clone();
+
+        if child.is_leaf() {
+            node.left = None;
+        } else {
+            sift(child, order);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SoftHeap<T: Clone, Order: HeapOrder<T>> {
+    // trees[k] is the tree of rank k, if there is one
+    trees: Vec<Option<Box<Node<T>>>>,
+    len: usize,
+    epsilon: f64,
+    // Nodes above this rank start accumulating bigger lists, which is where corruption comes from
+    rank_threshold: usize,
+    order: Order,
+}
+
+impl<T: Clone, Order: HeapOrder<T>> SoftHeap<T, Order> {
+    pub fn with_epsilon(epsilon: f64) -> Self where Order: Default {
+        Self::with_epsilon_and_cmp(epsilon, Order::default())
+    }
+
+    pub fn with_epsilon_and_cmp(epsilon: f64, order: Order) -> Self {
+        assert!(epsilon > 0.0 && epsilon < 1.0, "Soft heap epsilon must be between 0 and 1, not {}", epsilon);
+
+        Self {
+            trees: Vec::new(),
+            len: 0,
+            epsilon,
+            rank_threshold: 2 + 2 * (1.0 / epsilon).log2().ceil() as usize,
+            order,
+        }
+    }
+
+    pub fn epsilon(&self) -> f64 {
+        self.epsilon
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.trees.clear();
+        self.len = 0;
+    }
+
+    pub fn insert(&mut self, value: T) {
+        let mut tree = Box::new(Node {
+            key: value.clone(),
+            list: vec![value],
+            rank: 0,
+            size: 1,
+            left: None,
+            right: None,
+        });
+
+        // Combine trees of equal rank, like carrying in binary addition
+        let mut rank = 0;
+        while let Some(other) = self.trees.get_mut(rank).and_then(Option::take) {
+            tree = self.combine(other, tree);
+            rank += 1;
+        }
+
+        if rank >= self.trees.len() {
+            self.trees.resize_with(rank + 1, || None);
+        }
+
+        self.trees[rank] = Some(tree);
+        self.len += 1;
+    }
+
+    // The key that the next pop will report. Everything in the heap either goes below this key or is
+    // corrupted.
+    pub fn peek_key(&self) -> Option<&T> {
+        self.top_rank().map(|rank| &self.trees[rank].as_ref().unwrap().key)
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.pop_with_key().map(|(value, _)| value)
+    }
+
+    // Returns the element along with the key it was filed under. They are the same unless the element was
+    // corrupted, in which case the element should have come out earlier than it did.
+    pub fn pop_with_key(&mut self) -> Option<(T, T)> {
+        let rank = self.top_rank()?;
+        let tree = self.trees[rank].as_mut().unwrap();
+        let value = tree.list.pop().unwrap();
+        let key = tree.key.clone();
+
+        if 2 * tree.list.len() <= tree.size {
+            if !tree.is_leaf() {
+                sift(tree, &self.order);
+            } else if tree.list.is_empty() {
+                self.trees[rank] = None;
+                while let Some(None) = self.trees.last() {
+                    self.trees.pop();
+                }
+            }
+        }
+
+        self.len -= 1;
+        Some((value, key))
+    }
+
+    pub fn corrupted(&self) -> impl Iterator<Item = &T> + '_ {
+        let mut stack: Vec<&Node<T>> = self.trees.iter().flatten().map(|tree| &**tree).collect();
+        let mut current: std::slice::Iter<'_, T> = [].iter();
+        let mut key: Option<&T> = None;
+
+        std::iter::from_fn(move || loop {
+            if let Some(value) = current.next() {
+                if self.order.left_can_go_above(value, key.unwrap()) {
+                    return Some(value);
+                }
+
+                continue;
+            }
+
+            let node = stack.pop()?;
+            stack.extend(node.left.as_deref());
+            stack.extend(node.right.as_deref());
+            current = node.list.iter();
+            key = Some(&node.key);
+        })
+    }
+
+    fn top_rank(&self) -> Option<usize> {
+        let mut ret: Option<usize> = None;
+        for (rank, tree) in self.trees.iter().enumerate() {
+            if let Some(tree) = tree {
+                if ret.is_none_or(|best| self.order.left_can_go_above(&tree.key, &self.trees[best].as_ref().unwrap().key)) {
+                    ret = Some(rank);
+                }
+            }
+        }
+
+        ret
+    }
+
+    fn combine(&self, left: Box<Node<T>>, right: Box<Node<T>>) -> Box<Node<T>> {
+        let rank = left.rank + 1;
+        let size = if rank <= self.rank_threshold {
+            1
+        } else {
+            (3 * left.size).div_ceil(2)
+        };
+
+        // The key is a placeholder until sift pulls the first list up from a child
+        let mut node = Box::new(Node {
+            list: Vec::new(),
+            key: left.key.clone(),
+            rank,
+            size,
+            left: Some(left),
+            right: Some(right),
+        });
+
+        sift(&mut node, &self.order);
+        node
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MinOrder;
+    use rand::prelude::*;
+
+    fn shuffled(len: u32) -> Vec<u32> {
+        let mut values: Vec<u32> = (0..len).collect();
+        values.shuffle(&mut thread_rng());
+        values
+    }
+
+    #[test]
+    fn test_exact_when_small() {
+        // With a tiny epsilon no node gets big enough to hold more than one element, so nothing is corrupted
+        let mut heap = SoftHeap::<_, MinOrder<_>>::with_epsilon(1e-6);
+        shuffled(1000).into_iter().for_each(|value| heap.insert(value));
+
+        assert_eq!(heap.corrupted().count(), 0);
+        assert_eq!(std::iter::from_fn(|| heap.pop()).collect::<Vec<_>>(), (0..1000).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_corruption_bound() {
+        let epsilon = 0.1;
+        let mut heap = SoftHeap::<_, MinOrder<_>>::with_epsilon(epsilon);
+        let values = shuffled(100000);
+        let inserted = values.len();
+        values.into_iter().for_each(|value| heap.insert(value));
+
+        assert!(heap.corrupted().count() as f64 <= epsilon * inserted as f64);
+
+        let mut popped = 0;
+        let mut last_key = 0;
+        while let Some((value, key)) = heap.pop_with_key() {
+            // Keys never go backwards, and an element is either exactly its key or was corrupted below it
+            assert!(key >= last_key);
+            assert!(value <= key);
+            last_key = key;
+            popped += 1;
+
+            if popped % 1000 == 0 {
+                assert!(heap.corrupted().count() as f64 <= epsilon * inserted as f64);
+            }
+        }
+
+        assert_eq!(popped, inserted);
+        assert!(heap.is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+use crate::raw::{heapify_down, heapify_up};
+
+// Walks an array heap from the top down without changing it, for the iter_sorted of FrozenHeap and
+// PriorityQueue. It keeps a second heap of the positions whose parents have already been returned, which
+// is where the next one in order always is, so getting the first k positions costs O(k log k). It only
+// deals in positions, and the caller says which of two positions goes above the other.
+#[derive(Debug, Clone)]
+pub(crate) struct SortedFrontier {
+    frontier: Vec<usize>,
+    len: usize,
+    remaining: usize,
+}
+
+impl SortedFrontier {
+    pub(crate) fn new(len: usize) -> Self {
+        Self { frontier: if len == 0 { Vec::new() } else { vec![0] }, len, remaining: len }
+    }
+
+    pub(crate) fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    pub(crate) fn next(&mut self, goes_above: impl Fn(usize, usize) -> bool) -> Option<usize> {
+        let order = |left: &usize, right: &usize| goes_above(*left, *right);
+
+        let last = self.frontier.len().checked_sub(1)?;
+        self.frontier.swap(0, last);
+        let position = self.frontier.pop().unwrap();
+        heapify_down(&mut self.frontier, 0, &order);
+
+        for child in [2 * position + 1, 2 * position + 2] {
+            if child < self.len {
+                let index = self.frontier.len();
+                self.frontier.push(child);
+                heapify_up(&mut self.frontier, index, &order);
+            }
+        }
+
+        self.remaining -= 1;
+        Some(position)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::raw::heapify_in_place;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_positions_in_order() {
+        let mut rng = thread_rng();
+        for len in 0..50 {
+            let mut values: Vec<u32> = (0..len).map(|_| rng.gen_range(0..20)).collect();
+            heapify_in_place(&mut values, &|left: &u32, right: &u32| left < right);
+
+            let mut frontier = SortedFrontier::new(values.len());
+            let mut sorted = Vec::new();
+            while let Some(position) = frontier.next(|left, right| values[left] < values[right]) {
+                sorted.push(values[position]);
+                assert_eq!(frontier.remaining(), values.len() - sorted.len());
+            }
+
+            let mut expected = values.clone();
+            expected.sort();
+            assert_eq!(sorted, expected);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use crate::heap::{Heap, HeapOrder, Sequenced, StableOrder};
+
+// A heap where elements that the order can't tell apart come out in the order they went in. It tags each
+// element with the next value of a counter and breaks ties on it, which is what StableOrder does for
+// anyone wrapping their own type in Sequenced, but here the wrapping stays out of sight.
+//
+// A u64 counter isn't going to run out in practice, but if it does the elements still in the heap are
+// renumbered from zero in their existing order, which is O(n log n) once every 2^64 inserts.
+#[derive(Debug, Clone)]
+pub struct StableHeap<T, Order: HeapOrder<T>> {
+    heap: Heap<Sequenced<T>, StableOrder<Order>>,
+    next_sequence: u64,
+}
+
+impl<T, Order: HeapOrder<T>> StableHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self::with_capacity_and_cmp(0, order)
+    }
+
+    pub fn with_capacity_and_cmp(capacity: usize, order: Order) -> Self {
+        Self {
+            heap: Heap::with_capacity_and_cmp(capacity, StableOrder(order)),
+            next_sequence: 0,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.heap.order().0
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.heap.capacity()
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.next_sequence = 0;
+    }
+
+    // Iterates over the elements in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.heap.iter().map(|entry| &entry.value)
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.heap.peek().map(|entry| &entry.value)
+    }
+
+    pub fn insert(&mut self, value: T) {
+        if self.next_sequence == u64::MAX {
+            self.renumber();
+        }
+
+        self.heap.insert(Sequenced::new(self.next_sequence, value));
+        self.next_sequence += 1;
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        self.heap.pop().map(Sequenced::into_inner)
+    }
+
+    // Heap sorts it in place, and the sequence numbers come off afterwards. Ties went to whichever was
+    // inserted first, so of two equal elements the older one is nearer the end, where the top is.
+    pub fn into_sorted_vec(self) -> Vec<T> {
+        self.heap.into_sorted_vec().into_iter().map(Sequenced::into_inner).collect()
+    }
+
+    fn renumber(&mut self) {
+        // Each element's new sequence number is its rank among the old ones, which keeps ties in the same
+        // order, and the heap gets rebuilt when the guard goes out of scope
+        let mut sequences: Vec<u64> = self.heap.iter().map(|entry| entry.sequence).collect();
+        sequences.sort_unstable();
+
+        for entry in self.heap.as_mut_slice().iter_mut() {
+            entry.sequence = sequences.binary_search(&entry.sequence).unwrap() as u64;
+        }
+
+        self.next_sequence = sequences.len() as u64;
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for StableHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for StableHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> FromIterator<T> for StableHeap<T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{KeyOrder, MinOrder};
+    use rand::prelude::*;
+
+    #[test]
+    fn test_ties_in_insertion_order() {
+        let mut rng = thread_rng();
+        let mut heap = StableHeap::with_cmp(KeyOrder::new(|pair: &(u32, usize)| pair.0, MinOrder::default()));
+        heap.extend((0..1000).map(|index| (rng.gen_range(0..10), index)));
+
+        let popped: Vec<_> = std::iter::from_fn(|| heap.pop()).collect();
+        let mut expected = popped.clone();
+        expected.sort();
+        assert_eq!(popped, expected);
+    }
+
+    #[test]
+    fn test_sequence_overflow() {
+        let mut heap = StableHeap::with_cmp(KeyOrder::new(|pair: &(u32, char)| pair.0, MinOrder::default()));
+        heap.next_sequence = u64::MAX - 2;
+        heap.extend([(1, 'a'), (0, 'b'), (1, 'c'), (1, 'd'), (0, 'e')]);
+
+        assert!(heap.next_sequence < 10);
+        let popped: String = std::iter::from_fn(|| heap.pop()).map(|(_, name)| name).collect();
+        assert_eq!(popped, "beacd");
+    }
+}
@@ -0,0 +1,193 @@
+use crate::heap::{Heap, HeapOrder};
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+// A Heap that threads can share and block on, for worker pools that run on plain threads rather than an
+// async runtime. push wakes one waiting thread, and pop_blocking waits for there to be something to pop.
+// It is the thread counterpart to AsyncPriorityQueue, and closes the same way: once closed nothing more can
+// be pushed, but what is already in it can still be popped, and the pops only give up once it is empty.
+struct State<T, Order: HeapOrder<T>> {
+    heap: Heap<T, Order>,
+    closed: bool,
+}
+
+pub struct SyncHeap<T, Order: HeapOrder<T>> {
+    state: Mutex<State<T, Order>>,
+    ready: Condvar,
+}
+
+impl<T, Order: HeapOrder<T>> SyncHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self::from_heap(Heap::with_capacity_and_cmp(0, order))
+    }
+
+    pub fn from_heap(heap: Heap<T, Order>) -> Self {
+        Self {
+            state: Mutex::new(State { heap, closed: false }),
+            ready: Condvar::new(),
+        }
+    }
+
+    // Only a snapshot, since other threads can be pushing and popping at the same time
+    pub fn len(&self) -> usize {
+        self.lock().heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    // Hands the value back if the heap has been closed
+    pub fn push(&self, value: T) -> Result<(), T> {
+        let mut state = self.lock();
+        if state.closed {
+            return Err(value);
+        }
+
+        state.heap.insert(value);
+        drop(state);
+        self.ready.notify_one();
+        Ok(())
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        self.lock().heap.pop()
+    }
+
+    // Waits for the top of the heap. Returns None once the heap is closed and empty.
+    pub fn pop_blocking(&self) -> Option<T> {
+        let mut state = self.lock();
+        loop {
+            if let Some(value) = state.heap.pop() {
+                return Some(value);
+            }
+
+            if state.closed {
+                return None;
+            }
+
+            state = self.ready.wait(state).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+    }
+
+    // Like pop_blocking, but gives up with None once the timeout has gone by
+    pub fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        // There's no waiting until a deadline that far off, so it waits for a value however long that takes
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            return self.pop_blocking();
+        };
+
+        let mut state = self.lock();
+        loop {
+            if let Some(value) = state.heap.pop() {
+                return Some(value);
+            }
+
+            let now = Instant::now();
+            if state.closed || now >= deadline {
+                return None;
+            }
+
+            state = self.ready.wait_timeout(state, deadline - now).unwrap_or_else(|poisoned| poisoned.into_inner()).0;
+        }
+    }
+
+    // Stops any more pushes and wakes every waiting thread, so that they can all see whether there is
+    // anything left for them
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.ready.notify_all();
+    }
+
+    pub fn into_heap(self) -> Heap<T, Order> {
+        self.state.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner()).heap
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State<T, Order>> {
+        // Nothing runs user code while holding the lock apart from the order, and a panic in there can't
+        // lose elements, so there is no reason to give up on a poisoned heap
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for SyncHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> std::fmt::Debug for SyncHeap<T, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let state = self.lock();
+        f.debug_struct("SyncHeap").field("len", &state.heap.len()).field("closed", &state.closed).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MinOrder;
+
+    #[test]
+    fn test_timeout_and_close() {
+        let heap = SyncHeap::<u32, MinOrder<_>>::new();
+        heap.push(4).unwrap();
+        heap.push(2).unwrap();
+
+        assert_eq!(heap.pop_timeout(Duration::from_millis(10)), Some(2));
+        assert_eq!(heap.try_pop(), Some(4));
+
+        // Too long to have a deadline, but there is a value there to have straight away
+        heap.push(5).unwrap();
+        assert_eq!(heap.pop_timeout(Duration::MAX), Some(5));
+
+        let start = Instant::now();
+        assert_eq!(heap.pop_timeout(Duration::from_millis(20)), None);
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        heap.push(8).unwrap();
+        heap.close();
+        assert_eq!(heap.push(1), Err(1));
+        assert_eq!(heap.pop_blocking(), Some(8));
+        assert_eq!(heap.pop_blocking(), None);
+        // The heap inside was never bounded, so it takes whatever is inserted once it is back out
+        let mut heap = heap.into_heap();
+        assert_eq!(heap.try_insert(3), Ok(()));
+        assert_eq!(heap.bound(), None);
+    }
+
+    #[test]
+    fn test_worker_threads() {
+        let heap = SyncHeap::<u32, MinOrder<_>>::new();
+        let popped = Mutex::new(Vec::new());
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    while let Some(value) = heap.pop_blocking() {
+                        popped.lock().unwrap().push(value);
+                    }
+                });
+            }
+
+            for value in 0..1000 {
+                heap.push(value).unwrap();
+            }
+
+            heap.close();
+        });
+
+        let mut popped = popped.into_inner().unwrap();
+        popped.sort();
+        assert_eq!(popped, (0..1000).collect::<Vec<_>>());
+        assert!(heap.into_heap().is_empty());
+    }
+}
@@ -0,0 +1,308 @@
+use crate::heap::HeapOrder;
+use crate::indexed_heap::{Handle, IndexedHeap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+// A queue of values that each come out once their deadline has passed, for async code that needs to wait
+// on a lot of timeouts at once without a sleep per timeout. The values sit in an IndexedHeap with the
+// earliest deadline on top, so insert hands back a Handle that cancel can take the value out with later,
+// and expired only ever has one sleep going, for whatever deadline is on top at the time.
+//
+// The sleeping comes from a Timer, so that the queue isn't tied to any one runtime. TokioTimer uses tokio's
+// timer wheel, behind the tokio feature, and ThreadTimer needs nothing but std.
+pub trait Timer {
+    type Sleep: Future<Output = ()>;
+
+    fn sleep_until(&self, deadline: Instant) -> Self::Sleep;
+}
+
+// Sleeps on a thread of its own for each deadline. That works under any executor, but a thread per sleep
+// is a lot, so it is for tests and light use rather than for a service with timers going all the time.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ThreadTimer;
+
+impl Timer for ThreadTimer {
+    type Sleep = ThreadSleep;
+
+    fn sleep_until(&self, deadline: Instant) -> ThreadSleep {
+        ThreadSleep { deadline, waker: None }
+    }
+}
+
+pub struct ThreadSleep {
+    deadline: Instant,
+    // Shared with the thread once it has been started, which wakes whatever is in here at the deadline
+    waker: Option<Arc<Mutex<Option<Waker>>>>,
+}
+
+impl Future for ThreadSleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let deadline = self.deadline;
+        if Instant::now() >= deadline {
+            return Poll::Ready(());
+        }
+
+        match &self.waker {
+            Some(waker) => *waker.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(cx.waker().clone()),
+            None => {
+                let waker = Arc::new(Mutex::new(Some(cx.waker().clone())));
+                let shared = waker.clone();
+                std::thread::spawn(move || {
+                    std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
+                    if let Some(waker) = shared.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take() {
+                        waker.wake();
+                    }
+                });
+
+                self.waker = Some(waker);
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+impl Drop for ThreadSleep {
+    fn drop(&mut self) {
+        // The thread still runs to the deadline, but there is no point in it waking anyone
+        if let Some(waker) = &self.waker {
+            waker.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).take();
+        }
+    }
+}
+
+impl std::fmt::Debug for ThreadSleep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ThreadSleep").field("deadline", &self.deadline).finish_non_exhaustive()
+    }
+}
+
+// Sleeps on tokio's timer, so it has to be used from inside a tokio runtime with time enabled
+#[cfg(feature = "tokio")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TokioTimer;
+
+#[cfg(feature = "tokio")]
+impl Timer for TokioTimer {
+    type Sleep = tokio::time::Sleep;
+
+    fn sleep_until(&self, deadline: Instant) -> tokio::time::Sleep {
+        tokio::time::sleep_until(tokio::time::Instant::from_std(deadline))
+    }
+}
+
+struct Delayed<T> {
+    deadline: Instant,
+    value: T,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct DeadlineOrder;
+
+impl<T> HeapOrder<Delayed<T>> for DeadlineOrder {
+    fn left_can_go_above(&self, left: &Delayed<T>, right: &Delayed<T>) -> bool {
+        left.deadline < right.deadline
+    }
+}
+
+// Instant has no maximum to saturate at, so delays are capped at thirty years instead, which is as good as
+// never for anything waiting on them and keeps every delay past it in order with the rest
+const FAR_FUTURE: Duration = Duration::from_secs(30 * 365 * 24 * 60 * 60);
+
+pub(crate) fn deadline_after(delay: Duration) -> Instant {
+    Instant::now() + delay.min(FAR_FUTURE)
+}
+
+pub struct DelayQueue<T, Timer: self::Timer> {
+    heap: IndexedHeap<Delayed<T>, DeadlineOrder>,
+    timer: Timer,
+    // The sleep for the deadline on top, kept from one poll to the next until the top changes
+    sleep: Option<(Instant, Pin<Box<Timer::Sleep>>)>,
+}
+
+impl<T, Timer: self::Timer> DelayQueue<T, Timer> {
+    pub fn new() -> Self where Timer: Default {
+        Self::with_timer(Timer::default())
+    }
+
+    pub fn with_timer(timer: Timer) -> Self {
+        Self { heap: IndexedHeap::new(), timer, sleep: None }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+        self.sleep = None;
+    }
+
+    pub fn insert(&mut self, value: T, delay: Duration) -> Handle {
+        self.insert_at(value, deadline_after(delay))
+    }
+
+    pub fn insert_at(&mut self, value: T, deadline: Instant) -> Handle {
+        self.heap.insert(Delayed { deadline, value })
+    }
+
+    // Takes the value out before it expires. Returns None if it has already come out or been cancelled.
+    pub fn cancel(&mut self, handle: Handle) -> Option<T> {
+        self.heap.remove(handle).map(|delayed| delayed.value)
+    }
+
+    pub fn contains(&self, handle: Handle) -> bool {
+        self.heap.contains(handle)
+    }
+
+    pub fn get(&self, handle: Handle) -> Option<&T> {
+        self.heap.get(handle).map(|delayed| &delayed.value)
+    }
+
+    pub fn deadline(&self, handle: Handle) -> Option<Instant> {
+        self.heap.get(handle).map(|delayed| delayed.deadline)
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.heap.peek().map(|delayed| delayed.deadline)
+    }
+
+    // Waits for the earliest deadline and returns its value. Nothing can be inserted while it is waiting, so
+    // it returns None straight away if the queue is empty rather than waiting forever.
+    pub fn expired(&mut self) -> Expired<'_, T, Timer> {
+        Expired { queue: self }
+    }
+
+    // What expired does each time it is polled, for code that is writing its own futures
+    pub fn poll_expired(&mut self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let Some(deadline) = self.next_deadline() else {
+            self.sleep = None;
+            return Poll::Ready(None);
+        };
+
+        if deadline > Instant::now() {
+            let sleep = match &mut self.sleep {
+                Some((sleeping_until, sleep)) if *sleeping_until == deadline => sleep,
+                sleep => &mut sleep.insert((deadline, Box::pin(self.timer.sleep_until(deadline)))).1,
+            };
+
+            // Once the sleep is done the deadline counts as passed, even if the timer rounded it a little
+            // early, so that it can't spin waiting for the clock to catch up
+            if sleep.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+
+        self.sleep = None;
+        Poll::Ready(self.heap.pop().map(|delayed| delayed.value))
+    }
+}
+
+impl<T, Timer: self::Timer + Default> Default for DelayQueue<T, Timer> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: std::fmt::Debug, Timer: self::Timer> std::fmt::Debug for DelayQueue<T, Timer> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.heap.iter().map(|(_, delayed)| (delayed.deadline, &delayed.value))).finish()
+    }
+}
+
+pub struct Expired<'a, T, Timer: self::Timer> {
+    queue: &'a mut DelayQueue<T, Timer>,
+}
+
+impl<'a, T, Timer: self::Timer> Future for Expired<'a, T, Timer> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        self.get_mut().queue.poll_expired(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::task::Wake;
+    use std::thread::Thread;
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(ret) = future.as_mut().poll(&mut cx) {
+                return ret;
+            }
+
+            std::thread::park();
+        }
+    }
+
+    #[test]
+    fn test_deadline_order() {
+        let mut queue = DelayQueue::<&str, ThreadTimer>::new();
+        let start = Instant::now();
+        queue.insert("third", Duration::from_millis(60));
+        queue.insert("first", Duration::from_millis(20));
+        let cancelled = queue.insert("never", Duration::from_millis(30));
+        queue.insert("second", Duration::from_millis(40));
+
+        assert_eq!(queue.cancel(cancelled), Some("never"));
+        assert_eq!(queue.cancel(cancelled), None);
+
+        assert_eq!(block_on(queue.expired()), Some("first"));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+        assert_eq!(block_on(queue.expired()), Some("second"));
+        assert_eq!(block_on(queue.expired()), Some("third"));
+        assert!(start.elapsed() >= Duration::from_millis(60));
+        assert_eq!(block_on(queue.expired()), None);
+    }
+
+    #[test]
+    fn test_earlier_insert_while_sleeping() {
+        let mut queue = DelayQueue::<u32, ThreadTimer>::new();
+        queue.insert(1, Duration::from_secs(60));
+
+        // Start sleeping for the long deadline, then put in one that is already due
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        assert_eq!(queue.poll_expired(&mut Context::from_waker(&waker)), Poll::Pending);
+
+        let handle = queue.insert_at(2, Instant::now());
+        assert_eq!(queue.get(handle), Some(&2));
+        assert_eq!(block_on(queue.expired()), Some(2));
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_insert_forever() {
+        let mut queue = DelayQueue::<u32, ThreadTimer>::new();
+        let handle = queue.insert(1, Duration::MAX);
+        let later = queue.insert(2, Duration::from_secs(60));
+        assert!(queue.deadline(handle).unwrap() > queue.deadline(later).unwrap());
+
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        queue.cancel(later);
+        assert_eq!(queue.poll_expired(&mut Context::from_waker(&waker)), Poll::Pending);
+        assert_eq!(queue.cancel(handle), Some(1));
+    }
+}
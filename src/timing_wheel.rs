@@ -0,0 +1,330 @@
+use crate::heap::MinHeap;
+
+// A hierarchical timing wheel keeps timers in LEVELS wheels of SLOTS slots each. Level 0 has one slot per
+// tick, level 1 one slot per SLOTS ticks, and so on, so a timer goes into the lowest level whose slot
+// covers its deadline without wrapping past the current tick. Each tick expires the current level 0
+// slot. Whenever the low bits of the current tick roll over, the next slot up is emptied and its timers
+// are dropped down into the finer levels, the way a clock's minute hand carries into the hours. Timers
+// too far out for even the top level wait in a heap until the top level wraps around to them.
+//
+// Timers live in an arena and record which slot they are in and where, so insert and cancel are both
+// O(1), and ticking is O(1) plus the timers that expire or move down. Cancelling a timer that is still
+// waiting in the overflow heap leaves a stale entry behind, which is skipped when it comes up.
+const SLOT_BITS: u32 = 6;
+const SLOTS: usize = 1 << SLOT_BITS;
+const LEVELS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerHandle {
+    index: usize,
+    generation: u32,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Location {
+    Wheel { slot: usize, position: usize },
+    Overflow,
+}
+
+#[derive(Debug, Clone)]
+struct Timer<T> {
+    due: u64,
+    value: T,
+    location: Location,
+}
+
+#[derive(Debug, Clone)]
+struct Entry<T> {
+    generation: u32,
+    timer: Option<Timer<T>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TimingWheel<T> {
+    entries: Vec<Entry<T>>,
+    free: Vec<usize>,
+    // LEVELS wheels of SLOTS slots, flattened, each holding the indices of the timers in it
+    slots: Vec<Vec<usize>>,
+    overflow: MinHeap<(u64, usize, u32)>,
+    now: u64,
+    len: usize,
+}
+
+impl<T> TimingWheel<T> {
+    pub fn new() -> Self {
+        Self::starting_at(0)
+    }
+
+    pub fn starting_at(now: u64) -> Self {
+        Self {
+            entries: Vec::new(),
+            free: Vec::new(),
+            slots: (0..LEVELS * SLOTS).map(|_| Vec::new()).collect(),
+            overflow: MinHeap::new(),
+            now,
+            len: 0,
+        }
+    }
+
+    // The current tick. Timers due at or before it have already expired, apart from any inserted once it got
+    // to u64::MAX and stopped.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            if entry.timer.take().is_some() {
+                entry.generation = entry.generation.wrapping_add(1);
+            }
+        }
+
+        self.free = (0..self.entries.len()).rev().collect();
+        self.slots.iter_mut().for_each(Vec::clear);
+        self.overflow.clear();
+        self.len = 0;
+    }
+
+    pub fn get(&self, handle: TimerHandle) -> Option<&T> {
+        self.timer(handle).map(|timer| &timer.value)
+    }
+
+    pub fn get_mut(&mut self, handle: TimerHandle) -> Option<&mut T> {
+        self.resolve(handle)?;
+        self.entries[handle.index].timer.as_mut().map(|timer| &mut timer.value)
+    }
+
+    // The tick the timer will expire on
+    pub fn deadline(&self, handle: TimerHandle) -> Option<u64> {
+        self.timer(handle).map(|timer| timer.due)
+    }
+
+    pub fn contains(&self, handle: TimerHandle) -> bool {
+        self.resolve(handle).is_some()
+    }
+
+    // Starts a timer that expires delay ticks from now. A delay of zero expires on the next tick.
+    pub fn insert(&mut self, delay: u64, value: T) -> TimerHandle {
+        self.insert_at(self.now.saturating_add(delay), value)
+    }
+
+    // Starts a timer that expires on the given tick, or on the next tick if that has already passed
+    pub fn insert_at(&mut self, deadline: u64, value: T) -> TimerHandle {
+        let timer = Timer {
+            due: deadline.max(self.now.saturating_add(1)),
+            value,
+            location: Location::Overflow,
+        };
+
+        let index = self.free.pop().unwrap_or_else(|| {
+            self.entries.push(Entry { generation: 0, timer: None });
+            self.entries.len() - 1
+        });
+
+        self.entries[index].timer = Some(timer);
+        self.place(index);
+        self.len += 1;
+
+        TimerHandle {
+            index,
+            generation: self.entries[index].generation,
+        }
+    }
+
+    pub fn cancel(&mut self, handle: TimerHandle) -> Option<T> {
+        let index = self.resolve(handle)?;
+        if let Location::Wheel { slot, position } = self.entries[index].timer.as_ref().unwrap().location {
+            self.slots[slot].swap_remove(position);
+            if let Some(&moved_index) = self.slots[slot].get(position) {
+                self.entries[moved_index].timer.as_mut().unwrap().location = Location::Wheel { slot, position };
+            }
+        }
+
+        Some(self.release(index))
+    }
+
+    // Moves on one tick and returns the timers that expired, in no particular order
+    pub fn tick(&mut self) -> Vec<T> {
+        // u64::MAX is the last tick there is, so the wheel stops there. Anything inserted after that is due on
+        // u64::MAX too, and each tick from then on just expires it.
+        let Some(now) = self.now.checked_add(1) else {
+            return self.expire();
+        };
+
+        self.now = now;
+
+        // Carry from the top down, so that timers can cascade through several levels in one tick
+        let top_bits = SLOT_BITS * LEVELS as u32;
+        if self.now.trailing_zeros() >= top_bits {
+            while let Some(&(due, index, generation)) = self.overflow.peek() {
+                if (due ^ self.now) >> top_bits != 0 {
+                    break;
+                }
+
+                self.overflow.pop();
+                if self.entries[index].generation == generation && self.entries[index].timer.is_some() {
+                    self.place(index);
+                }
+            }
+        }
+
+        for level in (1..LEVELS).rev() {
+            let shift = SLOT_BITS * level as u32;
+            if self.now.trailing_zeros() >= shift {
+                let slot = level * SLOTS + ((self.now >> shift) as usize & (SLOTS - 1));
+                for index in std::mem::take(&mut self.slots[slot]) {
+                    self.place(index);
+                }
+            }
+        }
+
+        self.expire()
+    }
+
+    // Ticks until the given tick, returning everything that expired along the way in the order it expired
+    pub fn advance_to(&mut self, now: u64) -> Vec<T> {
+        let mut ret = Vec::new();
+        while self.now < now {
+            ret.extend(self.tick());
+        }
+
+        ret
+    }
+
+    fn expire(&mut self) -> Vec<T> {
+        let slot = self.now as usize & (SLOTS - 1);
+        std::mem::take(&mut self.slots[slot]).into_iter().map(|index| self.release(index)).collect()
+    }
+
+    fn resolve(&self, handle: TimerHandle) -> Option<usize> {
+        let entry = self.entries.get(handle.index)?;
+        if entry.generation == handle.generation && entry.timer.is_some() {
+            Some(handle.index)
+        } else {
+            None
+        }
+    }
+
+    fn timer(&self, handle: TimerHandle) -> Option<&Timer<T>> {
+        self.resolve(handle).and_then(|index| self.entries[index].timer.as_ref())
+    }
+
+    fn place(&mut self, index: usize) {
+        // The level is the highest group of bits in which the deadline differs from now
+        let timer = self.entries[index].timer.as_mut().unwrap();
+        let significant_bit = 63 - ((timer.due ^ self.now) | (SLOTS as u64 - 1)).leading_zeros();
+        let level = (significant_bit / SLOT_BITS) as usize;
+
+        if level < LEVELS {
+            let slot = level * SLOTS + ((timer.due >> (SLOT_BITS * level as u32)) as usize & (SLOTS - 1));
+            timer.location = Location::Wheel { slot, position: self.slots[slot].len() };
+            self.slots[slot].push(index);
+        } else {
+            timer.location = Location::Overflow;
+            self.overflow.insert((timer.due, index, self.entries[index].generation));
+        }
+    }
+
+    fn release(&mut self, index: usize) -> T {
+        let entry = &mut self.entries[index];
+        entry.generation = entry.generation.wrapping_add(1);
+        self.free.push(index);
+        self.len -= 1;
+
+        entry.timer.take().unwrap().value
+    }
+}
+
+impl<T> Default for TimingWheel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_random_timers() {
+        let mut rng = thread_rng();
+        let mut wheel = TimingWheel::starting_at(rng.gen_range(0..1_000_000));
+        let mut live: Vec<(TimerHandle, u64, u32)> = Vec::new();
+
+        for step in 0..20000 {
+            for _ in 0..rng.gen_range(0..3) {
+                let delay = if rng.gen_bool(0.9) { rng.gen_range(0..300) } else { rng.gen_range(0..20000) };
+                let handle = wheel.insert(delay, step);
+                live.push((handle, wheel.now() + delay.max(1), step));
+            }
+
+            if !live.is_empty() && rng.gen_bool(0.2) {
+                let (handle, _, value) = live.swap_remove(rng.gen_range(0..live.len()));
+                assert_eq!(wheel.cancel(handle), Some(value));
+                assert_eq!(wheel.cancel(handle), None);
+            }
+
+            let mut expired = wheel.tick();
+            let mut expected: Vec<_> = live.iter().filter(|(_, due, _)| *due == wheel.now()).map(|(_, _, value)| *value).collect();
+            live.retain(|(_, due, _)| *due != wheel.now());
+
+            expired.sort();
+            expected.sort();
+            assert_eq!(expired, expected);
+            assert_eq!(wheel.len(), live.len());
+        }
+    }
+
+    #[test]
+    fn test_overflow() {
+        // Start just short of the point where the top level wraps, so the far timers have to wait in the heap
+        let top = 1 << (SLOT_BITS * LEVELS as u32);
+        let mut wheel = TimingWheel::starting_at(top - 5);
+        let near = wheel.insert_at(top - 2, "near");
+        let far = wheel.insert_at(top + 3, "far");
+        let cancelled = wheel.insert_at(2 * top + 1, "cancelled");
+        wheel.insert_at(3 * top - 1, "very far");
+
+        assert_eq!(wheel.deadline(near), Some(top - 2));
+        assert_eq!(wheel.get(far), Some(&"far"));
+        assert_eq!(wheel.cancel(cancelled), Some("cancelled"));
+
+        assert_eq!(wheel.advance_to(top - 2), vec!["near"]);
+        assert_eq!(wheel.advance_to(top + 2), Vec::<&str>::new());
+        assert_eq!(wheel.tick(), vec!["far"]);
+        assert!(!wheel.contains(far));
+        assert_eq!(wheel.len(), 1);
+    }
+
+    #[test]
+    fn test_last_tick() {
+        let mut wheel = TimingWheel::starting_at(u64::MAX - 2);
+        let late = wheel.insert(10, "late");
+        wheel.insert(0, "next");
+        assert_eq!(wheel.deadline(late), Some(u64::MAX));
+
+        assert_eq!(wheel.tick(), vec!["next"]);
+        assert_eq!(wheel.tick(), vec!["late"]);
+        assert_eq!(wheel.now(), u64::MAX);
+
+        // Stuck on the last tick, where new timers still come out on the tick after they went in
+        assert_eq!(wheel.tick(), Vec::<&str>::new());
+        wheel.insert(5, "after");
+        wheel.insert_at(0, "before");
+        assert_eq!(wheel.advance_to(u64::MAX), Vec::<&str>::new());
+        let mut expired = wheel.tick();
+        expired.sort();
+        assert_eq!(expired, vec!["after", "before"]);
+        assert_eq!(wheel.now(), u64::MAX);
+        assert!(wheel.is_empty());
+    }
+}
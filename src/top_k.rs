@@ -0,0 +1,127 @@
+use crate::heap::{Heap, HeapOrder, ReverseOrder};
+
+// Keeps the k best elements seen so far, where best means closest to the top of a heap with the same
+// order, so a MaxOrder keeps the k largest. Internally it is a heap with the order reversed, which puts
+// the worst of the kept elements on top where it can be compared against each new element and evicted
+// in O(log k). Once it is full, an element that is no better than the worst kept one is rejected, so ties
+// go to whichever came first.
+#[derive(Debug, Clone)]
+pub struct TopK<T, Order: HeapOrder<T>> {
+    heap: Heap<T, ReverseOrder<Order>>,
+    k: usize,
+}
+
+impl<T, Order: HeapOrder<T>> TopK<T, Order> {
+    pub fn new(k: usize) -> Self where Order: Default {
+        Self::with_cmp(k, Order::default())
+    }
+
+    pub fn with_cmp(k: usize, order: Order) -> Self {
+        Self {
+            heap: Heap::with_capacity_and_cmp(k, ReverseOrder(order)),
+            k,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.heap.order().0
+    }
+
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.heap.len() == self.k
+    }
+
+    pub fn clear(&mut self) {
+        self.heap.clear();
+    }
+
+    // The worst of the kept elements. Once full, anything that wants in has to beat this.
+    pub fn peek_worst(&self) -> Option<&T> {
+        self.heap.peek()
+    }
+
+    // Iterates over the kept elements in no particular order
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.heap.iter()
+    }
+
+    // Returns whichever element didn't make the cut: the new one if it was rejected, or the one it
+    // evicted. Returns None while there is still room.
+    pub fn push(&mut self, value: T) -> Option<T> {
+        if self.heap.len() < self.k {
+            self.heap.insert(value);
+            None
+        } else {
+            Some(self.heap.push_pop(value))
+        }
+    }
+
+    // The kept elements, best first
+    pub fn into_sorted_vec(self) -> Vec<T> {
+        self.heap.into_sorted_vec()
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.heap.into_vec()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for TopK<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.push(item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{KeyOrder, MaxOrder, MinOrder};
+    use rand::prelude::*;
+
+    #[test]
+    fn test_largest() {
+        let mut values: Vec<u32> = (0..1000).collect();
+        values.shuffle(&mut thread_rng());
+
+        let mut top = TopK::<_, MaxOrder<_>>::new(10);
+        top.extend(values);
+        assert!(top.is_full());
+        assert_eq!(top.peek_worst(), Some(&990));
+        assert_eq!(top.into_sorted_vec(), (990..1000).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_push_returns_loser() {
+        let mut top = TopK::<_, MinOrder<_>>::new(2);
+        assert_eq!(top.push(5), None);
+        assert_eq!(top.push(3), None);
+        assert_eq!(top.push(7), Some(7));
+        assert_eq!(top.push(1), Some(5));
+
+        let mut empty = TopK::<_, MinOrder<_>>::new(0);
+        assert_eq!(empty.push(1), Some(1));
+    }
+
+    #[test]
+    fn test_ties_keep_first() {
+        let mut top = TopK::with_cmp(2, KeyOrder::new(|pair: &(u32, char)| pair.0, MaxOrder::default()));
+        top.extend([(1, 'a'), (1, 'b'), (1, 'c'), (0, 'd')]);
+        let mut names: Vec<_> = top.into_vec().into_iter().map(|(_, name)| name).collect();
+        names.sort();
+        assert_eq!(names, vec!['a', 'b']);
+    }
+}
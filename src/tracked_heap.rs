@@ -0,0 +1,183 @@
+use crate::heap::HeapOrder;
+use crate::raw::{heapify_up_tracked, remove_tracked, restore_tracked};
+
+// A plain binary heap that reports every element it moves. Each time an element lands at a new index the
+// heap calls on_moved with the element and that index, and insert returns where the new element ended up,
+// so the caller can keep their own map from elements to indices and hand those indices back to update or
+// remove. It is the do-it-yourself version of IndexedHeap, for when the positions want to live somewhere
+// the caller already has, like a field on each graph vertex, rather than in a second table inside the
+// heap.
+//
+// Indices only stay right as long as the map is kept up to date with every call, including the moves
+// that happen inside pop, remove and update.
+pub struct TrackedHeap<T, Order: HeapOrder<T>, F: FnMut(&T, usize)> {
+    data: Vec<T>,
+    order: Order,
+    on_moved: F,
+}
+
+impl<T, Order: HeapOrder<T>, F: FnMut(&T, usize)> TrackedHeap<T, Order, F> {
+    pub fn new(on_moved: F) -> Self where Order: Default {
+        Self::with_cmp(Order::default(), on_moved)
+    }
+
+    pub fn with_cmp(order: Order, on_moved: F) -> Self {
+        Self {
+            data: Vec::new(),
+            order,
+            on_moved,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn on_moved(&self) -> &F {
+        &self.on_moved
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.data.get(index)
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    // Returns the index the new element ended up at, which the callback only hears about if it had to move
+    // up from the end
+    pub fn insert(&mut self, value: T) -> usize {
+        let index = self.data.len();
+        self.data.push(value);
+        heapify_up_tracked(&mut self.data, index, &self.order, &mut self.on_moved)
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.data.is_empty() {
+            None
+        } else {
+            Some(self.remove(0))
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.data.len(), "Cannot remove index {} from a heap of length {}", index, self.data.len());
+
+        remove_tracked(&mut self.data, index, &self.order, &mut self.on_moved);
+        self.data.pop().unwrap()
+    }
+
+    // Replaces the element at index, moving the new value up or down as needed, and returns the old one.
+    // This covers both decrease_key and increase_key.
+    pub fn update(&mut self, index: usize, value: T) -> T {
+        assert!(index < self.data.len(), "Cannot update index {} in a heap of length {}", index, self.data.len());
+
+        let ret = std::mem::replace(&mut self.data[index], value);
+        restore_tracked(&mut self.data, index, &self.order, &mut self.on_moved);
+        ret
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+}
+
+impl<T: std::fmt::Debug, Order: HeapOrder<T>, F: FnMut(&T, usize)> std::fmt::Debug for TrackedHeap<T, Order, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.data.iter()).finish()
+    }
+}
+
+impl<T, Order: HeapOrder<T>, F: FnMut(&T, usize)> Extend<T> for TrackedHeap<T, Order, F> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{KeyOrder, MinOrder};
+    use rand::prelude::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_positions_follow_elements() {
+        let mut rng = thread_rng();
+
+        // Elements are (priority, id), and positions[id] is where the caller thinks that element is
+        let positions = Rc::new(RefCell::new(vec![usize::MAX; 1000]));
+        let tracked = positions.clone();
+        let mut heap = TrackedHeap::with_cmp(KeyOrder::new(|entry: &(u32, usize)| entry.0, MinOrder::default()), move |entry: &(u32, usize), index| {
+            tracked.borrow_mut()[entry.1] = index;
+        });
+
+        let mut live: Vec<usize> = Vec::new();
+        for id in 0..1000 {
+            let index = heap.insert((rng.gen_range(0..500), id));
+            positions.borrow_mut()[id] = index;
+            live.push(id);
+
+            if rng.gen_bool(0.3) {
+                let id = live[rng.gen_range(0..live.len())];
+                let index = positions.borrow()[id];
+                let priority = rng.gen_range(0..500);
+                assert_eq!(heap.update(index, (priority, id)).1, id);
+            }
+
+            if rng.gen_bool(0.2) {
+                let id = live.swap_remove(rng.gen_range(0..live.len()));
+                let index = positions.borrow()[id];
+                assert_eq!(heap.remove(index).1, id);
+            }
+
+            for &id in &live {
+                assert_eq!(heap.get(positions.borrow()[id]).map(|entry| entry.1), Some(id));
+            }
+        }
+
+        let mut previous = 0;
+        while let Some((priority, _)) = heap.pop() {
+            assert!(priority >= previous);
+            previous = priority;
+        }
+    }
+
+    #[test]
+    fn test_insert_index() {
+        let mut moves = 0;
+        let mut heap = TrackedHeap::<_, MinOrder<_>, _>::new(|_: &u32, _| moves += 1);
+        assert_eq!(heap.insert(5), 0);
+        assert_eq!(heap.insert(7), 1);
+        assert_eq!(heap.insert(1), 0);
+        assert_eq!(heap.as_slice(), [1, 7, 5]);
+        drop(heap);
+
+        // Only the last insert moved anything, the new element and the one it swapped with
+        assert_eq!(moves, 2);
+    }
+}
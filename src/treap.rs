@@ -0,0 +1,377 @@
+use crate::heap::HeapOrder;
+use std::cmp::Ordering;
+
+// A treap is a binary search tree by key and a heap by priority at the same time: keys increase from left
+// to right, and every node's priority can go at least as high as its children's. That makes the root the
+// top priority entry, so it works as a priority queue, while lookups, ordered iteration and splitting and
+// merging by key work like any other search tree. Split and merge are the basic operations, and insert
+// and remove are built out of them.
+//
+// The tree's shape is decided entirely by the priorities, so it is only balanced, and operations only
+// O(log n) expected, if the priorities are independent of the keys. Random priorities give the classic
+// randomized search tree. Priorities that rise or fall along with the keys make it a linked list, which is
+// the usual case for a queue keyed by deadline, so nothing here recurses down the tree. A list only makes
+// the operations slow, it can't run them out of stack.
+struct Node<K, P> {
+    key: K,
+    priority: P,
+    left: Option<Box<Node<K, P>>>,
+    right: Option<Box<Node<K, P>>>,
+}
+
+type Link<K, P> = Option<Box<Node<K, P>>>;
+
+// Splits the tree into the keys below the given key, and the keys at or above it. Each node on the way
+// down goes into whichever side it belongs on, in the hole left under the last node that went there.
+fn split<K: Ord, P>(mut node: Link<K, P>, key: &K) -> (Link<K, P>, Link<K, P>) {
+    let (mut less, mut greater) = (None, None);
+    let (mut less_hole, mut greater_hole) = (&mut less, &mut greater);
+    while let Some(mut current) = node {
+        if current.key < *key {
+            node = current.right.take();
+            less_hole = &mut less_hole.insert(current).right;
+        } else {
+            node = current.left.take();
+            greater_hole = &mut greater_hole.insert(current).left;
+        }
+    }
+
+    (less, greater)
+}
+
+// Joins two trees where every key in left is below every key in right. Whichever root has the higher
+// priority goes on top, and what is left to merge is the other tree and the inside subtree of that root.
+fn merge<K, P>(mut left: Link<K, P>, mut right: Link<K, P>, order: &impl HeapOrder<P>) -> Link<K, P> {
+    let mut ret = None;
+    let mut hole = &mut ret;
+    loop {
+        match (left, right) {
+            (Some(mut left_node), Some(mut right_node)) => {
+                if order.left_can_go_above(&right_node.priority, &left_node.priority) {
+                    (left, right) = (Some(left_node), right_node.left.take());
+                    hole = &mut hole.insert(right_node).left;
+                } else {
+                    (left, right) = (left_node.right.take(), Some(right_node));
+                    hole = &mut hole.insert(left_node).right;
+                }
+            }
+            (rest_left, rest_right) => {
+                *hole = rest_left.or(rest_right);
+                return ret;
+            }
+        }
+    }
+}
+
+fn remove<K: Ord, P>(mut link: &mut Link<K, P>, key: &K, order: &impl HeapOrder<P>) -> Option<P> {
+    loop {
+        link = match key.cmp(&link.as_ref()?.key) {
+            Ordering::Less => &mut link.as_mut().unwrap().left,
+            Ordering::Greater => &mut link.as_mut().unwrap().right,
+            Ordering::Equal => break,
+        };
+    }
+
+    let node = *link.take().unwrap();
+    *link = merge(node.left, node.right, order);
+    Some(node.priority)
+}
+
+fn count<K, P>(link: &Link<K, P>) -> usize {
+    let mut ret = 0;
+    let mut stack: Vec<_> = link.as_deref().into_iter().collect();
+    while let Some(node) = stack.pop() {
+        stack.extend(node.left.as_deref());
+        stack.extend(node.right.as_deref());
+        ret += 1;
+    }
+
+    ret
+}
+
+pub struct Treap<K: Ord, P, Order: HeapOrder<P>> {
+    root: Link<K, P>,
+    len: usize,
+    order: Order,
+}
+
+impl<K: Ord, P, Order: HeapOrder<P>> Treap<K, P, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            root: None,
+            len: 0,
+            order,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        // A badly shaped tree can be as deep as it is long, so take it apart without recursing
+        let mut stack: Vec<_> = self.root.take().into_iter().collect();
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.take());
+            stack.extend(node.right.take());
+        }
+
+        self.len = 0;
+    }
+
+    pub fn get(&self, key: &K) -> Option<&P> {
+        let mut link = &self.root;
+        while let Some(node) = link {
+            link = match key.cmp(&node.key) {
+                Ordering::Less => &node.left,
+                Ordering::Greater => &node.right,
+                Ordering::Equal => return Some(&node.priority),
+            };
+        }
+
+        None
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+
+    // Iterates over the entries in key order
+    pub fn iter(&self) -> Iter<'_, K, P> {
+        let mut ret = Iter {
+            stack: Vec::new(),
+            remaining: self.len,
+        };
+
+        ret.push_left(self.root.as_deref());
+        ret
+    }
+
+    // The entry with the top priority
+    pub fn peek(&self) -> Option<(&K, &P)> {
+        self.root.as_ref().map(|root| (&root.key, &root.priority))
+    }
+
+    // Returns the old priority if the key was already there
+    pub fn insert(&mut self, key: K, priority: P) -> Option<P> {
+        let ret = self.remove(&key);
+
+        let (less, greater) = split(self.root.take(), &key);
+        let node = Box::new(Node {
+            key,
+            priority,
+            left: None,
+            right: None,
+        });
+
+        self.root = merge(merge(less, Some(node), &self.order), greater, &self.order);
+        self.len += 1;
+        ret
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<P> {
+        let ret = remove(&mut self.root, key, &self.order);
+        if ret.is_some() {
+            self.len -= 1;
+        }
+
+        ret
+    }
+
+    // Removes the entry with the top priority
+    pub fn pop(&mut self) -> Option<(K, P)> {
+        let root = *self.root.take()?;
+        self.root = merge(root.left, root.right, &self.order);
+        self.len -= 1;
+
+        Some((root.key, root.priority))
+    }
+
+    // Moves every entry with a key at or above the given key into a new treap. The split itself is
+    // O(log n), but nodes don't track their subtree sizes, so counting what moved is linear in that.
+    pub fn split_off(&mut self, key: &K) -> Self where Order: Clone {
+        let (less, greater) = split(self.root.take(), key);
+        self.root = less;
+
+        let mut ret = Self::with_cmp(self.order.clone());
+        ret.root = greater;
+        ret.len = count(&ret.root);
+        self.len -= ret.len;
+        ret
+    }
+
+    // Moves everything out of other, whose keys must all be above the keys in this treap. This assumes
+    // both treaps were built with equivalent orders.
+    pub fn append(&mut self, other: &mut Self) {
+        if let (Some(last), Some(first)) = (self.last_key(), other.first_key()) {
+            assert!(last < first, "Cannot append a treap whose keys overlap this one's");
+        }
+
+        self.root = merge(self.root.take(), other.root.take(), &self.order);
+        self.len += std::mem::take(&mut other.len);
+    }
+
+    pub fn first_key(&self) -> Option<&K> {
+        let mut node = self.root.as_deref()?;
+        while let Some(left) = node.left.as_deref() {
+            node = left;
+        }
+
+        Some(&node.key)
+    }
+
+    pub fn last_key(&self) -> Option<&K> {
+        let mut node = self.root.as_deref()?;
+        while let Some(right) = node.right.as_deref() {
+            node = right;
+        }
+
+        Some(&node.key)
+    }
+}
+
+impl<K: Ord, P, Order: HeapOrder<P>> Drop for Treap<K, P, Order> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<K: Ord, P, Order: HeapOrder<P> + Default> Default for Treap<K, P, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Ord + std::fmt::Debug, P: std::fmt::Debug, Order: HeapOrder<P>> std::fmt::Debug for Treap<K, P, Order> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K: Ord, P, Order: HeapOrder<P>> Extend<(K, P)> for Treap<K, P, Order> {
+    fn extend<IntoIter: IntoIterator<Item = (K, P)>>(&mut self, iter: IntoIter) {
+        for (key, priority) in iter {
+            self.insert(key, priority);
+        }
+    }
+}
+
+impl<K: Ord, P, Order: HeapOrder<P> + Default> FromIterator<(K, P)> for Treap<K, P, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = (K, P)>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+pub struct Iter<'a, K, P> {
+    stack: Vec<&'a Node<K, P>>,
+    remaining: usize,
+}
+
+impl<'a, K, P> Iter<'a, K, P> {
+    fn push_left(&mut self, mut node: Option<&'a Node<K, P>>) {
+        while let Some(current) = node {
+            self.stack.push(current);
+            node = current.left.as_deref();
+        }
+    }
+}
+
+impl<'a, K, P> Iterator for Iter<'a, K, P> {
+    type Item = (&'a K, &'a P);
+
+    fn next(&mut self) -> Option<(&'a K, &'a P)> {
+        let node = self.stack.pop()?;
+        self.push_left(node.right.as_deref());
+        self.remaining -= 1;
+
+        Some((&node.key, &node.priority))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K, P> ExactSizeIterator for Iter<'a, K, P> { }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MaxOrder;
+    use rand::prelude::*;
+
+    fn random_treap(keys: impl Iterator<Item = u32>) -> Treap<u32, u64, MaxOrder<u64>> {
+        let mut rng = thread_rng();
+        keys.map(|key| (key, rng.gen())).collect()
+    }
+
+    #[test]
+    fn test_map_and_queue() {
+        let mut rng = thread_rng();
+        let mut keys: Vec<u32> = (0..1000).collect();
+        keys.shuffle(&mut rng);
+
+        let mut treap = random_treap(keys.iter().copied());
+        assert_eq!(treap.len(), 1000);
+        assert_eq!(treap.iter().map(|(key, _)| *key).collect::<Vec<_>>(), (0..1000).collect::<Vec<_>>());
+
+        let old = *treap.get(&500).unwrap();
+        assert_eq!(treap.insert(500, u64::MAX), Some(old));
+        assert_eq!(treap.peek(), Some((&500, &u64::MAX)));
+        assert!(treap.remove(&10).is_some());
+        assert!(!treap.contains_key(&10));
+
+        let mut priorities = Vec::new();
+        while let Some((_, priority)) = treap.pop() {
+            priorities.push(priority);
+        }
+
+        assert_eq!(priorities.len(), 999);
+        assert!(priorities.windows(2).all(|pair| pair[0] >= pair[1]));
+    }
+
+    #[test]
+    fn test_split_append() {
+        let mut treap = random_treap(0..100);
+        let mut upper = treap.split_off(&60);
+
+        assert_eq!(treap.len(), 60);
+        assert_eq!(upper.len(), 40);
+        assert_eq!(treap.last_key(), Some(&59));
+        assert_eq!(upper.first_key(), Some(&60));
+
+        treap.append(&mut upper);
+        assert!(upper.is_empty());
+        assert_eq!(treap.iter().map(|(key, _)| *key).collect::<Vec<_>>(), (0..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_priorities_rising_with_keys() {
+        // Which makes the tree a list as long as the treap, far deeper than the stack could recurse
+        let mut treap: Treap<u32, u32, MaxOrder<u32>> = (0..200000).map(|key| (key, key)).collect();
+        assert_eq!(treap.remove(&0), Some(0));
+        assert_eq!(treap.insert(1, 5), Some(1));
+
+        let mut upper = treap.split_off(&100000);
+        assert_eq!(upper.len(), 100000);
+        assert_eq!(treap.first_key(), Some(&1));
+        treap.append(&mut upper);
+
+        assert_eq!(treap.pop(), Some((199999, 199999)));
+        assert_eq!(treap.len(), 199998);
+    }
+}
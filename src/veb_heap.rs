@@ -0,0 +1,319 @@
+use crate::heap::HeapOrder;
+
+// A heap stored in the implicit van Emde Boas layout. The tree is cut in half by height, the top half
+// is laid out first and then each of the subtrees hanging off its leaves, each of them laid out the same
+// way recursively. Whatever the size of a cache line or a page, any root to leaf path crosses
+// O(log n / log B) blocks of B elements, without having to know B up front like the B-heap does.
+//
+// The heap is still a complete binary tree that is addressed in breadth first order, and only the step
+// from a breadth first index to a storage position knows about the layout. Storage is sized for a full
+// tree of the current height, so the slots past the end of the heap are empty. When the tree is full it
+// is laid out again one level taller, which is O(n) but only happens each time the heap doubles.
+//
+// The index arithmetic costs more than the plain layout's, and the empty slots make each element a little
+// bigger, so this only pays off for heaps far bigger than the last level cache. benches/layouts.rs
+// measures where that point is on a given machine.
+#[derive(Debug, Clone)]
+pub struct VebHeap<T, Order: HeapOrder<T>> {
+    data: Vec<Option<T>>,
+    height: u32,
+    layout: Vec<Split>,
+    len: usize,
+    order: Order,
+}
+
+// Every depth below the root is the top of the bottom trees of exactly one split in the recursion. For a
+// node at that depth, its position is the position of its ancestor at the root of the split, plus the
+// size of the split's top tree, plus one bottom tree for every bottom tree that comes before its own.
+// Walking down from the root, the ancestor's position is always already known.
+#[derive(Debug, Clone, Copy, Default)]
+struct Split {
+    top_depth: u32,
+    top_size: usize,
+    bottom_size: usize,
+}
+
+fn build_layout(layout: &mut [Split], top_depth: u32, height: u32) {
+    if height > 1 {
+        let top_height = height / 2;
+        let bottom_height = height - top_height;
+        layout[(top_depth + top_height) as usize] = Split {
+            top_depth,
+            top_size: (1 << top_height) - 1,
+            bottom_size: (1 << bottom_height) - 1,
+        };
+
+        build_layout(layout, top_depth, top_height);
+        build_layout(layout, top_depth + top_height, bottom_height);
+    }
+}
+
+// The storage position of a one based breadth first index in a complete tree of the given height, worked
+// out by recursing into the layout
+fn veb_position(mut index: usize, mut height: u32) -> usize {
+    let mut position = 0;
+    while height > 1 {
+        let top_height = height / 2;
+        let bottom_height = height - top_height;
+        let depth = index.ilog2();
+
+        if depth < top_height {
+            height = top_height;
+        } else {
+            // Find which bottom subtree the index is in, and where it is within that subtree
+            let bottom_depth = depth - top_height;
+            let subtree = (index >> bottom_depth) - (1 << top_height);
+            position += (1 << top_height) - 1 + subtree * ((1 << bottom_height) - 1);
+
+            index = (1 << bottom_depth) | (index & ((1 << bottom_depth) - 1));
+            height = bottom_height;
+        }
+    }
+
+    position
+}
+
+impl<T, Order: HeapOrder<T>> VebHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self {
+            data: Vec::new(),
+            height: 0,
+            layout: Vec::new(),
+            len: 0,
+            order,
+        }
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.height = 0;
+        self.layout.clear();
+        self.len = 0;
+    }
+
+    // Iterates over the elements in storage order
+    pub fn iter(&self) -> impl Iterator<Item = &T> + '_ {
+        self.data.iter().flatten()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first().and_then(Option::as_ref)
+    }
+
+    pub fn insert(&mut self, value: T) {
+        if self.len == self.data.len() {
+            self.grow();
+        }
+
+        let position = self.position(self.len);
+        self.data[position] = Some(value);
+        self.len += 1;
+        self.sift_up(self.len - 1);
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+
+        self.len -= 1;
+        let last_position = self.position(self.len);
+        let last = self.data[last_position].take();
+        let ret = if self.len == 0 { last } else { std::mem::replace(&mut self.data[0], last) };
+
+        self.sift_down(0);
+        ret
+    }
+
+    // Popped into a new Vec rather than sorted in place, as the storage is in van Emde Boas order with empty
+    // slots in it. Reversed so that the top of the heap is last, like Heap's.
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        let mut ret = Vec::with_capacity(self.len);
+        ret.extend(std::iter::from_fn(|| self.pop()));
+        ret.reverse();
+        ret
+    }
+
+    fn position(&self, index: usize) -> usize {
+        veb_position(index + 1, self.height)
+    }
+
+    // Fills in the positions of the zero based index and all of its ancestors, by depth, and returns the
+    // depth of the index
+    fn fill_path(&self, index: usize, path: &mut [usize]) -> usize {
+        let index = index + 1;
+        let depth = index.ilog2() as usize;
+
+        path[0] = 0;
+        for ancestor_depth in 1..=depth {
+            path[ancestor_depth] = self.child_position(index >> (depth - ancestor_depth), ancestor_depth, path);
+        }
+
+        depth
+    }
+
+    // The position of the one based index at the given depth, given the positions of its ancestors
+    fn child_position(&self, index: usize, depth: usize, path: &[usize]) -> usize {
+        let split = self.layout[depth];
+        let bottom_tree = index & ((1 << (depth - split.top_depth as usize)) - 1);
+        path[split.top_depth as usize] + split.top_size + bottom_tree * split.bottom_size
+    }
+
+    fn grow(&mut self) {
+        let height = self.height + 1;
+        let mut layout = vec![Split::default(); height as usize];
+        build_layout(&mut layout, 0, height);
+
+        let mut data: Vec<Option<T>> = std::iter::repeat_with(|| None).take((1 << height) - 1).collect();
+        for index in 0..self.len {
+            let position = self.position(index);
+            data[veb_position(index + 1, height)] = self.data[position].take();
+        }
+
+        self.data = data;
+        self.height = height;
+        self.layout = layout;
+    }
+
+    fn goes_above(&self, left_position: usize, right_position: usize) -> bool {
+        self.order.left_can_go_above(self.data[left_position].as_ref().unwrap(), self.data[right_position].as_ref().unwrap())
+    }
+
+    fn sift_up(&mut self, mut index: usize) {
+        // Most inserts only go up a level or two, so look up positions as needed rather than filling in
+        // the whole path from the root
+        let mut position = self.position(index);
+        while index > 0 {
+            let parent = (index - 1) / 2;
+            let parent_position = self.position(parent);
+            if !self.goes_above(position, parent_position) {
+                break;
+            }
+
+            self.data.swap(position, parent_position);
+            index = parent;
+            position = parent_position;
+        }
+    }
+
+    fn sift_down(&mut self, mut index: usize) {
+        let mut path = [0; usize::BITS as usize];
+        let mut depth = self.fill_path(index, &mut path);
+
+        loop {
+            let left = 2 * index + 1;
+            if left >= self.len {
+                break;
+            }
+
+            let mut best = left;
+            let mut best_position = self.child_position(left + 1, depth + 1, &path);
+            if left + 1 < self.len {
+                let right_position = self.child_position(left + 2, depth + 1, &path);
+                if self.goes_above(right_position, best_position) {
+                    best = left + 1;
+                    best_position = right_position;
+                }
+            }
+
+            if !self.goes_above(best_position, path[depth]) {
+                break;
+            }
+
+            self.data.swap(path[depth], best_position);
+            index = best;
+            depth += 1;
+            path[depth] = best_position;
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for VebHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for VebHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> FromIterator<T> for VebHeap<T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        let mut ret = Self::new();
+        ret.extend(iter);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::MinOrder;
+    use rand::prelude::*;
+
+    #[test]
+    fn test_layout() {
+        // A tree of height 4 is a top tree of height 2 followed by four bottom trees of height 2
+        let positions: Vec<_> = (1..16).map(|index| veb_position(index, 4)).collect();
+        assert_eq!(positions, vec![0, 1, 2, 3, 6, 9, 12, 4, 5, 7, 8, 10, 11, 13, 14]);
+
+        for height in 1..12 {
+            let mut positions: Vec<_> = (1..1 << height).map(|index| veb_position(index, height)).collect();
+            positions.sort();
+            assert_eq!(positions, (0..(1 << height) - 1).collect::<Vec<_>>());
+        }
+
+        // The precomputed splits have to agree with the recursive layout
+        let mut heap = VebHeap::<u32, MinOrder<_>>::new();
+        heap.extend(0..(1 << 13) - 1);
+        let mut path = [0; usize::BITS as usize];
+        for index in 0..heap.len() {
+            let depth = heap.fill_path(index, &mut path);
+            assert_eq!(path[depth], veb_position(index + 1, 13));
+        }
+    }
+
+    #[test]
+    fn test_insert_pop() {
+        let mut rng = thread_rng();
+        let mut heap = VebHeap::<_, MinOrder<_>>::new();
+        let mut reference = Vec::new();
+
+        for _ in 0..5000 {
+            if rng.gen_bool(0.6) {
+                let value = rng.gen_range(0..1000);
+                heap.insert(value);
+                reference.push(value);
+                reference.sort_by(|left, right| right.cmp(left));
+            } else {
+                assert_eq!(heap.pop(), reference.pop());
+            }
+
+            assert_eq!(heap.peek(), reference.last());
+        }
+
+        assert_eq!(heap.iter().count(), reference.len());
+        assert_eq!(heap.into_sorted_vec(), reference);
+    }
+}
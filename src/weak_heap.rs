@@ -0,0 +1,251 @@
+use crate::heap::HeapOrder;
+
+// A weak heap relaxes the binary heap so that each node only has to be above the elements in its right
+// subtree, and the root has no left subtree at all. Every node has a reverse bit that swaps which of its
+// children counts as left and right, so fixing a violation is just a comparison, a swap and a bit flip
+// rather than a whole subtree shuffle. The upshot is that building takes n - 1 comparisons and popping
+// takes about log2(n), roughly half of what a binary heap needs, which matters when comparisons are
+// expensive.
+//
+// The parent that a node has to be checked against is its distinguished ancestor: walk up the tree while
+// the node is a left child, and the parent of the last node on that walk is the one.
+#[derive(Debug, Clone)]
+pub struct WeakHeap<T, Order: HeapOrder<T>> {
+    data: Vec<T>,
+    reverse: Vec<bool>,
+    order: Order,
+}
+
+impl<T, Order: HeapOrder<T>> WeakHeap<T, Order> {
+    pub fn new() -> Self where Order: Default {
+        Self::with_cmp(Order::default())
+    }
+
+    pub fn with_cmp(order: Order) -> Self {
+        Self::with_capacity_and_cmp(0, order)
+    }
+
+    pub fn with_capacity_and_cmp(capacity: usize, order: Order) -> Self {
+        Self {
+            data: Vec::with_capacity(capacity),
+            reverse: Vec::with_capacity(capacity),
+            order,
+        }
+    }
+
+    pub fn from_vec_and_cmp(data: Vec<T>, order: Order) -> Self {
+        let mut ret = Self {
+            reverse: vec![false; data.len()],
+            data,
+            order,
+        };
+
+        for index in (1..ret.data.len()).rev() {
+            ret.join(ret.distinguished_ancestor(index), index);
+        }
+
+        ret
+    }
+
+    pub fn order(&self) -> &Order {
+        &self.order
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.reverse.clear();
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.data.iter()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.data.first()
+    }
+
+    pub fn insert(&mut self, value: T) {
+        let mut index = self.data.len();
+        self.data.push(value);
+        self.reverse.push(false);
+
+        // A new node in an even slot would otherwise become the right child of its parent, and thereby take
+        // over the parent's subtree. Resetting the parent's bit makes it the left child instead.
+        if index.is_multiple_of(2) && index > 0 {
+            self.reverse[index / 2] = false;
+        }
+
+        while index != 0 {
+            let ancestor_index = self.distinguished_ancestor(index);
+            if !self.join(ancestor_index, index) {
+                break;
+            }
+
+            index = ancestor_index;
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        // The last node is always a leaf, so its reverse bit can simply be dropped. The root's bit stays
+        // false, as the root only ever has a right child.
+        let ret = self.data.swap_remove(0);
+        self.reverse.pop();
+
+        if self.data.len() > 1 {
+            self.sift_down(self.data.len());
+        }
+
+        Some(ret)
+    }
+
+    // Weak-heapsort, in place like Heap's. Each pass swaps the top out past the end of what is left of the
+    // heap and sifts the new root down the same way pop does, so the top of the heap ends up last.
+    pub fn into_sorted_vec(mut self) -> Vec<T> {
+        for end in (1..self.data.len()).rev() {
+            self.data.swap(0, end);
+            if end > 1 {
+                self.sift_down(end);
+            }
+        }
+
+        self.data
+    }
+
+    fn sift_down(&mut self, len: usize) {
+        // Walk down the left children from the root's only child to the bottom of the first len nodes, and
+        // then join each node on the way back up against the root. Whatever wins ends up at the root.
+        let mut index = 1;
+        loop {
+            let left_child_index = 2 * index + usize::from(self.reverse[index]);
+            if left_child_index >= len {
+                break;
+            }
+
+            index = left_child_index;
+        }
+
+        while index > 0 {
+            self.join(0, index);
+            index /= 2;
+        }
+    }
+
+    fn distinguished_ancestor(&self, mut index: usize) -> usize {
+        // A node is a left child when its low bit matches its parent's reverse bit
+        while (index % 2 == 1) == self.reverse[index / 2] {
+            index /= 2;
+        }
+
+        index / 2
+    }
+
+    fn join(&mut self, ancestor_index: usize, index: usize) -> bool {
+        // Returns true if the pair were swapped. Flipping the bit hands the node's old right subtree, which
+        // the new value hasn't been compared against, over to the left.
+        if self.order.left_can_go_above(&self.data[index], &self.data[ancestor_index]) {
+            self.data.swap(ancestor_index, index);
+            self.reverse[index] = !self.reverse[index];
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> Default for WeakHeap<T, Order> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, Order: HeapOrder<T>> Extend<T> for WeakHeap<T, Order> {
+    fn extend<IntoIter: IntoIterator<Item = T>>(&mut self, iter: IntoIter) {
+        for item in iter {
+            self.insert(item);
+        }
+    }
+}
+
+impl<T, Order: HeapOrder<T> + Default> FromIterator<T> for WeakHeap<T, Order> {
+    fn from_iter<IntoIter: IntoIterator<Item = T>>(iter: IntoIter) -> Self {
+        Self::from_vec_and_cmp(iter.into_iter().collect(), Order::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::heap::{Heap, MinOrder};
+    use rand::prelude::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn test_from_vec() {
+        let mut rng = thread_rng();
+        for len in 0..100 {
+            let mut values: Vec<u32> = (0..len).collect();
+            values.shuffle(&mut rng);
+
+            let heap = WeakHeap::from_vec_and_cmp(values, MinOrder::default());
+            assert_eq!(heap.into_sorted_vec(), (0..len).rev().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_random_operations() {
+        let mut rng = thread_rng();
+        let mut heap = WeakHeap::<_, MinOrder<_>>::new();
+        let mut reference = Vec::new();
+
+        for _ in 0..5000 {
+            if rng.gen_bool(0.6) {
+                let value = rng.gen_range(0..100);
+                heap.insert(value);
+                reference.push(value);
+                reference.sort_by(|left, right| right.cmp(left));
+            } else {
+                assert_eq!(heap.pop(), reference.pop());
+            }
+
+            assert_eq!(heap.peek(), reference.last());
+        }
+    }
+
+    #[test]
+    fn test_comparison_count() {
+        let mut values: Vec<u32> = (0..10000).collect();
+        values.shuffle(&mut thread_rng());
+
+        let comparisons = Cell::new(0);
+        let counting_order = |left: &u32, right: &u32| {
+            comparisons.set(comparisons.get() + 1);
+            left < right
+        };
+
+        WeakHeap::from_vec_and_cmp(values.clone(), counting_order).into_sorted_vec();
+        let weak_comparisons = comparisons.replace(0);
+
+        Heap::from_vec_and_cmp(values, counting_order).into_sorted_vec();
+        let binary_comparisons = comparisons.get();
+
+        // log2(10000!) is about 118000
+        assert!(weak_comparisons < 10000 * 14, "{} comparisons", weak_comparisons);
+        assert!(weak_comparisons < binary_comparisons, "{} vs {} comparisons", weak_comparisons, binary_comparisons);
+    }
+}